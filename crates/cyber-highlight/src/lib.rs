@@ -100,10 +100,39 @@ impl TokenType {
 type Tokens<'a> = Vec<Token<'a>>;
 
 /// Returns a sorted, non-overlapping vector of tokens
-pub fn get_tokens<'a>(source: &str, tree: &'a tree_sitter::Tree, query: &Query) -> Tokens<'a> {
-    let mut query_cursor = tree_sitter::QueryCursor::new();
+pub fn get_tokens<'a>(source: &str, tree: &'a tree_sitter::Tree, query: &'a Query) -> Tokens<'a> {
+    get_scoped_tokens(source, tree, query)
+        .into_iter()
+        .map(|ScopedToken { node, token_type, .. }| Token { node, token_type })
+        .collect()
+}
+
+/// What a token's capture and position in the tree say about it, beyond its type
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Modifiers {
+    /// The name being declared by a function, object, variable or parameter
+    pub definition: bool,
+    /// A name used anywhere else
+    pub reference: bool,
+    /// Part of the standard library rather than the document
+    pub builtin: bool,
+}
+
+#[derive(Debug)]
+pub struct ScopedToken<'a> {
+    pub node: tree_sitter::Node<'a>,
+    pub token_type: TokenType,
+    /// The capture name of the highlights query, ie. `keyword.return` or `punctuation.bracket`
+    pub scope: &'a str,
+    pub modifiers: Modifiers,
+}
+
+/// Like `get_tokens`, with the capture name and modifiers of each token
+pub fn get_scoped_tokens<'a>(source: &str, tree: &'a tree_sitter::Tree, query: &'a Query) -> Vec<ScopedToken<'a>> {
+    let capture_names = query.0.capture_names();
+    let mut query_cursor = tree_sitter::pool::QUERY_CURSORS.get();
     let query_matches = query_cursor.matches(&query.0, tree.root_node(), source.as_bytes());
-    let mut tokens = Tokens::new();
+    let mut tokens = Vec::new();
 
     for query_match in query_matches {
         for capture in query_match.captures {
@@ -113,15 +142,32 @@ pub fn get_tokens<'a>(source: &str, tree: &'a tree_sitter::Tree, query: &Query)
                 continue;
             }
             if let Some(token_type) = TokenType::from_pattern_index(query_match.pattern_index) {
-                tokens.push(Token { node, token_type })
+                let scope = capture_names[capture.index as usize].as_str();
+                let modifiers = modifiers(node, &token_type, scope);
+                tokens.push(ScopedToken { node, token_type, scope, modifiers })
             }
         }
     }
-    tokens.sort_by_key(|&Token { node, .. }| node.start_byte());
+    tokens.sort_by_key(|&ScopedToken { node, .. }| node.start_byte());
     // TODO: verify that ranges are non-overlapping?
     tokens
 }
 
+fn modifiers(node: tree_sitter::Node, token_type: &TokenType, scope: &str) -> Modifiers {
+    let builtin = *token_type == TokenType::Builtin || scope.contains("builtin");
+    if node.kind() != "identifier" {
+        return Modifiers { builtin, ..Default::default() };
+    }
+
+    let definition = scope.contains("definition") || node.parent().map_or(false, |parent| {
+        let kind = parent.kind();
+        let declares = kind.ends_with("_definition") || kind.ends_with("_declaration");
+        (declares && parent.child_by_field_name("name") == Some(node))
+            || matches!(kind, "parameter_list" | "parameters" | "parameter")
+    });
+    Modifiers { definition, reference: !definition, builtin }
+}
+
 pub fn init_query() -> Query {
     try_init_query()
         .unwrap_or_else(|query_err| panic!("Error initialising tree-sitter query: {}", query_err))
@@ -0,0 +1,45 @@
+//! Validates the language definitions of `lang_docs/*/*.{json,yaml,yml}` and embeds them in the server
+
+use std::path::{Path, PathBuf};
+
+#[allow(dead_code)]
+#[path = "src/utils/doc_schema.rs"]
+mod doc_schema;
+
+const LANG_DOCS: &str = "lang_docs";
+
+fn main() {
+  println!("cargo:rerun-if-changed={}", LANG_DOCS);
+  println!("cargo:rerun-if-changed=src/utils/doc_schema.rs");
+
+  let files = doc_schema::doc_files(Path::new(LANG_DOCS));
+  if files.is_empty() {
+    panic!("No language definitions found in {}", LANG_DOCS);
+  }
+  for file in &files {
+    println!("cargo:rerun-if-changed={}", file.display());
+  }
+
+  let (definitions, errors) = doc_schema::load_all(&files);
+  if !errors.is_empty() {
+    let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+    panic!("Invalid language definitions, see {}/schema.json:\n  {}", LANG_DOCS, errors.join("\n  "));
+  }
+
+  let packs: Vec<String> = definitions.iter()
+    .map(|(path, _)| path.file_stem().unwrap_or_default().to_string_lossy().to_string())
+    .collect();
+  let definitions: Vec<_> = definitions.into_iter().map(|(_, definition)| definition).collect();
+
+  let json = serde_json::to_string(&definitions).expect("language definitions serialize");
+  let source = format!(
+    "/// Name of every embedded definition file, ie. `hover`\n\
+     pub const DOC_PACKS: &[&str] = &{:?};\n\n\
+     /// Every language definition as minified JSON, validated by `build.rs`\n\
+     pub const LANGUAGE_DEFINITIONS: &str = {:?};\n",
+    packs, json,
+  );
+
+  let out = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("lang_docs.rs");
+  std::fs::write(out, source).expect("Failed to write lang_docs.rs");
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use lsp_types::{
+  CodeAction, CodeActionKind, CompletionItem, CompletionItemKind, CompletionTextEdit, Diagnostic, Position, Range,
+  SymbolKind, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::modules::ImportStatement;
+use crate::symbols::{self, Symbol};
+
+// --| Import Edits ------------------
+// --|--------------------------------
+/// How a symbol of another module becomes reachable from the document
+#[derive(Clone, Debug)]
+pub struct ImportPlan {
+  pub alias: String,
+  pub specifier: String,
+  /// False when the module is already imported under `alias`
+  pub insert: bool,
+}
+
+impl ImportPlan {
+  /// Reuse an existing import of the module, or pick a free alias for a new one
+  pub fn new(imports: &[ImportStatement], existing: Option<&ImportStatement>, target: &Path, specifier: String) -> Self {
+    if let Some(existing) = existing {
+      return Self { alias: existing.alias.clone(), specifier: existing.specifier.clone(), insert: false };
+    }
+
+    let base = alias_for(target);
+    let mut alias = base.clone();
+    let mut suffix = 2;
+    while imports.iter().any(|import| import.alias == alias) {
+      alias = format!("{}{}", base, suffix);
+      suffix += 1;
+    }
+
+    Self { alias, specifier, insert: true }
+  }
+
+  /// The new import statement, placed after the last existing import
+  pub fn import_edit(&self, imports: &[ImportStatement]) -> Option<TextEdit> {
+    if !self.insert { return None; }
+
+    let position = imports.iter()
+      .map(|import| import.range.end.line + 1)
+      .max()
+      .map_or(Position::new(0, 0), |line| Position::new(line, 0));
+
+    Some(TextEdit::new(
+      Range::new(position, position),
+      format!("import {} '{}'\n", self.alias, self.specifier),
+    ))
+  }
+
+  /// Qualified name for a use of `name` from the module
+  pub fn qualified(&self, name: &str) -> String {
+    format!("{}.{}", self.alias, name)
+  }
+}
+
+/// Quick fix importing the module that declares `name` and qualifying the identifier
+pub fn auto_import_action(uri: &Url, imports: &[ImportStatement], plan: &ImportPlan, name: &str, diagnostic: &Diagnostic) -> CodeAction {
+  let mut edits: Vec<TextEdit> = plan.import_edit(imports).into_iter().collect();
+  edits.push(TextEdit::new(diagnostic.range, plan.qualified(name)));
+
+  CodeAction {
+    title: format!("Import {} from '{}'", name, plan.specifier),
+    kind: Some(CodeActionKind::QUICKFIX),
+    diagnostics: Some(vec![diagnostic.clone()]),
+    edit: Some(WorkspaceEdit {
+      changes: Some(HashMap::from([(uri.clone(), edits)])),
+      ..Default::default()
+    }),
+    ..Default::default()
+  }
+}
+
+/// Completion of a symbol from another module. Accepting it qualifies the name with
+/// the module alias and adds the import statement if the module is not imported yet.
+pub fn completion_item(symbol: &Symbol, plan: &ImportPlan, imports: &[ImportStatement], replace: Range) -> CompletionItem {
+  let kind = match symbol.kind {
+    SymbolKind::FUNCTION => CompletionItemKind::FUNCTION,
+    SymbolKind::OBJECT => CompletionItemKind::STRUCT,
+    _ => CompletionItemKind::VARIABLE,
+  };
+
+  let detail = if symbol.is_function() { symbol.signature() } else { symbol.name.clone() };
+  let source = if plan.insert { format!("Auto-import from '{}'", plan.specifier) } else { format!("From '{}'", plan.specifier) };
+  let documentation = match &symbol.doc {
+    Some(doc) => symbols::markdown(format!("{}\n\n---\n{}", doc, source)),
+    None => lsp_types::Documentation::String(source),
+  };
+
+  CompletionItem {
+    label: symbol.name.clone(),
+    kind: Some(kind),
+    detail: Some(detail),
+    documentation: Some(documentation),
+    filter_text: Some(symbol.name.clone()),
+    text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(replace, plan.qualified(&symbol.name)))),
+    additional_text_edits: plan.import_edit(imports).map(|edit| vec![edit]),
+    ..Default::default()
+  }
+}
+
+/// Module alias derived from a file name, ie. `string_utils` for `string-utils.cy`
+fn alias_for(path: &Path) -> String {
+  let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+  let mut alias: String = stem.chars().map(|ch| if ch.is_alphanumeric() { ch } else { '_' }).collect();
+
+  if alias.is_empty() || alias.starts_with(|ch: char| ch.is_ascii_digit()) {
+    alias.insert(0, '_');
+  }
+
+  alias
+}
@@ -5,12 +5,24 @@ use tower_lsp::LanguageServer;
 use tracing::debug;
 use tracing::info;
 
-use crate::Backend;
+use crate::SharedBackend;
+use crate::lanes::Lane;
 use crate::datatypes::Config;
+use crate::lints;
+use crate::project_config;
+use crate::utils::paths;
+use crate::matching::{MatchingBlockParams, MatchingBlockResult};
+use crate::server_info::ServerInfoResult;
+use crate::syntax_tree::{SyntaxTreeParams, SyntaxTreeResult};
+use crate::virtual_docs::{VirtualContentParams, VirtualContentResult};
+#[cfg(feature = "run-commands")]
+use crate::expand::{ExpandParams, ExpandResult};
+#[cfg(feature = "run-commands")]
+use crate::repl::{ReplEvalParams, ReplEvalResult, ReplParams, ReplStartResult};
 
 // --| Language Server Protocol (LSP) implementation
 #[tower_lsp::async_trait]
-impl LanguageServer for Backend {
+impl LanguageServer for SharedBackend {
 
   async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
     self.on_initialize(params).await
@@ -20,19 +32,16 @@ impl LanguageServer for Backend {
   // --|-------------------------------
   async fn initialized(&self, _: InitializedParams) {
     info!("Loading Cyber Language Definitions...");
+    self.load_folder_configs().await;
+    self.load_project_configs().await;
+    self.detect_configured_cyber().await;
+    self.schedule_reindex().await;
+    self.load_lints().await;
     self.client.log_message(MessageType::INFO, "cyberls initialized").await;
   }
 
   // --| Execute Command -------
   async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
-    self.client.log_message(MessageType::INFO, "command executed!").await;
-
-    match self.client.apply_edit(WorkspaceEdit::default()).await {
-      Ok(res) if res.applied => self.client.log_message(MessageType::INFO, "applied").await,
-      Ok(_) => self.client.log_message(MessageType::INFO, "rejected").await,
-      Err(err) => self.client.log_message(MessageType::ERROR, err).await,
-    }
-
     self.on_execute_command(params).await
   }
 
@@ -51,6 +60,11 @@ impl LanguageServer for Backend {
     self.on_save(params).await;
   }
 
+  // --| Will Save --------------------
+  async fn will_save_wait_until(&self, params: WillSaveTextDocumentParams) -> Result<Option<Vec<TextEdit>>> {
+    self.on_will_save_wait_until(params).await
+  }
+
   // --| File Close -------------------
   async fn did_close(&self, params: DidCloseTextDocumentParams) {
     self.on_close(params).await; 
@@ -58,32 +72,136 @@ impl LanguageServer for Backend {
 
   // --| Completion Request -----------
   async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
-    self.on_completion(params).await 
+    let _lane = self.lanes.enter(Lane::Interactive).await;
+    self.on_completion(params).await
   }
 
   // --| Hover Request ----------------
   async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+    let _lane = self.lanes.enter(Lane::Interactive).await;
     self.on_hover(params).await
   }
 
+  // --| Definition Request -----------
+  async fn goto_definition(&self, params: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>> {
+    self.on_definition(params).await
+  }
+
+  // --| Formatting Requests ----------
+  async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+    self.on_formatting(params).await
+  }
+
+  async fn on_type_formatting(&self, params: DocumentOnTypeFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+    self.on_type_formatting_request(params).await
+  }
+
+  // --| Folding Range Request --------
+  async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+    self.on_folding_range(params).await
+  }
+
+  // --| Document Symbol Request ------
+  async fn document_symbol(&self, params: DocumentSymbolParams) -> Result<Option<DocumentSymbolResponse>> {
+    self.on_document_symbol(params).await
+  }
+
+  // --| Moniker Request --------------
+  async fn moniker(&self, params: MonikerParams) -> Result<Option<Vec<Moniker>>> {
+    self.on_moniker(params).await
+  }
+
+  // --| Workspace Symbol Request -----
+  async fn symbol(&self, params: WorkspaceSymbolParams) -> Result<Option<Vec<SymbolInformation>>> {
+    self.on_workspace_symbol(params).await
+  }
+
+  // --| Pull Diagnostics Request -----
+  async fn diagnostic(&self, params: DocumentDiagnosticParams) -> Result<DocumentDiagnosticReportResult> {
+    self.on_diagnostic(params).await
+  }
+
+  // --| Rename Requests --------------
+  async fn prepare_rename(&self, params: TextDocumentPositionParams) -> Result<Option<PrepareRenameResponse>> {
+    self.on_prepare_rename(params).await
+  }
+
+  async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+    self.on_rename(params).await
+  }
+
+  // --| References Request -----------
+  async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+    self.on_references(params).await
+  }
+
+  // --| Call Hierarchy Requests --------
+  async fn prepare_call_hierarchy(&self, params: CallHierarchyPrepareParams) -> Result<Option<Vec<CallHierarchyItem>>> {
+    self.on_prepare_call_hierarchy(params).await
+  }
+
+  async fn incoming_calls(&self, params: CallHierarchyIncomingCallsParams) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+    self.on_incoming_calls(params).await
+  }
+
+  async fn outgoing_calls(&self, params: CallHierarchyOutgoingCallsParams) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+    self.on_outgoing_calls(params).await
+  }
+
+  // --| Semantic Tokens Request ------
+  async fn semantic_tokens_full(&self, params: SemanticTokensParams) -> Result<Option<SemanticTokensResult>> {
+    let _lane = self.lanes.enter(Lane::Interactive).await;
+    self.on_semantic_tokens_full(params).await
+  }
+
+  // --| Signature Help Request -------
+  async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+    self.on_signature_help(params).await
+  }
+
+  // --| Code Action Request ----------
+  async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+    self.on_code_action(params).await
+  }
+
   // --| Workspace Change -------------
-  async fn did_change_workspace_folders(&self, _: DidChangeWorkspaceFoldersParams) {
+  async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+    self.on_workspace_folders_change(params).await;
     self.client.log_message(MessageType::INFO, "workspace folders changed!").await;
   }
 
   // --| Configuration Change ---------
   async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+    self.load_folder_configs().await;
     if params.settings.is_null() { return; }
 
     if let Some(config) = params.settings.get("cyberls") {
        if let Ok(new_config) = serde_json::from_value::<Config>(config.clone()) { 
-          self.log_data.lock().await.verbose = new_config.verbose;
+          let new_config = new_config.with_env_overrides();
+          self.configure(&new_config).await;
 
-         {
+         let (reindex, cyber_changed) = {
            let mut config = self.config.lock().await;
+           let reindex = config.ignore != new_config.ignore;
+           let cyber_changed = config.cyber_path != new_config.cyber_path;
            *config = new_config;
+           (reindex, cyber_changed)
+         };
+
+         self.configure_module_paths().await;
+
+         // Cached semantic tokens depend on the injection, bracket and size settings
+         self.semantic_tokens.clear();
+         self.queries.invalidate();
+
+         if cyber_changed {
+           self.cyber_available.clear();
+           self.detect_configured_cyber().await;
          }
 
+         // Rebuild the ignore rules and drop newly ignored files from the index
+         if reindex { self.schedule_reindex().await; }
+
          debug!("Configuration changed: {:?}", params);
          return;
        }
@@ -99,19 +217,95 @@ impl LanguageServer for Backend {
 
     self.client.log_message(MessageType::INFO, "watched files have changed!").await;
 
+    let mut lints_changed = false;
+    let mut project_changed = false;
     for change in params.changes {
+      if lints::is_lint_file(&change.uri) {
+        lints_changed = true;
+        continue;
+      }
+      if project_config::is_project_file(&change.uri) {
+        project_changed = true;
+        continue;
+      }
+
+      if self.is_ignored(&change.uri) {
+        debug!("Ignoring change of {}", change.uri);
+        self.index.remove(&change.uri);
+        self.clear_diagnostics(&change.uri).await;
+        continue;
+      }
+
+      if let FileChangeType::DELETED | FileChangeType::CREATED = change.typ {
+        // Files appearing or disappearing can change how imports resolve
+        self.modules.invalidate(Some(&self.workspace_for(&change.uri)));
+        self.queries.invalidate();
+      }
+
       if let FileChangeType::DELETED = change.typ {
-      } else {
-        let _path = change.uri.path();
+        self.index.remove(&change.uri);
+        self.clear_diagnostics(&change.uri).await;
+      } else if let Some(path) = paths::uri_to_path(&change.uri) {
+        // Open documents are kept up to date from their buffers
+        if !self.docs.lock().await.contains_key(&change.uri) {
+          self.index.remove(&change.uri);
+          self.index.load_file(&path);
+          self.modules.forget_imports(&path);
+        }
       }
     }
 
-    self.update_diagnostics().await;
+    if lints_changed { self.load_lints().await; }
+    if project_changed { self.reload_project_configs().await; }
+
+    self.schedule_update_diagnostics().await;
     self.client.log_message(MessageType::INFO, "watched files have changed!").await;
   }
 
   // --| Shutdown ---------------------
   // --|-------------------------------
-  async fn shutdown(&self) -> Result<()> { Ok(()) }
+  async fn shutdown(&self) -> Result<()> {
+    self.on_shutdown().await
+  }
 }
 
+// --| Custom Requests ---------------
+// --|--------------------------------
+/// The `cyberls/*` requests registered by `build_service`
+impl SharedBackend {
+  pub(crate) async fn syntax_tree(&self, params: SyntaxTreeParams) -> Result<Option<SyntaxTreeResult>> {
+    self.on_syntax_tree(params).await
+  }
+
+  pub(crate) async fn matching_block(&self, params: MatchingBlockParams) -> Result<Option<MatchingBlockResult>> {
+    self.on_matching_block(params).await
+  }
+
+  pub(crate) async fn server_info(&self) -> Result<ServerInfoResult> {
+    self.on_server_info().await
+  }
+
+  pub(crate) async fn virtual_content(&self, params: VirtualContentParams) -> Result<Option<VirtualContentResult>> {
+    self.on_virtual_content(params).await
+  }
+
+  #[cfg(feature = "run-commands")]
+  pub(crate) async fn repl_start(&self, params: ReplParams) -> Result<ReplStartResult> {
+    self.on_repl_start(params).await
+  }
+
+  #[cfg(feature = "run-commands")]
+  pub(crate) async fn repl_eval(&self, params: ReplEvalParams) -> Result<ReplEvalResult> {
+    self.on_repl_eval(params).await
+  }
+
+  #[cfg(feature = "run-commands")]
+  pub(crate) async fn repl_interrupt(&self, params: ReplParams) -> Result<bool> {
+    self.on_repl_interrupt(params).await
+  }
+
+  #[cfg(feature = "run-commands")]
+  pub(crate) async fn expand(&self, params: ExpandParams) -> Result<Option<ExpandResult>> {
+    self.on_expand(params).await
+  }
+}
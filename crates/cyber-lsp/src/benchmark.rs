@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+use cyber_tree_sitter::{InputEdit, Point};
+use serde::Serialize;
+
+/// Iterations used when the command does not specify a count
+pub const DEFAULT_ITERATIONS: usize = 50;
+
+/// Upper bound to keep a mistyped count from stalling the server
+pub const MAX_ITERATIONS: usize = 10_000;
+
+// --| Parse Benchmark ---------------
+// --|--------------------------------
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Timings {
+  pub min_ms: f64,
+  pub p50_ms: f64,
+  pub p90_ms: f64,
+  pub p99_ms: f64,
+  pub max_ms: f64,
+}
+
+impl Timings {
+  fn from_samples(mut samples: Vec<Duration>) -> Self {
+    samples.sort();
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let percentile = |p: f64| {
+      let index = ((samples.len() - 1) as f64 * p).round() as usize;
+      ms(samples[index])
+    };
+
+    Self {
+      min_ms: ms(samples[0]),
+      p50_ms: percentile(0.50),
+      p90_ms: percentile(0.90),
+      p99_ms: percentile(0.99),
+      max_ms: ms(samples[samples.len() - 1]),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchmarkReport {
+  pub iterations: usize,
+  pub bytes: usize,
+  pub full: Timings,
+  pub incremental: Timings,
+}
+
+impl BenchmarkReport {
+  /// Short summary shown to the user
+  pub fn summary(&self) -> String {
+    format!(
+      "Parsed {} bytes {} times. full: p50 {:.3}ms p90 {:.3}ms p99 {:.3}ms | incremental: p50 {:.3}ms p90 {:.3}ms p99 {:.3}ms",
+      self.bytes, self.iterations,
+      self.full.p50_ms, self.full.p90_ms, self.full.p99_ms,
+      self.incremental.p50_ms, self.incremental.p90_ms, self.incremental.p99_ms,
+    )
+  }
+}
+
+/// Parse a source from scratch and incrementally, `iterations` times each. Blocking.
+///
+/// The incremental runs mark a single character in the middle of the document
+/// as edited, so tree-sitter reuses everything but the surrounding nodes.
+pub fn benchmark_parse(source: &str, iterations: usize) -> Option<BenchmarkReport> {
+  let iterations = iterations.clamp(1, MAX_ITERATIONS);
+  let mut parser = cyber_tree_sitter::try_init_parser().ok()?;
+
+  let mut full = Vec::with_capacity(iterations);
+  for _ in 0..iterations {
+    let start = Instant::now();
+    parser.parse(source, None)?;
+    full.push(start.elapsed());
+  }
+
+  let tree = parser.parse(source, None)?;
+  let edit = middle_edit(source);
+
+  let mut incremental = Vec::with_capacity(iterations);
+  for _ in 0..iterations {
+    let mut old_tree = tree.clone();
+    old_tree.edit(&edit);
+
+    let start = Instant::now();
+    parser.parse(source, Some(&old_tree))?;
+    incremental.push(start.elapsed());
+  }
+
+  Some(BenchmarkReport {
+    iterations,
+    bytes: source.len(),
+    full: Timings::from_samples(full),
+    incremental: Timings::from_samples(incremental),
+  })
+}
+
+/// An edit replacing the character at the middle of the source with itself
+fn middle_edit(source: &str) -> InputEdit {
+  let mut byte = source.len() / 2;
+  while !source.is_char_boundary(byte) { byte -= 1; }
+
+  let end = source[byte..].chars().next().map_or(byte, |ch| byte + ch.len_utf8());
+  let point = |offset: usize| {
+    let before = &source[..offset];
+    let row = before.matches('\n').count();
+    let column = offset - before.rfind('\n').map_or(0, |newline| newline + 1);
+    Point { row, column }
+  };
+
+  InputEdit {
+    start_byte: byte,
+    old_end_byte: end,
+    new_end_byte: end,
+    start_position: point(byte),
+    old_end_position: point(end),
+    new_end_position: point(end),
+  }
+}
@@ -0,0 +1,161 @@
+use lsp_types::{CallHierarchyItem, Position, Range, Url};
+use ropey::Rope;
+
+use crate::core_types::lsp_range;
+use crate::symbols::{CallSite, DocumentSymbols, Symbol};
+use crate::utils::treehelper::range_contains;
+
+// --| Call Hierarchy Items ----------
+// --|--------------------------------
+/// Item of a function declared in `uri`, in client positions of its `rope`
+pub fn item(uri: &Url, rope: &Rope, symbol: &Symbol) -> CallHierarchyItem {
+  CallHierarchyItem {
+    name: symbol.name.clone(),
+    kind: symbol.kind,
+    tags: None,
+    detail: Some(symbol.signature()),
+    uri: uri.clone(),
+    range: lsp_range(rope, symbol.range),
+    selection_range: lsp_range(rope, symbol.selection_range),
+    data: None,
+  }
+}
+
+/// The function declared at the position, or the top level function called there
+pub fn function_at(symbols: &DocumentSymbols, position: Position) -> Option<&Symbol> {
+  let declared = symbols.symbols.iter().find(|symbol| symbol.is_function() && range_contains(&symbol.selection_range, position));
+  declared.or_else(|| {
+    let call = symbols.calls.iter()
+      .filter(|call| call.receiver.is_none() && range_contains(&call.range, position))
+      .max_by_key(|call| (call.range.start.line, call.range.start.character))?;
+    symbols.function(&call.callee)
+  })
+}
+
+/// Innermost function containing the position
+pub fn enclosing_function(symbols: &DocumentSymbols, position: Position) -> Option<&Symbol> {
+  symbols.symbols.iter()
+    .filter(|symbol| symbol.is_function() && range_contains(&symbol.range, position))
+    .min_by_key(|symbol| (symbol.range.end.line - symbol.range.start.line, symbol.range.end.character))
+}
+
+// --| Calls -------------------------
+// --|--------------------------------
+/// Calls of `name` in a document grouped by the function making them. Plain calls only
+/// count in the file declaring the function, `local`, and calls through a receiver only
+/// when it is one of the `aliases` the document imports that file as. A local function
+/// or method of the same name is another function. Top level calls have no caller and
+/// are left out.
+pub fn incoming<'a>(symbols: &'a DocumentSymbols, name: &str, local: bool, aliases: &[String]) -> Vec<(&'a Symbol, Vec<Range>)> {
+  let mut callers: Vec<(&Symbol, Vec<Range>)> = vec![];
+
+  let calls = symbols.calls.iter().filter(|call| call.callee == name && match &call.receiver {
+    None => local,
+    Some(receiver) => aliases.contains(receiver),
+  });
+
+  for call in calls {
+    let caller = match enclosing_function(symbols, call.range.start) { Some(caller) => caller, None => continue };
+    match callers.iter_mut().find(|(known, _)| known.selection_range == caller.selection_range) {
+      Some((_, ranges)) => ranges.push(call.range),
+      None => callers.push((caller, vec![call.range])),
+    }
+  }
+
+  callers
+}
+
+/// Calls made from within `function`, one per receiver and callee with the ranges of all
+/// of them, in the order of their first call
+pub fn outgoing<'a>(symbols: &'a DocumentSymbols, function: &Symbol) -> Vec<(&'a CallSite, Vec<Range>)> {
+  let mut callees: Vec<(&CallSite, Vec<Range>)> = vec![];
+  let calls = symbols.calls.iter().filter(|call| range_contains(&function.range, call.range.start));
+
+  for call in calls {
+    match callees.iter_mut().find(|(known, _)| known.callee == call.callee && known.receiver == call.receiver) {
+      Some((_, ranges)) => ranges.push(call.range),
+      None => callees.push((call, vec![call.range])),
+    }
+  }
+  callees
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::symbols::collect_symbols;
+
+  const SOURCE: &str = "import lib 'lib.cy'
+
+func add(a, b):
+    return a + b
+
+func total():
+    var x = add(1, 2)
+    return lib.add(x, 3)
+
+func other():
+    return helper.add(1)
+
+add(3, 4)
+";
+
+  fn symbols() -> DocumentSymbols {
+    let tree = cyber_tree_sitter::init_parser().parse(SOURCE, None).unwrap();
+    collect_symbols(tree.root_node(), SOURCE)
+  }
+
+  fn names(callers: &[(&Symbol, Vec<Range>)]) -> Vec<(String, usize)> {
+    callers.iter().map(|(caller, ranges)| (caller.name.clone(), ranges.len())).collect()
+  }
+
+  #[test]
+  fn plain_calls_only_count_in_the_declaring_file() {
+    let symbols = symbols();
+    assert_eq!(names(&incoming(&symbols, "add", true, &[])), vec![("total".to_string(), 1)]);
+    assert!(incoming(&symbols, "add", false, &[]).is_empty());
+  }
+
+  #[test]
+  fn calls_through_a_receiver_need_an_alias_of_the_declaring_file() {
+    let symbols = symbols();
+    assert_eq!(names(&incoming(&symbols, "add", false, &["lib".to_string()])), vec![("total".to_string(), 1)]);
+    assert_eq!(names(&incoming(&symbols, "add", false, &["helper".to_string()])), vec![("other".to_string(), 1)]);
+  }
+
+  #[test]
+  fn outgoing_calls_are_grouped_by_receiver_and_callee() {
+    let symbols = symbols();
+    let total = symbols.function("total").unwrap();
+    let callees: Vec<(Option<String>, String)> = outgoing(&symbols, total).into_iter()
+      .map(|(call, _)| (call.receiver.clone(), call.callee.clone()))
+      .collect();
+    assert_eq!(callees, vec![(None, "add".to_string()), (Some("lib".to_string()), "add".to_string())]);
+  }
+
+  #[test]
+  fn items_count_utf16_columns() {
+    let rope = Rope::from_str("func greet():\n    print 'é'\n");
+    let greet = Symbol {
+      name: "greet".to_string(),
+      kind: lsp_types::SymbolKind::FUNCTION,
+      range: Range::new(Position::new(0, 0), Position::new(1, 13)),
+      selection_range: Range::new(Position::new(0, 5), Position::new(0, 10)),
+      params: vec![],
+      container: None,
+      doc: None,
+    };
+
+    let item = item(&Url::parse("file:///work/main.cy").unwrap(), &rope, &greet);
+    assert_eq!(item.range, Range::new(Position::new(0, 0), Position::new(1, 12)));
+    assert_eq!(item.selection_range, greet.selection_range);
+  }
+
+  #[test]
+  fn function_at_finds_declarations_and_called_functions() {
+    let symbols = symbols();
+    assert_eq!(function_at(&symbols, Position::new(2, 6)).map(|function| function.name.as_str()), Some("add"));
+    assert_eq!(function_at(&symbols, Position::new(6, 13)).map(|function| function.name.as_str()), Some("add"));
+    assert!(function_at(&symbols, Position::new(0, 2)).is_none());
+  }
+}
@@ -0,0 +1,117 @@
+use lsp_types::{ClientCapabilities, MarkupContent, MarkupKind};
+
+// --| Client Capabilities -----------
+// --|--------------------------------
+/// Completion items may use snippet syntax
+pub fn snippets(capabilities: &ClientCapabilities) -> bool {
+  capabilities.text_document.as_ref()
+    .and_then(|text| text.completion.as_ref())
+    .and_then(|completion| completion.completion_item.as_ref())
+    .and_then(|item| item.snippet_support)
+    .unwrap_or(false)
+}
+
+/// Completion items may carry their own commit characters
+pub fn commit_characters(capabilities: &ClientCapabilities) -> bool {
+  capabilities.text_document.as_ref()
+    .and_then(|text| text.completion.as_ref())
+    .and_then(|completion| completion.completion_item.as_ref())
+    .and_then(|item| item.commit_characters_support)
+    .unwrap_or(false)
+}
+
+/// Hover contents may be markdown. Clients not stating a format are assumed to render it
+pub fn markdown_hover(capabilities: &ClientCapabilities) -> bool {
+  capabilities.text_document.as_ref()
+    .and_then(|text| text.hover.as_ref())
+    .and_then(|hover| hover.content_format.as_ref())
+    .map_or(true, |formats| formats.contains(&MarkupKind::Markdown))
+}
+
+/// Semantic tokens may span several lines instead of being split per line
+pub fn multiline_tokens(capabilities: &ClientCapabilities) -> bool {
+  capabilities.text_document.as_ref()
+    .and_then(|text| text.semantic_tokens.as_ref())
+    .and_then(|tokens| tokens.multiline_token_support)
+    .unwrap_or(false)
+}
+
+/// File watchers can be registered at runtime
+pub fn watched_files_registration(capabilities: &ClientCapabilities) -> bool {
+  capabilities.workspace.as_ref()
+    .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+    .and_then(|watched| watched.dynamic_registration)
+    .unwrap_or(false)
+}
+
+/// Settings can be requested per scope with `workspace/configuration`
+pub fn workspace_configuration(capabilities: &ClientCapabilities) -> bool {
+  capabilities.workspace.as_ref()
+    .and_then(|workspace| workspace.configuration)
+    .unwrap_or(false)
+}
+
+/// The client shows `$/progress` notifications for server created tokens
+pub fn work_done_progress(capabilities: &ClientCapabilities) -> bool {
+  capabilities.window.as_ref()
+    .and_then(|window| window.work_done_progress)
+    .unwrap_or(false)
+}
+
+/// The client pulls diagnostics with `textDocument/diagnostic` instead of receiving them
+pub fn pull_diagnostics(capabilities: &ClientCapabilities) -> bool {
+  capabilities.text_document.as_ref()
+    .map_or(false, |text| text.diagnostic.is_some())
+}
+
+/// The client accepts `workspace/diagnostic/refresh` requests
+pub fn diagnostic_refresh(capabilities: &ClientCapabilities) -> bool {
+  capabilities.workspace.as_ref()
+    .and_then(|workspace| workspace.diagnostic.as_ref())
+    .and_then(|diagnostic| diagnostic.refresh_support)
+    .unwrap_or(false)
+}
+
+/// Workspace edits may carry snippets, announced as `experimental.snippetTextEdit`
+pub fn snippet_text_edits(capabilities: &ClientCapabilities) -> bool {
+  capabilities.experimental.as_ref()
+    .and_then(|experimental| experimental.get("snippetTextEdit"))
+    .and_then(|value| value.as_bool())
+    .unwrap_or(false)
+}
+
+/// Markdown content, reduced to plain text for clients that can't render it
+pub fn markup(capabilities: &ClientCapabilities, value: String) -> MarkupContent {
+  if markdown_hover(capabilities) {
+    return MarkupContent { kind: MarkupKind::Markdown, value };
+  }
+
+  MarkupContent { kind: MarkupKind::PlainText, value: plaintext(&value) }
+}
+
+/// Drop code fences, rules, heading markers and html tags
+fn plaintext(markdown: &str) -> String {
+  let lines: Vec<String> = markdown.lines()
+    .filter(|line| !line.trim_start().starts_with("```") && line.trim() != "---")
+    .map(|line| line.trim_start_matches('#').trim_start().to_string())
+    .map(|line| strip_tags(&line))
+    .collect();
+
+  lines.join("\n").trim().to_string()
+}
+
+fn strip_tags(line: &str) -> String {
+  let mut result = String::with_capacity(line.len());
+  let mut in_tag = false;
+
+  for ch in line.chars() {
+    match ch {
+      '<' => in_tag = true,
+      '>' if in_tag => in_tag = false,
+      _ if !in_tag => result.push(ch),
+      _ => {}
+    }
+  }
+
+  result
+}
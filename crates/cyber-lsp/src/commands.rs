@@ -0,0 +1,289 @@
+use std::path::PathBuf;
+
+use lsp_types::{ExecuteCommandOptions, Range, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::docgen::DocFormat;
+use crate::structural::STRUCTURAL_REPLACE_COMMAND;
+use crate::syntax_tree::SyntaxTreeFormat;
+
+pub const TOGGLE_VERBOSE: &str = "cyberls.toggle_verbose";
+pub const LOG_LEVEL: &str = "cyberls.loglevel";
+pub const SPELL_IGNORE: &str = "cyberls.spell_ignore";
+pub const RUN_FILE: &str = "cyberls.run_file";
+pub const RUN_SELECTION: &str = "cyberls.run_selection";
+pub const STOP_RUN: &str = "cyberls.stop_run";
+pub const BENCHMARK_PARSE: &str = "cyberls.benchmark_parse";
+pub const DEAD_CODE_REPORT: &str = "cyberls.dead_code_report";
+pub const CLEAR_DIAGNOSTICS: &str = "cyberls.clear_diagnostics";
+pub const CHECK_FILE: &str = "cyberls.check_file";
+pub const REINDEX_WORKSPACE: &str = "cyberls.reindex_workspace";
+pub const SHOW_SYNTAX_TREE: &str = "cyberls.show_syntax_tree";
+pub const ORGANIZE_IMPORTS: &str = "cyberls.organize_imports";
+pub const RESTART_PARSER: &str = "cyberls.restart_parser";
+pub const GENERATE_DOCS: &str = "cyberls.generate_docs";
+pub const SHOW_IMPORT_GRAPH: &str = "cyberls.show_import_graph";
+pub const VALIDATE_DOCS: &str = "cyberls.validate_docs";
+pub const DUMP_STATS: &str = "cyberls.dump_stats";
+pub const MEMORY_REPORT: &str = "cyberls.memory_report";
+pub const SHOW_RECENT_LOG: &str = "cyberls.show_recent_log";
+pub const CLEAR_LOG: &str = "cyberls.clear_log";
+pub const COMPLETION_ACCEPTED: &str = "cyberls.completion_accepted";
+
+// --| Command Registry --------------
+// --|--------------------------------
+pub struct CommandInfo {
+  pub name: &'static str,
+  /// Positional arguments, as shown when they fail to parse
+  pub arguments: &'static str,
+}
+
+/// Every command the server executes. `Command::parse` must handle each of them.
+/// Commands of features left out of the build are not advertised.
+pub const COMMANDS: &[CommandInfo] = &[
+  CommandInfo { name: TOGGLE_VERBOSE, arguments: "no arguments" },
+  CommandInfo { name: LOG_LEVEL, arguments: "a level: error, warn, info, debug or trace" },
+  CommandInfo { name: SPELL_IGNORE, arguments: "a word and a document uri" },
+  #[cfg(feature = "run-commands")]
+  CommandInfo { name: RUN_FILE, arguments: "a document uri" },
+  #[cfg(feature = "run-commands")]
+  CommandInfo { name: RUN_SELECTION, arguments: "a document uri and a range" },
+  #[cfg(feature = "run-commands")]
+  CommandInfo { name: STOP_RUN, arguments: "no arguments" },
+  CommandInfo { name: BENCHMARK_PARSE, arguments: "an open document uri and an optional iteration count" },
+  CommandInfo { name: DEAD_CODE_REPORT, arguments: "an optional publish flag" },
+  CommandInfo { name: CLEAR_DIAGNOSTICS, arguments: "an optional document uri" },
+  CommandInfo { name: CHECK_FILE, arguments: "a document uri" },
+  CommandInfo { name: STRUCTURAL_REPLACE_COMMAND, arguments: "a document uri, a query, a template and an optional workspace flag" },
+  CommandInfo { name: REINDEX_WORKSPACE, arguments: "an optional workspace folder uri" },
+  CommandInfo { name: SHOW_SYNTAX_TREE, arguments: "a document uri, an optional range and an optional format" },
+  CommandInfo { name: ORGANIZE_IMPORTS, arguments: "a document uri and an optional workspace flag" },
+  CommandInfo { name: RESTART_PARSER, arguments: "no arguments" },
+  CommandInfo { name: GENERATE_DOCS, arguments: "an optional workspace folder uri, an optional format and an optional output directory" },
+  CommandInfo { name: SHOW_IMPORT_GRAPH, arguments: "an optional workspace folder uri" },
+  CommandInfo { name: VALIDATE_DOCS, arguments: "an optional language definition file or folder path" },
+  CommandInfo { name: DUMP_STATS, arguments: "an optional reset flag" },
+  CommandInfo { name: MEMORY_REPORT, arguments: "no arguments" },
+  CommandInfo { name: SHOW_RECENT_LOG, arguments: "an optional size in bytes and an optional stream flag" },
+  CommandInfo { name: CLEAR_LOG, arguments: "no arguments" },
+  CommandInfo { name: COMPLETION_ACCEPTED, arguments: "no arguments" },
+];
+
+/// The `executeCommandProvider` capability, built from the registry
+pub fn execute_command_options() -> ExecuteCommandOptions {
+  ExecuteCommandOptions {
+    commands: COMMANDS.iter().map(|command| command.name.to_string()).collect(),
+    work_done_progress_options: Default::default(),
+  }
+}
+
+// --| Command Arguments -------------
+// --|--------------------------------
+#[derive(Debug, Clone, Deserialize)]
+pub struct DocumentArgs {
+  pub uri: Url,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogLevelArgs {
+  pub level: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpellIgnoreArgs {
+  pub word: String,
+  pub uri: Url,
+}
+
+#[cfg(feature = "run-commands")]
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunArgs {
+  pub uri: Url,
+  /// The selection to run, required by `cyberls.run_selection`
+  #[serde(default)]
+  pub range: Option<Range>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BenchmarkArgs {
+  pub uri: Url,
+  #[serde(default)]
+  pub iterations: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeadCodeArgs {
+  /// Also publish the unused symbols as hints
+  #[serde(default)]
+  pub publish: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ClearDiagnosticsArgs {
+  /// Every document when omitted
+  #[serde(default)]
+  pub uri: Option<Url>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StructuralReplaceArgs {
+  pub uri: Url,
+  pub query: String,
+  pub template: String,
+  /// Replace in every indexed file of the workspace, not only `uri`
+  #[serde(default)]
+  pub workspace: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OrganizeImportsArgs {
+  pub uri: Url,
+  /// Organize every indexed file of the workspace, not only `uri`
+  #[serde(default)]
+  pub workspace: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReindexArgs {
+  /// Only this folder is rebuilt. Every workspace folder when omitted
+  #[serde(default)]
+  pub folder: Option<Url>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GenerateDocsArgs {
+  /// Every workspace folder when omitted
+  #[serde(default)]
+  pub folder: Option<Url>,
+  #[serde(default)]
+  pub format: DocFormat,
+  /// Pages are written below this directory, relative to the folder. Returned instead when omitted
+  #[serde(default)]
+  pub out: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ShowImportGraphArgs {
+  /// Every workspace folder when omitted
+  #[serde(default)]
+  pub folder: Option<Url>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ValidateDocsArgs {
+  /// A definition file, or a folder laid out as `lang_docs`. The `lang_docs` folder of
+  /// every workspace folder when omitted
+  #[serde(default)]
+  pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DumpStatsArgs {
+  /// Forget the statistics once dumped, including the saved ones
+  #[serde(default)]
+  pub reset: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ShowRecentLogArgs {
+  /// Bytes from the end of the log, `logs::DEFAULT_TAIL_BYTES` when omitted
+  #[serde(default)]
+  pub bytes: Option<u64>,
+  /// Start or stop sending new lines as `cyberls/logStream` notifications, left as is when omitted
+  #[serde(default)]
+  pub stream: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShowSyntaxTreeArgs {
+  pub uri: Url,
+  #[serde(default)]
+  pub range: Option<Range>,
+  #[serde(default)]
+  pub format: SyntaxTreeFormat,
+}
+
+// --| Commands ----------------------
+// --|--------------------------------
+#[derive(Debug, Clone)]
+pub enum Command {
+  ToggleVerbose,
+  LogLevel(LogLevelArgs),
+  SpellIgnore(SpellIgnoreArgs),
+  #[cfg(feature = "run-commands")]
+  RunFile(RunArgs),
+  #[cfg(feature = "run-commands")]
+  RunSelection(RunArgs),
+  #[cfg(feature = "run-commands")]
+  StopRun,
+  BenchmarkParse(BenchmarkArgs),
+  DeadCodeReport(DeadCodeArgs),
+  ClearDiagnostics(ClearDiagnosticsArgs),
+  CheckFile(DocumentArgs),
+  StructuralReplace(StructuralReplaceArgs),
+  ReindexWorkspace(ReindexArgs),
+  ShowSyntaxTree(ShowSyntaxTreeArgs),
+  OrganizeImports(OrganizeImportsArgs),
+  RestartParser,
+  GenerateDocs(GenerateDocsArgs),
+  ShowImportGraph(ShowImportGraphArgs),
+  ValidateDocs(ValidateDocsArgs),
+  DumpStats(DumpStatsArgs),
+  MemoryReport,
+  ShowRecentLog(ShowRecentLogArgs),
+  ClearLog,
+  /// Sent by clients running the command of an accepted completion item
+  CompletionAccepted,
+}
+
+impl Command {
+  /// Parse a `workspace/executeCommand` request into its typed arguments. The error is
+  /// a message for the client log naming the arguments the command expects.
+  pub fn parse(name: &str, arguments: &[Value]) -> Result<Command, String> {
+    let info = COMMANDS.iter().find(|command| command.name == name).ok_or_else(|| format!("Unknown command: {}", name))?;
+    let invalid = || format!("{} expects {}", name, info.arguments);
+
+    let command = match name {
+      TOGGLE_VERBOSE => Command::ToggleVerbose,
+      LOG_LEVEL => Command::LogLevel(parse_args(arguments).ok_or_else(invalid)?),
+      SPELL_IGNORE => Command::SpellIgnore(parse_args(arguments).ok_or_else(invalid)?),
+      #[cfg(feature = "run-commands")]
+      RUN_FILE => Command::RunFile(parse_args(arguments).ok_or_else(invalid)?),
+      #[cfg(feature = "run-commands")]
+      RUN_SELECTION => {
+        let args: RunArgs = parse_args(arguments).ok_or_else(invalid)?;
+        if args.range.is_none() { return Err(invalid()); }
+        Command::RunSelection(args)
+      }
+      #[cfg(feature = "run-commands")]
+      STOP_RUN => Command::StopRun,
+      BENCHMARK_PARSE => Command::BenchmarkParse(parse_args(arguments).ok_or_else(invalid)?),
+      DEAD_CODE_REPORT => Command::DeadCodeReport(parse_args(arguments).ok_or_else(invalid)?),
+      CLEAR_DIAGNOSTICS => Command::ClearDiagnostics(parse_args(arguments).ok_or_else(invalid)?),
+      CHECK_FILE => Command::CheckFile(parse_args(arguments).ok_or_else(invalid)?),
+      STRUCTURAL_REPLACE_COMMAND => Command::StructuralReplace(parse_args(arguments).ok_or_else(invalid)?),
+      REINDEX_WORKSPACE => Command::ReindexWorkspace(parse_args(arguments).ok_or_else(invalid)?),
+      SHOW_SYNTAX_TREE => Command::ShowSyntaxTree(parse_args(arguments).ok_or_else(invalid)?),
+      ORGANIZE_IMPORTS => Command::OrganizeImports(parse_args(arguments).ok_or_else(invalid)?),
+      RESTART_PARSER => Command::RestartParser,
+      GENERATE_DOCS => Command::GenerateDocs(parse_args(arguments).ok_or_else(invalid)?),
+      SHOW_IMPORT_GRAPH => Command::ShowImportGraph(parse_args(arguments).ok_or_else(invalid)?),
+      VALIDATE_DOCS => Command::ValidateDocs(parse_args(arguments).ok_or_else(invalid)?),
+      DUMP_STATS => Command::DumpStats(parse_args(arguments).ok_or_else(invalid)?),
+      MEMORY_REPORT => Command::MemoryReport,
+      SHOW_RECENT_LOG => Command::ShowRecentLog(parse_args(arguments).ok_or_else(invalid)?),
+      CLEAR_LOG => Command::ClearLog,
+      COMPLETION_ACCEPTED => Command::CompletionAccepted,
+      _ => return Err(format!("Command {} has no handler", name)),
+    };
+
+    Ok(command)
+  }
+}
+
+/// Positional arguments map onto the fields of `T` in order, trailing ones may be omitted
+fn parse_args<T: DeserializeOwned>(arguments: &[Value]) -> Option<T> {
+  serde_json::from_value(Value::Array(arguments.to_vec())).ok()
+}
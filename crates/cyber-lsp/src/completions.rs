@@ -1,37 +1,50 @@
+mod cache;
 mod scanner;
 
+pub use cache::CompletionCache;
+
 // use crate::CompletionResponse;
 use std::path::{Path, PathBuf};
-use crate::utils::treehelper::{get_pos_type, PositionType, get_from_position};
-use lsp_types::{CompletionItem, CompletionItemKind, MessageType, Position, CompletionResponse};
+use crate::symbols;
+use crate::modules;
+use crate::utils::treehelper::{get_pos_type, PositionType, node_text};
+use cyber_tree_sitter::Tree;
+use lsp_types::{CompletionItem, CompletionItemKind, MessageType, Position, CompletionResponse, Url};
 use tracing::info;
 
-/// get the completion messages
-pub async fn get_completion(source: &str, location: Position, client: &tower_lsp::Client, local_path: &str,) -> Option<CompletionResponse> {
-    let lsp_action = "completion".to_string();  
-
-    info!("Loading tree-sitter-cyber parser...");
-    let mut parser = cyber_tree_sitter::try_init_parser().expect("Parser failed to load");
+/// get the completion messages, from the document tree when it is already parsed
+pub async fn get_completion(source: &str, tree: Option<&Tree>, location: Position, cache: &CompletionCache, uri: &Url, client: &tower_lsp::Client, local_path: &str,) -> Option<CompletionResponse> {
+    let parsed;
+    let tree = match tree {
+        Some(tree) => tree,
+        None => {
+            info!("Loading tree-sitter-cyber parser...");
+            let mut parser = cyber_tree_sitter::try_init_parser().expect("Parser failed to load");
+            parsed = parser.parse(source, None)?;
+            info!("Loading tree-sitter-cyber parser...done");
+            &parsed
+        }
+    };
 
-    let ts_tree = parser.parse(source, None);
-    let tree = ts_tree.unwrap();
-    
     let mut complete: Vec<CompletionItem> = vec![];
-    info!("Loading tree-sitter-cyber parser...done");
 
     // --| Load Completion Items ----------
-    let type_data = get_from_position(location, tree.root_node(), source, lsp_action);
+    let pos_type = get_pos_type(location, tree.root_node(), source, PositionType::NotFind);
 
-    if let Some(type_data) = type_data {
-      info!("KeywordDetail: {:?} Desc: {:?}", type_data.keyword, type_data.description); 
-    } else {
-      info!("KeywordDetail: None");
-    }
+    // Statements above the cursor are answered from the cache, the one under it only
+    // offers what is declared before the cursor line
+    let root = tree.root_node();
+    let local_path = Path::new(local_path);
+    let mut cursor = root.walk();
 
-    let pos_type = get_pos_type(location, tree.root_node(), source, PositionType::NotFind);
+    for child in root.children(&mut cursor) {
+        if child.start_position().row as u32 > location.line { break; }
 
-    if let Some(mut message) = get_nested_completion(tree.root_node(), source, Path::new(local_path), pos_type, Some(location),){ 
-      complete.append(&mut message); 
+        if child.end_position().row as u32 >= location.line {
+            complete.extend(get_statement_completion(child, source, local_path, pos_type, Some(location)).unwrap_or_default());
+        } else {
+            complete.extend(cache.scope(uri, child, || get_statement_completion(child, source, local_path, pos_type, None).unwrap_or_default()));
+        }
     }
 
     if complete.is_empty() {
@@ -48,7 +61,6 @@ fn get_nested_completion(input: tree_sitter::Node, source: &str, local_path: &Pa
         if input.start_position().row as u32 > location.line { return None; }
     }
 
-    let source_array: Vec<&str> = source.lines().collect();
     let mut cursor = input.walk();
     let mut completion_item: Vec<CompletionItem> = vec![];
 
@@ -58,34 +70,47 @@ fn get_nested_completion(input: tree_sitter::Node, source: &str, local_path: &Pa
             if child.start_position().row as u32 > location.line { break; }
         }
 
-        match child.kind() {
-            "function_definition" => {
-                let h = child.start_position().row;
-                let ids = child.child(0).unwrap();
-                let x = ids.start_position().column;
-                let y = ids.end_position().column;
-                let name = &source_array[h][x..y];
-                completion_item.push(CompletionItem {
-                    label: format!("{name}()"),
-                    kind: Some(CompletionItemKind::FUNCTION),
-                    detail: Some(format!(
-                        "defined function\nfrom: {}",
-                        local_path.file_name().unwrap().to_str().unwrap()
-                    )),
-                    ..Default::default()
-                });
-            }
-            
-            "if_condition" | "for_range_loop" | "for_iterable_loop" => {
-                if let Some(mut message) =
-                    get_nested_completion(child, source, local_path, pos_type, location)
-                {
-                    completion_item.append(&mut message);
-                }
+        if let Some(mut message) = get_statement_completion(child, source, local_path, pos_type, location) {
+            completion_item.append(&mut message);
+        }
+    }
+    if completion_item.is_empty() {
+        None
+    } else {
+        Some(completion_item)
+    }
+}
+
+/// the functions a single statement declares, looking into its nested blocks
+fn get_statement_completion(child: tree_sitter::Node, source: &str, local_path: &Path, pos_type: PositionType, location: Option<Position>,) -> Option<Vec<CompletionItem>> {
+    let mut completion_item: Vec<CompletionItem> = vec![];
+
+    match child.kind() {
+        "function_definition" => {
+            let ids = child.child(0).unwrap();
+            let name = node_text(ids, source);
+            completion_item.push(CompletionItem {
+                label: format!("{name}()"),
+                kind: Some(CompletionItemKind::FUNCTION),
+                detail: Some(format!(
+                    "defined function\nfrom: {}",
+                    local_path.file_name().unwrap().to_str().unwrap()
+                )),
+                documentation: symbols::doc_comment(child, source).map(symbols::markdown),
+                ..Default::default()
+            });
+        }
+        
+        "if_condition" | "for_range_loop" | "for_iterable_loop" => {
+            if let Some(mut message) =
+                get_nested_completion(child, source, local_path, pos_type, location)
+            {
+                completion_item.append(&mut message);
             }
-            _ => {}
         }
+        _ => {}
     }
+
     if completion_item.is_empty() {
         None
     } else {
@@ -101,3 +126,169 @@ fn cyber_try_exists(input: &PathBuf) -> std::io::Result<bool> {
     }
 }
 
+
+// --| Line Context ------------------
+// --|--------------------------------
+/// What the cursor completes, told from the text of its line before it so most
+/// requests pick their source without walking the tree of the document
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineContext<'a> {
+    /// Inside the module string of an `import`
+    Import,
+    /// After `receiver.` or `receiver.partial`
+    Member(&'a str),
+    /// After `name:` in a declaration or parameter
+    TypeAnnotation,
+    /// Inside a string or a comment, nothing to complete
+    Literal,
+    /// The first word of a statement
+    StatementStart,
+    /// Anywhere else in an expression
+    Other,
+}
+
+/// Classify the text before the cursor. Strings spanning lines are not seen, their
+/// continuation lines are classified as code.
+pub fn line_context(line_prefix: &str) -> LineContext<'_> {
+    let code = line_prefix.trim_start();
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (index, ch) in code.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if ch == '\\' => escaped = true,
+            Some(open) if ch == open => quote = None,
+            Some(_) => {}
+            None if ch == '\'' || ch == '"' || ch == '`' => quote = Some(ch),
+            None if code[index..].starts_with("--") => return LineContext::Literal,
+            None => {}
+        }
+    }
+
+    if quote.is_some() {
+        let is_import = code.strip_prefix("import").map_or(false, |rest| rest.starts_with(char::is_whitespace));
+        return if is_import { LineContext::Import } else { LineContext::Literal };
+    }
+
+    if let Some(receiver) = modules::member_access_alias(code) { return LineContext::Member(receiver); }
+    if is_type_annotation(code) { return LineContext::TypeAnnotation; }
+    if code.chars().all(|ch| ch.is_alphanumeric() || ch == '_') { return LineContext::StatementStart; }
+    LineContext::Other
+}
+
+
+// --| Commit Characters -------------
+// --|--------------------------------
+/// Accept the selected item when a comma is typed, ie. while filling in arguments
+pub const ALL_COMMIT_CHARACTERS: &[&str] = &[","];
+
+/// Characters naturally typed right after an item of this kind: a call after a
+/// function, member access after a module, object or value
+pub fn commit_characters(kind: Option<CompletionItemKind>) -> Option<Vec<String>> {
+    let characters: &[&str] = match kind? {
+        CompletionItemKind::FUNCTION | CompletionItemKind::METHOD => &["("],
+        CompletionItemKind::MODULE | CompletionItemKind::CLASS | CompletionItemKind::STRUCT => &["."],
+        CompletionItemKind::VARIABLE | CompletionItemKind::FIELD | CompletionItemKind::PROPERTY => &[".", "("],
+        _ => return None,
+    };
+    Some(characters.iter().map(|character| character.to_string()).collect())
+}
+
+pub fn with_commit_characters(items: Vec<CompletionItem>) -> Vec<CompletionItem> {
+    items.into_iter().map(|mut item| {
+        if item.commit_characters.is_none() { item.commit_characters = commit_characters(item.kind); }
+        item
+    }).collect()
+}
+
+// --| Type Annotations --------------
+// --|--------------------------------
+/// Builtin types offered after `name:` in a declaration or parameter list
+pub const TYPE_NAMES: &[&str] = &["any", "bool", "int", "number", "string", "List", "Map", "fiber", "error", "none"];
+
+/// Keywords whose line ends in `:` to open a block, not to annotate a type
+const BLOCK_KEYWORDS: &[&str] = &[
+    "if", "else", "for", "while", "func", "object", "try", "catch", "match", "each", "import", "return",
+];
+
+/// Whether the text before the cursor ends in `name:` of a declaration or parameter
+pub fn is_type_annotation(line_prefix: &str) -> bool {
+    let before = match line_prefix.trim_end().strip_suffix(':') {
+        Some(before) => before.trim_end(),
+        None => return false,
+    };
+
+    let name_start = before.rfind(|ch: char| !(ch.is_alphanumeric() || ch == '_')).map_or(0, |index| index + 1);
+    let name = &before[name_start..];
+    if name.is_empty() || name.starts_with(|ch: char| ch.is_ascii_digit()) || BLOCK_KEYWORDS.contains(&name) { return false; }
+
+    // `if ready:` has text before the name, a declaration only `var` or `static`
+    let rest = before[..name_start].trim_end();
+    rest.is_empty() || rest.ends_with(['(', ',']) || matches!(rest, "var" | "static")
+}
+
+/// Builtin types and the objects declared in the document and workspace
+pub fn type_completions(objects: &[String]) -> Vec<CompletionItem> {
+    let builtins = TYPE_NAMES.iter().map(|name| CompletionItem {
+        label: name.to_string(),
+        kind: Some(CompletionItemKind::TYPE_PARAMETER),
+        detail: Some("builtin type".to_string()),
+        ..Default::default()
+    });
+
+    let declared = objects.iter().map(|name| CompletionItem {
+        label: name.clone(),
+        kind: Some(CompletionItemKind::CLASS),
+        detail: Some("object".to_string()),
+        ..Default::default()
+    });
+
+    builtins.chain(declared).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_strings_complete_modules() {
+        assert_eq!(line_context("import util '"), LineContext::Import);
+        assert_eq!(line_context("  import util './lib/"), LineContext::Import);
+        // Only a statement starting with the keyword imports
+        assert_eq!(line_context("important = '"), LineContext::Literal);
+    }
+
+    #[test]
+    fn strings_and_comments_are_literals() {
+        assert_eq!(line_context("print 'hello "), LineContext::Literal);
+        assert_eq!(line_context("var s = \"a"), LineContext::Literal);
+        assert_eq!(line_context("var s = `a"), LineContext::Literal);
+        assert_eq!(line_context("var s = 'it\\'s "), LineContext::Literal);
+        assert_eq!(line_context("-- a comment"), LineContext::Literal);
+        assert_eq!(line_context("var a = 1 -- note"), LineContext::Literal);
+    }
+
+    #[test]
+    fn closed_strings_and_quoted_dashes_are_code() {
+        assert_eq!(line_context("print 'a' + b"), LineContext::Other);
+        assert_eq!(line_context("print '--' + os."), LineContext::Member("os"));
+        assert_eq!(line_context("var s = 'it\\'s' + x."), LineContext::Member("x"));
+    }
+
+    #[test]
+    fn member_access_completes_the_receiver() {
+        assert_eq!(line_context("math."), LineContext::Member("math"));
+        assert_eq!(line_context("  print list.ap"), LineContext::Member("list"));
+    }
+
+    #[test]
+    fn annotations_statements_and_expressions() {
+        assert_eq!(line_context("var count:"), LineContext::TypeAnnotation);
+        assert_eq!(line_context("func add(a: int, b:"), LineContext::TypeAnnotation);
+        assert_eq!(line_context("if ready:"), LineContext::Other);
+        assert_eq!(line_context(""), LineContext::StatementStart);
+        assert_eq!(line_context("    ret"), LineContext::StatementStart);
+        assert_eq!(line_context("var a = 1 + "), LineContext::Other);
+    }
+}
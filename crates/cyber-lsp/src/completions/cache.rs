@@ -0,0 +1,142 @@
+use cyber_tree_sitter::InputEdit;
+use dashmap::DashMap;
+use lsp_types::{CompletionItem, Url};
+use tree_sitter::Node;
+
+// --| Completion Cache --------------
+// --|--------------------------------
+/// Completion items contributed by each top level statement of a document. An edit
+/// only drops the statements it overlaps and moves the ones after it, so typing
+/// within one function keeps the items of every other statement.
+#[derive(Default)]
+pub struct CompletionCache {
+    documents: DashMap<Url, Vec<ScopeItems>>,
+}
+
+struct ScopeItems {
+    start: usize,
+    end: usize,
+    items: Vec<CompletionItem>,
+}
+
+impl CompletionCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Items of a top level statement, computed again only when an edit touched it or
+    /// the doc comment above it, which the items' documentation comes from
+    pub fn scope(&self, uri: &Url, node: Node, compute: impl FnOnce() -> Vec<CompletionItem>) -> Vec<CompletionItem> {
+        let (start, end) = (documented_start(node), node.end_byte());
+        let mut scopes = self.documents.entry(uri.clone()).or_default();
+        if let Some(scope) = scopes.iter().find(|scope| scope.start == start && scope.end == end) {
+            return scope.items.clone();
+        }
+
+        let items = compute();
+        scopes.retain(|scope| scope.end <= start || scope.start >= end);
+        scopes.push(ScopeItems { start, end, items: items.clone() });
+        items
+    }
+
+    /// Drop the statements overlapping an edit, an edit right at their end included
+    /// as it may extend them, and shift the statements after it
+    pub fn edit(&self, uri: &Url, edit: &InputEdit) {
+        let mut scopes = match self.documents.get_mut(uri) {
+            Some(scopes) => scopes,
+            None => return,
+        };
+
+        scopes.retain_mut(|scope| {
+            if scope.end < edit.start_byte { return true; }
+            if scope.start <= edit.old_end_byte { return false; }
+
+            scope.start = scope.start - edit.old_end_byte + edit.new_end_byte;
+            scope.end = scope.end - edit.old_end_byte + edit.new_end_byte;
+            true
+        });
+    }
+
+    /// Drop the items of a closed or reloaded document
+    pub fn forget(&self, uri: &Url) {
+        self.documents.remove(uri);
+    }
+}
+
+/// Start of the comments on the lines right above a statement, as `symbols::doc_comment`
+/// collects them, or of the statement itself
+fn documented_start(node: Node) -> usize {
+    let mut start = node;
+    while let Some(comment) = start.prev_sibling().filter(|sibling| sibling.kind() == "comment") {
+        if comment.end_position().row + 1 < start.start_position().row { break; }
+        start = comment;
+    }
+    start.start_byte()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tree_sitter::Point;
+
+    fn uri() -> Url {
+        Url::parse("file:///work/main.cy").unwrap()
+    }
+
+    fn item(label: &str) -> Vec<CompletionItem> {
+        vec![CompletionItem { label: label.to_string(), ..Default::default() }]
+    }
+
+    fn insert(start_byte: usize, len: usize) -> InputEdit {
+        let point = Point::new(0, 0);
+        InputEdit {
+            start_byte, old_end_byte: start_byte, new_end_byte: start_byte + len,
+            start_position: point, old_end_position: point, new_end_position: point,
+        }
+    }
+
+    fn cached(cache: &CompletionCache) -> Vec<(usize, usize)> {
+        cache.documents.get(&uri()).map_or_else(Vec::new, |scopes| scopes.iter().map(|scope| (scope.start, scope.end)).collect())
+    }
+
+    fn with_scopes(ranges: &[(usize, usize)]) -> CompletionCache {
+        let cache = CompletionCache::new();
+        let scopes = ranges.iter().map(|(start, end)| ScopeItems { start: *start, end: *end, items: item("x") }).collect();
+        cache.documents.insert(uri(), scopes);
+        cache
+    }
+
+    #[test]
+    fn edit_shifts_statements_after_it() {
+        let cache = with_scopes(&[(0, 10), (20, 30), (40, 50)]);
+        cache.edit(&uri(), &insert(15, 3));
+        assert_eq!(cached(&cache), vec![(0, 10), (23, 33), (43, 53)]);
+    }
+
+    #[test]
+    fn edit_drops_statements_it_touches() {
+        let cache = with_scopes(&[(0, 10), (20, 30), (40, 50)]);
+        cache.edit(&uri(), &insert(25, 3));
+        assert_eq!(cached(&cache), vec![(0, 10), (43, 53)]);
+
+        // Typing right at the end of a statement may extend it
+        cache.edit(&uri(), &insert(10, 1));
+        assert_eq!(cached(&cache), vec![(44, 54)]);
+    }
+
+    #[test]
+    fn doc_comment_edits_invalidate_the_statement() {
+        let source = "var a = 1\n\n-- Adds two numbers\nfunc add(x, y):\n    return x + y\n";
+        let tree = cyber_tree_sitter::init_parser().parse(source, None).unwrap();
+        let root = tree.root_node();
+        let function = (0..root.child_count()).filter_map(|index| root.child(index)).find(|child| child.kind() == "function_definition").unwrap();
+
+        let cache = CompletionCache::new();
+        assert_eq!(cache.scope(&uri(), function, || item("first"))[0].label, "first");
+        assert_eq!(cache.scope(&uri(), function, || item("second"))[0].label, "first");
+
+        // An edit inside the comment, which ends before the function starts
+        cache.edit(&uri(), &insert(source.find("two").unwrap(), 4));
+        assert!(cached(&cache).is_empty());
+    }
+}
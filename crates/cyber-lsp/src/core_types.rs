@@ -0,0 +1,247 @@
+use std::borrow::Cow;
+
+use lsp_types::{Position, Range};
+use ropey::Rope;
+use tree_sitter::{Node, Point};
+
+// --| Document Coordinates ----------
+// --|--------------------------------
+// LSP positions count UTF-16 code units, tree-sitter points and the rest of the
+// server count bytes. `DocPoint` and `DocRange` are the byte based coordinates,
+// `ToDoc` and `ToLsp` convert between both through the document rope, which
+// treats `\r\n` as a single line break, so columns never land inside one.
+
+/// A line and a byte column within it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DocPoint {
+  pub line: usize,
+  pub column: usize,
+}
+
+impl DocPoint {
+  pub fn new(line: usize, column: usize) -> Self {
+    Self { line, column }
+  }
+
+  /// Point of a byte offset, clamped to the end of the document
+  pub fn at_offset(rope: &Rope, offset: usize) -> Self {
+    let offset = offset.min(rope.len_bytes());
+    let line = rope.byte_to_line(offset);
+    Self::new(line, offset - rope.line_to_byte(line))
+  }
+
+  /// Byte offset, clamped to the content of the line
+  pub fn offset(&self, rope: &Rope) -> usize {
+    if self.line >= rope.len_lines() { return rope.len_bytes(); }
+
+    let line_end = rope.char_to_byte(line_end_char(rope, self.line));
+    (rope.line_to_byte(self.line) + self.column).min(line_end)
+  }
+
+  /// Char index, clamped to the content of the line
+  pub fn char_index(&self, rope: &Rope) -> usize {
+    rope.byte_to_char(self.offset(rope))
+  }
+
+  /// Symbols, references and diagnostics keep byte columns in LSP positions until
+  /// they are sent, these two read and write them without a rope
+  pub fn from_byte_position(position: Position) -> Self {
+    Self::new(position.line as usize, position.character as usize)
+  }
+
+  pub fn byte_position(self) -> Position {
+    Position::new(self.line as u32, self.column as u32)
+  }
+}
+
+impl From<Point> for DocPoint {
+  fn from(point: Point) -> Self {
+    Self::new(point.row, point.column)
+  }
+}
+
+impl From<DocPoint> for Point {
+  fn from(point: DocPoint) -> Self {
+    Point::new(point.line, point.column)
+  }
+}
+
+/// A span between two byte based points, the end excluded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DocRange {
+  pub start: DocPoint,
+  pub end: DocPoint,
+}
+
+impl DocRange {
+  pub fn new(start: DocPoint, end: DocPoint) -> Self {
+    Self { start, end }
+  }
+
+  pub fn of_node(node: &Node) -> Self {
+    Self::new(node.start_position().into(), node.end_position().into())
+  }
+
+  /// See `DocPoint::from_byte_position`
+  pub fn from_byte_range(range: Range) -> Self {
+    Self::new(DocPoint::from_byte_position(range.start), DocPoint::from_byte_position(range.end))
+  }
+
+  pub fn byte_range(self) -> Range {
+    Range::new(self.start.byte_position(), self.end.byte_position())
+  }
+
+  /// Text covered by the range, or `None` when it is empty or inverted
+  pub fn text<'a>(&self, rope: &'a Rope) -> Option<Cow<'a, str>> {
+    let (start, end) = (self.start.char_index(rope), self.end.char_index(rope));
+    if start >= end { return None; }
+    Some(rope.slice(start..end).into())
+  }
+}
+
+// --| Conversions -------------------
+// --|--------------------------------
+/// LSP coordinates to document ones, clamped to the content of their line
+pub trait ToDoc {
+  type Doc;
+  fn to_doc(&self, rope: &Rope) -> Self::Doc;
+}
+
+impl ToDoc for Position {
+  type Doc = DocPoint;
+
+  fn to_doc(&self, rope: &Rope) -> DocPoint {
+    DocPoint::at_offset(rope, rope.char_to_byte(char_at(rope, *self)))
+  }
+}
+
+impl ToDoc for Range {
+  type Doc = DocRange;
+
+  fn to_doc(&self, rope: &Rope) -> DocRange {
+    DocRange::new(self.start.to_doc(rope), self.end.to_doc(rope))
+  }
+}
+
+/// Document coordinates to LSP ones, clamped to the content of their line
+pub trait ToLsp {
+  type Lsp;
+  fn to_lsp(&self, rope: &Rope) -> Self::Lsp;
+}
+
+impl ToLsp for DocPoint {
+  type Lsp = Position;
+
+  fn to_lsp(&self, rope: &Rope) -> Position {
+    let char = rope.byte_to_char(self.offset(rope));
+    let line = rope.char_to_line(char);
+    let column = rope.char_to_utf16_cu(char) - rope.char_to_utf16_cu(rope.line_to_char(line));
+    Position::new(line as u32, column as u32)
+  }
+}
+
+impl ToLsp for DocRange {
+  type Lsp = Range;
+
+  fn to_lsp(&self, rope: &Rope) -> Range {
+    Range::new(self.start.to_lsp(rope), self.end.to_lsp(rope))
+  }
+}
+
+/// Client range of a byte based one kept in an LSP `Range`, like the ranges of symbols
+pub fn lsp_range(rope: &Rope, range: Range) -> Range {
+  DocRange::from_byte_range(range).to_lsp(rope)
+}
+
+/// Byte based position of a client position, to look up in trees and symbols
+pub fn byte_position(rope: &Rope, position: Position) -> Position {
+  position.to_doc(rope).byte_position()
+}
+
+/// UTF-16 column of a byte column, for a single line of text without a rope
+pub fn utf16_column(line: &str, byte_column: usize) -> usize {
+  let byte_column = byte_column.min(line.len());
+  match line.get(..byte_column) {
+    Some(prefix) => prefix.encode_utf16().count(),
+    None => byte_column,
+  }
+}
+
+/// Char index where the content of `line` ends, before its line break
+fn line_end_char(rope: &Rope, line: usize) -> usize {
+  let slice = rope.line(line);
+  let mut len = slice.len_chars();
+  if len > 0 && slice.char(len - 1) == '\n' { len -= 1; }
+  if len > 0 && slice.char(len - 1) == '\r' { len -= 1; }
+  rope.line_to_char(line) + len
+}
+
+/// Char index of an LSP position, clamped to the content of its line
+fn char_at(rope: &Rope, position: Position) -> usize {
+  let line = position.line as usize;
+  if line >= rope.len_lines() { return rope.len_chars(); }
+
+  let start = rope.line_to_char(line);
+  let utf16 = (rope.char_to_utf16_cu(start) + position.character as usize).min(rope.len_utf16_cu());
+  rope.utf16_cu_to_char(utf16).clamp(start, line_end_char(rope, line))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CRLF_SOURCE: &str = "a = 1\r\nfunc foo(x):\r\n  return x\r\n";
+  const LF_SOURCE: &str = "a = 1\nfunc foo(x):\n  return x\n";
+  const MULTIBYTE_CRLF_SOURCE: &str = "s = 'héllo'\r\nprint s\r\n";
+
+  #[test]
+  fn crlf_positions_match_lf_positions() {
+    let crlf = Rope::from_str(CRLF_SOURCE);
+    let lf = Rope::from_str(LF_SOURCE);
+
+    for (line, character) in [(0, 0), (0, 5), (1, 5), (2, 9)] {
+      let position = Position::new(line, character);
+      assert_eq!(position.to_doc(&crlf), position.to_doc(&lf));
+    }
+  }
+
+  #[test]
+  fn crlf_line_end_is_clamped_before_the_line_break() {
+    let rope = Rope::from_str(CRLF_SOURCE);
+
+    // Past the end of `a = 1` stays on the first line instead of between `\r` and `\n`
+    assert_eq!(Position::new(0, 6).to_doc(&rope).char_index(&rope), 5);
+    assert_eq!(Position::new(0, 40).to_doc(&rope), DocPoint::new(0, 5));
+    assert_eq!(DocPoint::new(0, 6).to_lsp(&rope), Position::new(0, 5));
+  }
+
+  #[test]
+  fn crlf_byte_offsets_round_trip() {
+    let rope = Rope::from_str(CRLF_SOURCE);
+    let byte = CRLF_SOURCE.find("return").unwrap();
+
+    assert_eq!(DocPoint::at_offset(&rope, byte).to_lsp(&rope), Position::new(2, 2));
+    assert_eq!(Position::new(2, 2).to_doc(&rope).offset(&rope), byte);
+    assert_eq!(DocPoint::at_offset(&rope, byte), DocPoint::new(2, 2));
+  }
+
+  #[test]
+  fn multibyte_columns_count_utf16_units() {
+    let rope = Rope::from_str(MULTIBYTE_CRLF_SOURCE);
+    let closing_quote = MULTIBYTE_CRLF_SOURCE.find("'\r").unwrap();
+
+    // `é` is two bytes but a single UTF-16 unit
+    assert_eq!(DocPoint::at_offset(&rope, closing_quote).to_lsp(&rope), Position::new(0, 10));
+    assert_eq!(Position::new(0, 10).to_doc(&rope), DocPoint::new(0, 11));
+    assert_eq!(DocPoint::new(1, 6).to_lsp(&rope), Position::new(1, 6));
+    assert_eq!(utf16_column("s = 'héllo'", 11), 10);
+  }
+
+  #[test]
+  fn ranges_slice_their_text() {
+    let rope = Rope::from_str(MULTIBYTE_CRLF_SOURCE);
+
+    assert_eq!(Range::new(Position::new(0, 5), Position::new(0, 10)).to_doc(&rope).text(&rope).as_deref(), Some("héllo"));
+    assert_eq!(Range::new(Position::new(1, 2), Position::new(1, 2)).to_doc(&rope).text(&rope), None);
+  }
+}
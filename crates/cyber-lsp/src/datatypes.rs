@@ -1,7 +1,19 @@
+use std::collections::HashMap;
+
 use lsp_types::{Url, TextDocumentContentChangeEvent};
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
 use tracing_subscriber::filter;
 
+use crate::diagnostics::{CompilerMode, DiagnosticsConfig};
+use crate::folding::FoldingConfig;
+use crate::format::IndentStyle;
+use crate::lanes::LanesConfig;
+use crate::project_config;
+use crate::run::RunOutputMode;
+use crate::spellcheck::SpellCheckConfig;
+
 pub(crate) struct TextDocumentItem {
   pub uri: Url,
   pub text: String,
@@ -9,12 +21,6 @@ pub(crate) struct TextDocumentItem {
   pub changes: Vec<TextDocumentContentChangeEvent>
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LanguageDoc {
-  pub docname: String,
-  pub path: String,
-}
-
 pub type _LanguageDefinitions = Vec<LanguageDefinition>;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +45,12 @@ pub struct KeywordDetail {
     pub node_type: Vec<String>,
     pub description: String,
     pub example: String,
+    /// Set when the keyword or builtin is deprecated, explaining why or what to use instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+    /// Name the deprecated keyword is replaced with by the quick fix
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
 }
 
 impl LanguageDefinition {
@@ -56,19 +68,94 @@ impl LanguageDefinition {
 // --| Debug Structures ----------
 // --|----------------------------
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
   pub enable: bool,
   pub verbose: bool,
   pub show_warnings: bool,
   pub unstable_features: bool,
+  /// Keep local statistics of request counts, latencies and completion acceptance in the
+  /// log directory, see `cyberls.dump_stats`. Nothing is sent over the network
   pub performance_metrics: bool,
   pub root_dir: Option<String>,
   pub log_level: Option<String>,
   pub client_name: Option<String>,
+
+  /// Download and cache modules imported by URL so they can be analyzed
+  pub remote_imports: bool,
+  /// Only use already cached remote modules, never touch the network
+  pub offline: bool,
+  /// Where remote modules are cached. Defaults to the user cache directory
+  pub cache_dir: Option<String>,
+  /// Expected SHA-256 of remote modules by URL. Downloads not matching are rejected
+  pub remote_hashes: HashMap<String, String>,
+
+  /// Spell checking of comments and string literals
+  pub spellcheck: SpellCheckConfig,
+
+  /// Gitignore style patterns skipped by indexing and watched file diagnostics,
+  /// in addition to `.gitignore` and `.cyberlsignore`
+  pub ignore: Vec<String>,
+  /// Extra file extensions, ie. `cys`, treated as Cyber when opened and indexed
+  pub cyber_extensions: Vec<String>,
+
+  /// Directory imports of untitled and other unsaved buffers are resolved from
+  pub virtual_root: Option<String>,
+  /// Folders imports are also looked up in when not found next to the document. Relative
+  /// ones are relative to the workspace folder. The cyber runtime doesn't search them, so
+  /// imports only found there are flagged
+  pub module_paths: Vec<String>,
+
+  /// Path to the cyber executable. Looked up on PATH when unset
+  pub cyber_path: Option<String>,
+  /// Compile diagnostics from a process per check, or a long-lived check server
+  pub compiler_mode: CompilerMode,
+  /// Where output of `cyberls.run_file` / `cyberls.run_selection` is sent
+  pub run_output: RunOutputMode,
+
+  /// Severity overrides per diagnostic code, applied to every diagnostic published
+  pub diagnostics: DiagnosticsConfig,
+
+  /// Document sizes above which features are reduced or disabled
+  pub limits: LimitsConfig,
+  /// Minutes cached results of a closed document are kept after their last use. Open
+  /// documents keep theirs. 0 keeps every result
+  pub idle_tree_minutes: u64,
+  /// Requests and background work handled at once, see `LanesConfig`. How many messages
+  /// are read at once is fixed by the settings the server starts with, ie.
+  /// `CYBERLS_LANES__BACKGROUND`, settings sent later only resize the lanes
+  pub lanes: LanesConfig,
+
+  /// Semantic tokens for format placeholders, JSON and shell commands inside strings
+  pub injection_highlighting: bool,
+  /// Mark brackets, and the delimiters between them, with a `bracketDepthN` token modifier
+  pub rainbow_brackets: bool,
+
+  /// Comment markers of foldable regions
+  pub folding: FoldingConfig,
+
+  /// `spaces` or `tabs`. Taken from the client's formatting options when unset
+  pub indent_style: Option<IndentStyle>,
+  /// Spaces per indentation level, or the tab width
+  pub indent_width: Option<u32>,
+  /// Format the document when the client asks through `willSaveWaitUntil`, off unless opted in
+  pub format_on_save: bool,
+  /// Also apply the fix all edits on save: whitespace cleanup and unambiguous imports
+  pub fix_all_on_save: bool,
+
+  /// Append the syntax node kind, byte range and scope chain to hovers
+  pub developer_hover: bool,
+
+  /// Allow starting the cyber executable for compile diagnostics, runs, the REPL and
+  /// expansion previews. Unset trusts local folders and not remote ones
+  pub trusted: Option<bool>,
+
+  /// Also show alerts as desktop notifications through `notify-send`, in trusted workspaces
+  pub desktop_notifications: bool,
 }
 
-impl Config {
-  pub fn default() -> Config {
+impl Default for Config {
+  fn default() -> Config {
     Config {
       enable: true,
       verbose:false, 
@@ -78,8 +165,149 @@ impl Config {
       show_warnings: true,
       unstable_features: false,
       performance_metrics: false,
+      remote_imports: false,
+      offline: false,
+      cache_dir: None,
+      remote_hashes: HashMap::new(),
+      spellcheck: SpellCheckConfig::default(),
+      ignore: vec![],
+      cyber_extensions: vec![],
+      virtual_root: None,
+      module_paths: vec![],
+      cyber_path: None,
+      compiler_mode: CompilerMode::default(),
+      run_output: RunOutputMode::default(),
+      diagnostics: DiagnosticsConfig::default(),
+      limits: LimitsConfig::default(),
+      idle_tree_minutes: 30,
+      lanes: LanesConfig::default(),
+      injection_highlighting: true,
+      rainbow_brackets: false,
+      folding: FoldingConfig::default(),
+      indent_style: None,
+      indent_width: None,
+      format_on_save: false,
+      fix_all_on_save: false,
+      developer_hover: false,
+      trusted: None,
+      desktop_notifications: false,
+    }
+  }
+}
+
+// --| Startup Configuration -----
+// --|----------------------------
+/// Environment variables overriding settings, see `Config::with_env_overrides`
+pub const ENV_PREFIX: &str = "CYBERLS_";
+
+impl Config {
+  /// Settings sent as `initializationOptions` or in a configuration change, either on
+  /// their own or in a `cyberls` section. Doc packs are built in, there is no setting
+  /// for folders of them.
+  pub fn from_settings(settings: &Value) -> Option<Config> {
+    Config::default().with_settings(settings)
+  }
+
+  /// These settings with those sent by the client laid over them. Tables are merged key
+  /// by key like project files are, settings the client leaves out are kept.
+  pub fn with_settings(&self, settings: &Value) -> Option<Config> {
+    let overlay = settings.get("cyberls").unwrap_or(settings);
+    let mut merged = serde_json::to_value(self).ok()?;
+    project_config::merge(&mut merged, overlay);
+    serde_json::from_value(merged).ok()
+  }
+
+  /// Apply `CYBERLS_*` variables over the settings, which wins over anything a client
+  /// sends. `CYBERLS_CYBER_PATH` sets `cyber_path`, `__` reaches nested settings, ie.
+  /// `CYBERLS_LIMITS__MAX_PARSE_SIZE`. Values are read as JSON, or else as a string.
+  pub fn with_env_overrides(self) -> Config {
+    self.with_overrides(std::env::vars())
+  }
+
+  pub fn with_overrides(self, variables: impl IntoIterator<Item = (String, String)>) -> Config {
+    let mut config = self;
+    for (name, raw) in variables {
+      // `CYBERLS_SUPERVISED` and the like are not settings
+      let pointer = match name.strip_prefix(ENV_PREFIX) {
+        Some(key) if !key.is_empty() => format!("/{}", key.to_lowercase().replace("__", "/")),
+        _ => continue,
+      };
+      if !config.has_setting(&pointer) { continue; }
+
+      let parsed = serde_json::from_str(&raw).ok().into_iter().chain([Value::String(raw.clone())]);
+      match parsed.filter_map(|value| config.with_setting(&pointer, value)).next() {
+        Some(overridden) => config = overridden,
+        None => warn!("Ignoring {}: {:?} is not a valid value", name, raw),
+      }
+    }
+    config
+  }
+
+  fn has_setting(&self, pointer: &str) -> bool {
+    serde_json::to_value(self).map_or(false, |settings| settings.pointer(pointer).is_some())
+  }
+
+  /// The settings with one of them replaced, `None` when the value does not fit it
+  fn with_setting(&self, pointer: &str, value: Value) -> Option<Config> {
+    let mut settings = serde_json::to_value(self).ok()?;
+    *settings.pointer_mut(pointer)? = value;
+    serde_json::from_value(settings).ok()
+  }
+}
+
+// --| Large File Limits ---------
+// --|----------------------------
+/// Size thresholds in bytes. Documents above a threshold degrade to a cheaper
+/// mode instead of blocking the server.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+  /// Documents larger than this are not parsed; only text synchronisation is kept
+  pub max_parse_size: usize,
+  /// Documents larger than this only get syntax error diagnostics
+  pub max_diagnostics_size: usize,
+  /// Documents larger than this get no semantic tokens
+  pub max_semantic_tokens_size: usize,
+}
+
+impl Default for LimitsConfig {
+  fn default() -> Self {
+    LimitsConfig {
+      max_parse_size: 4 * 1024 * 1024,
+      max_diagnostics_size: 1024 * 1024,
+      max_semantic_tokens_size: 512 * 1024,
+    }
+  }
+}
+
+/// How much analysis a document of a given size receives
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnalysisLevel {
+  Full,
+  /// Parsed, but only syntax errors are reported
+  SyntaxOnly,
+  /// Not parsed at all
+  Disabled,
+}
+
+impl LimitsConfig {
+  pub fn analysis_level(&self, size: usize) -> AnalysisLevel {
+    if size > self.max_parse_size {
+      AnalysisLevel::Disabled
+    } else if size > self.max_diagnostics_size {
+      AnalysisLevel::SyntaxOnly
+    } else {
+      AnalysisLevel::Full
     }
   }
+
+  pub fn allows_parse(&self, size: usize) -> bool {
+    size <= self.max_parse_size
+  }
+
+  pub fn allows_semantic_tokens(&self, size: usize) -> bool {
+    size <= self.max_semantic_tokens_size
+  }
 }
 
 #[derive(Debug)]
@@ -99,3 +327,44 @@ impl LogData {
     Self { log_level, verbose }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn overrides(variables: &[(&str, &str)]) -> Config {
+    Config::default().with_overrides(variables.iter().map(|(name, value)| (name.to_string(), value.to_string())))
+  }
+
+  #[test]
+  fn overrides_set_top_level_and_nested_settings() {
+    let config = overrides(&[("CYBERLS_INDENT_WIDTH", "4"), ("CYBERLS_LIMITS__MAX_PARSE_SIZE", "10")]);
+    assert_eq!(config.indent_width, Some(4));
+    assert_eq!(config.limits.max_parse_size, 10);
+  }
+
+  #[test]
+  fn override_values_that_are_not_json_are_strings() {
+    let config = overrides(&[("CYBERLS_CYBER_PATH", "/opt/cyber/bin/cyber"), ("CYBERLS_IGNORE", r#"["build/**"]"#)]);
+    assert_eq!(config.cyber_path.as_deref(), Some("/opt/cyber/bin/cyber"));
+    assert_eq!(config.ignore, vec!["build/**".to_string()]);
+  }
+
+  #[test]
+  fn overrides_skip_invalid_values_and_other_variables() {
+    let config = overrides(&[("CYBERLS_INDENT_WIDTH", "wide"), ("CYBERLS_SUPERVISED", "1"), ("CYBERLS_", "1"), ("INDENT_WIDTH", "8")]);
+    assert_eq!(config.indent_width, None);
+  }
+
+  #[test]
+  fn client_settings_are_merged_over_the_current_ones() {
+    let current = overrides(&[("CYBERLS_INDENT_WIDTH", "4"), ("CYBERLS_LIMITS__MAX_PARSE_SIZE", "10")]);
+    let config = current.with_settings(&json!({ "cyberls": { "limits": { "max_diagnostics_size": 5 } } })).unwrap();
+
+    assert_eq!(config.indent_width, Some(4));
+    assert_eq!(config.limits.max_parse_size, 10);
+    assert_eq!(config.limits.max_diagnostics_size, 5);
+    assert!(current.with_settings(&json!({ "indent_width": "wide" })).is_none());
+  }
+}
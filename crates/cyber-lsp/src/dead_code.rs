@@ -0,0 +1,89 @@
+use lsp_types::{DiagnosticSeverity, Range, SymbolKind, Url};
+use serde::Serialize;
+
+use crate::diagnostics::ErrorInfo;
+use crate::index::WorkspaceIndex;
+use crate::core_types::DocPoint;
+
+/// Diagnostic code of the optional dead code hints
+pub const DEAD_CODE_CODE: &str = "dead_code";
+
+// --| Dead Code Report --------------
+// --|--------------------------------
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadCodeEntry {
+  pub name: String,
+  /// `function` or `variable`
+  pub kind: String,
+  pub uri: Url,
+  pub range: Range,
+  /// References within the defining file
+  pub local_references: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadCodeReport {
+  pub files_scanned: usize,
+  pub entries: Vec<DeadCodeEntry>,
+}
+
+impl DeadCodeReport {
+  pub fn uris(&self) -> Vec<Url> {
+    let mut uris: Vec<Url> = self.entries.iter().map(|entry| entry.uri.clone()).collect();
+    uris.dedup();
+    uris
+  }
+
+  /// Hint diagnostics for the entries of one file
+  pub fn hints(&self, uri: &Url) -> Option<ErrorInfo> {
+    let mut error_info = ErrorInfo::new();
+
+    for entry in self.entries.iter().filter(|entry| &entry.uri == uri) {
+      let message = match entry.local_references {
+        0 => format!("`{}` is never used", entry.name),
+        _ => format!("`{}` is not used outside this file", entry.name),
+      };
+
+      error_info.add_coded(
+        DocPoint::from_byte_position(entry.range.start),
+        DocPoint::from_byte_position(entry.range.end),
+        message,
+        Some(DiagnosticSeverity::HINT),
+        DEAD_CODE_CODE,
+      );
+    }
+
+    if error_info.entries.is_empty() { None } else { Some(error_info) }
+  }
+}
+
+/// Top level functions and variables never referenced from another file of their workspace folder
+pub fn dead_code_report(index: &WorkspaceIndex, workspace_for: impl Fn(&Url) -> Url) -> DeadCodeReport {
+  let files = index.entries();
+  let mut report = DeadCodeReport { files_scanned: files.len(), entries: vec![] };
+
+  for (uri, symbols) in &files {
+    for symbol in symbols.symbols.iter().filter(|symbol| symbol.container.is_none()) {
+      let kind = match symbol.kind {
+        SymbolKind::FUNCTION => "function",
+        SymbolKind::VARIABLE => "variable",
+        _ => continue,
+      };
+
+      if index.external_references(&symbol.name, uri, &workspace_for(uri)) > 0 { continue; }
+
+      report.entries.push(DeadCodeEntry {
+        name: symbol.name.clone(),
+        kind: kind.to_string(),
+        uri: uri.clone(),
+        range: symbol.selection_range,
+        local_references: symbols.reference_count(&symbol.name),
+      });
+    }
+  }
+
+  report.entries.sort_by(|a, b| a.uri.as_str().cmp(b.uri.as_str()).then(a.name.cmp(&b.name)));
+  report
+}
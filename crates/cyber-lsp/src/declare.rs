@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use lsp_types::{CodeAction, CodeActionKind, Diagnostic, Position, Range, TextEdit, Url, WorkspaceEdit};
+use tree_sitter::{Node, Point};
+
+use crate::core_types::DocPoint;
+
+// --| Declare Missing Variable ------
+// --|--------------------------------
+/// Quick fixes declaring an undefined identifier, either as a local above the
+/// statement using it or at module level above the enclosing top level statement
+pub fn declare_actions(root: Node, source: &str, uri: &Url, name: &str, diagnostic: &Diagnostic) -> Vec<CodeAction> {
+  let point: Point = DocPoint::from_byte_position(diagnostic.range.start).into();
+  let node = match root.descendant_for_point_range(point, point) {
+    Some(node) => node,
+    None => return vec![],
+  };
+
+  let mut actions = vec![];
+  let top_level = ancestor_below(node, |parent| parent.id() == root.id());
+  let statement = ancestor_below(node, |parent| is_block(parent) || parent.id() == root.id());
+
+  // A use at the top level has no local scope, the module level action covers it
+  if let Some(statement) = statement.filter(|statement| Some(statement.id()) != top_level.map(|top| top.id())) {
+    let indent = indentation(source, statement.start_position().row);
+    actions.push(declare_action(uri, diagnostic, format!("Declare local `{}` above", name), statement.start_position().row, indent, name));
+  }
+
+  if let Some(top_level) = top_level {
+    actions.push(declare_action(uri, diagnostic, format!("Declare module-level `{}`", name), top_level.start_position().row, "", name));
+  }
+
+  actions
+}
+
+/// The ancestor of `node`, itself included, whose parent matches
+fn ancestor_below<'a>(node: Node<'a>, matches: impl Fn(Node) -> bool) -> Option<Node<'a>> {
+  let mut current = node;
+  while let Some(parent) = current.parent() {
+    if matches(parent) { return Some(current); }
+    current = parent;
+  }
+  None
+}
+
+fn is_block(node: Node) -> bool {
+  let kind = node.kind();
+  kind == "block" || kind.ends_with("_block") || kind.ends_with("_body")
+}
+
+fn indentation(source: &str, row: usize) -> &str {
+  let line = source.lines().nth(row).unwrap_or("");
+  &line[..line.len() - line.trim_start().len()]
+}
+
+fn declare_action(uri: &Url, diagnostic: &Diagnostic, title: String, row: usize, indent: &str, name: &str) -> CodeAction {
+  let position = Position::new(row as u32, 0);
+  let edit = TextEdit::new(Range::new(position, position), format!("{}var {} = none\n", indent, name));
+
+  CodeAction {
+    title,
+    kind: Some(CodeActionKind::QUICKFIX),
+    diagnostics: Some(vec![diagnostic.clone()]),
+    edit: Some(WorkspaceEdit {
+      changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+      ..Default::default()
+    }),
+    ..Default::default()
+  }
+}
@@ -0,0 +1,90 @@
+use std::collections::{HashMap, HashSet};
+
+use lsp_types::{CodeAction, CodeActionKind, Diagnostic, DiagnosticSeverity, TextEdit, Url, WorkspaceEdit};
+use once_cell::sync::Lazy;
+use tree_sitter::Node;
+
+use crate::diagnostics::ErrorInfo;
+use crate::symbols::DocumentSymbols;
+use crate::utils::treehelper::MESSAGE_STORAGE;
+
+/// Diagnostic code of uses of deprecated keywords and builtins
+pub const DEPRECATED_CODE: &str = "deprecated";
+
+#[derive(Clone, Debug)]
+pub struct Deprecation {
+  pub message: String,
+  pub replacement: Option<String>,
+}
+
+/// Keywords and builtins marked `deprecated` in any doc pack
+static DEPRECATED: Lazy<HashMap<String, Deprecation>> = Lazy::new(|| {
+  MESSAGE_STORAGE.values()
+    .flat_map(|definition| definition.type_categories.iter())
+    .flat_map(|category| category.keyword_details.iter())
+    .filter_map(|detail| {
+      let message = detail.deprecated.clone()?;
+      Some((detail.keyword.clone(), Deprecation { message, replacement: detail.replacement.clone() }))
+    })
+    .collect()
+});
+
+pub fn lookup(name: &str) -> Option<&'static Deprecation> {
+  DEPRECATED.get(name)
+}
+
+// --| Deprecated Usages -------------
+// --|--------------------------------
+/// Hint at every use of a deprecated keyword or builtin the document doesn't declare itself
+pub fn check_deprecated(root: Node, source: &str, symbols: &DocumentSymbols) -> Option<ErrorInfo> {
+  if DEPRECATED.is_empty() { return None; }
+
+  let declared: HashSet<&str> = symbols.symbols.iter().map(|symbol| symbol.name.as_str())
+    .chain(symbols.bindings.iter().map(|binding| binding.name.as_str()))
+    .collect();
+
+  let mut error_info = ErrorInfo::new();
+  collect_usages(root, source, &declared, &mut error_info);
+  if error_info.entries.is_empty() { None } else { Some(error_info) }
+}
+
+fn collect_usages(node: Node, source: &str, declared: &HashSet<&str>, out: &mut ErrorInfo) {
+  if node.child_count() == 0 {
+    let text = node.utf8_text(source.as_bytes()).unwrap_or_default();
+    // Identifiers name builtins, anonymous tokens are keywords
+    let candidate = node.kind() == "identifier" || (!node.is_named() && node.kind() == text);
+
+    if let Some(deprecation) = lookup(text).filter(|_| candidate && !declared.contains(text)) {
+      let message = match &deprecation.replacement {
+        Some(replacement) => format!("`{}` is deprecated, use `{}`: {}", text, replacement, deprecation.message),
+        None => format!("`{}` is deprecated: {}", text, deprecation.message),
+      };
+      out.add_coded(node.start_position(), node.end_position(), message, Some(DiagnosticSeverity::HINT), DEPRECATED_CODE);
+    }
+    return;
+  }
+
+  let mut cursor = node.walk();
+  for child in node.children(&mut cursor) {
+    collect_usages(child, source, declared, out);
+  }
+}
+
+// --| Quick Fix ---------------------
+// --|--------------------------------
+/// Replace a deprecated name flagged by `diagnostic` with its replacement
+pub fn replace_action(uri: &Url, name: &str, diagnostic: &Diagnostic) -> Option<CodeAction> {
+  let replacement = lookup(name)?.replacement.clone()?;
+
+  Some(CodeAction {
+    title: format!("Replace `{}` with `{}`", name, replacement),
+    kind: Some(CodeActionKind::QUICKFIX),
+    diagnostics: Some(vec![diagnostic.clone()]),
+    edit: Some(WorkspaceEdit {
+      changes: Some(HashMap::from([(uri.clone(), vec![TextEdit::new(diagnostic.range, replacement)])])),
+      ..Default::default()
+    }),
+    is_preferred: Some(true),
+    ..Default::default()
+  })
+}
@@ -1,13 +1,70 @@
+use std::collections::HashMap;
 use std::fs;
-use std::process::Command;
 use std::path::{Path, PathBuf};
-use cyber_tree_sitter::Point;
-use lsp_types::DiagnosticSeverity;
-use tracing::info;
+use lsp_types::{DiagnosticRelatedInformation, DiagnosticSeverity};
+use serde::{Deserialize, Serialize};
+
+use crate::core_types::DocPoint;
+
+pub mod pipeline;
+#[cfg(feature = "compiler-diagnostics")]
+pub mod compiler;
+
+/// How the cyber executable is run for compile diagnostics
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompilerMode {
+  /// `cyber compile` for every check
+  #[default]
+  Spawn,
+  /// A long-lived `cyber check --server` process, when the executable supports it.
+  /// Experimental: released cyber versions don't have the mode and fall back to `Spawn`
+  Server,
+}
+
+// --| Severity Overrides ------------
+// --|--------------------------------
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DiagnosticsConfig {
+  /// Severity per diagnostic code, ie. `{ "dead_code": "hint", "spelling": "off" }`
+  pub severity: HashMap<String, SeverityLevel>,
+}
+
+impl DiagnosticsConfig {
+  pub fn level(&self, code: Option<&str>) -> Option<SeverityLevel> {
+    self.severity.get(code?).copied()
+  }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SeverityLevel {
+  Error,
+  #[serde(alias = "warn")]
+  Warning,
+  #[serde(alias = "info")]
+  Information,
+  Hint,
+  /// Not reported at all
+  Off,
+}
+
+impl SeverityLevel {
+  pub fn severity(self) -> Option<DiagnosticSeverity> {
+    match self {
+      SeverityLevel::Error => Some(DiagnosticSeverity::ERROR),
+      SeverityLevel::Warning => Some(DiagnosticSeverity::WARNING),
+      SeverityLevel::Information => Some(DiagnosticSeverity::INFORMATION),
+      SeverityLevel::Hint => Some(DiagnosticSeverity::HINT),
+      SeverityLevel::Off => None,
+    }
+  }
+}
 
 /// Check for syntax errors. If there is error,
 /// return the position of the error and message
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct ErrorInfo {
   pub entries: Vec<ErrorEntry>,
 }
@@ -21,69 +78,40 @@ impl ErrorInfo {
     self.entries.append(&mut other.entries);
   }
 
-  pub fn add(&mut self, start: Point, end: Point, message: String, severity: Option<DiagnosticSeverity>) {
-    self.entries.push(ErrorEntry { start, end, message, severity });
+  pub fn add(&mut self, start: impl Into<DocPoint>, end: impl Into<DocPoint>, message: String, severity: Option<DiagnosticSeverity>) {
+    self.entries.push(ErrorEntry { start: start.into(), end: end.into(), message, severity, code: None, source: None, related: vec![] });
+  }
+
+  /// Adds an entry tagged with a diagnostic code so code actions can recognize it
+  pub fn add_coded(&mut self, start: impl Into<DocPoint>, end: impl Into<DocPoint>, message: String, severity: Option<DiagnosticSeverity>, code: &str) {
+    self.entries.push(ErrorEntry { start: start.into(), end: end.into(), message, severity, code: Some(code.to_string()), source: None, related: vec![] });
   }
 
   fn is_empty(&self) -> bool { self.entries.is_empty() }
 }
 
+/// Columns count bytes, they are converted to UTF-16 when published
+#[derive(Clone)]
 pub struct ErrorEntry {
-  pub start: Point,
-  pub end: Point,
+  pub start: DocPoint,
+  pub end: DocPoint,
   pub message: String,
   pub severity: Option<DiagnosticSeverity>,
+  pub code: Option<String>,
+  pub source: Option<String>,
+  /// Other files the entry points at
+  pub related: Vec<DiagnosticRelatedInformation>,
 }
 
-
-pub fn check_compile_error(local_path: &Path, _source: &str) -> Option<ErrorInfo> {
-  let mut diag_result = ErrorInfo::new();
-  let path_str = local_path.to_str().unwrap();
-
-  let output = if cfg!(target_os = "windows") {
-    Command::new("cmd")
-      .args(["/C", &format!("cyber compile {:?}", path_str)]).output()
-      .expect("failed to execute process")
-  } else {
-    Command::new("sh")
-      .args(["-c", &format!("cyber compile {:?}", path_str)]).output()
-      .expect("failed to execute process")
-  };
-
-  let results = output.stdout;
-  let error = output.stderr;
-  let error = String::from_utf8(error).unwrap();
-  let results = String::from_utf8(results).unwrap();
-
-  if !error.is_empty() {
-    let err_lines: Vec<&str> = error.lines().collect();
-
-    if err_lines[0].contains("Bytecode:") { return None; }
-    else if err_lines[0].contains("ParseError:") {
-      let err_msg = err_lines[0].split("ParseError: ").collect::<Vec<&str>>()[1];
-      let err_row = err_lines[2].split(":").collect::<Vec<&str>>()[1].trim().parse::<usize>().unwrap();
-      let err_col = err_lines[2].split(":").collect::<Vec<&str>>()[2].trim().parse::<usize>().unwrap();
-
-      diag_result.add(
-        tree_sitter::Point{ row: err_row - 1, column: err_col }, 
-        tree_sitter::Point{ row: err_row - 1, column: err_col }, 
-        err_msg.to_string(), 
-        Some(DiagnosticSeverity::ERROR),
-      );
-    }
-  }
-
-  if !results.is_empty() { info!("Results: {}", results); }
-  
-  Some(diag_result)
-}
+/// Diagnostic code of syntax errors, the one the compiler reports them with
+pub const SYNTAX_ERROR_CODE: &str = "parse_error";
 
 pub fn check_tree_error(_local_path: &Path, source: &str, input: tree_sitter::Node) -> Option<ErrorInfo> {
   let _source_array: Vec<&str> = source.lines().collect();
   let mut error_info = ErrorInfo::new();
 
   if input.is_error() {
-    error_info.add( input.start_position(), input.end_position(), "Grammar Error".to_string(), None);
+    error_info.add_coded( input.start_position(), input.end_position(), "Grammar Error".to_string(), None, SYNTAX_ERROR_CODE);
     Some(error_info)
   } else {
     let _cursor = input.walk();
@@ -163,3 +191,33 @@ fn _scanner_include_error(path: &PathBuf) -> bool {
   }
 }
 
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn level(name: &str) -> Option<SeverityLevel> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+  }
+
+  #[test]
+  fn severity_levels_deserialize_with_their_aliases() {
+    assert_eq!(level("error"), Some(SeverityLevel::Error));
+    assert_eq!(level("warning"), Some(SeverityLevel::Warning));
+    assert_eq!(level("warn"), Some(SeverityLevel::Warning));
+    assert_eq!(level("information"), Some(SeverityLevel::Information));
+    assert_eq!(level("info"), Some(SeverityLevel::Information));
+    assert_eq!(level("hint"), Some(SeverityLevel::Hint));
+    assert_eq!(level("off"), Some(SeverityLevel::Off));
+    assert_eq!(level("Error"), None);
+    assert_eq!(level("fatal"), None);
+  }
+
+  #[test]
+  fn severity_overrides_are_looked_up_by_code() {
+    let config: DiagnosticsConfig = serde_json::from_str(r#"{ "severity": { "parse_error": "warn", "spelling": "off" } }"#).unwrap();
+    assert_eq!(config.level(Some(SYNTAX_ERROR_CODE)), Some(SeverityLevel::Warning));
+    assert_eq!(config.level(Some("spelling")).and_then(SeverityLevel::severity), None);
+    assert_eq!(config.level(Some("dead_code")), None);
+    assert_eq!(config.level(None), None);
+  }
+}
@@ -0,0 +1,501 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use lsp_types::{DiagnosticRelatedInformation, DiagnosticSeverity, Location, Range};
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use super::{CompilerMode, ErrorEntry, ErrorInfo};
+use crate::core_types::DocPoint;
+use crate::modules;
+use crate::utils::paths;
+
+/// Diagnostic source of compiler messages
+pub const COMPILER_SOURCE: &str = "cyber";
+
+impl ErrorInfo {
+  /// Adds an entry reported by the cyber compiler
+  pub fn add_compiler(&mut self, start: impl Into<DocPoint>, end: impl Into<DocPoint>, message: String, severity: DiagnosticSeverity, code: &str) {
+    self.entries.push(ErrorEntry {
+      start: start.into(), end: end.into(), message, severity: Some(severity), code: Some(code.to_string()), source: Some(COMPILER_SOURCE.to_string()),
+      related: vec![],
+    });
+  }
+}
+
+/// Compiler message headers, their diagnostic code and severity
+const COMPILER_MESSAGES: &[(&str, &str, DiagnosticSeverity)] = &[
+  ("ParseError:", "parse_error", DiagnosticSeverity::ERROR),
+  ("TokenError:", "parse_error", DiagnosticSeverity::ERROR),
+  ("CompileError:", "compile_error", DiagnosticSeverity::ERROR),
+  ("error:", "compile_error", DiagnosticSeverity::ERROR),
+  ("Warning:", "compile_warning", DiagnosticSeverity::WARNING),
+  ("warning:", "compile_warning", DiagnosticSeverity::WARNING),
+  ("Note:", "compile_note", DiagnosticSeverity::INFORMATION),
+  ("note:", "compile_note", DiagnosticSeverity::INFORMATION),
+];
+
+
+// --| Compiler Backends -------------
+// --|--------------------------------
+/// A way of running the cyber compiler over a document. Called on a blocking thread.
+pub trait CompilerBackend: Send + Sync {
+  /// Diagnostics of `source` compiled as the file at `local_path`, `None` when the compiler could not run
+  fn check(&self, program: &str, local_path: &Path, source: &str) -> Option<ErrorInfo>;
+}
+
+/// Starts `cyber compile` for every check
+#[derive(Default)]
+pub struct SpawnCompiler;
+
+impl CompilerBackend for SpawnCompiler {
+  fn check(&self, program: &str, local_path: &Path, source: &str) -> Option<ErrorInfo> {
+    check_compile_error(program, local_path, source)
+  }
+}
+
+/// How long a check server may take to answer before it is stopped
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keeps one `cyber check --server` process per executable. The server reads a JSON request
+/// per line, `{"path", "source"}`, and answers each with a line `{"output"}` holding what
+/// `cyber compile` prints.
+///
+/// No released cyber version has this mode yet, the protocol is a proposal the setting is
+/// ready for. Executables without it, and servers that don't answer within the timeout,
+/// fall back to `SpawnCompiler`.
+pub struct ServerCompiler {
+  /// Each executable's server is locked on its own, checks of other executables don't wait
+  servers: Mutex<HashMap<String, Arc<Mutex<Option<CheckServer>>>>>,
+  unsupported: Mutex<HashSet<String>>,
+  timeout: Duration,
+}
+
+impl Default for ServerCompiler {
+  fn default() -> Self {
+    Self::with_timeout(CHECK_TIMEOUT)
+  }
+}
+
+impl CompilerBackend for ServerCompiler {
+  fn check(&self, program: &str, local_path: &Path, source: &str) -> Option<ErrorInfo> {
+    if !self.is_unsupported(program) {
+      if let Some(output) = self.request(program, local_path, source) {
+        let mut diag_result = ErrorInfo::new();
+        parse_compiler_output(&output, &local_path.to_string_lossy(), &mut diag_result);
+        return Some(diag_result);
+      }
+    }
+
+    SpawnCompiler.check(program, local_path, source)
+  }
+}
+
+impl ServerCompiler {
+  pub fn with_timeout(timeout: Duration) -> Self {
+    Self { servers: Mutex::default(), unsupported: Mutex::default(), timeout }
+  }
+
+  fn is_unsupported(&self, program: &str) -> bool {
+    self.unsupported.lock().unwrap_or_else(|err| err.into_inner()).contains(program)
+  }
+
+  /// Output of the server for a document. A server that fails its first request does not
+  /// support the mode, one failing later is started again on the next check. One that
+  /// doesn't answer in time is stopped, the executable is compiled for each check from then on.
+  fn request(&self, program: &str, local_path: &Path, source: &str) -> Option<String> {
+    let slot = self.servers.lock().unwrap_or_else(|err| err.into_inner())
+      .entry(program.to_string())
+      .or_default()
+      .clone();
+    let mut server = slot.lock().unwrap_or_else(|err| err.into_inner());
+
+    let started = server.is_some();
+    if !started { *server = CheckServer::start(program); }
+
+    let answer = match server.as_mut() {
+      Some(running) => running.check(local_path, source, self.timeout),
+      None => Answer::Stopped,
+    };
+
+    match answer {
+      Answer::Output(output) => return Some(output),
+      Answer::Stopped if started => debug!("{} check server stopped, it is started again on the next check", program),
+      Answer::Stopped => {
+        info!("{} has no check server mode, compiling for each check instead", program);
+        self.unsupported.lock().unwrap_or_else(|err| err.into_inner()).insert(program.to_string());
+      }
+      Answer::TimedOut => {
+        warn!("{} check server did not answer within {:?}, compiling for each check instead", program, self.timeout);
+        self.unsupported.lock().unwrap_or_else(|err| err.into_inner()).insert(program.to_string());
+      }
+    }
+
+    // Dropping the server kills it
+    *server = None;
+    None
+  }
+}
+
+enum Answer {
+  Output(String),
+  /// The server exited or answered something else than a response
+  Stopped,
+  TimedOut,
+}
+
+struct CheckServer {
+  child: Child,
+  stdin: ChildStdin,
+  /// Lines of the server's stdout, read on a thread of their own so a wait can time out
+  lines: Receiver<String>,
+}
+
+impl CheckServer {
+  fn start(program: &str) -> Option<Self> {
+    let mut child = Command::new(program).args(["check", "--server"])
+      .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null())
+      .spawn()
+      .map_err(|err| debug!("Failed to start {} check server: {}", program, err))
+      .ok()?;
+
+    let stdin = child.stdin.take()?;
+    let stdout = BufReader::new(child.stdout.take()?);
+    let (sender, lines) = mpsc::channel();
+    std::thread::spawn(move || {
+      for line in stdout.lines().map_while(Result::ok) {
+        if sender.send(line).is_err() { break; }
+      }
+    });
+
+    Some(Self { child, stdin, lines })
+  }
+
+  fn check(&mut self, local_path: &Path, source: &str, timeout: Duration) -> Answer {
+    let request = json!({ "path": local_path.to_string_lossy(), "source": source });
+    if writeln!(self.stdin, "{}", request).and_then(|_| self.stdin.flush()).is_err() { return Answer::Stopped; }
+
+    let line = match self.lines.recv_timeout(timeout) {
+      Ok(line) => line,
+      Err(RecvTimeoutError::Timeout) => return Answer::TimedOut,
+      Err(RecvTimeoutError::Disconnected) => return Answer::Stopped,
+    };
+
+    let output = serde_json::from_str::<Value>(&line).ok()
+      .and_then(|response| response.get("output")?.as_str().map(str::to_string));
+    match output {
+      Some(output) => Answer::Output(output),
+      None => Answer::Stopped,
+    }
+  }
+}
+
+impl Drop for CheckServer {
+  fn drop(&mut self) {
+    let _ = self.child.kill();
+    let _ = self.child.wait();
+  }
+}
+
+/// The backend of the configured `CompilerMode`, replaced when the setting changes
+pub struct ActiveCompiler {
+  current: RwLock<(CompilerMode, Arc<dyn CompilerBackend>)>,
+}
+
+impl ActiveCompiler {
+  pub fn new(mode: CompilerMode) -> Self {
+    Self { current: RwLock::new((mode, backend(mode))) }
+  }
+
+  /// Switch backends, a running server is stopped once its last check finishes
+  pub fn configure(&self, mode: CompilerMode) {
+    let mut current = self.current.write().unwrap_or_else(|err| err.into_inner());
+    if current.0 != mode { *current = (mode, backend(mode)); }
+  }
+
+  pub fn backend(&self) -> Arc<dyn CompilerBackend> {
+    self.current.read().unwrap_or_else(|err| err.into_inner()).1.clone()
+  }
+}
+
+fn backend(mode: CompilerMode) -> Arc<dyn CompilerBackend> {
+  match mode {
+    CompilerMode::Spawn => Arc::new(SpawnCompiler),
+    CompilerMode::Server => Arc::new(ServerCompiler::default()),
+  }
+}
+
+// --| Compiler Output ---------------
+// --|--------------------------------
+/// Compile the buffer contents rather than the saved file, so unsaved edits are checked.
+/// The source is written to a scratch file in the temp directory with its relative
+/// imports anchored to the document's directory, and reported paths are mapped back to
+/// the document.
+pub fn check_compile_error(program: &str, local_path: &Path, source: &str) -> Option<ErrorInfo> {
+  let mut diag_result = ErrorInfo::new();
+
+  let scratch = match write_scratch(local_path, source) {
+    Some(scratch) => scratch,
+    None => return None,
+  };
+
+  let output = Command::new(program).arg("compile").arg(&scratch).output();
+  let _ = fs::remove_file(&scratch);
+
+  let output = match output {
+    Ok(output) => output,
+    Err(err) => {
+      debug!("Failed to run {} compile: {}", program, err);
+      return None;
+    }
+  };
+
+  let scratch_str = scratch.to_string_lossy().to_string();
+  let local_str = local_path.to_string_lossy().to_string();
+  let error = String::from_utf8_lossy(&output.stderr).replace(&scratch_str, &local_str);
+  let results = String::from_utf8_lossy(&output.stdout).replace(&scratch_str, &local_str);
+
+  if error.lines().next().map_or(false, |line| line.contains("Bytecode:")) { return None; }
+  parse_compiler_output(&error, &local_str, &mut diag_result);
+
+  if !results.is_empty() { info!("Results: {}", results); }
+  
+  Some(diag_result)
+}
+
+/// Collect every message of the compiler output. A message header is followed by a
+/// `path:row:col` line; messages without one are placed at the start of the file, as are
+/// messages located in other files, which link to them as related information.
+fn parse_compiler_output(output: &str, local_path: &str, diag_result: &mut ErrorInfo) {
+  let mut pending: Option<(&str, DiagnosticSeverity, String)> = None;
+
+  let mut flush = |pending: &mut Option<(&str, DiagnosticSeverity, String)>, location: Option<MessageLocation>| {
+    if let Some((code, severity, message)) = pending.take() {
+      let (point, related) = match location {
+        Some(MessageLocation::Document(row, col)) => (DocPoint::new(row.saturating_sub(1), col), vec![]),
+        // Messages about other files stay at the start of the document and point there
+        Some(MessageLocation::Other(other, row, col)) => {
+          let position = DocPoint::new(row.saturating_sub(1), col).byte_position();
+          let related = paths::path_to_uri(&other).map(|uri| DiagnosticRelatedInformation {
+            location: Location::new(uri, Range::new(position, position)),
+            message: message.clone(),
+          });
+          (DocPoint::new(0, 0), related.into_iter().collect())
+        }
+        None => (DocPoint::new(0, 0), vec![]),
+      };
+      diag_result.add_compiler(point, point, message, severity, code);
+      if let Some(entry) = diag_result.entries.last_mut() { entry.related = related; }
+    }
+  };
+
+  for line in output.lines() {
+    let trimmed = line.trim_start();
+    let header = COMPILER_MESSAGES.iter().find(|(prefix, _, _)| trimmed.starts_with(prefix));
+
+    if let Some((prefix, code, severity)) = header {
+      flush(&mut pending, None);
+      pending = Some((*code, *severity, trimmed[prefix.len()..].trim().to_string()));
+    } else if pending.is_some() {
+      if let Some(location) = error_location(line, local_path) {
+        flush(&mut pending, Some(location));
+      }
+    }
+  }
+
+  flush(&mut pending, None);
+}
+
+/// Numbers the scratch files, so documents checked at once don't overwrite each other
+static SCRATCH_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Copy of the document in the server's temp folder, kept out of the workspace where
+/// watchers and version control would see it
+fn write_scratch(local_path: &Path, source: &str) -> Option<PathBuf> {
+  let dir = std::env::temp_dir().join(format!("cyberls-check-{}", std::process::id()));
+  let path = dir.join(format!("{}.cy", SCRATCH_COUNTER.fetch_add(1, Ordering::Relaxed)));
+  let source = match local_path.parent() {
+    Some(base_dir) => modules::anchor_imports(source, base_dir),
+    None => source.to_string(),
+  };
+
+  match fs::create_dir_all(&dir).and_then(|_| fs::write(&path, source)) {
+    Ok(_) => Some(path),
+    Err(err) => {
+      debug!("Failed to write {:?}: {}", path, err);
+      None
+    }
+  }
+}
+
+/// Where a compiler message points
+#[derive(Debug, PartialEq)]
+enum MessageLocation {
+  /// Row and column in the checked document
+  Document(usize, usize),
+  /// Row and column in another file, ie. a module the document imports
+  Other(PathBuf, usize, usize),
+}
+
+/// Row and column of the `:row:col` rest of a location line
+fn row_col(rest: &str) -> Option<(usize, usize)> {
+  let mut parts = rest.split(':').map(str::trim).filter(|part| !part.is_empty());
+  let row = parts.next()?.parse::<usize>().ok()?;
+  let col = parts.next()?.parse::<usize>().ok()?;
+  Some((row, col))
+}
+
+/// Location of a `path:row:col` line. The document's path is stripped first as it may contain
+/// colons; any other path is what precedes the last two fields, relative ones being read from
+/// the document's folder.
+fn error_location(line: &str, path: &str) -> Option<MessageLocation> {
+  if let Some(start) = line.find(path) {
+    let (row, col) = row_col(&line[start + path.len()..])?;
+    return Some(MessageLocation::Document(row, col));
+  }
+
+  let mut fields = line.trim().trim_end_matches(':').rsplitn(3, ':');
+  let col = fields.next()?.trim().parse::<usize>().ok()?;
+  let row = fields.next()?.trim().parse::<usize>().ok()?;
+  let named = Path::new(fields.next()?.trim());
+  let document = Path::new(path);
+  let other = match document.parent() {
+    Some(dir) if named.is_relative() => dir.join(named),
+    _ => named.to_path_buf(),
+  };
+
+  if other == document { return Some(MessageLocation::Document(row, col)); }
+  if !other.is_absolute() { return None; }
+  Some(MessageLocation::Other(other, row, col))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn scratch_files_stay_out_of_the_workspace() {
+    let dir = std::env::temp_dir().join(format!("cyberls-scratch-doc-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("util.cy"), "var a = 1\n").unwrap();
+    let document = dir.join("main.cy");
+
+    let scratch = write_scratch(&document, "import util 'util.cy'\nprint util.a").unwrap();
+    assert_ne!(scratch.parent(), Some(dir.as_path()));
+    let util = dir.join("util.cy").canonicalize().unwrap().to_string_lossy().trim_start_matches(r"\\?\").replace('\\', "/");
+    assert_eq!(fs::read_to_string(&scratch).unwrap(), format!("import util '{}'\nprint util.a", util));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    let _ = fs::remove_file(&scratch);
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn active_compiler_switches_backends_on_mode_changes() {
+    let active = ActiveCompiler::new(CompilerMode::Spawn);
+    let spawn = active.backend();
+
+    active.configure(CompilerMode::Spawn);
+    assert!(Arc::ptr_eq(&spawn, &active.backend()));
+
+    active.configure(CompilerMode::Server);
+    let server = active.backend();
+    assert!(!Arc::ptr_eq(&spawn, &server));
+
+    active.configure(CompilerMode::Spawn);
+    assert!(!Arc::ptr_eq(&server, &active.backend()));
+  }
+
+  /// An executable behaving as `script` for `check` and exiting at once for anything else
+  #[cfg(unix)]
+  fn fake_cyber(name: &str, script: &str) -> (PathBuf, PathBuf) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("cyberls-compiler-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let program = dir.join("cyber");
+    fs::write(&program, format!("#!/bin/sh\nif [ \"$1\" = check ]; then\n{}\nfi\n", script)).unwrap();
+    fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+    (dir, program)
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn server_answers_are_parsed() {
+    let (dir, program) = fake_cyber("answer", r#"while read line; do echo '{"output": "CompileError: bad call\n main.cy:3:5"}'; done"#);
+    let compiler = ServerCompiler::default();
+    let program = program.to_string_lossy();
+
+    for _ in 0..2 {
+      let errors = compiler.check(&program, &dir.join("main.cy"), "print 1").unwrap();
+      assert_eq!(errors.entries.len(), 1);
+      assert_eq!(errors.entries[0].message, "bad call");
+      assert_eq!(errors.entries[0].start, DocPoint::new(2, 5));
+    }
+    assert!(!compiler.is_unsupported(&program));
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn executables_without_server_mode_fall_back_to_spawning() {
+    let (dir, program) = fake_cyber("unsupported", "exit 1");
+    let compiler = ServerCompiler::default();
+    let program = program.to_string_lossy();
+
+    let errors = compiler.check(&program, &dir.join("main.cy"), "print 1").unwrap();
+    assert!(errors.entries.is_empty());
+    assert!(compiler.is_unsupported(&program));
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn servers_not_answering_in_time_are_stopped() {
+    let (dir, program) = fake_cyber("silent", "exec sleep 30");
+    let compiler = ServerCompiler::with_timeout(Duration::from_millis(200));
+    let program = program.to_string_lossy();
+
+    let errors = compiler.check(&program, &dir.join("main.cy"), "print 1").unwrap();
+    assert!(errors.entries.is_empty());
+    assert!(compiler.is_unsupported(&program));
+    assert!(compiler.servers.lock().unwrap()[program.as_ref()].lock().unwrap().is_none());
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn locations_in_other_files_become_related_information() {
+    let dir = std::env::temp_dir().join(format!("cyberls-related-{}", std::process::id()));
+    let document = dir.join("main.cy");
+    let other = dir.join("lib").join("util.cy");
+    let output = format!(
+      "CompileError: bad call\n {}:3:5\nCompileError: missing symbol\n {}:7:2\nCompileError: in the module\n lib/util.cy:1:1\n",
+      document.display(), other.display(),
+    );
+
+    let mut errors = ErrorInfo::new();
+    parse_compiler_output(&output, &document.to_string_lossy(), &mut errors);
+
+    assert_eq!(errors.entries.len(), 3);
+    assert_eq!(errors.entries[0].start, DocPoint::new(2, 5));
+    assert!(errors.entries[0].related.is_empty());
+
+    // Not read as a row and column of the document
+    assert_eq!(errors.entries[1].start, DocPoint::new(0, 0));
+    let related = &errors.entries[1].related[0];
+    assert_eq!(related.location.uri, paths::path_to_uri(&other).unwrap());
+    assert_eq!(related.location.range.start, lsp_types::Position::new(6, 2));
+    assert_eq!(related.message, "missing symbol");
+
+    assert_eq!(errors.entries[2].related[0].location.uri, paths::path_to_uri(&other).unwrap());
+  }
+}
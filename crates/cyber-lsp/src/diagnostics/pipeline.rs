@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use lsp_types::Url;
+
+use super::{ErrorEntry, ErrorInfo};
+
+/// Quiet period after the last edit before semantic analysis runs
+pub const SEMANTIC_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Quiet period after the last watched file event before open documents are checked again
+pub const WORKSPACE_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Documents checked at once when updating every open document
+pub const UPDATE_CONCURRENCY: usize = 4;
+
+// --| Tiers -------------------------
+// --|--------------------------------
+/// Stages of the diagnostics pipeline, from cheapest to most expensive
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Tier {
+  /// Tree-sitter syntax errors, published on every change
+  Syntax,
+  /// Types, imports, call arity, undefined names and spelling, after the debounce
+  Semantic,
+  /// The cyber compiler, only on save or an explicit check
+  Compiler,
+}
+
+// --| Diagnostic Sets ---------------
+// --|--------------------------------
+/// The latest results of each tier for one document
+#[derive(Clone, Default)]
+pub struct DiagnosticSet {
+  tiers: HashMap<Tier, Vec<ErrorEntry>>,
+}
+
+impl DiagnosticSet {
+  /// Replace the results of a tier, keeping the others
+  pub fn update(&mut self, tier: Tier, errors: Option<ErrorInfo>) {
+    self.tiers.insert(tier, errors.map(|errors| errors.entries).unwrap_or_default());
+  }
+
+  /// All entries, ordered by position and then by tier. The sort is stable, so
+  /// entries of a tier keep the order they were reported in.
+  pub fn merged(&self) -> ErrorInfo {
+    let mut entries: Vec<(Tier, ErrorEntry)> = self.tiers.iter()
+      .flat_map(|(tier, entries)| entries.iter().map(move |entry| (*tier, entry.clone())))
+      .collect();
+    entries.sort_by_key(|(tier, entry)| (entry.start, *tier));
+
+    ErrorInfo { entries: entries.into_iter().map(|(_, entry)| entry).collect() }
+  }
+}
+
+// --| Pipeline ----------------------
+// --|--------------------------------
+/// Per document diagnostic sets and the edit generations used to debounce the semantic tier,
+/// plus a workspace generation coalescing bursts of file events
+#[derive(Default)]
+pub struct DiagnosticPipeline {
+  sets: DashMap<Url, DiagnosticSet>,
+  generations: DashMap<Url, u64>,
+  workspace: AtomicU64,
+}
+
+impl DiagnosticPipeline {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Store the results of a tier and return the merged set to publish
+  pub fn update(&self, uri: &Url, tier: Tier, errors: Option<ErrorInfo>) -> ErrorInfo {
+    let mut set = self.sets.entry(uri.clone()).or_default();
+    set.update(tier, errors);
+    set.merged()
+  }
+
+  /// The merged set of a document, if any tier ran for it
+  pub fn current(&self, uri: &Url) -> Option<ErrorInfo> {
+    self.sets.get(uri).map(|set| set.merged())
+  }
+
+  /// Mark a new edit, returning its generation
+  pub fn touch(&self, uri: &Url) -> u64 {
+    let mut generation = self.generations.entry(uri.clone()).or_insert(0);
+    *generation += 1;
+    *generation
+  }
+
+  /// Whether no edit happened since `generation`
+  pub fn is_current(&self, uri: &Url, generation: u64) -> bool {
+    self.generations.get(uri).map_or(false, |current| *current == generation)
+  }
+
+  /// Mark a workspace event, returning its generation
+  pub fn touch_workspace(&self) -> u64 {
+    self.workspace.fetch_add(1, Ordering::SeqCst) + 1
+  }
+
+  /// Whether no workspace event happened since `generation`
+  pub fn is_workspace_current(&self, generation: u64) -> bool {
+    self.workspace.load(Ordering::SeqCst) == generation
+  }
+
+  pub fn remove(&self, uri: &Url) {
+    self.sets.remove(uri);
+    self.generations.remove(uri);
+  }
+
+  pub fn clear(&self) {
+    self.sets.clear();
+    self.generations.clear();
+  }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use dashmap::DashMap;
+use lsp_types::{CodeAction, CodeActionKind, Diagnostic, NumberOrString, Position, Range, TextEdit, Url, WorkspaceEdit};
+use tree_sitter::Node;
+
+use crate::dead_code::DEAD_CODE_CODE;
+
+/// Comments starting with this, after the `--`, are read as directives:
+///
+/// - `-- cyberls: ignore[dead_code]` suppresses codes in the whole file
+/// - `-- cyberls: disable-line[...]` on the line it ends
+/// - `-- cyberls: disable-next-line[...]` on the following line
+/// - `-- cyberls: disable[...]` up to the next `-- cyberls: enable`, or the end of the file
+///
+/// Without the brackets every code is suppressed. Diagnostics without a code are always
+/// reported.
+pub const DIRECTIVE_PREFIX: &str = "cyberls:";
+
+/// Names accepted in place of the codes diagnostics carry
+const CODE_ALIASES: &[(&str, &str)] = &[("unused", DEAD_CODE_CODE)];
+
+// --| Suppressions ------------------
+// --|--------------------------------
+/// Codes suppressed, `None` for all of them
+type Codes = Option<Vec<String>>;
+
+#[derive(Debug, Default)]
+pub struct Suppressions {
+  file: Vec<Codes>,
+  /// Rows, zero based, and the codes suppressed on them
+  lines: Vec<(usize, Codes)>,
+  /// First and last row of `disable` blocks
+  blocks: Vec<(usize, usize, Codes)>,
+}
+
+impl Suppressions {
+  pub fn is_empty(&self) -> bool {
+    self.file.is_empty() && self.lines.is_empty() && self.blocks.is_empty()
+  }
+
+  /// Whether a diagnostic of `code` starting on `row` is suppressed
+  pub fn suppresses(&self, row: usize, code: Option<&str>) -> bool {
+    let code = match code { Some(code) => code, None => return false };
+    let matches = |codes: &Codes| codes.as_ref().map_or(true, |codes| codes.iter().any(|known| known == code));
+
+    self.file.iter().any(matches)
+      || self.lines.iter().any(|(line, codes)| *line == row && matches(codes))
+      || self.blocks.iter().any(|(start, end, codes)| (*start..=*end).contains(&row) && matches(codes))
+  }
+}
+
+// --| Suppression Cache -------------
+// --|--------------------------------
+/// What the suppressions of a document were read from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Revision {
+  /// Version and generation of an open document
+  Open(i64, u64),
+  /// Modification time and length of a file on disk
+  Disk(SystemTime, u64),
+}
+
+/// Suppressions per document, read again only once it changed
+#[derive(Debug, Default)]
+pub struct SuppressionCache(DashMap<Url, (Revision, Arc<Suppressions>)>);
+
+impl SuppressionCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The suppressions read at `revision`, if those are the last read
+  pub fn get(&self, uri: &Url, revision: Revision) -> Option<Arc<Suppressions>> {
+    self.0.get(uri).filter(|entry| entry.0 == revision).map(|entry| entry.1.clone())
+  }
+
+  pub fn insert(&self, uri: Url, revision: Revision, suppressions: Suppressions) -> Arc<Suppressions> {
+    let suppressions = Arc::new(suppressions);
+    self.0.insert(uri, (revision, suppressions.clone()));
+    suppressions
+  }
+
+  pub fn remove(&self, uri: &Url) {
+    self.0.remove(uri);
+  }
+}
+
+/// Read the directives of every comment in the document
+pub fn collect(root: Node, source: &str) -> Suppressions {
+  let mut suppressions = Suppressions::default();
+  let mut open: Vec<(usize, Codes)> = vec![];
+
+  for comment in comments(root) {
+    let text = comment.utf8_text(source.as_bytes()).unwrap_or_default();
+    let (name, codes) = match parse(text) { Some(directive) => directive, None => continue };
+    let row = comment.start_position().row;
+
+    match name {
+      "ignore" => suppressions.file.push(codes),
+      "disable-line" => suppressions.lines.push((comment.end_position().row, codes)),
+      "disable-next-line" => suppressions.lines.push((comment.end_position().row + 1, codes)),
+      "disable" => open.push((row, codes)),
+      "enable" => {
+        for (start, codes) in open.drain(..) {
+          suppressions.blocks.push((start, row, codes));
+        }
+      }
+      _ => {}
+    }
+  }
+
+  for (start, codes) in open {
+    suppressions.blocks.push((start, usize::MAX, codes));
+  }
+  suppressions
+}
+
+fn comments(root: Node) -> Vec<Node> {
+  let mut comments = vec![];
+  let mut stack = vec![root];
+  while let Some(node) = stack.pop() {
+    if node.kind() == "comment" {
+      comments.push(node);
+      continue;
+    }
+
+    let mut cursor = node.walk();
+    stack.extend(node.named_children(&mut cursor));
+  }
+
+  comments.sort_by_key(|comment| comment.start_byte());
+  comments
+}
+
+/// The directive name and its codes, ie. `disable-next-line[spelling, deprecated]`
+fn parse(comment: &str) -> Option<(&str, Codes)> {
+  let body = comment.trim_start_matches('-').trim().strip_prefix(DIRECTIVE_PREFIX)?.trim();
+
+  let (name, codes) = match body.split_once('[') {
+    Some((name, rest)) => {
+      let codes = rest.split(']').next().unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|code| !code.is_empty())
+        .map(|listed| CODE_ALIASES.iter().find(|(alias, _)| *alias == listed).map_or(listed, |(_, code)| *code).to_string())
+        .collect();
+      (name.trim(), Some(codes))
+    }
+    None => (body.split_whitespace().next().unwrap_or_default(), None),
+  };
+
+  Some((name, codes))
+}
+
+// --| Quick Fixes -------------------
+// --|--------------------------------
+/// Actions adding a directive that suppresses a diagnostic on its line, or in the whole file
+pub fn suppress_actions(uri: &Url, source: &str, diagnostic: &Diagnostic) -> Vec<CodeAction> {
+  let code = match &diagnostic.code {
+    Some(NumberOrString::String(code)) => code.clone(),
+    Some(NumberOrString::Number(code)) => code.to_string(),
+    None => return vec![],
+  };
+
+  let row = diagnostic.range.start.line;
+  let line = source.lines().nth(row as usize).unwrap_or_default();
+  let indent = &line[..line.len() - line.trim_start().len()];
+  let at = |line: u32| Range { start: Position { line, character: 0 }, end: Position { line, character: 0 } };
+
+  let action = |title: String, edit: TextEdit| CodeAction {
+    title,
+    kind: Some(CodeActionKind::QUICKFIX),
+    diagnostics: Some(vec![diagnostic.clone()]),
+    edit: Some(WorkspaceEdit {
+      changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+      ..Default::default()
+    }),
+    ..Default::default()
+  };
+
+  vec![
+    action(
+      format!("Suppress `{}` on this line", code),
+      TextEdit::new(at(row), format!("{}-- {} disable-next-line[{}]\n", indent, DIRECTIVE_PREFIX, code)),
+    ),
+    action(
+      format!("Suppress `{}` in this file", code),
+      TextEdit::new(at(0), format!("-- {} ignore[{}]\n", DIRECTIVE_PREFIX, code)),
+    ),
+  ]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn collected(source: &str) -> Suppressions {
+    let tree = cyber_tree_sitter::init_parser().parse(source, None).unwrap();
+    collect(tree.root_node(), source)
+  }
+
+  #[test]
+  fn parses_directive_names_and_codes() {
+    assert_eq!(parse("-- cyberls: ignore"), Some(("ignore", None)));
+    assert_eq!(parse("--cyberls: disable-line[spelling, deprecated]"), Some(("disable-line", Some(vec!["spelling".to_string(), "deprecated".to_string()]))));
+    assert_eq!(parse("-- cyberls: disable[unused]"), Some(("disable", Some(vec![DEAD_CODE_CODE.to_string()]))));
+    assert_eq!(parse("-- cyberls: enable  trailing words"), Some(("enable", None)));
+    assert_eq!(parse("-- cyberls: disable-next-line[]"), Some(("disable-next-line", Some(vec![]))));
+    assert_eq!(parse("-- just a comment"), None);
+  }
+
+  #[test]
+  fn suppresses_lines_blocks_and_files() {
+    let source = "\
+var a = 1 -- cyberls: disable-line[spelling]
+-- cyberls: disable-next-line
+var b = 2
+-- cyberls: disable[deprecated]
+var c = 3
+-- cyberls: enable
+var d = 4
+";
+    let suppressions = collected(source);
+    assert!(suppressions.suppresses(0, Some("spelling")));
+    assert!(!suppressions.suppresses(0, Some("deprecated")));
+    assert!(suppressions.suppresses(2, Some("deprecated")));
+    assert!(suppressions.suppresses(4, Some("deprecated")));
+    assert!(!suppressions.suppresses(4, Some("spelling")));
+    assert!(!suppressions.suppresses(6, Some("deprecated")));
+    // Diagnostics without a code are always reported
+    assert!(!suppressions.suppresses(2, None));
+  }
+
+  #[test]
+  fn file_directives_and_unclosed_blocks_reach_the_end() {
+    let suppressions = collected("-- cyberls: ignore[spelling]\n-- cyberls: disable[deprecated]\nvar a = 1\n");
+    assert!(suppressions.suppresses(1000, Some("spelling")));
+    assert!(suppressions.suppresses(1000, Some("deprecated")));
+    assert!(!suppressions.suppresses(1000, Some(DEAD_CODE_CODE)));
+    assert!(collected("var a = 1\n").is_empty());
+  }
+
+  #[test]
+  fn cached_suppressions_are_kept_per_revision() {
+    let cache = SuppressionCache::new();
+    let uri = Url::parse("file:///work/main.cy").unwrap();
+    cache.insert(uri.clone(), Revision::Open(1, 0), Suppressions::default());
+
+    assert!(cache.get(&uri, Revision::Open(1, 0)).is_some());
+    assert!(cache.get(&uri, Revision::Open(2, 0)).is_none());
+    assert!(cache.get(&uri, Revision::Open(1, 1)).is_none());
+    cache.remove(&uri);
+    assert!(cache.get(&uri, Revision::Open(1, 0)).is_none());
+  }
+}
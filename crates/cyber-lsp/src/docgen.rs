@@ -0,0 +1,174 @@
+use std::path::{Component, Path, PathBuf};
+
+use lsp_types::{SymbolKind, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::index;
+use crate::modules;
+use crate::symbols::{DocumentSymbols, Symbol};
+use crate::utils::paths;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DocFormat {
+  #[default]
+  Markdown,
+  Json,
+}
+
+impl DocFormat {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      DocFormat::Markdown => "md",
+      DocFormat::Json => "json",
+    }
+  }
+}
+
+// --| Module Documentation ----------
+// --|--------------------------------
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleDoc {
+  /// Path of the module below the documented folder without its extension, ie. `lib/math`
+  pub module: String,
+  pub uri: Url,
+  pub items: Vec<ItemDoc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemDoc {
+  pub name: String,
+  /// `function`, `object`, `variable` or `method`
+  pub kind: String,
+  /// Declaration as shown in hover, ie. `func add(a, b)`
+  pub signature: String,
+  pub doc: Option<String>,
+  /// 0-based line of the declaration
+  pub line: u32,
+  /// Methods, for objects
+  pub members: Vec<ItemDoc>,
+}
+
+/// Top level declarations of every indexed file below `root`, methods nested in their objects
+pub fn module_docs(entries: Vec<(Url, DocumentSymbols)>, root: &Path) -> Vec<ModuleDoc> {
+  let mut modules: Vec<ModuleDoc> = entries.into_iter().filter_map(|(uri, symbols)| {
+    let module = modules::module_name(root, &paths::uri_to_path(&uri)?)?;
+
+    let items = symbols.symbols.iter()
+      .filter(|symbol| symbol.container.is_none())
+      .filter_map(|symbol| item_doc(symbol, &symbols))
+      .collect();
+
+    Some(ModuleDoc { module, uri, items })
+  }).collect();
+
+  modules.sort_by(|a, b| a.module.cmp(&b.module));
+  modules
+}
+
+fn item_doc(symbol: &Symbol, symbols: &DocumentSymbols) -> Option<ItemDoc> {
+  let kind = match symbol.kind {
+    SymbolKind::FUNCTION => "function",
+    SymbolKind::OBJECT => "object",
+    SymbolKind::VARIABLE => "variable",
+    SymbolKind::METHOD => "method",
+    _ => return None,
+  };
+
+  let members = match symbol.kind {
+    SymbolKind::OBJECT => symbols.symbols.iter()
+      .filter(|member| member.kind == SymbolKind::METHOD && member.container.as_deref() == Some(symbol.name.as_str()))
+      .filter_map(|member| item_doc(member, symbols))
+      .collect(),
+    _ => vec![],
+  };
+
+  Some(ItemDoc {
+    name: symbol.name.clone(),
+    kind: kind.to_string(),
+    signature: symbol.label(),
+    doc: symbol.doc.clone(),
+    line: symbol.selection_range.start.line,
+    members,
+  })
+}
+
+// --| Rendering ---------------------
+// --|--------------------------------
+/// Module page, declarations grouped by kind in source order. Doc comments are Markdown already.
+fn render_markdown(module: &ModuleDoc) -> String {
+  let mut out = format!("# {}\n", module.module);
+
+  for (kind, heading) in [("function", "Functions"), ("object", "Objects"), ("variable", "Variables")] {
+    let items: Vec<&ItemDoc> = module.items.iter().filter(|item| item.kind == kind).collect();
+    if items.is_empty() { continue; }
+
+    out.push_str(&format!("\n## {}\n", heading));
+    for item in items {
+      render_item(&mut out, item, "###");
+      for member in &item.members {
+        render_item(&mut out, member, "####");
+      }
+    }
+  }
+
+  out
+}
+
+fn render_item(out: &mut String, item: &ItemDoc, heading: &str) {
+  out.push_str(&format!("\n{} `{}`\n", heading, item.signature));
+  if let Some(doc) = &item.doc {
+    out.push_str(&format!("\n{}\n", doc));
+  }
+}
+
+pub fn render(module: &ModuleDoc, format: DocFormat) -> String {
+  match format {
+    DocFormat::Markdown => render_markdown(module),
+    DocFormat::Json => serde_json::to_string_pretty(module).unwrap_or_default(),
+  }
+}
+
+/// The folder `out` names below `root`, `None` when it is absolute or leaves `root` through `..`
+pub fn output_dir(root: &Path, out: &Path) -> Option<PathBuf> {
+  let inside = out.components().all(|part| matches!(part, Component::Normal(_) | Component::CurDir));
+  if inside { Some(root.join(out)) } else { None }
+}
+
+/// Write a page per module below `out_dir`, mirroring the module paths. Returns the written files.
+pub fn write_docs(modules: &[ModuleDoc], format: DocFormat, out_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+  let mut written = vec![];
+
+  for module in modules {
+    let file = out_dir.join(format!("{}.{}", module.module, format.extension()));
+    if let Some(parent) = file.parent() { std::fs::create_dir_all(parent)?; }
+    std::fs::write(&file, render(module, format))?;
+    written.push(file);
+  }
+
+  Ok(written)
+}
+
+/// Index `root` from disk and write its documentation, for the `cyberls doc` command
+pub fn generate(root: &Path, format: DocFormat, out_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+  let root = std::fs::canonicalize(root)?;
+  let modules = module_docs(index::scan_folder(&root, &[], &[]), &root);
+  write_docs(&modules, format, out_dir)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn output_dirs_stay_inside_the_folder() {
+    let root = Path::new("/work");
+    assert_eq!(output_dir(root, Path::new("docs/api")), Some(PathBuf::from("/work/docs/api")));
+    assert_eq!(output_dir(root, Path::new("./docs")), Some(PathBuf::from("/work/./docs")));
+    assert_eq!(output_dir(root, Path::new("../elsewhere")), None);
+    assert_eq!(output_dir(root, Path::new("docs/../../elsewhere")), None);
+    assert_eq!(output_dir(root, Path::new("/etc")), None);
+  }
+}
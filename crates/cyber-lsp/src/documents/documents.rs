@@ -1,4 +1,5 @@
 use serde_json::Value;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use lsp_types::{
   notification::{ DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification, },
@@ -66,16 +67,16 @@ impl TextDocuments {
   ///
   /// // get document all content
   /// let content = text_documents.get_document_content(&uri, None);
-  /// assert_eq!(content, Some("hello rust!"));
+  /// assert_eq!(content.as_deref(), Some("hello rust!"));
   ///
   /// // get document specify content by range
   /// let (start, end) = (Position::new(0, 1), Position::new(0, 9));
   /// let range = Range::new(start, end);
   /// let sub_content = text_documents.get_document_content(&uri, Some(range));
-  /// assert_eq!(sub_content, Some("ello rus"));
+  /// assert_eq!(sub_content.as_deref(), Some("ello rus"));
   /// ```
-  pub fn get_document_content(&self, uri: &Url, _range: Option<Range>) -> Option<&str> {
-    self.0.get(uri).map(|document| document.get_content())
+  pub fn get_document_content(&self, uri: &Url, range: Option<Range>) -> Option<Cow<'_, str>> {
+    self.0.get(uri).map(|document| document.content(range))
   }
 
   /// Get specify document's language by giving Url
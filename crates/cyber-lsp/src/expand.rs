@@ -0,0 +1,78 @@
+use std::path::{Path, PathBuf};
+
+use lsp_types::{Position, Range, TextDocumentIdentifier};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use tree_sitter::Node;
+
+use crate::modules;
+use crate::symbols::node_range;
+use crate::utils::platform::Command;
+use crate::utils::treehelper::range_contains;
+
+pub const EXPAND_METHOD: &str = "cyberls/expand";
+
+// --| Request Types -----------------
+// --|--------------------------------
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandParams {
+  pub text_document: TextDocumentIdentifier,
+  pub position: Position,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandResult {
+  /// The top level statement that was compiled
+  pub range: Range,
+  pub source: String,
+  /// What `cyber compile` printed for it
+  pub expansion: String,
+  pub success: bool,
+}
+
+// --| Expansion ---------------------
+// --|--------------------------------
+/// Cyber has no macros, so the closest thing to an expansion is the compiler's
+/// bytecode dump. The top level statement under the cursor is compiled together
+/// with the document's imports so that module references still resolve.
+pub fn expansion_source(root: Node, source: &str, position: Position) -> Option<(Range, String)> {
+  let mut cursor = root.walk();
+  let statement = root.named_children(&mut cursor).find(|child| range_contains(&node_range(*child), position))?;
+
+  let mut inner = root.walk();
+  let imports: Vec<&str> = root.named_children(&mut inner)
+    .filter(|child| child.kind() == "import_statement" && child.id() != statement.id())
+    .filter_map(|child| child.utf8_text(source.as_bytes()).ok())
+    .collect();
+
+  let text = statement.utf8_text(source.as_bytes()).ok()?;
+  let mut program = imports.join("\n");
+  if !program.is_empty() { program.push('\n'); }
+  program.push_str(text);
+
+  Some((node_range(statement), program))
+}
+
+/// Compile a snippet from the temp directory, with its relative imports anchored to the
+/// original document's directory so they still resolve
+pub async fn compile_snippet(program: &str, snippet: &str, document_dir: Option<&Path>) -> std::io::Result<(String, bool)> {
+  let path: PathBuf = std::env::temp_dir().join(format!("cyberls-expand-{}.cy", std::process::id()));
+  let snippet = match document_dir {
+    Some(dir) => modules::anchor_imports(snippet, dir),
+    None => snippet.to_string(),
+  };
+  std::fs::write(&path, snippet)?;
+
+  let output = Command::new(program).arg("compile").arg(&path).output().await;
+  if let Err(err) = std::fs::remove_file(&path) {
+    debug!("Failed to remove {:?}: {}", path, err);
+  }
+
+  let output = output?;
+  let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+  text.push_str(&String::from_utf8_lossy(&output.stderr));
+
+  Ok((text, output.status.success()))
+}
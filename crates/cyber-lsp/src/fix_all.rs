@@ -0,0 +1,89 @@
+use lsp_types::{CodeActionKind, Position, Range, TextEdit};
+
+/// Code action kind of the combined fix, run by clients on save through `source.fixAll`
+pub const FIX_ALL_KIND: &str = "source.fixAll.cyberls";
+
+pub fn fix_all_kind() -> CodeActionKind {
+  CodeActionKind::from(FIX_ALL_KIND)
+}
+
+/// Whether the client asked for fix all actions through `source.fixAll` or this server's
+/// kind below it. Like other source actions it is left out of unfiltered requests.
+pub fn requested(only: Option<&Vec<CodeActionKind>>) -> bool {
+  only.map_or(false, |kinds| kinds.iter().any(|kind| {
+    let kind = kind.as_str();
+    kind == CodeActionKind::SOURCE_FIX_ALL.as_str() || kind == FIX_ALL_KIND
+  }))
+}
+
+// --| Whitespace Fixes --------------
+// --|--------------------------------
+/// Strip trailing whitespace and leave exactly one newline at the end of the document
+pub fn whitespace_edits(source: &str) -> Vec<TextEdit> {
+  let mut edits = vec![];
+  let lines: Vec<&str> = source.split('\n').collect();
+
+  // Index of the last line with content, everything after it is trailing blank lines
+  let last_content = lines.iter().rposition(|line| !line.trim().is_empty());
+
+  for (row, line) in lines.iter().enumerate() {
+    if last_content.map_or(true, |last| row > last) { break; }
+
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let trimmed = line.trim_end();
+    if trimmed.len() == line.len() { continue; }
+
+    let start = trimmed.encode_utf16().count() as u32;
+    let end = line.encode_utf16().count() as u32;
+    edits.push(TextEdit::new(Range::new(Position::new(row as u32, start), Position::new(row as u32, end)), String::new()));
+  }
+
+  let last = match last_content {
+    Some(last) => last,
+    None => return edits,
+  };
+
+  // Whatever follows the last content, including its trailing whitespace, becomes a single line break
+  let newline = if source.contains("\r\n") { "\r\n" } else { "\n" };
+  let tail = &source[source.trim_end().len()..];
+  if tail != newline {
+    let tail_start = Position::new(last as u32, lines[last].trim_end().encode_utf16().count() as u32);
+    let tail_end = Position::new((lines.len() - 1) as u32, lines[lines.len() - 1].encode_utf16().count() as u32);
+
+    edits.retain(|edit| edit.range.start.line as usize != last);
+    edits.push(TextEdit::new(Range::new(tail_start, tail_end), newline.to_string()));
+  }
+
+  edits
+}
+
+/// Add a group of edits unless one of them overlaps an edit already collected
+pub fn merge(edits: &mut Vec<TextEdit>, group: Vec<TextEdit>) -> bool {
+  let overlaps = |a: &Range, b: &Range| {
+    let (a_start, a_end) = ((a.start.line, a.start.character), (a.end.line, a.end.character));
+    let (b_start, b_end) = ((b.start.line, b.start.character), (b.end.line, b.end.character));
+    a_start < b_end && b_start < a_end || (a_start == b_start && (a_start == a_end || b_start == b_end))
+  };
+
+  if group.iter().any(|edit| edits.iter().any(|existing| overlaps(&edit.range, &existing.range))) {
+    return false;
+  }
+
+  edits.extend(group);
+  true
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn fix_all_is_only_offered_when_asked_for() {
+    let only = |kinds: &[&str]| Some(kinds.iter().map(|kind| CodeActionKind::from(kind.to_string())).collect::<Vec<_>>());
+    assert!(!requested(None));
+    assert!(!requested(only(&["quickfix"]).as_ref()));
+    assert!(!requested(only(&["source"]).as_ref()));
+    assert!(requested(only(&["source.fixAll"]).as_ref()));
+    assert!(requested(only(&["quickfix", FIX_ALL_KIND]).as_ref()));
+  }
+}
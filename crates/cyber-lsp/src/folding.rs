@@ -0,0 +1,203 @@
+use lsp_types::{DocumentSymbol, FoldingRange, FoldingRangeKind, Position, Range, SymbolKind};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tree_sitter::Node;
+
+use crate::core_types::lsp_range;
+use crate::symbols::{DocumentSymbols, Symbol};
+
+/// Node kinds that fold when they span several lines
+const FOLDING_KINDS: &[&str] = &[
+  "function_definition", "object_definition", "object_declaration", "if_statement", "else_clause",
+  "for_statement", "while_statement", "match_statement", "try_statement", "block", "list", "map",
+];
+
+// --| Folding Config ----------------
+// --|--------------------------------
+/// Comment markers delimiting a foldable region, ie. `-- region: Helpers` ... `-- endregion`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FoldingConfig {
+  pub region_start: String,
+  pub region_end: String,
+}
+
+impl Default for FoldingConfig {
+  fn default() -> Self {
+    Self { region_start: "region".to_string(), region_end: "endregion".to_string() }
+  }
+}
+
+// --| Regions -----------------------
+// --|--------------------------------
+#[derive(Clone, Debug)]
+pub struct Region {
+  pub name: String,
+  /// From the start marker to the end marker, both included
+  pub range: Range,
+}
+
+/// Regions delimited by marker comments. Regions nest, unmatched markers are ignored.
+pub fn regions(source: &str, config: &FoldingConfig) -> Vec<Region> {
+  let mut open: Vec<(u32, String)> = vec![];
+  let mut regions = vec![];
+
+  for (line_number, line) in source.lines().enumerate() {
+    let comment = match line.trim().strip_prefix("--") {
+      Some(comment) => comment.trim(),
+      None => continue,
+    };
+
+    // The end marker is checked first as it usually contains the start marker
+    if marker(comment, &config.region_end).is_some() {
+      if let Some((start, name)) = open.pop() {
+        regions.push(Region {
+          name,
+          range: Range::new(Position::new(start, 0), Position::new(line_number as u32, line.len() as u32)),
+        });
+      }
+    } else if let Some(name) = marker(comment, &config.region_start) {
+      let name = if name.is_empty() { "region".to_string() } else { name.to_string() };
+      open.push((line_number as u32, name));
+    }
+  }
+
+  regions.sort_by_key(|region| (region.range.start.line, region.range.end.line));
+  regions
+}
+
+/// The region name following a marker, ie. `Helpers` for `region: Helpers`
+fn marker<'a>(comment: &'a str, marker: &str) -> Option<&'a str> {
+  if marker.is_empty() { return None; }
+  let rest = comment.strip_prefix(marker)?;
+  if rest.starts_with(|ch: char| ch.is_alphanumeric() || ch == '_') { return None; }
+  Some(rest.trim_start_matches(':').trim())
+}
+
+// --| Folding Ranges ----------------
+// --|--------------------------------
+pub fn folding_ranges(root: Node, source: &str, config: &FoldingConfig) -> Vec<FoldingRange> {
+  let mut ranges = vec![];
+  syntax_ranges(root, &mut ranges);
+  comment_and_import_ranges(root, &mut ranges);
+
+  for region in regions(source, config) {
+    ranges.push(folding_range(region.range.start.line, region.range.end.line, Some(FoldingRangeKind::Region)));
+  }
+
+  // Keep the outermost range for each start line
+  ranges.sort_by_key(|range| (range.start_line, std::cmp::Reverse(range.end_line)));
+  ranges.dedup_by_key(|range| range.start_line);
+  ranges
+}
+
+fn syntax_ranges(node: Node, out: &mut Vec<FoldingRange>) {
+  let mut cursor = node.walk();
+  for child in node.named_children(&mut cursor) {
+    let (start, end) = (child.start_position().row, child.end_position().row);
+    if end > start && FOLDING_KINDS.contains(&child.kind()) {
+      out.push(folding_range(start as u32, end as u32, None));
+    }
+    syntax_ranges(child, out);
+  }
+}
+
+/// Runs of consecutive line comments and import statements at the top level
+fn comment_and_import_ranges(root: Node, out: &mut Vec<FoldingRange>) {
+  let mut cursor = root.walk();
+  let children: Vec<Node> = root.named_children(&mut cursor).collect();
+
+  for (kind, fold_kind) in [("comment", FoldingRangeKind::Comment), ("import_statement", FoldingRangeKind::Imports)] {
+    let mut runs: Vec<(usize, usize)> = vec![];
+
+    for child in children.iter().filter(|child| child.kind() == kind) {
+      let (start, end) = (child.start_position().row, child.end_position().row);
+      match runs.last_mut() {
+        Some(run) if start == run.1 + 1 => run.1 = end,
+        _ => runs.push((start, end)),
+      }
+    }
+
+    for (start, end) in runs.into_iter().filter(|(start, end)| end > start) {
+      out.push(folding_range(start as u32, end as u32, Some(fold_kind.clone())));
+    }
+  }
+}
+
+fn folding_range(start_line: u32, end_line: u32, kind: Option<FoldingRangeKind>) -> FoldingRange {
+  FoldingRange { start_line, start_character: None, end_line, end_character: None, kind }
+}
+
+// --| Outline -----------------------
+// --|--------------------------------
+/// Document outline: top level declarations with their members, grouped by region. Items are
+/// nested by their byte columns and then converted to client positions of the `rope`.
+pub fn outline(symbols: &DocumentSymbols, regions: &[Region], rope: &Rope) -> Vec<DocumentSymbol> {
+  let top_level: Vec<&Symbol> = symbols.symbols.iter().filter(|symbol| symbol.container.is_none()).collect();
+  let items: Vec<DocumentSymbol> = top_level.iter().map(|symbol| symbol_item(symbol, symbols)).collect();
+  let mut grouped = group(items, regions);
+  to_client(&mut grouped, rope);
+  grouped
+}
+
+fn to_client(items: &mut [DocumentSymbol], rope: &Rope) {
+  for item in items {
+    item.range = lsp_range(rope, item.range);
+    item.selection_range = lsp_range(rope, item.selection_range);
+    if let Some(children) = item.children.as_mut() { to_client(children, rope); }
+  }
+}
+
+fn symbol_item(symbol: &Symbol, symbols: &DocumentSymbols) -> DocumentSymbol {
+  let children: Vec<DocumentSymbol> = symbols.symbols.iter()
+    .filter(|child| child.container.as_deref() == Some(symbol.name.as_str()) && contains(&symbol.range, &child.range))
+    .map(|child| symbol_item(child, symbols))
+    .collect();
+
+  let detail = symbol.is_function().then(|| symbol.signature());
+  document_symbol(symbol.name.clone(), detail, symbol.kind, symbol.range, symbol.selection_range, children)
+}
+
+/// Nest items inside the innermost region containing them
+fn group(items: Vec<DocumentSymbol>, regions: &[Region]) -> Vec<DocumentSymbol> {
+  // Regions not contained in another region form the top level
+  let outer: Vec<&Region> = regions.iter()
+    .filter(|region| !regions.iter().any(|other| !std::ptr::eq(*region, other) && contains(&other.range, &region.range)))
+    .collect();
+
+  let mut remaining = items;
+  let mut result = vec![];
+
+  for region in outer {
+    let (inside, outside): (Vec<DocumentSymbol>, Vec<DocumentSymbol>) = remaining.into_iter()
+      .partition(|item| contains(&region.range, &item.range));
+    remaining = outside;
+
+    let nested: Vec<Region> = regions.iter()
+      .filter(|other| !std::ptr::eq(region, *other) && contains(&region.range, &other.range))
+      .cloned()
+      .collect();
+
+    let children = group(inside, &nested);
+    result.push(document_symbol(region.name.clone(), None, SymbolKind::NAMESPACE, region.range, region.range, children));
+  }
+
+  result.extend(remaining);
+  result.sort_by_key(|item| (item.range.start.line, item.range.start.character));
+  result
+}
+
+#[allow(deprecated)]
+fn document_symbol(name: String, detail: Option<String>, kind: SymbolKind, range: Range, selection_range: Range, children: Vec<DocumentSymbol>) -> DocumentSymbol {
+  DocumentSymbol {
+    name, detail, kind, range, selection_range,
+    tags: None,
+    deprecated: None,
+    children: if children.is_empty() { None } else { Some(children) },
+  }
+}
+
+fn contains(outer: &Range, inner: &Range) -> bool {
+  (outer.start.line, outer.start.character) <= (inner.start.line, inner.start.character)
+    && (inner.end.line, inner.end.character) <= (outer.end.line, outer.end.character)
+}
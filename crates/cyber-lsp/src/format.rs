@@ -0,0 +1,138 @@
+use lsp_types::{FormattingOptions, Position, Range, TextEdit};
+use serde::{Deserialize, Serialize};
+use tree_sitter::Node;
+
+/// Indent width when neither the configuration nor the client sets one
+pub const DEFAULT_INDENT_WIDTH: u32 = 4;
+
+// --| Indentation -------------------
+// --|--------------------------------
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IndentStyle {
+  #[default]
+  Spaces,
+  Tabs,
+}
+
+/// The indentation unit used by the formatter, on type formatting and generated edits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Indent {
+  pub style: IndentStyle,
+  pub width: u32,
+}
+
+impl Indent {
+  pub fn new(style: IndentStyle, width: u32) -> Self {
+    Self { style, width: width.max(1) }
+  }
+
+  /// Configured settings win; anything left unset comes from the client's
+  /// formatting options, when the request carries them, or the defaults
+  pub fn resolve(style: Option<IndentStyle>, width: Option<u32>, options: Option<&FormattingOptions>) -> Self {
+    let client_style = options.map(|options| if options.insert_spaces { IndentStyle::Spaces } else { IndentStyle::Tabs });
+    let style = style.or(client_style).unwrap_or_default();
+    let width = width.or_else(|| options.map(|options| options.tab_size)).unwrap_or(DEFAULT_INDENT_WIDTH);
+    Self::new(style, width)
+  }
+
+  pub fn unit(&self) -> String {
+    match self.style {
+      IndentStyle::Spaces => " ".repeat(self.width as usize),
+      IndentStyle::Tabs => "\t".to_string(),
+    }
+  }
+
+  pub fn level(&self, level: usize) -> String {
+    self.unit().repeat(level)
+  }
+
+  /// Width of leading whitespace, with tabs expanded to the indent width
+  pub(crate) fn measure(&self, line: &str) -> usize {
+    line.chars()
+      .take_while(|ch| *ch == ' ' || *ch == '\t')
+      .map(|ch| if ch == '\t' { self.width as usize } else { 1 })
+      .sum()
+  }
+}
+
+// --| Formatter ---------------------
+// --|--------------------------------
+/// Re-indent a document with the given unit and drop trailing whitespace. Cyber blocks
+/// are indentation based, so nesting is taken from the existing indentation rather
+/// than the tree. Lines inside multi-line strings are left untouched.
+pub fn format_document(root: Node, source: &str, indent: Indent) -> Vec<TextEdit> {
+  let protected = string_lines(root);
+  let mut levels: Vec<usize> = vec![0];
+  let mut edits = vec![];
+
+  for (row, line) in source.lines().enumerate() {
+    if protected.iter().any(|(start, end)| row > *start && row <= *end) { continue; }
+
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let content = line.trim();
+
+    let formatted = if content.is_empty() {
+      String::new()
+    } else {
+      let width = indent.measure(line);
+      while levels.len() > 1 && width < *levels.last().unwrap() { levels.pop(); }
+      if width > *levels.last().unwrap() { levels.push(width); }
+      format!("{}{}", indent.level(levels.len() - 1), content)
+    };
+
+    if formatted != line {
+      let end = line.encode_utf16().count() as u32;
+      edits.push(TextEdit::new(Range::new(Position::new(row as u32, 0), Position::new(row as u32, end)), formatted));
+    }
+  }
+
+  edits
+}
+
+/// Start and end rows of string literals spanning several lines
+fn string_lines(node: Node) -> Vec<(usize, usize)> {
+  let mut rows = vec![];
+  let mut cursor = node.walk();
+
+  for child in node.named_children(&mut cursor) {
+    let (start, end) = (child.start_position().row, child.end_position().row);
+    if end <= start { continue; }
+
+    if child.kind().contains("string") {
+      rows.push((start, end));
+    } else {
+      rows.extend(string_lines(child));
+    }
+  }
+
+  rows
+}
+
+// --| On Type Formatting ------------
+// --|--------------------------------
+/// Characters that trigger on type formatting
+pub const ON_TYPE_TRIGGER: &str = "\n";
+
+/// Indent a freshly inserted line like the previous one, one level deeper after a block opening `:`
+pub fn on_type_edits(source: &str, position: Position, indent: Indent) -> Vec<TextEdit> {
+  let lines: Vec<&str> = source.lines().collect();
+  let row = position.line as usize;
+
+  let previous = match lines[..row.min(lines.len())].iter().rev().find(|line| !line.trim().is_empty()) {
+    Some(previous) => previous,
+    None => return vec![],
+  };
+
+  let mut level = indent.measure(previous) / indent.width as usize;
+  let code = previous.split("--").next().unwrap_or(previous).trim_end();
+  if code.ends_with(':') { level += 1; }
+
+  let current = lines.get(row).copied().unwrap_or("");
+  let existing = current.len() - current.trim_start().len();
+  let expected = indent.level(level);
+  if current[..existing] == expected { return vec![]; }
+
+  let end = current[..existing].encode_utf16().count() as u32;
+  vec![TextEdit::new(Range::new(Position::new(position.line, 0), Position::new(position.line, end)), expected)]
+}
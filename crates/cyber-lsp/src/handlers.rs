@@ -1,25 +1,103 @@
-use std::path::Path;
-use std::time::Instant;
-
-use cyber_tree_sitter::Tree;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use cyber_tree_sitter::{Node, Tree};
+use futures::future::join_all;
+use ropey::Rope;
+use serde::Serialize;
 use serde_json::Value;
+use tokio::sync::Semaphore;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tracing::debug;
 use tracing::error;
 use tracing::info;
+use tracing::warn;
 use tracing::metadata::LevelFilter;
 
 use crate::Backend;
 use crate::State;
 
-use crate::completions;
-use crate::diagnostics::ErrorInfo;
+use crate::index;
+use crate::ignores::IgnoreRules;
+use crate::capabilities;
+use crate::commands;
+use crate::commands::{BenchmarkArgs, ClearDiagnosticsArgs, Command, DeadCodeArgs, DocumentArgs, GenerateDocsArgs, LogLevelArgs, OrganizeImportsArgs, ReindexArgs, ShowImportGraphArgs, ShowSyntaxTreeArgs, SpellIgnoreArgs, StructuralReplaceArgs, ValidateDocsArgs, DumpStatsArgs, ShowRecentLogArgs};
+use crate::benchmark;
+use crate::stats;
+use crate::auto_import;
+use crate::auto_import::ImportPlan;
+use crate::dead_code;
+use crate::moniker::{self, MonikerContext};
+use crate::docgen;
+use crate::declare;
+use crate::deprecations;
+use crate::directives;
+use crate::directives::Suppressions;
+#[cfg(feature = "run-commands")]
+use crate::expand;
+use crate::fix_all;
+use crate::surround;
+use crate::folding;
+use crate::format;
+use crate::format::Indent;
+#[cfg(feature = "run-commands")]
+use crate::commands::RunArgs;
+#[cfg(feature = "run-commands")]
+use crate::expand::{ExpandParams, ExpandResult};
+use crate::matching;
+use crate::matching::{MatchingBlockParams, MatchingBlockResult};
+use crate::infer;
+use crate::lints;
+use crate::logs;
+use crate::run;
+#[cfg(feature = "run-commands")]
+use crate::repl::{ReplEvalParams, ReplEvalResult, ReplParams, ReplStartResult};
+use crate::project_config;
+use crate::rename;
+use crate::call_hierarchy;
+use crate::partial::{self, Pager, PartialResult};
+use crate::rename::RenameTarget;
+use crate::signature;
+use crate::semantic_tokens;
+use crate::undefined;
+use crate::syntax_tree;
+use crate::trace;
+use crate::virtual_docs;
+use crate::virtual_docs::{VirtualContentParams, VirtualContentResult};
+use crate::syntax_tree::{SyntaxTreeParams, SyntaxTreeResult};
+use crate::spellcheck;
+use crate::structural;
+use crate::spellcheck::SpellChecker;
+use crate::status;
+use crate::server_info;
+use crate::server_info::{CyberInfo, ServerInfoResult, Subsystems};
+use crate::status::Activity;
+use crate::completions::{self, LineContext};
+use crate::symbols::{collect_symbols, CallSite, DocumentSymbols, Symbol};
+use crate::core_types::{byte_position, lsp_range, DocPoint, ToDoc, ToLsp};
+use crate::lanes::Lane;
+use crate::memory;
+use crate::queries::Revision;
+use crate::modules;
+use crate::notify;
+use crate::notify::Severity;
+use crate::modules::{ImportStatement, RemoteCache, ResolvedModule};
+use crate::datatypes::{AnalysisLevel, Config};
+use crate::diagnostics::{ErrorInfo, SeverityLevel};
+use crate::diagnostics::pipeline;
+use crate::diagnostics::pipeline::Tier;
 use crate::documents::FullTextDocument;
-use crate::diagnostics::{check_compile_error, check_tree_error};
+use crate::diagnostics::{check_tree_error, SYNTAX_ERROR_CODE};
+use crate::utils::loader::DOC_PACKS;
+use crate::utils::doc_schema;
 use crate::utils::treehelper::get_parser_errors;
-use crate::utils::treehelper::position_to_point;
-use crate::utils::treehelper::{ TreeWrapper, get_range, get_tree_edits, get_from_position };
+use crate::utils::paths;
+use crate::utils::platform;
+use crate::utils::treehelper::{ TreeWrapper, get_range, get_tree_edits, get_from_position, enclosing_name, get_string_at_pos, identifier_at, in_error, lookup_keyword, range_contains, word_at };
 
 // --| Backend Implementation ---------
 // --|---------------------------------
@@ -30,6 +108,62 @@ impl Backend{
     docs.iter().map(|(url, _)| url.clone()).collect::<Vec<Url>>()
   }
 
+  // --| Get Tree ----------------
+  /// The tree of an open document, parsed when it has none yet
+  pub async fn tree_for(&self, uri: &Url) -> Option<Tree> {
+    self.idle.touch(uri);
+    if let Some(tree) = self.parse_tree.lock().await.get(uri) { return Some(tree.clone()); }
+
+    let limits = self.config_for(uri).await.limits;
+    let mut docs = self.docs.lock().await;
+    let doc = docs.get_mut(uri).filter(|doc| !doc.plain_text)?;
+    if !limits.allows_parse(doc.get_content().len()) { return None; }
+
+    let tree = self.parser.lock().await.parse(doc.get_content(), None)?;
+    doc.tree = Some(tree.clone());
+    self.parse_tree.lock().await.insert(uri.clone(), tree.clone());
+    debug!("Parsed document without a tree: {}", uri);
+    Some(tree)
+  }
+
+  // --| Reclaim Idle Results --------
+  /// Drop what the caches still hold for documents that are no longer open and went unused
+  /// for `idle_tree_minutes`, ie. results of checks that finished after the document closed.
+  /// Open documents keep their trees.
+  pub async fn reclaim_idle_documents(&self) {
+    let minutes = self.config.lock().await.idle_tree_minutes;
+    if minutes == 0 { return; }
+
+    let stale = self.idle.sweep(Duration::from_secs(minutes * 60));
+    if stale.is_empty() { return; }
+
+    let closed: Vec<Url> = {
+      let docs = self.docs.lock().await;
+      stale.into_iter().filter(|uri| !docs.contains_key(uri)).collect()
+    };
+    if closed.is_empty() { return; }
+
+    let mut parse_tree = self.parse_tree.lock().await;
+    for uri in &closed {
+      parse_tree.remove(uri);
+      self.semantic_tokens.remove(uri);
+      self.queries.forget(uri);
+      self.completions.forget(uri);
+    }
+    info!("Reclaimed the cached results of {} closed documents", closed.len());
+  }
+
+  // --| Get Workspace -----------
+  /// Returns the workspace folder owning the document, or the document's
+  /// own directory when it lives outside every known folder.
+  pub fn workspace_for(&self, uri: &Url) -> Url {
+    self.workspace_map.iter()
+      .map(|entry| entry.key().clone())
+      .filter(|folder| index::in_scope(uri, folder))
+      .max_by_key(|folder| folder.as_str().len())
+      .unwrap_or_else(|| uri.join(".").unwrap_or_else(|_| uri.clone()))
+  }
+
   // --| Initialize -----------------------------
   // --|-----------------------------------------
   // --| Initialize handler -----------
@@ -39,15 +173,13 @@ impl Backend{
     
     let capabilities = params.capabilities;
     let options = params.initialization_options;
+
+    *self.client_capabilities.write().unwrap_or_else(|err| err.into_inner()) = capabilities.clone();
     debug!("Initialize: {:?}", options);
 
     // Last I heard, only vscode supports dynamic_registration
     // nvim does not support dynamic or static registration.
-    state.client_monitor = capabilities.workspace.map_or(false, |wrk| {
-      wrk.did_change_watched_files.map_or(false, |dynamic| {
-        dynamic.dynamic_registration.unwrap_or(false)
-      })
-    });
+    state.client_monitor = capabilities::watched_files_registration(&capabilities);
 
     if let Some(folders) = params.workspace_folders {
       folders.into_iter().for_each(|folder| {
@@ -57,6 +189,14 @@ impl Backend{
       });
     }
 
+    // Settings the client starts the server with, over those the server was started with
+    let current = self.config.lock().await.clone();
+    if let Some(config) = options.as_ref().and_then(|options| current.with_settings(options)) {
+      let config = config.with_env_overrides();
+      self.configure(&config).await;
+      *self.config.lock().await = config;
+    }
+
     let pattern = GlobPattern::String("**/*.{cy,cyber}".to_string());
 
     // --| Register current workspace file watcher
@@ -65,16 +205,30 @@ impl Backend{
       method: "workspace/didChangeWatchedFiles".to_string(),
       register_options: Some(
         serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
-          watchers: vec![FileSystemWatcher {
-            glob_pattern: pattern, 
-            kind: None,
-          }]
+          watchers: vec![
+            FileSystemWatcher { glob_pattern: pattern, kind: None },
+            FileSystemWatcher { glob_pattern: GlobPattern::String(format!("**/{}/*.{{scm,json}}", lints::LINTS_DIR)), kind: None },
+            FileSystemWatcher { glob_pattern: GlobPattern::String(format!("**/{{{}}}", project_config::PROJECT_FILES.join(","))), kind: None },
+          ]
         }).unwrap_or_default(),
         ) 
     };
 
-    let registrations = vec![registration];
-    let _ = self.client.register_capability(registrations).await;
+    if state.client_monitor {
+      let registrations = vec![registration];
+      let _ = self.client.register_capability(registrations).await;
+    } else {
+      debug!("Initialize: client does not support dynamic file watcher registration");
+    }
+
+    let diagnostic_provider = capabilities::pull_diagnostics(&capabilities).then(|| {
+      DiagnosticServerCapabilities::Options(DiagnosticOptions {
+        identifier: Some("cyberls".to_string()),
+        inter_file_dependencies: true,
+        workspace_diagnostics: false,
+        work_done_progress_options: Default::default(),
+      })
+    });
 
     debug!("Initialize: {:?}", start.elapsed().as_secs_f64());
     Ok(InitializeResult {
@@ -85,7 +239,7 @@ impl Backend{
           TextDocumentSyncOptions {
             open_close: Some(true),
             will_save: Some(false),
-            will_save_wait_until: Some(false),
+            will_save_wait_until: Some(true),
             change: Some(TextDocumentSyncKind::INCREMENTAL),
             save: Some(lsp_types::TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
               include_text: Some(true),
@@ -94,18 +248,63 @@ impl Backend{
 
         completion_provider: Some(CompletionOptions {
           resolve_provider: Some(false),
-          trigger_characters: Some(vec![".".to_string()]),
+          // `:` completes type annotations, `'` import paths
+          trigger_characters: Some(vec![".".to_string(), ":".to_string(), "'".to_string()]),
           work_done_progress_options: Default::default(),
-          all_commit_characters: None,
+          all_commit_characters: Some(completions::ALL_COMMIT_CHARACTERS.iter().map(|character| character.to_string()).collect()),
           ..Default::default()
         }),
 
-        execute_command_provider: Some(ExecuteCommandOptions {
-          commands: vec!["dummy.do_something".to_string()],
+        execute_command_provider: Some(commands::execute_command_options()),
+
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+
+        diagnostic_provider,
+
+        code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+          code_action_kinds: Some(vec![CodeActionKind::QUICKFIX, fix_all::fix_all_kind(), surround::surround_kind()]),
           work_done_progress_options: Default::default(),
+          resolve_provider: None,
+        })),
+
+        definition_provider: Some(OneOf::Left(true)),
+
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+
+        document_formatting_provider: Some(OneOf::Left(true)),
+
+        document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+          first_trigger_character: format::ON_TYPE_TRIGGER.to_string(),
+          more_trigger_character: None,
         }),
 
-        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+
+        workspace_symbol_provider: Some(OneOf::Left(true)),
+
+        moniker_provider: Some(OneOf::Left(true)),
+
+        references_provider: Some(OneOf::Left(true)),
+
+        call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+
+        rename_provider: Some(OneOf::Right(RenameOptions {
+          prepare_provider: Some(true),
+          work_done_progress_options: Default::default(),
+        })),
+
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
+          legend: semantic_tokens::legend(),
+          full: Some(SemanticTokensFullOptions::Bool(true)),
+          range: None,
+          work_done_progress_options: Default::default(),
+        })),
+
+        signature_help_provider: Some(SignatureHelpOptions {
+          trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+          retrigger_characters: None,
+          work_done_progress_options: Default::default(),
+        }),
 
         workspace: Some(WorkspaceServerCapabilities {
           workspace_folders: Some(WorkspaceFoldersServerCapabilities {
@@ -125,347 +324,3042 @@ impl Backend{
   // --|-----------------------------------------
   // --| Publish Diagnostics ----------
   pub async fn publish_diagnostics(&self, uri: Url, errors: Option<ErrorInfo>) {
-    if let Some(diag) = errors {
-      let mut diagnostic_items = vec![];
+    let mut diagnostic_items = vec![];
+
+    // Entries count columns in bytes, open documents convert them through their rope
+    let rope = self.docs.lock().await.get(&uri).map(|doc| doc.rope.clone());
+    let position = |point: DocPoint| match &rope {
+      Some(rope) => point.to_lsp(rope),
+      None => point.byte_position(),
+    };
 
-      for err in diag.entries {
-        let pointx = lsp_types::Position::new(err.start.row as u32, err.start.column as u32);
-        let pointy = lsp_types::Position::new(err.end.row as u32, err.end.column as u32);
-        let range = Range { start: pointx, end: pointy };
+    let entries = errors.map(|diag| diag.entries).unwrap_or_default();
+    let suppressions = if entries.iter().any(|err| err.code.is_some()) { self.suppressions_for(&uri).await } else { Arc::default() };
 
-        let diagnose = Diagnostic { 
-          range, severity: err.severity, code: None, code_description: None,
-          source: None, message: err.message, related_information: None, tags: None, data: None,
-        };
+    let overrides = self.config_for(&uri).await.diagnostics;
+
+    for err in entries {
+      if suppressions.suppresses(err.start.line, err.code.as_deref()) { continue; }
+      let severity = match overrides.level(err.code.as_deref()) {
+        Some(SeverityLevel::Off) => continue,
+        Some(level) => level.severity(),
+        None => err.severity,
+      };
+
+      let range = Range { start: position(err.start), end: position(err.end) };
+      let tags = match err.code.as_deref() {
+        Some(deprecations::DEPRECATED_CODE) => Some(vec![DiagnosticTag::DEPRECATED]),
+        _ => None,
+      };
+
+      let diagnose = Diagnostic { 
+        range, severity, code: err.code.map(NumberOrString::String), code_description: None,
+        source: err.source, message: err.message, related_information: Some(err.related).filter(|related| !related.is_empty()), tags, data: None,
+      };
+
+      diagnostic_items.push(diagnose);
+    }
 
-        diagnostic_items.push(diagnose);
+    // Errors of the last run stay until the next edit
+    let runtime: Vec<Diagnostic> = self.diagnostics.get(&uri)
+      .map(|entry| entry.value().iter().filter(|diagnostic| trace::is_runtime(diagnostic)).cloned().collect())
+      .unwrap_or_default();
+    let runtime = runtime.into_iter().filter_map(|mut diagnostic| {
+      match overrides.level(Some(trace::PANIC_CODE)) {
+        Some(SeverityLevel::Off) => return None,
+        Some(level) => diagnostic.severity = level.severity(),
+        None => {}
       }
+      Some(diagnostic)
+    });
+    diagnostic_items.extend(runtime);
+
+    if !diagnostic_items.is_empty() {
+      self.diagnostics.insert(uri.clone(), diagnostic_items.clone());
+      if self.pull_diagnostics() { return self.refresh_diagnostics().await; }
 
       debug!("Publish Diagnostics");
       self.client.publish_diagnostics(uri, diagnostic_items, Some(1)).await;
     } else {
+      self.diagnostics.remove(&uri);
+      if self.pull_diagnostics() { return self.refresh_diagnostics().await; }
+
       self.client.publish_diagnostics(uri, vec![], None).await;
     }
   }
 
-  pub async fn obtain_basic_diagnostics(&self, uri: Url, context: String, tree: Tree) {
-    let start = Instant::now();
-    let errors = get_parser_errors(&context, Some(tree.clone()));
+  /// Directive comments suppressing diagnostics of the document, read from disk when it is
+  /// not open. They are read again only once the document or file changed.
+  async fn suppressions_for(&self, uri: &Url) -> Arc<Suppressions> {
+    let open = self.docs.lock().await.get(uri).map(|doc| directives::Revision::Open(doc.version, doc.generation));
+    if let Some(revision) = open {
+      if let Some(cached) = self.suppressions.get(uri, revision) { return cached; }
+
+      let source = match self.docs.lock().await.get(uri) {
+        Some(doc) => doc.content(None).into_owned(),
+        None => return Arc::default(),
+      };
+      let suppressions = self.tree_for(uri).await.map(|tree| directives::collect(tree.root_node(), &source)).unwrap_or_default();
+      return self.suppressions.insert(uri.clone(), revision, suppressions);
+    }
+
+    let path = match paths::uri_to_path(uri) { Some(path) => path, None => return Arc::default() };
+    let read = platform::blocking(move || {
+      let metadata = std::fs::metadata(&path).ok()?;
+      let revision = directives::Revision::Disk(metadata.modified().unwrap_or(std::time::UNIX_EPOCH), metadata.len());
+      Some((revision, path))
+    }).await.flatten();
+    let (revision, path) = match read { Some(read) => read, None => return Arc::default() };
+    if let Some(cached) = self.suppressions.get(uri, revision) { return cached; }
+
+    let source = match platform::blocking(move || std::fs::read_to_string(path).ok()).await.flatten() {
+      Some(source) => source,
+      None => return Arc::default(),
+    };
+    let tree = self.parser.lock().await.parse(&source, None);
+    let suppressions = tree.map(|tree| directives::collect(tree.root_node(), &source)).unwrap_or_default();
+    self.suppressions.insert(uri.clone(), revision, suppressions)
+  }
+
+  /// Clear the diagnostics of a document, if it has any
+  pub async fn clear_diagnostics(&self, uri: &Url) {
+    self.pipeline.remove(uri);
+    if self.diagnostics.remove(uri).is_some() {
+      self.publish_diagnostics(uri.clone(), None).await;
+    }
+  }
+
+  /// Clear the diagnostics of every document, returning how many were cleared
+  pub async fn clear_all_diagnostics(&self) -> usize {
+    self.pipeline.clear();
+    self.runner.take_traced();
+    let uris: Vec<Url> = self.diagnostics.iter().map(|entry| entry.key().clone()).collect();
+    self.diagnostics.clear();
+    for uri in &uris {
+      self.publish_diagnostics(uri.clone(), None).await;
+    }
+    uris.len()
+  }
 
-    let mut err_info: ErrorInfo = ErrorInfo::new();
+  /// Drop the diagnostics of the last run's trace, leaving the checked ones
+  pub async fn clear_runtime_diagnostics(&self) {
+    for uri in self.runner.take_traced() {
+      if let Some(mut entry) = self.diagnostics.get_mut(&uri) {
+        entry.retain(|diagnostic| !trace::is_runtime(diagnostic));
+      }
+      let current = self.pipeline.current(&uri);
+      self.publish_diagnostics(uri, current).await;
+    }
+  }
+
+  fn pull_diagnostics(&self) -> bool {
+    capabilities::pull_diagnostics(&self.client_capabilities.read().unwrap_or_else(|err| err.into_inner()))
+  }
+
+  /// Ask a pulling client to request diagnostics again after they changed
+  async fn refresh_diagnostics(&self) {
+    let supported = capabilities::diagnostic_refresh(&self.client_capabilities.read().unwrap_or_else(|err| err.into_inner()));
+    if !supported { return; }
+
+    if let Err(err) = self.client.send_request::<request::WorkspaceDiagnosticRefresh>(()).await {
+      debug!("Diagnostic refresh failed: {:?}", err);
+    }
+  }
+
+  // --| Pull Diagnostics -------------
+  pub async fn on_diagnostic(&self, params: DocumentDiagnosticParams) -> Result<DocumentDiagnosticReportResult> {
+    let items = self.diagnostics.get(&params.text_document.uri)
+      .map(|entry| entry.value().clone())
+      .unwrap_or_default();
+
+    Ok(DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+      related_documents: None,
+      full_document_diagnostic_report: FullDocumentDiagnosticReport { result_id: None, items },
+    })))
+  }
 
-    if errors.len() > 0 {
-      for error in errors.iter() {
-        err_info.add(
-            position_to_point(error.start), 
-            position_to_point(error.end), 
-            "Syntax Error".to_string(), 
-            Some(DiagnosticSeverity::ERROR)
-           );
-    }}
+  /// Syntax tier, published as soon as the document is parsed
+  pub async fn obtain_basic_diagnostics(&self, uri: Url, context: String, tree: Tree) {
+    let start = Instant::now();
+    let errors = syntax_diagnostics(&uri, &context, &tree);
 
     debug!("Obtain Basic Diagnostics: {:?}", start.elapsed().as_secs_f64());
-    self.publish_diagnostics(uri.clone(), Some(err_info)).await;
+    self.publish_tier(&uri, Tier::Syntax, errors).await;
   }
 
+  /// Every tier, compiler included. Runs on save and on explicit checks.
   pub async fn obtain_full_diagnostics(&self, uri: Url, context: String) {
+    self.begin_activity(Activity::Checking).await;
+    self.check_document(uri, context, true).await;
+    self.end_activity(Activity::Checking).await;
+  }
+
+  /// Syntax and semantic tiers, keeping the last compiler results
+  pub async fn obtain_analysis_diagnostics(&self, uri: Url, context: String) {
+    self.begin_activity(Activity::Checking).await;
+    self.check_document(uri, context, false).await;
+    self.end_activity(Activity::Checking).await;
+  }
+
+  /// Run the semantic tier once the document has been idle for the debounce period. A shared
+  /// backend waits in a task of its own so the change notification returns right away.
+  pub async fn schedule_semantic_diagnostics(&self, uri: Url) {
+    let generation = self.pipeline.touch(&uri);
+    match self.shared() {
+      Some(backend) => { tokio::spawn(async move { backend.debounced_semantic_diagnostics(uri, generation).await }); }
+      None => self.debounced_semantic_diagnostics(uri, generation).await,
+    }
+  }
+
+  /// Analysis after the debounce, skipped once a later change touched the document
+  async fn debounced_semantic_diagnostics(&self, uri: Url, generation: u64) {
+    let mut shutdown = self.shutdown.subscribe();
+    tokio::select! {
+      _ = tokio::time::sleep(pipeline::SEMANTIC_DEBOUNCE) => {},
+      _ = shutdown.recv() => return,
+    }
+    if self.is_shutting_down() || !self.pipeline.is_current(&uri, generation) { return; }
+
+    let content = self.docs.lock().await.get(&uri).map(|doc| doc.get_content().to_string());
+    if let Some(content) = content {
+      self.obtain_analysis_diagnostics(uri, content).await;
+    }
+  }
+
+  /// Store the results of one tier and publish the merged set of the document
+  async fn publish_tier(&self, uri: &Url, tier: Tier, errors: Option<ErrorInfo>) {
+    let merged = self.pipeline.update(uri, tier, errors);
+    self.publish_diagnostics(uri.clone(), Some(merged)).await;
+  }
+
+  async fn check_document(&self, uri: Url, context: String, compile: bool) {
+    match self.config_for(&uri).await.limits.analysis_level(context.len()) {
+      AnalysisLevel::Full => {}
+      AnalysisLevel::SyntaxOnly => {
+        debug!("Syntax only diagnostics for large document: {}", uri);
+        self.pipeline.remove(&uri);
+        let tree = self.tree_for(&uri).await;
+        match tree {
+          Some(tree) => self.obtain_basic_diagnostics(uri, context, tree).await,
+          None => self.publish_diagnostics(uri, None).await,
+        }
+        return;
+      }
+      AnalysisLevel::Disabled => {
+        self.pipeline.remove(&uri);
+        self.publish_diagnostics(uri, None).await;
+        return;
+      }
+    }
+
     let start = Instant::now();
+
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return,
+    };
+    self.publish_tier(&uri, Tier::Syntax, syntax_diagnostics(&uri, &context, &tree)).await;
+
+    // --| Semantic tier --------
+    let revision = Revision::of(&context);
+    let errors = match self.queries.semantic(&uri, revision, &self.index) {
+      Some(errors) => {
+        debug!("Semantic diagnostics unchanged: {}", uri);
+        errors
+      }
+      None => self.semantic_diagnostics(&uri, &context, &tree, revision).await,
+    };
+    self.publish_tier(&uri, Tier::Semantic, Some(errors)).await;
+
+    if compile { self.compiler_tier(&uri, &context).await; }
+
+    debug!("Obtain Full Diagnostics: {:?}", start.elapsed().as_secs_f64());
+  }
+
+  /// Types, calls, names, spelling, imports, lints and dead code of a document, kept by the
+  /// query cache until the document, a file it imports or the environment changes
+  async fn semantic_diagnostics(&self, uri: &Url, context: &str, tree: &Tree, revision: Revision) -> ErrorInfo {
+    let environment = self.queries.environment();
     let mut errors = ErrorInfo::new();
 
-    let uri_path = Path::new(uri.path());
-    let mut diag_results = check_compile_error(&uri_path, &context);
-    if diag_results.is_some() {
-      errors.combine(diag_results.as_mut().unwrap());
+    let mut type_results = infer::check_types(tree.root_node(), context);
+    if type_results.is_some() {
+      errors.combine(type_results.as_mut().unwrap());
+    }
+
+    let workspace = self.workspace_for(uri);
+    let base_dir = self.modules.base_dir(uri);
+    let imports = modules::collect_imports(tree.root_node(), context);
+    self.fetch_remote_imports(uri, &imports).await;
+
+    let symbols = self.queries.symbols(uri, context, tree);
+    let mut arity_results = signature::check_call_arity(&symbols.calls, |call| self.resolve_call(uri, &symbols, &imports, call));
+    if arity_results.is_some() {
+      errors.combine(arity_results.as_mut().unwrap());
     }
 
-    let tree = self.parse_tree.lock().await.get(&uri).unwrap().clone();
-    let mut tree_results = check_tree_error(&uri_path, &context, tree.root_node());
-    if tree_results.is_some() {
-      errors.combine(tree_results.as_mut().unwrap());
+    let mut undefined_results = undefined::check_undefined(&symbols);
+    if undefined_results.is_some() {
+      errors.combine(undefined_results.as_mut().unwrap());
     }
 
-    if errors.entries.len() == 0{ self.publish_diagnostics(uri.clone(), None).await; }
-    else { self.publish_diagnostics(uri.clone(), Some(errors)).await; }
+    let mut deprecated_results = deprecations::check_deprecated(tree.root_node(), context, &symbols);
+    if deprecated_results.is_some() {
+      errors.combine(deprecated_results.as_mut().unwrap());
+    }
+    self.index.update(uri.clone(), (*symbols).clone());
 
-    debug!("Obtain Full Diagnostics: {:?}", start.elapsed().as_secs_f64());
+    let spellcheck = self.config_for(uri).await.spellcheck.enable;
+    if let Some(checker) = self.spellchecker.lock().await.as_ref().filter(|_| spellcheck) {
+      let mut spelling_results = checker.check(tree.root_node(), context);
+      if spelling_results.is_some() {
+        errors.combine(spelling_results.as_mut().unwrap());
+      }
+    }
+
+    let mut import_results = modules::check_imports(&self.modules, &workspace, base_dir.as_deref(), tree.root_node(), context);
+    if import_results.is_some() {
+      errors.combine(import_results.as_mut().unwrap());
+    }
+
+    if let Some(path) = paths::uri_to_path(uri) {
+      let mut cycle_results = modules::check_import_cycles(&self.modules, &workspace, &path, &imports);
+      if cycle_results.is_some() {
+        errors.combine(cycle_results.as_mut().unwrap());
+      }
+    }
+
+    if let Some(lints) = self.lints.get(&workspace) {
+      let mut lint_results = lints::check_lints(lints.value(), tree.root_node(), context);
+      if lint_results.is_some() {
+        errors.combine(lint_results.as_mut().unwrap());
+      }
+    }
+
+    if let Some(report) = self.dead_code.lock().await.as_ref() {
+      let mut dead_code_results = report.hints(uri);
+      if dead_code_results.is_some() {
+        errors.combine(dead_code_results.as_mut().unwrap());
+      }
+    }
+
+    // Modules imported through others count too, a cycle may close anywhere down the chain
+    let imported: Vec<PathBuf> = imports.iter()
+      .filter_map(|import| self.modules.source_path(&self.modules.resolve(&workspace, base_dir.as_deref(), &import.specifier)))
+      .collect();
+    let dependencies: Vec<Url> = self.modules.reachable_modules(&workspace, imported).iter()
+      .filter_map(|path| paths::path_to_uri(path))
+      .collect();
+    self.queries.store_semantic(uri, revision, environment, dependencies, &errors, &self.index);
+    errors
+  }
+
+  // --| Compiler tier --------
+  /// Untitled and other virtual buffers have no path to compile next to, they keep the tree-sitter tiers
+  /// A shared backend compiles in a task of its own, the handler doesn't wait for room on
+  /// the background lane.
+  #[cfg(feature = "compiler-diagnostics")]
+  async fn compiler_tier(&self, uri: &Url, context: &str) {
+    match self.shared() {
+      Some(backend) => {
+        let (uri, context) = (uri.clone(), context.to_string());
+        tokio::spawn(async move { backend.run_compiler_tier(&uri, &context).await });
+      }
+      None => self.run_compiler_tier(uri, context).await,
+    }
+  }
+
+  #[cfg(feature = "compiler-diagnostics")]
+  async fn run_compiler_tier(&self, uri: &Url, context: &str) {
+    let file_path = match paths::uri_to_path(uri) {
+      Some(file_path) => file_path,
+      None => return,
+    };
+
+    if self.is_trusted(uri).await {
+      let program = self.cyber_program(uri).await;
+      if self.cyber_available(&program).await {
+        let source = context.to_string();
+        let lane = self.lanes.enter(Lane::Background).await;
+        let compiler = self.compiler.backend();
+        let compile_results = platform::blocking(move || compiler.check(&program, &file_path, &source)).await.flatten();
+        drop(lane);
+        self.publish_tier(uri, Tier::Compiler, compile_results).await;
+      }
+    }
   }
 
+  /// Built without the cyber executable, the tree-sitter tiers are all there is
+  #[cfg(not(feature = "compiler-diagnostics"))]
+  async fn compiler_tier(&self, _uri: &Url, _context: &str) {}
 
   // --| Updated diagnostics ----------
-  pub async fn update_diagnostics(&self) {
+  /// Check every open document again, a few at a time. Documents whose text, imports and
+  /// environment are unchanged since their published diagnostics are skipped, apart from
+  /// the compiler tier when `on_disk` files changed: the compiler reads imports from disk.
+  pub async fn update_diagnostics(&self, on_disk: bool) {
     let urls = self.get_urls().await;
+    let permits = &Semaphore::new(pipeline::UPDATE_CONCURRENCY);
+
+    debug!("Update Diagnostics: {} documents", urls.len());
+    let checks = urls.into_iter().map(|url| async move {
+      let _permit = permits.acquire().await;
+      let content = match self.docs.lock().await.get(&url) {
+        Some(doc) => doc.get_content().to_string(),
+        None => return,
+      };
+
+      let unchanged = self.queries.semantic(&url, Revision::of(&content), &self.index).is_some();
+      if unchanged && self.pipeline.current(&url).is_some() {
+        debug!("Diagnostics up to date: {}", url);
+        if on_disk { self.compiler_tier(&url, &content).await; }
+        return;
+      }
 
-    debug!("Update Diagnostics");
-    let docs = &self.docs.lock().await;   
+      if on_disk {
+        self.obtain_full_diagnostics(url, content).await;
+      } else {
+        self.obtain_analysis_diagnostics(url, content).await;
+      }
+    });
+    join_all(checks).await;
+  }
 
-    for url in urls {
-      let doc = docs.get(&url).unwrap();
-      let context = doc.get_content();
-      self.obtain_full_diagnostics(url.clone(), context.to_string()).await;
+  /// Update the open documents once watched file events stop arriving, so a burst
+  /// such as a branch switch checks them once. A shared backend waits in a task of its own.
+  pub async fn schedule_update_diagnostics(&self) {
+    let generation = self.pipeline.touch_workspace();
+    match self.shared() {
+      Some(backend) => { tokio::spawn(async move { backend.debounced_update_diagnostics(generation).await }); }
+      None => self.debounced_update_diagnostics(generation).await,
     }
   }
 
-  // --| Change Events -------------------------- 
+  /// The update after the debounce, skipped once later events arrived
+  async fn debounced_update_diagnostics(&self, generation: u64) {
+    let mut shutdown = self.shutdown.subscribe();
+    tokio::select! {
+      _ = tokio::time::sleep(pipeline::WORKSPACE_DEBOUNCE) => {},
+      _ = shutdown.recv() => return,
+    }
+    if self.is_shutting_down() || !self.pipeline.is_workspace_current(generation) { return; }
+
+    self.update_diagnostics(true).await;
+  }
+
+  // --| Shutdown -------------------------------
   // --|-----------------------------------------
-  // --| did_open handler -------------
-  pub async fn on_open(&self, params: DidOpenTextDocumentParams) {
-    let start = Instant::now();
+  /// Stop background work and the processes started for the client. tower-lsp
+  /// rejects requests arriving after this, debouncers and the indexer check
+  /// `is_shutting_down` so work already in flight ends early.
+  pub async fn on_shutdown(&self) -> Result<()> {
+    info!("Shutting down");
+    self.shutting_down.store(true, Ordering::SeqCst);
+    let _ = self.shutdown.send(());
+
+    if self.runner.stop().await { info!("Stopped running script"); }
+    self.log_stream.stop();
+    #[cfg(feature = "run-commands")]
+    {
+      let sessions = self.repl.stop_all().await;
+      if sessions > 0 { info!("Stopped {} REPL sessions", sessions); }
+    }
 
-    let docs = &mut self.docs.lock().await; 
+    self.pipeline.clear();
+    self.stats.save();
+    Ok(())
+  }
 
-    let mut parser = self.parser.lock().await;
-    let parse_tree = &mut self.parse_tree.lock().await;
+  pub fn is_shutting_down(&self) -> bool {
+    self.shutting_down.load(Ordering::SeqCst)
+  }
 
-    let document = FullTextDocument::from_params(&params, &mut parser);
-    docs.insert(document.uri.clone(), document.clone());
-    if let Some(tree) = document.tree {
-      parse_tree.insert(document.uri.clone(), tree.clone());
-      debug!("{}", TreeWrapper(tree));
-    } 
-      // debug!("Begin Publishing Diagnostics: {:?}", uri.clone());
-      // self.publish_diagnostics(uri.clone(), content.to_string()).await;
+  // --| Workspace Index ------------------------
+  // --|-----------------------------------------
+  // --| Index workspace folders ------
+  /// Index the workspace folders again and drop the files now ignored. A shared backend does
+  /// it in a task of its own, so the notification asking for it doesn't wait on the
+  /// background lane.
+  pub async fn schedule_reindex(&self) {
+    match self.shared() {
+      Some(backend) => { tokio::spawn(async move { backend.reindex_workspace().await }); }
+      None => self.reindex_workspace().await,
+    }
+  }
 
-      // debug!("Diagnostic Published: {:?}", uri.clone());
-    debug!("File Opened: {}ms", start.elapsed().as_secs_f64());
-    self.client.log_message(MessageType::INFO, format!("file opened: {:?}", document.uri)).await;
-    // }
+  async fn reindex_workspace(&self) {
+    self.index_workspace().await;
+    for uri in self.index.urls().into_iter().filter(|uri| self.is_ignored(uri)) {
+      self.index.remove(&uri);
+    }
   }
 
-  // --| onChange event handler -------
-  pub async fn on_change(&self, params: DidChangeTextDocumentParams) {
-    if params.content_changes.is_empty() { return; }
+  pub async fn index_workspace(&self) {
     let start = Instant::now();
+    let folders: Vec<PathBuf> = self.workspace_map.iter()
+      .filter_map(|entry| paths::uri_to_path(entry.key()))
+      .collect();
+
+    self.begin_activity(Activity::Indexing).await;
+    for folder in folders {
+      if self.is_shutting_down() { break; }
+      self.index_folder(folder).await;
+    }
+    self.end_activity(Activity::Indexing).await;
 
-    if let Some(document) = self.docs.lock().await.get_mut(&params.text_document.uri) {
-      let mut parser = self.parser.lock().await;
-      let mut parse_tree = self.parse_tree.lock().await;
-      let changes: Vec<TextDocumentContentChangeEvent> = params.content_changes.into_iter()
-        .map(|change| {
-          let range = change.range.map(|range| {
-            get_range(
-              range.start.line as u32, range.start.character as u32,
-              range.end.line as u32, range.end.character as u32,
-              )
-          });
-
-          TextDocumentContentChangeEvent {
-            range,
-            range_length: change.range_length.and_then(|v| Some(v as u32)),
-            text: change.text,
-          }
-        }).collect();
+    info!("Indexed {} files in {:?}", self.index.len(), start.elapsed());
+  }
 
-      let version = params.text_document.version;
-      let tree = parse_tree.get_mut(&params.text_document.uri).unwrap();
+  async fn index_folder(&self, folder: PathBuf) {
+    let open = self.get_urls().await;
+    let (patterns, extensions) = match paths::dir_to_uri(&folder) {
+      Some(uri) => {
+        let config = self.config_for(&uri).await;
+        self.ignores.insert(self.workspace_for(&uri), IgnoreRules::load(&folder, &config.ignore));
+        (config.ignore, config.cyber_extensions)
+      }
+      None => (vec![], vec![]),
+    };
 
-      for change in changes {
-        let edits = &get_tree_edits(&change, document, version as i64);
-        if let Some(edits) = edits { tree.edit(edits); }
+    let lane = self.lanes.enter(Lane::Background).await;
+    let scanned = platform::blocking(move || index::scan_folder(&folder, &patterns, &extensions)).await;
+    drop(lane);
+
+    match scanned {
+      Some(indexed) => {
+        self.report_error(None).await;
+        // Open documents are indexed from their buffers instead
+        for (uri, symbols) in indexed.into_iter().filter(|(uri, _)| !open.contains(uri)) {
+          self.index.update(uri, symbols);
+        }
       }
+      None => self.report_error(Some("Workspace indexing failed".to_string())).await,
+    }
+  }
 
-      let level = &self.log_data.lock().await;
-      let new_tree: Tree;
-      let content = document.rope.to_string();
-      let uri = params.text_document.uri.clone();
+  // --| Status -----------------------
+  /// Send `cyberls/status` when the combined status of the subsystems changed
+  pub async fn send_status(&self) {
+    let changed = self.status.changed();
+    if let Some(params) = changed {
+      self.client.send_notification::<status::Status>(params).await;
+    }
+  }
 
-      if level.log_level == LevelFilter::DEBUG {
-        new_tree = parser.parse(&content, Some(tree)).unwrap();
-        let old_tree = parse_tree.insert(uri.clone(), new_tree.clone());
+  pub async fn begin_activity(&self, activity: Activity) {
+    self.status.begin(activity);
+    self.send_status().await;
+  }
 
-        if level.verbose {
-          debug!("{}", TreeWrapper(old_tree.unwrap().clone()));
-          debug!("{}", TreeWrapper(new_tree.clone()));
-        }
+  pub async fn end_activity(&self, activity: Activity) {
+    self.status.end(activity);
+    self.send_status().await;
+  }
 
-        debug!("Verbose: {}", level.verbose);
+  // --| Progress ---------------------
+  /// Start a `$/progress` report. `None` when the client does not show them,
+  /// in which case reporting and ending it does nothing.
+  pub async fn begin_progress(&self, title: &str) -> Option<NumberOrString> {
+    static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
 
-        debug!("Incremental updating: {}ms", start.elapsed().as_secs_f64());
-      } else{
-        new_tree = parser.parse(&content, Some(tree)).unwrap();
-        parse_tree.insert(uri.clone(), new_tree.clone());
-      } 
+    let supported = capabilities::work_done_progress(&self.client_capabilities.read().unwrap_or_else(|err| err.into_inner()));
+    if !supported { return None; }
 
-      if !new_tree.root_node().has_error() {
-        self.publish_diagnostics(params.text_document.uri.clone(), None).await;
-      } else {
-        self.obtain_basic_diagnostics(uri, content , new_tree).await;
-      }
+    let token = NumberOrString::String(format!("cyberls/{}", NEXT_TOKEN.fetch_add(1, Ordering::Relaxed)));
+    let created = self.client.send_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams { token: token.clone() }).await;
+    if let Err(err) = created {
+      debug!("Progress not created: {:?}", err);
+      return None;
     }
+
+    let begin = WorkDoneProgress::Begin(WorkDoneProgressBegin { title: title.to_string(), cancellable: Some(false), message: None, percentage: Some(0) });
+    self.send_progress(&token, begin).await;
+    Some(token)
   }
 
-  // --| didSave handler -------------
-  pub async fn on_save(&self, params: DidSaveTextDocumentParams) {
-    let content = params.text;
-    let uri = params.text_document.uri;
+  pub async fn report_progress(&self, token: &Option<NumberOrString>, message: String, percentage: u32) {
+    if let Some(token) = token {
+      let report = WorkDoneProgress::Report(WorkDoneProgressReport { cancellable: Some(false), message: Some(message), percentage: Some(percentage) });
+      self.send_progress(token, report).await;
+    }
+  }
 
-    if let Some(text) = content {
-      debug!("Begin Publishing Diagnostics: {:?}", uri.clone());
-      self.obtain_full_diagnostics(uri.clone(), text.to_string()).await;
+  pub async fn end_progress(&self, token: Option<NumberOrString>, message: String) {
+    if let Some(token) = token {
+      self.send_progress(&token, WorkDoneProgress::End(WorkDoneProgressEnd { message: Some(message) })).await;
     }
-    else{
-      error!("Failed to get document content: {:?}", uri);
-      self.client.log_message(MessageType::ERROR, format!("Failed to get document content: {:?}", uri)).await;
-      return;
+  }
+
+  async fn send_progress(&self, token: &NumberOrString, progress: WorkDoneProgress) {
+    let params = ProgressParams { token: token.clone(), value: ProgressParamsValue::WorkDone(progress) };
+    self.client.send_notification::<notification::Progress>(params).await;
+  }
+
+  // --| Notifications ----------------
+  /// Show a message to the user, also as a desktop notification when configured and the
+  /// workspace is trusted to start processes
+  pub async fn notify(&self, severity: Severity, message: impl Into<String>) {
+    let message = message.into();
+    let desktop = self.config.lock().await.desktop_notifications;
+    if desktop && self.all_trusted().await {
+      notify::desktop_notification(severity, &message);
     }
+    self.client.show_message(severity.into(), message).await;
+  }
 
-    info!("File Saved: {:?}", uri);
-    self.client.log_message(MessageType::INFO, "file saved!").await;
+  /// Ask the user to pick one of `choices`. `None` when the message was dismissed
+  pub async fn ask(&self, severity: Severity, message: impl Into<String>, choices: &[&str]) -> Option<String> {
+    match self.client.show_message_request(severity.into(), message.into(), Some(notify::actions(choices))).await {
+      Ok(choice) => choice.map(|item| item.title),
+      Err(err) => {
+        warn!("Message request failed: {:?}", err);
+        None
+      }
+    }
   }
 
-  // --| didClose handler ------------
-  pub async fn on_close(&self, params: DidCloseTextDocumentParams) {
-    let uri = params.text_document.uri;
-    let docs = &mut self.docs.lock().await;
-    let parse_tree = &mut self.parse_tree.lock().await;
+  /// Show an error in the status until it is cleared with `None`
+  pub async fn report_error(&self, message: Option<String>) {
+    self.status.set_error(message);
+    self.send_status().await;
+  }
 
-    debug!("Removing Document: {:?}", uri);
-    docs.remove(&uri);
-    parse_tree.remove(&uri);
+  /// Whether a file is excluded by its workspace folder's ignore files or patterns
+  pub fn is_ignored(&self, uri: &Url) -> bool {
+    let path = match paths::uri_to_path(uri) { Some(path) => path, None => return false };
+    self.ignores.get(&self.workspace_for(uri)).map_or(false, |rules| rules.is_ignored(&path))
+  }
 
-    info!("File Closed: {:?}", uri);
-    self.client.log_message(MessageType::INFO, "file closed!").await;
+  // --| Workspace Folders ------------
+  /// Settings of the workspace folder owning the document, falling back to the global settings,
+  /// with those of the folder's project file laid over them
+  pub async fn config_for(&self, uri: &Url) -> Config {
+    let workspace = self.workspace_for(uri);
+    let folder = self.folder_configs.get(&workspace).map(|config| config.value().clone());
+    let config = match folder {
+      Some(config) => config,
+      None => self.config.lock().await.clone(),
+    };
+
+    // Environment overrides win over the project file too
+    match self.project_configs.get(&workspace) {
+      Some(project) => project_config::apply(config, project.value()).with_env_overrides(),
+      None => config,
+    }
   }
 
-  // --| Action Requests ------------------------
-  // --|-----------------------------------------
+  /// Read the `cyberls.toml` or `.cyberls.json` of each workspace folder
+  pub async fn load_project_configs(&self) {
+    let folders: Vec<Url> = self.workspace_map.iter().map(|entry| entry.key().clone()).collect();
+    for folder in folders {
+      match paths::uri_to_path(&folder).and_then(|root| project_config::load(&root)) {
+        Some(settings) => { self.project_configs.insert(folder, settings); }
+        None => { self.project_configs.remove(&folder); }
+      }
+    }
 
-  // --| Completion Handler -----------
-  pub async fn on_completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
-    self.client.log_message(MessageType::INFO, "Completion Requested").await;
-    let location = params.text_document_position.position;
+    self.configure_module_paths().await;
+    self.queries.invalidate();
+  }
 
-    debug!("Completion Requested: {:?}", params);
+  /// Apply edited project files: settings, ignores and module paths may all have changed
+  pub async fn reload_project_configs(&self) {
+    info!("Project configuration changed");
+    self.load_project_configs().await;
+    self.semantic_tokens.clear();
+    self.schedule_reindex().await;
+  }
 
-    if params.context.is_some() {
-      let uri = params.text_document_position.text_document.uri;
+  /// Hand each folder's `module_paths` to the module resolver
+  pub async fn configure_module_paths(&self) {
+    let folders: Vec<Url> = self.workspace_map.iter().map(|entry| entry.key().clone()).collect();
+    for folder in folders {
+      let root = match paths::uri_to_path(&folder) { Some(root) => root, None => continue };
+      let search_paths = self.config_for(&folder).await.module_paths.iter().map(|path| root.join(path)).collect();
+      self.modules.configure_search_paths(&folder, search_paths);
+    }
+  }
 
-      let tmp = &mut self.docs.lock().await;
-      let doc_tmp = tmp.get_mut(&uri).unwrap();
+  /// Request each folder's settings with `workspace/configuration`, scoped to the folder
+  pub async fn load_folder_configs(&self) {
+    let supported = capabilities::workspace_configuration(&self.client_capabilities.read().unwrap_or_else(|err| err.into_inner()));
+    if !supported { return; }
 
-      let doc_data = doc_tmp.get_content();
-      if doc_data.len() == 0 { debug!("Completion: No document found"); return Ok(None); }
+    let folders: Vec<Url> = self.workspace_map.iter().map(|entry| entry.key().clone()).collect();
+    let items = folders.iter().map(|folder| ConfigurationItem {
+      scope_uri: Some(folder.clone()),
+      section: Some("cyberls".to_string()),
+    }).collect();
 
-      debug!("Context is Some() requesting getcomplete({:?}, {:?}, {:?})", &self.client, location, uri.path());
+    let values = match self.client.configuration(items).await {
+      Ok(values) => values,
+      Err(err) => { error!("Failed to load folder configuration: {:?}", err); return; }
+    };
 
-      match Some(doc_data) {
-        Some(context) => Ok(completions::get_completion(context, location, &self.client, uri.path()).await),
-        None => { debug!("No document? Content was None"); Ok(None) }
+    for (folder, value) in folders.into_iter().zip(values) {
+      match serde_json::from_value::<Config>(value) {
+        Ok(config) => { self.folder_configs.insert(folder, config.with_env_overrides()); }
+        Err(_) => { self.folder_configs.remove(&folder); }
       }
-    } else {
-      debug!("No document? Content was None");
-      Ok(None)
     }
+    self.configure_module_paths().await;
+    self.queries.invalidate();
   }
 
-  // --| Hover Handler ----------------
-  pub async fn on_hover(&self, params: HoverParams) -> Result<Option<Hover>> {
-    debug!("Hover Requested: {:?}", &params);
+  pub async fn on_workspace_folders_change(&self, params: DidChangeWorkspaceFoldersParams) {
+    for folder in params.event.removed {
+      info!("Workspace removed: {} {}", folder.uri, folder.name);
+      self.workspace_map.remove(&folder.uri);
+      self.folder_configs.remove(&folder.uri);
+      self.project_configs.remove(&folder.uri);
+      self.ignores.remove(&folder.uri);
+      self.index.remove_scope(&folder.uri);
+    }
 
-    let position = params.text_document_position_params.position;
-    let uri = params.text_document_position_params.text_document.uri;
+    let added: Vec<Url> = params.event.added.iter().map(|folder| folder.uri.clone()).collect();
 
-    let tmp = &mut self.docs.lock().await;
-    let doc_tmp = tmp.get_mut(&uri).unwrap();
+    // Documents may now belong to a different folder
+    self.modules.invalidate(None);
+    self.queries.invalidate();
 
-    let doc_data = doc_tmp.get_content();
+    for folder in params.event.added {
+      info!("Workspace added: {} {}", folder.uri, folder.name);
+      self.workspace_map.insert(folder.uri.clone(), folder.name);
+    }
 
-    self.client.log_message(MessageType::INFO, "Hovered!").await;
+    // Project files decide what the new folders' indexing ignores
+    self.load_project_configs().await;
+    self.load_folder_configs().await;
+    self.load_lints().await;
 
-    if doc_data.len() == 0 {
-      info!("Hover: No document found");
-      return Ok(None);
+    let folders: Vec<PathBuf> = added.iter().filter_map(paths::uri_to_path).collect();
+    match self.shared() {
+      Some(backend) => { tokio::spawn(async move { backend.index_added_folders(folders).await }); }
+      None => self.index_added_folders(folders).await,
     }
+  }
 
-    else if doc_data.lines().count() > 1000 {
-      info!("Hover: Document too large");
-      return Ok(None);
+  /// Index folders added to the workspace, then check the open documents against them
+  async fn index_added_folders(&self, folders: Vec<PathBuf>) {
+    for folder in folders {
+      if self.is_shutting_down() { break; }
+      self.index_folder(folder).await;
     }
+    self.update_diagnostics(false).await;
+  }
 
-    match Some(doc_data) {
-      Some(context) => {
-        let mut parser = self.parser.lock().await; 
-        debug!("Hover: Parser Loaded");
+  // --| Resolve call target ----------
+  /// Find the function a call site refers to, following import aliases into other modules
+  pub fn resolve_call(&self, uri: &Url, symbols: &DocumentSymbols, imports: &[ImportStatement], call: &CallSite) -> Option<Symbol> {
+    let receiver = match &call.receiver {
+      Some(receiver) => receiver,
+      None => return symbols.function(&call.callee).cloned(),
+    };
+
+    let path = self.import_path(uri, imports, receiver)?;
+    self.index.load_file(&path)?.function(&call.callee).cloned()
+  }
+
+  /// The function a call site refers to and the file declaring it
+  pub fn resolve_call_location(&self, uri: &Url, symbols: &DocumentSymbols, imports: &[ImportStatement], call: &CallSite) -> Option<(Url, Symbol)> {
+    let file = match &call.receiver {
+      Some(receiver) => paths::path_to_uri(&self.import_path(uri, imports, receiver)?)?,
+      None => uri.clone(),
+    };
+    Some((file, self.resolve_call(uri, symbols, imports, call)?))
+  }
+
+  /// Module file imported under `alias`
+  fn import_path(&self, uri: &Url, imports: &[ImportStatement], alias: &str) -> Option<PathBuf> {
+    let import = imports.iter().find(|import| import.alias == alias)?;
+    let workspace = self.workspace_for(uri);
+    let base_dir = self.modules.base_dir(uri);
+
+    let resolved = self.modules.resolve(&workspace, base_dir.as_deref(), &import.specifier);
+    self.modules.source_path(&resolved)
+  }
+
+  /// Imports of a document, from its buffer when open or else from disk
+  async fn imports_of(&self, uri: &Url) -> Vec<ImportStatement> {
+    let open = self.docs.lock().await.get(uri).map(|doc| doc.get_content().to_string());
+    let source = match open.or_else(|| paths::uri_to_path(uri).and_then(|path| std::fs::read_to_string(path).ok())) {
+      Some(source) => source,
+      None => return vec![],
+    };
+
+    match self.parser.lock().await.parse(&source, None) {
+      Some(tree) => modules::collect_imports(tree.root_node(), &source),
+      None => vec![],
+    }
+  }
+
+  // --| Change Events -------------------------- 
+  // --|-----------------------------------------
+  // --| did_open handler -------------
+  pub async fn on_open(&self, params: DidOpenTextDocumentParams) {
+    let start = Instant::now();
+    let uri = params.text_document.uri.clone();
+    let version: i64 = params.text_document.version.into();
+
+    // Editors reopen documents when reloading them, only a changed text is parsed again
+    let mut generation = 0;
+    if let Some(doc) = self.docs.lock().await.get_mut(&uri) {
+      if version < doc.version {
+        warn!("Document reopened with older version {} < {}: {}", version, doc.version, uri);
+      }
+
+      if doc.rope == params.text_document.text.as_str() {
+        debug!("Document reopened unchanged: {}", uri);
+        doc.version = version;
+        return;
+      }
+      generation = doc.generation + 1;
+    }
+
+    if generation > 0 {
+      debug!("Document reloaded, generation {}: {}", generation, uri);
+      self.pipeline.touch(&uri);
+    }
+
+    self.semantic_tokens.remove(&uri);
+    self.completions.forget(&uri);
+    let config = self.config_for(&uri).await;
+    let limits = config.limits;
+    let cyber = index::is_cyber_document(&params.text_document.language_id, &uri, &config.cyber_extensions);
+    let mut docs = self.docs.lock().await;
+
+    let mut parser = self.parser.lock().await;
+    let mut parse_tree = self.parse_tree.lock().await;
+
+    let mut document = if !cyber {
+      info!("Not a Cyber document ({}), tracking it as plain text: {}", params.text_document.language_id, uri);
+      let item = &params.text_document;
+      let mut document = FullTextDocument::new(item.uri.clone(), item.language_id.clone(), version, item.text.clone());
+      document.plain_text = true;
+      document
+    } else if limits.allows_parse(params.text_document.text.len()) {
+      FullTextDocument::from_params(&params, &mut parser)
+    } else {
+      info!("Document too large to parse, syntax features disabled: {}", uri);
+      let item = &params.text_document;
+      FullTextDocument::new(item.uri.clone(), item.language_id.clone(), version, item.text.clone())
+    };
+    document.generation = generation;
+
+    docs.insert(document.uri.clone(), document.clone());
+    match document.tree {
+      Some(tree) => {
+        parse_tree.insert(document.uri.clone(), tree.clone());
+        self.index.update(document.uri.clone(), (*self.queries.symbols(&uri, &document.text, &tree)).clone());
+        debug!("{}", TreeWrapper(tree));
+      }
+      // A reload past the parse limit must not keep the tree of the old content
+      None => {
+        parse_tree.remove(&uri);
+        self.index.remove(&uri);
+      }
+    }
+
+    // Reclaiming locks the documents again
+    drop(parse_tree);
+    drop(parser);
+    drop(docs);
+    self.idle.touch(&uri);
+    self.reclaim_idle_documents().await;
+
+    debug!("File Opened: {}ms", start.elapsed().as_secs_f64());
+    self.client.log_message(MessageType::INFO, format!("file opened: {:?}", document.uri)).await;
+  }
+
+  // --| onChange event handler -------
+  pub async fn on_change(&self, params: DidChangeTextDocumentParams) {
+    if params.content_changes.is_empty() { return; }
+    self.clear_runtime_diagnostics().await;
+    self.semantic_tokens.remove(&params.text_document.uri);
+    let uri = params.text_document.uri.clone();
+    self.idle.touch(&uri);
+    self.reclaim_idle_documents().await;
+
+    // Diagnostics are published once the document lock is released
+    let (content, tree) = match self.apply_changes(params).await {
+      Some(updated) => updated,
+      None => return,
+    };
+
+    match tree {
+      Some(tree) => self.obtain_basic_diagnostics(uri.clone(), content, tree).await,
+      None => {
+        self.pipeline.remove(&uri);
+        self.publish_diagnostics(uri, None).await;
+        return;
+      }
+    }
+
+    self.schedule_semantic_diagnostics(uri).await;
+  }
+
+  /// Apply the changes to the document and re-parse it. Returns the new content with
+  /// its tree, which is `None` when the document grew past the parse limit.
+  async fn apply_changes(&self, params: DidChangeTextDocumentParams) -> Option<(String, Option<Tree>)> {
+    let start = Instant::now();
+    let limits = self.config_for(&params.text_document.uri).await.limits;
+
+    let mut docs = self.docs.lock().await;
+    let document = docs.get_mut(&params.text_document.uri)?;
+    let mut parser = self.parser.lock().await;
+    let mut parse_tree = self.parse_tree.lock().await;
+    let changes: Vec<TextDocumentContentChangeEvent> = params.content_changes.into_iter()
+      .map(|change| {
+        let range = change.range.map(|range| {
+          get_range(
+            range.start.line as u32, range.start.character as u32,
+            range.end.line as u32, range.end.character as u32,
+            )
+        });
+
+        TextDocumentContentChangeEvent {
+          range,
+          range_length: change.range_length.and_then(|v| Some(v as u32)),
+          text: change.text,
+        }
+      }).collect();
+
+    let version = params.text_document.version;
+    let mut tree = parse_tree.get(&params.text_document.uri).cloned();
+
+    for change in changes {
+      let edits = &get_tree_edits(&change, document, version as i64);
+      if let Some(edits) = edits { self.completions.edit(&params.text_document.uri, edits); }
+      if let (Some(edits), Some(tree)) = (edits, tree.as_mut()) { tree.edit(edits); }
+    }
+
+    let level = &self.log_data.lock().await;
+    let new_tree: Tree;
+    let content = document.rope.to_string();
+    let uri = params.text_document.uri.clone();
+    if document.plain_text { return Some((content, None)); }
+
+    // Documents grown past the parse limit keep only their text
+    if !limits.allows_parse(content.len()) {
+      if parse_tree.remove(&uri).is_some() {
+        info!("Document too large to parse, syntax features disabled: {}", uri);
+      }
+      self.index.remove(&uri);
+      return Some((content, None));
+    }
+
+    if level.log_level == LevelFilter::DEBUG {
+      new_tree = parser.parse(&content, tree.as_ref()).unwrap();
+      let old_tree = parse_tree.insert(uri.clone(), new_tree.clone());
+
+      if level.verbose {
+        if let Some(old_tree) = old_tree { debug!("{}", TreeWrapper(old_tree)); }
+        debug!("{}", TreeWrapper(new_tree.clone()));
+      }
+
+      debug!("Verbose: {}", level.verbose);
+
+      debug!("Incremental updating: {}ms", start.elapsed().as_secs_f64());
+    } else{
+      new_tree = parser.parse(&content, tree.as_ref()).unwrap();
+      parse_tree.insert(uri.clone(), new_tree.clone());
+    } 
+
+    self.index.update(uri.clone(), (*self.queries.symbols(&uri, &content, &new_tree)).clone());
+    Some((content, Some(new_tree)))
+  }
+
+  // --| didSave handler -------------
+  pub async fn on_save(&self, params: DidSaveTextDocumentParams) {
+    let content = params.text;
+    let uri = params.text_document.uri;
+
+    if let Some(text) = content {
+      // Saving without edits, or undoing back to the checked text, needs no compiler run
+      let revision = Revision::of(&text);
+      if self.queries.is_checked(&uri, revision) && self.pipeline.current(&uri).is_some() {
+        debug!("Saved text unchanged since the last check, skipping diagnostics: {}", uri);
+        return;
+      }
+
+      debug!("Begin Publishing Diagnostics: {:?}", uri.clone());
+      let environment = self.queries.environment();
+      self.pipeline.touch(&uri);
+      self.obtain_full_diagnostics(uri.clone(), text.to_string()).await;
+      self.queries.set_checked(&uri, revision, environment);
+    }
+    else{
+      error!("Failed to get document content: {:?}", uri);
+      self.client.log_message(MessageType::ERROR, format!("Failed to get document content: {:?}", uri)).await;
+      return;
+    }
+
+    info!("File Saved: {:?}", uri);
+    self.client.log_message(MessageType::INFO, "file saved!").await;
+  }
+
+  // --| didClose handler ------------
+  pub async fn on_close(&self, params: DidCloseTextDocumentParams) {
+    let uri = params.text_document.uri;
+
+    debug!("Removing Document: {:?}", uri);
+    self.docs.lock().await.remove(&uri);
+    self.parse_tree.lock().await.remove(&uri);
+    self.semantic_tokens.remove(&uri);
+    self.queries.forget(&uri);
+    self.completions.forget(&uri);
+    self.idle.forget(&uri);
+    self.clear_diagnostics(&uri).await;
+
+    info!("File Closed: {:?}", uri);
+    self.client.log_message(MessageType::INFO, "file closed!").await;
+  }
+
+  // --| Action Requests ------------------------
+  // --|-----------------------------------------
+
+  // --| Completion Handler -----------
+  pub async fn on_completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+    let uri = params.text_document_position.text_document.uri.clone();
+    let response = self.completion_response(params).await?;
+    let (snippets, commit_characters) = {
+      let capabilities = self.client_capabilities.read().unwrap_or_else(|err| err.into_inner());
+      (capabilities::snippets(&capabilities), capabilities::commit_characters(&capabilities))
+    };
+    let counted = self.stats.is_enabled();
+    if !snippets && !commit_characters && !counted { return Ok(response); }
+
+    let mut items = match response {
+      Some(CompletionResponse::Array(items)) => items,
+      Some(CompletionResponse::List(list)) => list.items,
+      None => return Ok(None),
+    };
+    if commit_characters { items = completions::with_commit_characters(items); }
+    if counted { items = stats::with_accept_command(items); }
+    if !snippets { return Ok(Some(CompletionResponse::Array(items))); }
+
+    let document = self.index.get(&uri);
+    let workspace = self.workspace_for(&uri);
+    let items = signature::with_call_snippets(items, |name| {
+      document.as_ref().and_then(|symbols| symbols.function(name).cloned())
+        .or_else(|| self.index.find_functions(name, &workspace).into_iter().next().map(|(_, function)| function))
+    });
+
+    Ok(Some(CompletionResponse::Array(items)))
+  }
+
+  async fn completion_response(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+    self.client.log_message(MessageType::INFO, "Completion Requested").await;
+    let location = params.text_document_position.position;
+
+    if params.context.is_some() {
+      let uri = params.text_document_position.text_document.uri;
+
+      // Copy what is needed out of the document, the completion sources lock the documents themselves
+      let (content, prefix, location, tree) = {
+        let docs = self.docs.lock().await;
+        let doc = match docs.get(&uri) { Some(doc) => doc, None => return Ok(None) };
+        let location = location.to_doc(&doc.rope).byte_position();
+        (doc.content(None).into_owned(), doc.line_prefix(params.text_document_position.position).into_owned(), location, doc.tree.clone())
+      };
+      let doc_data: &str = &content;
+      if doc_data.is_empty() { debug!("Completion: No document found"); return Ok(None); }
+
+      // The line before the cursor decides which source answers, the tree is only walked for them
+      let trigger = params.context.as_ref().and_then(|context| context.trigger_character.as_deref());
+      match completions::line_context(&prefix) {
+        LineContext::Import => {
+          return Ok(self.import_completions(&uri, location, doc_data).await.map(CompletionResponse::Array));
+        }
+        LineContext::Literal => return Ok(None),
+        LineContext::TypeAnnotation if trigger == Some(":") => {
+          return Ok(self.type_completions(&uri).map(CompletionResponse::Array));
+        }
+        LineContext::Member(receiver) => {
+          let items = match self.module_member_completions(&uri, receiver, doc_data).await {
+            Some(items) => Some(items),
+            None => self.typed_member_completions(&uri, receiver, doc_data).await,
+          };
+          return Ok(items.map(CompletionResponse::Array));
+        }
+        _ => {}
+      }
+
+      // `:` and `'` only trigger in annotations and import paths, elsewhere they end a block header or open a string
+      if matches!(trigger, Some(":") | Some("'")) { return Ok(None); }
+
+      // Untitled buffers keep their name as the path
+      let local_path = paths::document_path(&uri);
+      debug!("Context is Some() requesting getcomplete({:?}, {:?}, {:?})", &self.client, location, local_path);
+
+      let workspace_items = self.workspace_symbol_completions(&uri, params.text_document_position.position, &prefix, doc_data).await;
+
+      match Some(doc_data) {
+        Some(context) => {
+          let tree = match tree { Some(tree) => Some(tree), None => self.tree_for(&uri).await };
+          let response = completions::get_completion(context, tree.as_ref(), location, &self.completions, &uri, &self.client, &local_path.to_string_lossy()).await;
+          if workspace_items.is_empty() { return Ok(response); }
+
+          let mut items = match response {
+            Some(CompletionResponse::Array(items)) => items,
+            Some(CompletionResponse::List(list)) => list.items,
+            None => vec![],
+          };
+          items.extend(workspace_items);
+          Ok(Some(CompletionResponse::Array(items)))
+        }
+        None => { debug!("No document? Content was None"); Ok(None) }
+      }
+    } else {
+      debug!("No document? Content was None");
+      Ok(None)
+    }
+  }
+
+  // --| Type Completion -------------
+  /// Builtin types and known objects, when the cursor follows `name:` in a declaration or parameter
+  fn type_completions(&self, uri: &Url) -> Option<Vec<CompletionItem>> {
+    let mut objects: Vec<String> = self.index.top_level(&self.workspace_for(uri), |_, kind| kind == SymbolKind::OBJECT).into_iter()
+      .map(|(_, symbol)| symbol.name)
+      .collect();
+    objects.sort();
+    objects.dedup();
+
+    Some(completions::type_completions(&objects))
+  }
+
+  // --| Import Completion -----------
+  /// Completes module specifiers when the cursor is inside an import string
+  async fn import_completions(&self, uri: &Url, location: Position, source: &str) -> Option<Vec<CompletionItem>> {
+    let tree = self.tree_for(uri).await?;
+    let imports = modules::collect_imports(tree.root_node(), source);
+
+    let import = imports.iter().find(|import| {
+      import.specifier_range.map_or(false, |range| range_contains(&range, location))
+    })?;
+
+    let range = import.specifier_range?;
+    let typed = location.character.saturating_sub(range.start.character + 1) as usize;
+    let partial: String = import.specifier.chars().take(typed).collect();
+
+    let base_dir = self.modules.base_dir(uri);
+    Some(modules::specifier_completions(base_dir.as_deref(), &partial))
+  }
+
+  // --| Module Member Completion ----
+  /// Completes the exports of an imported module after `alias.`
+  async fn module_member_completions(&self, uri: &Url, alias: &str, source: &str) -> Option<Vec<CompletionItem>> {
+    let tree = self.tree_for(uri).await?;
+    let imports = modules::collect_imports(tree.root_node(), source);
+    let import = imports.iter().find(|import| import.alias == alias)?;
+
+    let workspace = self.workspace_for(uri);
+    let base_dir = self.modules.base_dir(uri);
+    let resolved = self.modules.resolve(&workspace, base_dir.as_deref(), &import.specifier);
+    let path = self.modules.source_path(&resolved)?;
+
+    let items = modules::module_exports(&path);
+    if items.is_empty() { None } else { Some(items) }
+  }
+
+  // --| Typed Member Completion -----
+  /// Completes methods after `value.` when the type of `value` can be inferred
+  async fn typed_member_completions(&self, uri: &Url, receiver: &str, source: &str) -> Option<Vec<CompletionItem>> {
+    let tree = self.tree_for(uri).await?;
+    let mut env = infer::infer_document(tree.root_node(), source);
+
+    // The declaration of the receiver may be in a region that doesn't parse while typing
+    if env.lookup(receiver).is_none() && tree.root_node().has_error() {
+      let (valid_tree, valid_source) = self.queries.last_valid(uri)?;
+      env = infer::infer_document(valid_tree.root_node(), &valid_source);
+    }
+
+    let items = infer::member_completions(env.lookup(receiver)?);
+    if items.is_empty() { None } else { Some(items) }
+  }
+
+  // --| Workspace Symbol Completion --
+  /// Completes top level symbols of other workspace files, importing their module on accept
+  async fn workspace_symbol_completions(&self, uri: &Url, location: Position, before: &str, source: &str) -> Vec<CompletionItem> {
+    let partial: String = before.chars().rev().take_while(|ch| ch.is_alphanumeric() || *ch == '_').collect::<Vec<_>>().into_iter().rev().collect();
+
+    // Members are completed by the module and type aware completions
+    if partial.is_empty() || before[..before.len() - partial.len()].ends_with('.') { return vec![]; }
+
+    let imports = match self.tree_for(uri).await {
+      Some(tree) => modules::collect_imports(tree.root_node(), source),
+      None => return vec![],
+    };
+
+    let start = Position::new(location.line, location.character.saturating_sub(partial.encode_utf16().count() as u32));
+    let replace = Range::new(start, location);
+    let mut items = vec![];
+
+    let exported = self.index.top_level(&self.workspace_for(uri), |name, kind| {
+      name.starts_with(&partial) && matches!(kind, SymbolKind::FUNCTION | SymbolKind::OBJECT | SymbolKind::VARIABLE)
+    });
+
+    for (file, symbol) in exported {
+      if &file == uri { continue; }
+      let target = match paths::uri_to_path(&file) { Some(target) => target, None => continue };
+
+      if let Some(plan) = self.import_plan(uri, &imports, &target) {
+        items.push(auto_import::completion_item(&symbol, &plan, &imports, replace));
+      }
+    }
+
+    items
+  }
+
+  /// Apply settings to the parts of the server holding on to them, before they replace the configuration
+  pub async fn configure(&self, config: &Config) {
+    self.log_data.lock().await.verbose = config.verbose;
+    self.stats.set_enabled(config.performance_metrics);
+    self.lanes.configure(&config.lanes);
+    #[cfg(feature = "compiler-diagnostics")]
+    self.compiler.configure(config.compiler_mode);
+    self.configure_modules(config);
+    self.configure_spellcheck(config).await;
+  }
+
+  // --| Spell Checking ---------------
+  /// Load or drop the spell checker according to the configuration. Folders enabling
+  /// it on their own share the checker, documents elsewhere skip it.
+  pub async fn configure_spellcheck(&self, config: &Config) {
+    let settings = if config.spellcheck.enable {
+      Some(config.spellcheck.clone())
+    } else {
+      self.folder_configs.iter().map(|entry| entry.value().spellcheck.clone()).find(|settings| settings.enable)
+    };
+
+    let checker = settings.map(|settings| {
+      let roots: Vec<PathBuf> = self.workspace_map.iter()
+        .filter_map(|entry| paths::uri_to_path(entry.key()))
+        .collect();
+      SpellChecker::load(&settings, &roots)
+    });
+
+    *self.spellchecker.lock().await = checker;
+    self.queries.invalidate();
+  }
+
+  // --| Custom Lints -----------------
+  /// Load the `.cyberls/lints` rules of every workspace folder, warning about rules that fail to load
+  pub async fn load_lints(&self) {
+    let folders: Vec<Url> = self.workspace_map.iter().map(|entry| entry.key().clone()).collect();
+    let mut failed = vec![];
+
+    self.lints.clear();
+    for folder in folders {
+      let root = match paths::uri_to_path(&folder) { Some(root) => root, None => continue };
+      let (loaded, errors) = lints::load_lints(&root);
+      failed.extend(errors);
+      if !loaded.is_empty() { self.lints.insert(folder, loaded); }
+    }
+    self.queries.invalidate();
+
+    if !failed.is_empty() {
+      self.notify(Severity::Warning, format!("Failed to load lint rules: {}", failed.join("; "))).await;
+    }
+  }
+
+  // --| Remote Modules ---------------
+  /// Apply the remote import settings to the module resolver
+  pub fn configure_modules(&self, config: &Config) {
+    let remote = config.remote_imports.then(|| {
+      RemoteCache::new(config.cache_dir.as_ref().map(PathBuf::from), config.offline).with_pinned(config.remote_hashes.clone())
+    });
+
+    self.modules.configure_remote(remote);
+    self.modules.configure_virtual_root(config.virtual_root.as_ref().map(PathBuf::from));
+    self.modules.invalidate(None);
+  }
+
+  /// Download URL imports of a document into the remote cache so they can be analyzed
+  async fn fetch_remote_imports(&self, uri: &Url, imports: &[ImportStatement]) {
+    let remote = match self.modules.remote() {
+      Some(remote) => remote,
+      None => return,
+    };
+
+    // Opening an untrusted folder must not reach out to the URLs it names
+    if !self.is_trusted(uri).await {
+      debug!("Untrusted workspace, not fetching remote imports of {}", uri);
+      return;
+    }
+
+    let workspace = self.workspace_for(uri);
+    let base_dir = self.modules.base_dir(uri);
+
+    for import in imports {
+      let url = match self.modules.resolve(&workspace, base_dir.as_deref(), &import.specifier) {
+        ResolvedModule::Remote(url) => url,
+        _ => continue,
+      };
+
+      if remote.offline || remote.cached(&url).is_some() { continue; }
+
+      let remote = remote.clone();
+      match platform::blocking(move || remote.fetch(&url)).await {
+        Some(Ok(path)) => debug!("Remote module cached: {:?}", path),
+        Some(Err(err)) => {
+          error!("Failed to fetch remote module: {:#}", err);
+          self.client.log_message(MessageType::WARNING, format!("{:#}", err)).await;
+        }
+        None => {}
+      }
+    }
+  }
+
+  // --| Module Hover -----------------
+  fn module_hover(&self, uri: &Url, import: &ImportStatement, position: Position) -> Hover {
+    let workspace = self.workspace_for(uri);
+    let base_dir = self.modules.base_dir(uri);
+
+    let target = match self.modules.resolve(&workspace, base_dir.as_deref(), &import.specifier) {
+      ResolvedModule::Builtin(name) => format!("builtin module `{}`", name),
+      ResolvedModule::File(path) => format!("`{}`", path.display()),
+      ResolvedModule::Remote(url) => match self.modules.remote().and_then(|remote| remote.cached(&url)) {
+        Some(path) => format!("{}\n\ncached at `{}`", url, path.display()),
+        None => format!("{} (not cached)", url),
+      },
+      ResolvedModule::Unresolved(spec) => format!("unresolved module '{}'", spec),
+    };
+
+    Hover {
+      contents: HoverContents::Markup(self.markup(format!("```cyber\nimport {} '{}'\n```\n---\n{}", import.alias, import.specifier, target))),
+      range: Some(Range { start: position, end: position }),
+    }
+  }
+
+  /// Declaration of `name` with a doc comment, from the document itself or else a top level one of its workspace
+  fn documented_symbol(&self, uri: &Url, root: Node, source: &str, name: &str) -> Option<Symbol> {
+    let documented = |symbol: &Symbol| symbol.name == name && symbol.doc.is_some();
+
+    collect_symbols(root, source).symbols.into_iter().find(|symbol| documented(symbol)).or_else(|| {
+      self.index.top_level(&self.workspace_for(uri), |symbol, _| symbol == name).into_iter()
+        .find(|(file, symbol)| file != uri && documented(symbol))
+        .map(|(_, symbol)| symbol)
+    })
+  }
+
+  // --| Definition Handler -----------
+  pub async fn on_definition(&self, params: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>> {
+    let position = params.text_document_position_params.position;
+    let uri = params.text_document_position_params.text_document.uri;
+
+    let (source, rope) = match self.docs.lock().await.get(&uri) {
+      Some(doc) => (doc.get_content().to_string(), doc.rope.clone()),
+      None => return Ok(None),
+    };
+    let position = byte_position(&rope, position);
+
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let imports = modules::collect_imports(tree.root_node(), &source);
+    let word = get_string_at_pos(position, tree.root_node(), &source);
+
+    let import = match modules::import_at(&imports, position, word.as_deref()) {
+      Some(import) => import,
+      None => return Ok(word.and_then(|word| self.builtin_definition(&uri, &source, &tree, &word))),
+    };
+
+    let workspace = self.workspace_for(&uri);
+    let base_dir = self.modules.base_dir(&uri);
+
+    let resolved = self.modules.resolve(&workspace, base_dir.as_deref(), &import.specifier);
+
+    match self.modules.source_path(&resolved) {
+      Some(path) => {
+        let target = match paths::path_to_uri(&path) {
+          Some(target) => target,
+          None => return Ok(None),
+        };
+
+        Ok(Some(GotoDefinitionResponse::Scalar(Location::new(target, Range::default()))))
+      }
+      None => {
+        debug!("Definition: module has no source file: {:?}", resolved);
+        Ok(None)
+      }
+    }
+  }
+
+  /// Builtins have no source file, their definition is the virtual document of their documentation
+  fn builtin_definition(&self, uri: &Url, source: &str, tree: &Tree, word: &str) -> Option<GotoDefinitionResponse> {
+    let symbols = self.queries.symbols(uri, source, tree);
+    if symbols.symbols.iter().any(|symbol| symbol.name == word) { return None; }
+
+    virtual_docs::lookup(word)?;
+    let target = virtual_docs::builtin_uri(word)?;
+    Some(GotoDefinitionResponse::Scalar(Location::new(target, Range::default())))
+  }
+
+  // --| Virtual Content Request ------
+  /// Rendered documentation of the `cyber-doc` documents returned by go to definition
+  pub async fn on_virtual_content(&self, params: VirtualContentParams) -> Result<Option<VirtualContentResult>> {
+    let detail = virtual_docs::builtin_name(&params.uri).and_then(virtual_docs::lookup);
+    Ok(detail.map(|detail| VirtualContentResult { content: virtual_docs::render(detail) }))
+  }
+
+  // --| Declaring Module -------------
+  /// Files of the workspace that can refer to the top level `name` seen in `uri`: the file
+  /// declaring it, being `uri` or a module `uri` imports, and the files importing that one.
+  /// Files only using an unrelated declaration of the same name are left out.
+  fn files_sharing_declaration(&self, uri: &Url, symbols: &DocumentSymbols, imports: &[ImportStatement], name: &str) -> Vec<Url> {
+    let workspace = self.workspace_for(uri);
+    let declares = |symbols: &DocumentSymbols| symbols.symbols.iter().any(|symbol| symbol.name == name && symbol.container.is_none());
+
+    let declaring = if declares(symbols) {
+      paths::uri_to_path(uri)
+    } else {
+      let base_dir = self.modules.base_dir(uri);
+      imports.iter()
+        .filter_map(|import| self.modules.source_path(&self.modules.resolve(&workspace, base_dir.as_deref(), &import.specifier)))
+        .find(|path| paths::path_to_uri(path).and_then(|url| self.index.get(&url)).map_or(false, |symbols| declares(&symbols)))
+    };
+
+    let declaring = match declaring {
+      Some(declaring) => declaring,
+      None => return vec![],
+    };
+
+    let files = self.index.name(name).map_or_else(Vec::new, |name| self.index.files_mentioning(name, &workspace));
+    files.into_iter()
+      .filter(|file| match paths::uri_to_path(file) {
+        Some(path) => path == declaring || self.modules.imports_file(&workspace, &path, &declaring),
+        None => false,
+      })
+      .collect()
+  }
+
+  // --| Rename Handlers --------------
+  pub async fn on_prepare_rename(&self, params: TextDocumentPositionParams) -> Result<Option<PrepareRenameResponse>> {
+    let uri = params.text_document.uri;
+
+    let (source, rope) = match self.docs.lock().await.get(&uri) {
+      Some(doc) => (doc.get_content().to_string(), doc.rope.clone()),
+      None => return Ok(None),
+    };
+
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let imports = modules::collect_imports(tree.root_node(), &source);
+    Ok(identifier_at(tree.root_node(), &source, byte_position(&rope, params.position))
+      .filter(|(_, range)| !imports.iter().any(|import| import.specifier_range.map_or(false, |spec| range_contains(&spec, range.start))))
+      .map(|(_, range)| PrepareRenameResponse::Range(lsp_range(&rope, range))))
+  }
+
+  pub async fn on_rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+    let position = params.text_document_position.position;
+    let uri = params.text_document_position.text_document.uri;
+
+    if !rename::is_valid_identifier(&params.new_name) {
+      return Err(tower_lsp::jsonrpc::Error::invalid_params(format!("`{}` is not a valid identifier", params.new_name)));
+    }
+
+    let (source, rope) = match self.docs.lock().await.get(&uri) {
+      Some(doc) => (doc.get_content().to_string(), doc.rope.clone()),
+      None => return Ok(None),
+    };
+    let position = byte_position(&rope, position);
+
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let name = match identifier_at(tree.root_node(), &source, position) {
+      Some((name, _)) => name,
+      None => return Ok(None),
+    };
+
+    let symbols = self.queries.symbols(&uri, &source, &tree);
+    let target = RenameTarget::at(&symbols, &name, position);
+
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), rename::rename_edits(&symbols, &rope, &target, &params.new_name, true));
+
+    if target.is_workspace_wide() {
+      // Only files sharing the declaration are built from the index
+      let imports = modules::collect_imports(tree.root_node(), &source);
+      let files = self.files_sharing_declaration(&uri, &symbols, &imports, &name);
+
+      for file in files {
+        if file == uri { continue; }
+        let file_symbols = match self.index.get(&file) { Some(symbols) => symbols, None => continue };
+        let file_rope = match self.rope_of(&file).await { Some(rope) => rope, None => continue };
+
+        let edits = rename::rename_edits(&file_symbols, &file_rope, &target, &params.new_name, false);
+        if !edits.is_empty() { changes.insert(file, edits); }
+      }
+    }
+
+    Ok(Some(WorkspaceEdit { changes: Some(changes), ..Default::default() }))
+  }
+
+  // --| References Handler ----------
+  /// Uses of the identifier at the position. Workspace wide names are streamed a page
+  /// of locations at a time when the client sends a partial result token.
+  pub async fn on_references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+    let position = params.text_document_position.position;
+    let uri = params.text_document_position.text_document.uri;
+    let include_declaration = params.context.include_declaration;
+
+    let (source, rope) = match self.docs.lock().await.get(&uri) {
+      Some(doc) => (doc.get_content().to_string(), doc.rope.clone()),
+      None => return Ok(None),
+    };
+    let position = byte_position(&rope, position);
+
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let name = match identifier_at(tree.root_node(), &source, position) {
+      Some((name, _)) => name,
+      None => return Ok(None),
+    };
+
+    let symbols = self.queries.symbols(&uri, &source, &tree);
+    let target = RenameTarget::at(&symbols, &name, position);
+    let locations = |file: &Url, rope: &Rope, symbols: &DocumentSymbols, origin: bool| -> Vec<Location> {
+      rename::occurrences(symbols, &target, origin).into_iter()
+        .filter(|range| include_declaration || !rename::is_declaration(symbols, *range))
+        .map(|range| Location::new(file.clone(), lsp_range(rope, range)))
+        .collect()
+    };
+
+    let mut pager = Pager::new(params.partial_result_params.partial_result_token);
+    pager.extend(locations(&uri, &rope, symbols.as_ref(), true));
+
+    if target.is_workspace_wide() {
+      let imports = modules::collect_imports(tree.root_node(), &source);
+      let files = self.files_sharing_declaration(&uri, &symbols, &imports, &name);
+
+      for file in files {
+        if file == uri { continue; }
+        let file_symbols = match self.index.get(&file) { Some(symbols) => symbols, None => continue };
+        let file_rope = match self.rope_of(&file).await { Some(rope) => rope, None => continue };
+
+        pager.extend(locations(&file, &file_rope, &file_symbols, false));
+        self.send_page(&mut pager).await;
+      }
+    }
+
+    Ok(self.finish_pages(pager).await)
+  }
+
+  // --| Call Hierarchy Handlers -------
+  /// The function declared or called at the position
+  pub async fn on_prepare_call_hierarchy(&self, params: CallHierarchyPrepareParams) -> Result<Option<Vec<CallHierarchyItem>>> {
+    let position = params.text_document_position_params.position;
+    let uri = params.text_document_position_params.text_document.uri;
+
+    let (source, rope) = match self.docs.lock().await.get(&uri) {
+      Some(doc) => (doc.get_content().to_string(), doc.rope.clone()),
+      None => return Ok(None),
+    };
+    let position = byte_position(&rope, position);
+
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let symbols = self.queries.symbols(&uri, &source, &tree);
+    if let Some(function) = call_hierarchy::function_at(&symbols, position) {
+      return Ok(Some(vec![call_hierarchy::item(&uri, &rope, function)]));
+    }
+
+    // A function of another workspace file, called through its import
+    let name = match identifier_at(tree.root_node(), &source, position) {
+      Some((name, _)) => name,
+      None => return Ok(None),
+    };
+
+    let call = symbols.calls.iter()
+      .filter(|call| call.callee == name && range_contains(&call.range, position))
+      .max_by_key(|call| (call.range.start.line, call.range.start.character));
+    let call = match call { Some(call) => call, None => return Ok(None) };
+
+    let imports = modules::collect_imports(tree.root_node(), &source);
+    let (file, function) = match self.resolve_call_location(&uri, &symbols, &imports, call) {
+      Some(found) => found,
+      None => return Ok(None),
+    };
+    Ok(self.rope_of(&file).await.map(|file_rope| vec![call_hierarchy::item(&file, &file_rope, &function)]))
+  }
+
+  /// Functions calling the item, streamed a file at a time with a partial result token.
+  /// Other files only call it through an import of the file declaring it.
+  pub async fn on_incoming_calls(&self, params: CallHierarchyIncomingCallsParams) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+    let item = params.item;
+    let workspace = self.workspace_for(&item.uri);
+    let declaring = paths::uri_to_path(&item.uri);
+    let files = self.index.name(&item.name).map_or_else(Vec::new, |name| self.index.files_mentioning(name, &workspace));
+    let mut pager = Pager::new(params.partial_result_params.partial_result_token);
+
+    for file in files {
+      let local = file == item.uri;
+      let aliases = match (&declaring, paths::uri_to_path(&file)) {
+        (Some(declaring), Some(path)) if !local && self.modules.imports_file(&workspace, &path, declaring) => {
+          self.import_aliases(&file, declaring).await
+        }
+        _ => vec![],
+      };
+      if !local && aliases.is_empty() { continue; }
+
+      let symbols = match self.index.get(&file) { Some(symbols) => symbols, None => continue };
+      let rope = match self.rope_of(&file).await { Some(rope) => rope, None => continue };
+      pager.extend(call_hierarchy::incoming(&symbols, &item.name, local, &aliases).into_iter().map(|(caller, from_ranges)| {
+        let from_ranges = from_ranges.into_iter().map(|range| lsp_range(&rope, range)).collect();
+        CallHierarchyIncomingCall { from: call_hierarchy::item(&file, &rope, caller), from_ranges }
+      }));
+      self.send_page(&mut pager).await;
+    }
+
+    Ok(self.finish_pages(pager).await)
+  }
+
+  /// Aliases a document imports the module file `module` under
+  async fn import_aliases(&self, uri: &Url, module: &Path) -> Vec<String> {
+    let module = module.canonicalize().unwrap_or_else(|_| module.to_path_buf());
+    let imports = self.imports_of(uri).await;
+    imports.iter()
+      .filter(|import| {
+        let path = self.import_path(uri, &imports, &import.alias);
+        path.map_or(false, |path| path.canonicalize().unwrap_or(path) == module)
+      })
+      .map(|import| import.alias.clone())
+      .collect()
+  }
+
+  /// Functions the item calls, declared in its own file or in a module it imports
+  pub async fn on_outgoing_calls(&self, params: CallHierarchyOutgoingCallsParams) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+    let item = params.item;
+    let symbols = match self.index.get(&item.uri) {
+      Some(symbols) => symbols,
+      None => return Ok(None),
+    };
+    let rope = match self.rope_of(&item.uri).await {
+      Some(rope) => rope,
+      None => return Ok(None),
+    };
+
+    // The item comes back in client positions, symbols keep byte columns
+    let selection_range = item.selection_range.to_doc(&rope).byte_range();
+    let function = match symbols.symbols.iter().find(|symbol| symbol.is_function() && symbol.selection_range == selection_range) {
+      Some(function) => function,
+      None => return Ok(None),
+    };
+
+    let imports = self.imports_of(&item.uri).await;
+    let mut pager = Pager::new(params.partial_result_params.partial_result_token);
+
+    // Calls of functions declared in the file or in a module it imports, methods are left out
+    for (call, from_ranges) in call_hierarchy::outgoing(&symbols, function) {
+      let (file, target) = match self.resolve_call_location(&item.uri, &symbols, &imports, call) {
+        Some(found) => found,
+        None => continue,
+      };
+      let file_rope = match self.rope_of(&file).await { Some(file_rope) => file_rope, None => continue };
+
+      let from_ranges = from_ranges.into_iter().map(|range| lsp_range(&rope, range)).collect();
+      pager.extend([CallHierarchyOutgoingCall { to: call_hierarchy::item(&file, &file_rope, &target), from_ranges }]);
+      self.send_page(&mut pager).await;
+    }
+
+    Ok(self.finish_pages(pager).await)
+  }
+
+  // --| Paged Results ----------------
+  /// Send a full page of a scan as partial results. Also lets a cancelled request stop
+  /// between files, the scan only continues when polled again.
+  async fn send_page<T: Serialize>(&self, pager: &mut Pager<T>) {
+    if let Some(page) = pager.page() {
+      self.client.send_notification::<PartialResult>(page).await;
+    }
+    tokio::task::yield_now().await;
+  }
+
+  /// The response of a finished scan, once its last page is sent
+  async fn finish_pages<T: Serialize>(&self, pager: Pager<T>) -> Option<Vec<T>> {
+    let (last, response) = pager.finish();
+    if let Some(page) = last {
+      self.client.send_notification::<PartialResult>(page).await;
+    }
+    response
+  }
+
+  // --| Signature Help Handler -------
+  pub async fn on_signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+    let position = params.text_document_position_params.position;
+    let uri = params.text_document_position_params.text_document.uri;
+
+    let (source, rope) = match self.docs.lock().await.get(&uri) {
+      Some(doc) => (doc.get_content().to_string(), doc.rope.clone()),
+      None => return Ok(None),
+    };
+    let position = byte_position(&rope, position);
+
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let symbols = self.queries.symbols(&uri, &source, &tree);
+    let imports = modules::collect_imports(tree.root_node(), &source);
+
+    let call = match symbols.call_at(position) {
+      Some(call) => call,
+      None => return Ok(None),
+    };
+
+    Ok(self.resolve_call(&uri, &symbols, &imports, call)
+      .map(|function| signature::signature_help(&function, call, position)))
+  }
+
+  // --| Hover Handler ----------------
+  pub async fn on_hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+    let position = params.text_document_position_params.position;
+    let uri = params.text_document_position_params.text_document.uri.clone();
+    let hover = self.hover_at(params).await?;
+    if !self.config_for(&uri).await.developer_hover { return Ok(hover); }
+
+    let tree = self.tree_for(&uri).await;
+    let source = self.docs.lock().await.get(&uri).map(|doc| (doc.get_content().to_string(), doc.rope.clone()));
+    let details = match (tree, source) {
+      (Some(tree), Some((source, rope))) => syntax_tree::developer_details(tree.root_node(), &source, position.to_doc(&rope).byte_position()),
+      _ => None,
+    };
+    let details = match details { Some(details) => details, None => return Ok(hover) };
+
+    let (value, range) = match hover {
+      Some(Hover { contents: HoverContents::Markup(content), range }) => (format!("{}\n\n---\n{}", content.value, details), range),
+      Some(hover) => return Ok(Some(hover)),
+      None => (details, Some(Range { start: position, end: position })),
+    };
+
+    Ok(Some(Hover { contents: HoverContents::Markup(self.markup(value)), range }))
+  }
+
+  async fn hover_at(&self, params: HoverParams) -> Result<Option<Hover>> {
+    let position = params.text_document_position_params.position;
+    let uri = params.text_document_position_params.text_document.uri;
+
+    let tmp = &mut self.docs.lock().await;
+    let doc_tmp = tmp.get_mut(&uri).unwrap();
+
+    let content = doc_tmp.content(None);
+    let doc_data: &str = &content;
+    // Trees count bytes, the hover range goes back to the client in its own positions
+    let point = byte_position(&doc_tmp.rope, position);
+
+    self.client.log_message(MessageType::INFO, "Hovered!").await;
+
+    if doc_data.len() == 0 {
+      info!("Hover: No document found");
+      return Ok(None);
+    }
+
+    else if doc_tmp.plain_text || !self.config_for(&uri).await.limits.allows_parse(doc_data.len()) {
+      info!("Hover: Document too large");
+      return Ok(None);
+    }
+
+    match Some(doc_data) {
+      Some(context) => {
+        let mut parser = self.parser.lock().await; 
+        debug!("Hover: Parser Loaded");
 
         let ts_tree = parser.parse(context.clone(), None);
         let tree = ts_tree.unwrap();
 
-        debug!("Hover: Looking up token at position: {:?} ctx: {:?} tree: {:?}", position, context, tree.root_node());
-        let lsp_action = "hover".to_string();
-        let output = get_from_position(position, tree.root_node(), context, lsp_action);
-        if output.is_none() { debug!("Hover: No token found"); }
+        // Mid-edit the region around the cursor may not parse, the word is then read from the
+        // text, or else named by the enclosing declaration, and declarations and types looked
+        // up in the last revision that did parse
+        let broken = in_error(tree.root_node(), point);
+        let recovered = if broken { self.queries.last_valid(&uri) } else { None };
+        let (lookup_tree, lookup_source) = match &recovered {
+          Some((tree, source)) => (tree, &**source),
+          None => (&tree, context),
+        };
+
+        let imports = modules::collect_imports(tree.root_node(), context);
+        let word = match broken {
+          true => word_at(context, point).map(|(word, _)| word).or_else(|| enclosing_name(tree.root_node(), context, point)),
+          false => get_string_at_pos(point, tree.root_node(), context),
+        };
+        if let Some(import) = word.as_ref().and_then(|word| imports.iter().find(|import| &import.alias == word)) {
+          return Ok(Some(self.module_hover(&uri, import, position)));
+        }
+
+        debug!("Hover: Looking up token at position: {:?} ctx: {:?} tree: {:?}", point, context, tree.root_node());
+        let lsp_action = "hover".to_string();
+        let output = match (broken, &word) {
+          (true, Some(word)) => lookup_keyword(&lsp_action, word),
+          _ => get_from_position(point, tree.root_node(), context, lsp_action),
+        };
+        if output.is_none() { debug!("Hover: No token found"); }
+
+        // --| Declaration with a doc comment, typed when the binding's type is inferred
+        if let (None, Some(word)) = (&output, &word) {
+          if let Some(symbol) = self.documented_symbol(&uri, lookup_tree.root_node(), lookup_source, word) {
+            let label = match infer::infer_document(lookup_tree.root_node(), lookup_source).lookup(word) {
+              Some(ty) if !symbol.is_function() => format!("{}: {}", word, ty),
+              _ => symbol.label(),
+            };
+
+            return Ok(Some(Hover {
+              contents: HoverContents::Markup(self.markup(format!("```cyber\n{}\n```\n---\n{}", label, symbol.doc.unwrap_or_default()))),
+              range: Some(Range { start: position, end: position }),
+            }));
+          }
+        }
+
+        // --| Inferred type of a binding
+        if let (None, Some(word)) = (&output, &word) {
+          let env = infer::infer_document(lookup_tree.root_node(), lookup_source);
+          if let Some(ty) = env.lookup(word) {
+            return Ok(Some(Hover {
+              contents: HoverContents::Markup(self.markup(format!("```cyber\n{}: {}\n```", word, ty))),
+              range: Some(Range { start: position, end: position }),
+            }));
+          }
+        }
+
+        match output {
+          Some(result) => {
+            let hover_str: String;
+            if self.lsp_client == "vscode" {
+              hover_str  = format!("
+### {} 
+<p align='right'>{}</p>
+
+---
+#### {}  
+
+```cyber
+{}
+```  ", result.keyword, result.keyword_detail_type, result.description, result.example);
+            } else {
+              hover_str = format!( "
+```cyber
+{}
+```
+---
+{}
+
+```cyber
+{}
+``` ", result.keyword,  result.description, result.example);
+            }
+
+            Ok(Some(Hover {
+              contents: HoverContents::Markup(self.markup(hover_str)),
+              range: Some(Range { start: position, end: position }),
+            }))
+          },
+          None => Ok(None),
+        }
+      }
+      None => Ok(None),
+    }
+  } 
+
+  // --| Code Action Handler ----------
+  pub async fn on_code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+    let uri = params.text_document.uri;
+    let (source, rope) = match self.docs.lock().await.get(&uri) {
+      Some(doc) => (doc.get_content().to_string(), doc.rope.clone()),
+      None => return Ok(None),
+    };
+
+    let mut actions: Vec<CodeActionOrCommand> = vec![];
+    let spelling_code = NumberOrString::String(spellcheck::SPELLING_CODE.to_string());
+    let undefined_code = NumberOrString::String(undefined::UNDEFINED_CODE.to_string());
+    let deprecated_code = NumberOrString::String(deprecations::DEPRECATED_CODE.to_string());
+
+    let tree = self.tree_for(&uri).await;
+    let imports = match &tree {
+      Some(tree) => modules::collect_imports(tree.root_node(), &source),
+      None => vec![],
+    };
+
+    for diagnostic in params.context.diagnostics.iter() {
+      if diagnostic.code.as_ref() == Some(&spelling_code) {
+        let word = diagnostic.range.to_doc(&rope).text(&rope);
+
+        if let (Some(word), Some(checker)) = (word, self.spellchecker.lock().await.as_ref()) {
+          actions.extend(spellcheck::quick_fixes(checker, &uri, &word, diagnostic));
+        }
+      }
+
+      if diagnostic.code.as_ref() == Some(&deprecated_code) {
+        let action = diagnostic.range.to_doc(&rope).text(&rope).and_then(|name| deprecations::replace_action(&uri, &name, diagnostic));
+        actions.extend(action.map(CodeActionOrCommand::CodeAction));
+      }
+
+      if diagnostic.code.as_ref() == Some(&undefined_code) {
+        let name = match diagnostic.range.to_doc(&rope).text(&rope) { Some(name) => name, None => continue };
+        let name = name.as_ref();
+
+        for (file, _) in self.index.find_exports(name, &self.workspace_for(&uri)) {
+          if file == uri { continue; }
+          let target = match paths::uri_to_path(&file) { Some(target) => target, None => continue };
+
+          if let Some(plan) = self.import_plan(&uri, &imports, &target) {
+            actions.push(CodeActionOrCommand::CodeAction(auto_import::auto_import_action(&uri, &imports, &plan, name, diagnostic)));
+          }
+        }
+
+        if let Some(tree) = &tree {
+          let declarations = declare::declare_actions(tree.root_node(), &source, &uri, name, diagnostic);
+          actions.extend(declarations.into_iter().map(CodeActionOrCommand::CodeAction));
+        }
+      }
+    }
+
+    // After the fixes, suppressing is the last resort
+    for diagnostic in params.context.diagnostics.iter() {
+      actions.extend(directives::suppress_actions(&uri, &source, diagnostic).into_iter().map(CodeActionOrCommand::CodeAction));
+    }
+
+    if fix_all::requested(params.context.only.as_ref()) {
+      if let Some(action) = self.fix_all_action(&uri, &source, &rope, &imports) {
+        actions.push(CodeActionOrCommand::CodeAction(action));
+      }
+    }
+
+    if surround::requested(params.context.only.as_ref()) {
+      let indent = self.indent_for(&uri, None).await;
+      let snippets = capabilities::snippet_text_edits(&self.client_capabilities.read().unwrap_or_else(|err| err.into_inner()));
+      let surround = surround::surround_actions(&source, &uri, params.range, indent, snippets);
+      actions.extend(surround.into_iter().map(CodeActionOrCommand::CodeAction));
+    }
+
+    if actions.is_empty() { Ok(None) } else { Ok(Some(actions)) }
+  }
+
+  /// Whitespace cleanup plus every autofix with a single unambiguous outcome, in one edit
+  fn fix_all_action(&self, uri: &Url, source: &str, rope: &Rope, imports: &[ImportStatement]) -> Option<CodeAction> {
+    let mut edits = fix_all::whitespace_edits(source);
+    self.fix_all_imports(uri, rope, imports, &mut edits);
+    if edits.is_empty() { return None; }
+
+    Some(CodeAction {
+      title: "Fix all auto-fixable problems".to_string(),
+      kind: Some(fix_all::fix_all_kind()),
+      edit: Some(WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), edits)])),
+        ..Default::default()
+      }),
+      ..Default::default()
+    })
+  }
+
+  /// Add the imports resolving undefined names to `edits`, skipping any that overlap an edit already there
+  fn fix_all_imports(&self, uri: &Url, rope: &Rope, imports: &[ImportStatement], edits: &mut Vec<TextEdit>) {
+    let undefined_code = NumberOrString::String(undefined::UNDEFINED_CODE.to_string());
+    let diagnostics = self.diagnostics.get(uri).map(|entry| entry.value().clone()).unwrap_or_default();
+    let mut imported: Vec<String> = vec![];
+
+    for diagnostic in diagnostics.iter().filter(|diagnostic| diagnostic.code.as_ref() == Some(&undefined_code)) {
+      let range = diagnostic.range;
+      let name = match range.to_doc(rope).text(rope) { Some(name) => name, None => continue };
+      let name = name.as_ref();
+
+      // Only imports with exactly one candidate module are safe to apply unattended
+      let candidates: Vec<Url> = self.index.find_exports(name, &self.workspace_for(uri)).into_iter()
+        .map(|(file, _)| file)
+        .filter(|file| file != uri)
+        .collect();
+      if candidates.len() != 1 { continue; }
+
+      let target = match paths::uri_to_path(&candidates[0]) { Some(target) => target, None => continue };
+      let mut plan = match self.import_plan(uri, imports, &target) { Some(plan) => plan, None => continue };
+      if imported.contains(&plan.specifier) { plan.insert = false; }
+
+      let mut group: Vec<TextEdit> = plan.import_edit(imports).into_iter().collect();
+      group.push(TextEdit::new(range, plan.qualified(name)));
+      if fix_all::merge(edits, group) && plan.insert {
+        imported.push(plan.specifier.clone());
+      }
+    }
+  }
+
+  // --| Execute Command Handler ------
+  pub async fn on_execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+    let command = match Command::parse(&params.command, &params.arguments) {
+      Ok(command) => command,
+      Err(err) => {
+        self.client.log_message(MessageType::ERROR, err).await;
+        return Ok(None);
+      }
+    };
+
+    match command {
+      Command::ToggleVerbose => self.toggle_verbose_command().await,
+      Command::LogLevel(args) => self.log_level_command(args).await,
+      Command::SpellIgnore(args) => self.spell_ignore_command(args).await,
+      #[cfg(feature = "run-commands")]
+      Command::RunFile(args) => self.run_command(args, false).await,
+      #[cfg(feature = "run-commands")]
+      Command::RunSelection(args) => self.run_command(args, true).await,
+      #[cfg(feature = "run-commands")]
+      Command::StopRun => Ok(Some(serde_json::json!({ "stopped": self.runner.stop().await }))),
+      Command::BenchmarkParse(args) => self.benchmark_parse_command(args).await,
+      Command::DeadCodeReport(args) => self.dead_code_report_command(args).await,
+      Command::ClearDiagnostics(args) => self.clear_diagnostics_command(args).await,
+      Command::CheckFile(args) => self.check_file_command(args).await,
+      Command::StructuralReplace(args) => self.structural_replace_command(args).await,
+      Command::ReindexWorkspace(args) => self.reindex_workspace_command(args).await,
+      Command::ShowSyntaxTree(args) => self.show_syntax_tree_command(args).await,
+      Command::OrganizeImports(args) => self.organize_imports_command(args).await,
+      Command::RestartParser => self.restart_parser_command().await,
+      Command::GenerateDocs(args) => self.generate_docs_command(args).await,
+      Command::ShowImportGraph(args) => self.show_import_graph_command(args).await,
+      Command::ValidateDocs(args) => self.validate_docs_command(args).await,
+      Command::DumpStats(args) => self.dump_stats_command(args).await,
+      Command::MemoryReport => self.memory_report_command().await,
+      Command::ShowRecentLog(args) => self.show_recent_log_command(args).await,
+      Command::ClearLog => self.clear_log_command().await,
+      Command::CompletionAccepted => {
+        self.stats.record_accepted();
+        Ok(None)
+      }
+    }
+  }
+
+  // --| Commands ---------------------
+  // --|-------------------------------
+  async fn toggle_verbose_command(&self) -> Result<Option<Value>> {
+    let mut log_data = self.log_data.lock().await;
+    log_data.verbose = !log_data.verbose;
+
+    debug!("Verbose: {}", log_data.verbose);
+    self.client.log_message(MessageType::INFO, format!("Verbose: {}", log_data.verbose)).await;
+    Ok(Some(serde_json::json!({ "verbose": log_data.verbose })))
+  }
+
+  async fn log_level_command(&self, args: LogLevelArgs) -> Result<Option<Value>> {
+    let level = match args.level.parse::<LevelFilter>() {
+      Ok(level) => level,
+      Err(_) => {
+        self.client.log_message(MessageType::ERROR, format!("Unknown log level: {}", args.level)).await;
+        return Ok(None);
+      }
+    };
+
+    self.log_data.lock().await.log_level = level;
+    self.client.log_message(MessageType::INFO, format!("Log level: {}", level)).await;
+    Ok(Some(serde_json::json!({ "level": level.to_string() })))
+  }
+
+  async fn spell_ignore_command(&self, args: SpellIgnoreArgs) -> Result<Option<Value>> {
+    let SpellIgnoreArgs { word, uri } = args;
+    if let Some(checker) = self.spellchecker.lock().await.as_mut() { checker.accept(&word); }
+    self.queries.invalidate();
+
+    if let Some(root) = paths::uri_to_path(&self.workspace_for(&uri)) {
+      if let Err(err) = spellcheck::persist_word(&root, &word) {
+        error!("Failed to persist word {:?}: {}", word, err);
+      }
+    }
+
+    let content = self.docs.lock().await.get(&uri).map(|doc| doc.get_content().to_string());
+    if let Some(content) = content {
+      self.obtain_analysis_diagnostics(uri, content).await;
+    }
+    Ok(None)
+  }
+
+  #[cfg(feature = "run-commands")]
+  async fn run_command(&self, args: RunArgs, selection: bool) -> Result<Option<Value>> {
+    let RunArgs { uri, range } = args;
+    if !self.is_trusted(&uri).await {
+      self.notify(Severity::Warning, run::UNTRUSTED_MESSAGE).await;
+      return Ok(None);
+    }
+    let script = if selection {
+      let selection = match (range, self.docs.lock().await.get_mut(&uri)) {
+        (Some(range), Some(doc)) => {
+          let (start, end) = (doc.offset_at(range.start), doc.offset_at(range.end));
+          Some(doc.rope.slice(start..end).to_string())
+        }
+        _ => None,
+      };
+
+      selection.and_then(|text| run::write_selection(&text).ok())
+    } else {
+      paths::uri_to_path(&uri)
+    };
+
+    let script = match script {
+      Some(script) => script,
+      None => {
+        self.client.log_message(MessageType::ERROR, format!("Nothing to run for {}", uri)).await;
+        return Ok(None);
+      }
+    };
+
+    let config = self.config_for(&uri).await;
+    let program = self.cyber_program(&uri).await;
+    let mode = config.run_output;
+    let line_offset = if selection { range.map_or(0, |range| range.start.line) } else { 0 };
+
+    self.clear_runtime_diagnostics().await;
+    match self.runner.start(self.client.clone(), self.this.clone(), &program, &script, uri, line_offset, mode).await {
+      Ok(pid) => {
+        self.report_error(None).await;
+        Ok(Some(serde_json::json!({ "pid": pid })))
+      }
+      Err(err) => {
+        error!("Failed to run {:?}: {}", script, err);
+        self.report_error(Some(format!("Failed to run {}: {}", program, err))).await;
+        self.notify(Severity::Error, format!("Failed to run {}: {}", program, err)).await;
+        Ok(None)
+      }
+    }
+  }
+
+  async fn benchmark_parse_command(&self, args: BenchmarkArgs) -> Result<Option<Value>> {
+    let iterations = args.iterations.unwrap_or(benchmark::DEFAULT_ITERATIONS);
+    let content = match self.docs.lock().await.get(&args.uri) {
+      Some(doc) => doc.get_content().to_string(),
+      None => {
+        self.client.log_message(MessageType::ERROR, format!("{} expects an open document uri", commands::BENCHMARK_PARSE)).await;
+        return Ok(None);
+      }
+    };
+
+    let report = platform::blocking(move || benchmark::benchmark_parse(&content, iterations)).await.flatten();
+    match report {
+      Some(report) => {
+        info!("{}", report.summary());
+        self.notify(Severity::Info, report.summary()).await;
+        Ok(serde_json::to_value(report).ok())
+      }
+      None => Ok(None),
+    }
+  }
+
+  async fn dead_code_report_command(&self, args: DeadCodeArgs) -> Result<Option<Value>> {
+    let report = dead_code::dead_code_report(&self.index, |uri| self.workspace_for(uri));
+    info!("Dead code report: {} entries in {} files", report.entries.len(), report.files_scanned);
+
+    let result = serde_json::to_value(&report).ok();
+    if args.publish {
+      let previous = self.dead_code.lock().await.replace(report.clone());
+      self.queries.invalidate();
+      let mut uris = previous.map(|previous| previous.uris()).unwrap_or_default();
+      uris.extend(report.uris());
+      uris.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+      uris.dedup();
+
+      for uri in uris {
+        let content = self.docs.lock().await.get(&uri).map(|doc| doc.get_content().to_string());
+        match content {
+          Some(content) => self.obtain_analysis_diagnostics(uri, content).await,
+          None => self.publish_tier(&uri, Tier::Semantic, report.hints(&uri)).await,
+        }
+      }
+    }
+
+    Ok(result)
+  }
+
+  async fn clear_diagnostics_command(&self, args: ClearDiagnosticsArgs) -> Result<Option<Value>> {
+    let cleared = match args.uri {
+      Some(uri) => {
+        let cleared = self.diagnostics.contains_key(&uri) as usize;
+        self.clear_diagnostics(&uri).await;
+        cleared
+      }
+      None => {
+        // Dead code hints would otherwise come back with the next check
+        self.dead_code.lock().await.take();
+        self.queries.invalidate();
+        self.clear_all_diagnostics().await
+      }
+    };
+
+    Ok(Some(serde_json::json!({ "cleared": cleared })))
+  }
+
+  async fn check_file_command(&self, args: DocumentArgs) -> Result<Option<Value>> {
+    let content = self.docs.lock().await.get(&args.uri).map(|doc| doc.get_content().to_string());
+    if let Some(content) = content {
+      self.pipeline.touch(&args.uri);
+      self.obtain_full_diagnostics(args.uri, content).await;
+    }
+    Ok(None)
+  }
+
+  async fn structural_replace_command(&self, args: StructuralReplaceArgs) -> Result<Option<Value>> {
+    let StructuralReplaceArgs { uri, query, template, workspace } = args;
+    let query = match structural::compile(&query) {
+      Ok(query) => query,
+      Err(err) => {
+        self.notify(Severity::Error, err).await;
+        return Ok(None);
+      }
+    };
+
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+    for target in self.edit_targets(&uri, workspace) {
+      let (source, tree) = match self.load_source(&target).await {
+        Some(loaded) => loaded,
+        None => continue,
+      };
+
+      let edits = structural::replacements(&query, tree.root_node(), &source, &template);
+      if !edits.is_empty() { changes.insert(target, edits); }
+    }
+
+    let matches: usize = changes.values().map(Vec::len).sum();
+    let files = changes.len();
+    let applied = if changes.is_empty() { false } else { self.apply_changes_edit(changes, "Structural replace").await };
+
+    Ok(Some(serde_json::json!({ "matches": matches, "files": files, "applied": applied })))
+  }
+
+  /// Drop the index and scan again, after changes the file watcher may have missed
+  async fn reindex_workspace_command(&self, args: ReindexArgs) -> Result<Option<Value>> {
+    let start = Instant::now();
+    let scopes: Vec<Url> = match &args.folder {
+      Some(folder) => vec![folder.clone()],
+      None => self.workspace_map.iter().map(|entry| entry.key().clone()).collect(),
+    };
+
+    match &args.folder {
+      Some(folder) => self.index.remove_scope(folder),
+      None => self.index.clear(),
+    }
+
+    let token = self.begin_progress("Reindexing workspace").await;
+    self.begin_activity(Activity::Indexing).await;
+
+    for (done, scope) in scopes.iter().enumerate() {
+      if self.is_shutting_down() { break; }
+      let folder = match paths::uri_to_path(scope) { Some(folder) => folder, None => continue };
+      let percentage = (done * 100 / scopes.len()) as u32;
+      self.report_progress(&token, folder.display().to_string(), percentage).await;
+      self.index_folder(folder).await;
+    }
+
+    // Open documents are indexed from their buffers, which the folder scan skips
+    let open: Vec<(Url, String)> = self.docs.lock().await.iter()
+      .filter(|(uri, _)| scopes.iter().any(|scope| index::in_scope(uri, scope)))
+      .map(|(uri, doc)| (uri.clone(), doc.get_content().to_string()))
+      .collect();
+    for (uri, source) in open {
+      if let Some(tree) = self.tree_for(&uri).await {
+        self.index.update(uri.clone(), collect_symbols(tree.root_node(), &source));
+      }
+    }
+
+    self.end_activity(Activity::Indexing).await;
+    let files: usize = scopes.iter().map(|scope| self.index.urls_in(scope).len()).sum();
+    self.end_progress(token, format!("Indexed {} files", files)).await;
+
+    info!("Reindexed {} files in {:?}", files, start.elapsed());
+    Ok(Some(serde_json::json!({ "files": files })))
+  }
+
+  async fn show_syntax_tree_command(&self, args: ShowSyntaxTreeArgs) -> Result<Option<Value>> {
+    let params = SyntaxTreeParams { text_document: TextDocumentIdentifier::new(args.uri), range: args.range, format: args.format };
+    let result = self.on_syntax_tree(params).await?;
+
+    if let Some(text) = result.as_ref().and_then(|result| result.text.as_ref()) {
+      info!("Syntax tree:\n{}", text);
+    }
+    Ok(result.and_then(|result| serde_json::to_value(result).ok()))
+  }
+
+  async fn organize_imports_command(&self, args: OrganizeImportsArgs) -> Result<Option<Value>> {
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+    for target in self.edit_targets(&args.uri, args.workspace) {
+      let (source, tree) = match self.load_source(&target).await {
+        Some(loaded) => loaded,
+        None => continue,
+      };
+
+      let imports = modules::collect_imports(tree.root_node(), &source);
+      if let Some(edit) = modules::organize_imports(&imports, &Rope::from_str(&source)) {
+        changes.insert(target, vec![edit]);
+      }
+    }
+
+    let files = changes.len();
+    let applied = if changes.is_empty() { false } else { self.apply_changes_edit(changes, "Organize imports").await };
+    Ok(Some(serde_json::json!({ "files": files, "applied": applied })))
+  }
+
+  /// The document, followed by the other indexed files of its workspace folder when `workspace` is set
+  fn edit_targets(&self, uri: &Url, workspace: bool) -> Vec<Url> {
+    let mut targets = vec![uri.clone()];
+    if workspace {
+      let indexed = self.index.urls_in(&self.workspace_for(uri)).into_iter();
+      targets.extend(indexed.filter(|file| file != uri));
+    }
+    targets
+  }
 
-        match output {
-          Some(result) => {
-            let hover_str: String;
-            if self.lsp_client == "vscode" {
-              hover_str  = format!("
-### {} 
-<p align='right'>{}</p>
+  /// Rope of a file, from the editor buffer when it is open or else from disk, to convert
+  /// the byte columns of its symbols to client positions
+  async fn rope_of(&self, uri: &Url) -> Option<Rope> {
+    let open = self.docs.lock().await.get(uri).map(|doc| doc.rope.clone());
+    if open.is_some() { return open; }
 
----
-#### {}  
+    let path = paths::uri_to_path(uri)?;
+    let source = platform::blocking(move || std::fs::read_to_string(path).ok()).await.flatten()?;
+    Some(Rope::from_str(&source))
+  }
 
-```cyber
-{}
-```  ", result.keyword, result.keyword_detail_type, result.description, result.example);
-            } else {
-              hover_str = format!( "
-```cyber
-{}
-```
----
-{}
+  /// Text and tree of a file, from the editor buffer when it is open or else from disk
+  async fn load_source(&self, uri: &Url) -> Option<(String, Tree)> {
+    let open = self.docs.lock().await.get(uri).map(|doc| doc.get_content().to_string());
+    let source = open.or_else(|| paths::uri_to_path(uri).and_then(|path| std::fs::read_to_string(path).ok()))?;
 
-```cyber
-{}
-``` ", result.keyword,  result.description, result.example);
+    let tree = self.tree_for(uri).await;
+    let tree = tree.or_else(|| structural::parse_source(&source))?;
+    Some((source, tree))
+  }
+
+  /// Documentation of the indexed modules of a folder, written below `out` or returned per module
+  async fn generate_docs_command(&self, args: GenerateDocsArgs) -> Result<Option<Value>> {
+    let folders: Vec<Url> = match args.folder {
+      Some(folder) => vec![folder],
+      None => self.workspace_map.iter().map(|entry| entry.key().clone()).collect(),
+    };
+
+    let mut pages = vec![];
+    for folder in folders {
+      let root = match paths::uri_to_path(&folder) { Some(root) => root, None => continue };
+      let modules = docgen::module_docs(self.index.entries_in(&folder), &root);
+
+      let out_dir = match &args.out {
+        Some(out) => {
+          if !self.is_trusted(&folder).await {
+            self.notify(Severity::Warning, "This workspace is not trusted, so cyberls does not write documentation into it").await;
+            continue;
+          }
+          match docgen::output_dir(&root, Path::new(out)) {
+            Some(out_dir) => out_dir,
+            None => {
+              let message = format!("Documentation is written below the workspace folder, not to {}", out);
+              self.client.log_message(MessageType::ERROR, message).await;
+              continue;
             }
+          }
+        }
+        None => {
+          pages.extend(modules.iter().map(|module| serde_json::json!({
+            "module": module.module,
+            "uri": module.uri,
+            "content": docgen::render(module, args.format),
+          })));
+          continue;
+        }
+      };
+
+      match docgen::write_docs(&modules, args.format, &out_dir) {
+        Ok(files) => {
+          info!("Generated documentation for {} modules in {:?}", files.len(), out_dir);
+          pages.extend(modules.iter().zip(files).map(|(module, file)| serde_json::json!({
+            "module": module.module,
+            "uri": module.uri,
+            "file": file,
+          })));
+        }
+        Err(err) => {
+          let message = format!("Failed to write documentation to {}: {}", out_dir.display(), err);
+          self.client.log_message(MessageType::ERROR, message).await;
+        }
+      }
+    }
 
-            Ok(Some(Hover {
-              contents: HoverContents::Markup(MarkupContent {
-                kind: MarkupKind::Markdown,
-                value: hover_str,
-              }),
-              range: Some(Range { start: position, end: position }),
-            }))
-          },
-          None => Ok(None),
+    Ok(Some(serde_json::json!({ "modules": pages })))
+  }
+
+  /// Modules of a folder, or every workspace folder, with the imports between them
+  async fn show_import_graph_command(&self, args: ShowImportGraphArgs) -> Result<Option<Value>> {
+    let folders: Vec<Url> = match args.folder {
+      Some(folder) => vec![folder],
+      None => self.workspace_map.iter().map(|entry| entry.key().clone()).collect(),
+    };
+
+    let mut graph = modules::ImportGraphView::default();
+    for folder in folders {
+      let files = self.index.urls_in(&folder).into_iter().filter_map(|uri| paths::uri_to_path(&uri)).collect();
+      let view = modules::import_graph(&self.modules, &folder, files);
+      graph.nodes.extend(view.nodes);
+      graph.edges.extend(view.edges);
+    }
+
+    info!("Import graph: {} modules, {} imports", graph.nodes.len(), graph.edges.len());
+    Ok(serde_json::to_value(&graph).ok())
+  }
+
+  /// Check language definition files against `lang_docs/schema.json`, logging every error
+  /// with its file and JSON pointer
+  async fn validate_docs_command(&self, args: ValidateDocsArgs) -> Result<Option<Value>> {
+    let files: Vec<PathBuf> = match args.path {
+      Some(path) if path.is_file() => vec![path],
+      Some(path) => doc_schema::doc_files(&path),
+      None => self.workspace_map.iter()
+        .filter_map(|entry| paths::uri_to_path(entry.key()))
+        .flat_map(|root| doc_schema::doc_files(&root.join("lang_docs")))
+        .collect(),
+    };
+
+    let (valid, errors) = doc_schema::load_all(&files);
+    for error in &errors {
+      warn!("Invalid language definition {}", error);
+      self.client.log_message(MessageType::WARNING, format!("Invalid language definition {}", error)).await;
+    }
+
+    info!("Validated {} language definition files, {} errors", files.len(), errors.len());
+    if !errors.is_empty() {
+      let invalid = files.len() - valid.len();
+      self.notify(Severity::Warning, format!("{} of {} language definition files are invalid, see the log", invalid, files.len())).await;
+    }
+
+    Ok(Some(serde_json::json!({ "files": files, "valid": valid.len(), "errors": errors })))
+  }
+
+  /// Usage statistics collected with `performance_metrics`, saved to the log directory first
+  async fn dump_stats_command(&self, args: DumpStatsArgs) -> Result<Option<Value>> {
+    self.stats.save();
+    let report = self.stats.report();
+    info!("Usage statistics: {}", report);
+
+    if !self.stats.is_enabled() {
+      self.client.log_message(MessageType::INFO, "Usage statistics are collected while `performance_metrics` is enabled").await;
+    }
+    if args.reset { self.stats.reset(); }
+
+    Ok(Some(report))
+  }
+
+  /// Estimated memory of every open document, largest first
+  async fn memory_report_command(&self) -> Result<Option<Value>> {
+    let mut documents: Vec<memory::DocumentMemory> = {
+      let docs = self.docs.lock().await;
+      let parse_tree = self.parse_tree.lock().await;
+      docs.iter().map(|(uri, doc)| {
+        let tokens = self.semantic_tokens.get(uri).map(|entry| entry.value().1.clone());
+        memory::document_memory(doc, parse_tree.get(uri), tokens.as_ref(), self.idle.idle_for(uri))
+      }).collect()
+    };
+    documents.sort_by(|a, b| b.total.cmp(&a.total));
+
+    let total: usize = documents.iter().map(|document| document.total).sum();
+    let trees = documents.iter().filter(|document| document.tree.is_some()).count();
+    info!("Memory report: {} documents, {} trees, ~{} bytes", documents.len(), trees, total);
+
+    Ok(Some(serde_json::json!({
+      "documents": documents,
+      "total": total,
+      "trees": trees,
+      "indexed_files": self.index.urls().len(),
+    })))
+  }
+
+  /// The end of the server log, so it needn't be looked up in the log folder
+  async fn show_recent_log_command(&self, args: ShowRecentLogArgs) -> Result<Option<Value>> {
+    let path = match &self.log_file {
+      Some(path) => path.clone(),
+      None => {
+        self.client.log_message(MessageType::WARNING, "cyberls is not writing a log").await;
+        return Ok(None);
+      }
+    };
+
+    match args.stream {
+      Some(true) => self.log_stream.start(self.client.clone(), path.clone()),
+      Some(false) => { self.log_stream.stop(); }
+      None => {}
+    }
+
+    let text = match logs::tail(&path, args.bytes.unwrap_or(logs::DEFAULT_TAIL_BYTES)) {
+      Ok(text) => text,
+      Err(err) => {
+        self.client.log_message(MessageType::ERROR, format!("Failed to read {}: {}", path.display(), err)).await;
+        return Ok(None);
+      }
+    };
+
+    Ok(Some(serde_json::json!({
+      "path": path,
+      "text": text,
+      "streaming": self.log_stream.is_streaming(),
+    })))
+  }
+
+  /// Empty the server log and delete the logs kept from earlier runs
+  async fn clear_log_command(&self) -> Result<Option<Value>> {
+    let (dir, current) = match self.log_file.as_deref().and_then(|path| Some((path.parent()?, path.file_name()?))) {
+      Some((dir, current)) => (dir.to_path_buf(), current.to_string_lossy().to_string()),
+      None => {
+        self.client.log_message(MessageType::WARNING, "cyberls is not writing a log").await;
+        return Ok(None);
+      }
+    };
+
+    match logs::clear(&dir, &current) {
+      Ok(deleted) => {
+        info!("Log cleared, deleted {} earlier logs", deleted);
+        Ok(Some(serde_json::json!({ "deleted": deleted })))
+      }
+      Err(err) => {
+        self.client.log_message(MessageType::ERROR, format!("Failed to clear the logs in {}: {}", dir.display(), err)).await;
+        Ok(None)
+      }
+    }
+  }
+
+  /// Discard the parser and every cached tree, then parse the open documents again from their text
+  async fn restart_parser_command(&self) -> Result<Option<Value>> {
+    let start = Instant::now();
+    self.semantic_tokens.clear();
+
+    let mut reparsed: Vec<(Url, String)> = vec![];
+    {
+      let mut docs = self.docs.lock().await;
+      let mut parser = self.parser.lock().await;
+      let mut parse_tree = self.parse_tree.lock().await;
+
+      *parser = cyber_tree_sitter::init_parser();
+      parse_tree.clear();
+
+      for (uri, doc) in docs.iter_mut().filter(|(_, doc)| !doc.plain_text) {
+        let content = doc.rope.to_string();
+        let limits = self.config_for(uri).await.limits;
+
+        doc.tree = if limits.allows_parse(content.len()) { parser.parse(&content, None) } else { None };
+        match &doc.tree {
+          Some(tree) => {
+            parse_tree.insert(uri.clone(), tree.clone());
+            self.index.update(uri.clone(), collect_symbols(tree.root_node(), &content));
+          }
+          None => self.index.remove(uri),
         }
+        reparsed.push((uri.clone(), content));
       }
-      None => Ok(None),
     }
-  } 
 
-  // --| Execute Command Handler ------
-  pub async fn on_execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
-    debug!("Execute Command Requested: {:?}", &params);
+    let documents = reparsed.len();
+    for (uri, content) in reparsed {
+      self.pipeline.touch(&uri);
+      self.obtain_analysis_diagnostics(uri, content).await;
+    }
 
-    let command = params.command;
-    let _args = &params.arguments;
+    info!("Parser restarted, {} documents parsed again in {:?}", documents, start.elapsed());
+    self.client.log_message(MessageType::INFO, format!("Parser restarted, {} documents parsed again", documents)).await;
+    Ok(Some(serde_json::json!({ "documents": documents })))
+  }
 
-    match command.as_str() {
-      "cyberls.toggle_verbose" => {
-        let mut log_data = self.log_data.lock().await;
-        log_data.verbose = !log_data.verbose;
+  /// Ask the client to apply edits, returning whether it did. Edits spanning several
+  /// files are only applied once the user confirmed them.
+  async fn apply_changes_edit(&self, changes: HashMap<Url, Vec<TextEdit>>, label: &str) -> bool {
+    if changes.len() > 1 {
+      let edits: usize = changes.values().map(Vec::len).sum();
+      let message = format!("{} will change {} files ({} edits). Apply the changes?", label, changes.len(), edits);
+      if self.ask(Severity::Warning, message, &[notify::APPLY_CHOICE, notify::CANCEL_CHOICE]).await.as_deref() != Some(notify::APPLY_CHOICE) {
+        info!("{} cancelled by the user", label);
+        return false;
+      }
+    }
 
-        debug!("Verbose: {}", log_data.verbose);
-        self.client.log_message(MessageType::INFO, format!("Verbose: {}", log_data.verbose)).await;
-      },
-      // "cyberls.loglevel" => {
-      //   let debug = &mut self.log_data.log_level.clone();
-      //   // *debug = LevelFilter::try_from(args);
-      //   let command_params = match serde_json::value::from_value(args[0].clone()) {
-      //     Ok(value) => value,
-      //     Err(err) => {}
-      //   };
-      //   *debug = LevelFilter::from(command_params);
-      //
-      //   self.client.log_message(MessageType::INFO, format!("Debug: {}", *debug)).await;
-      // },
-      _ => {
-        self.client.log_message(MessageType::ERROR, format!("Unknown command: {}", command)).await;
+    let edit = WorkspaceEdit { changes: Some(changes), ..Default::default() };
+    match self.client.apply_edit(edit).await {
+      Ok(response) => response.applied,
+      Err(err) => {
+        error!("{} failed: {:?}", label, err);
+        false
       }
     }
-    Ok(None)
   }
+
+  /// How `target` would be imported into the document, reusing an existing import of it
+  pub fn import_plan(&self, uri: &Url, imports: &[ImportStatement], target: &Path) -> Option<ImportPlan> {
+    let workspace = self.workspace_for(uri);
+    let base_dir = self.modules.base_dir(uri)?;
+    let canonical = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+    let existing = imports.iter().find(|import| {
+      let resolved = self.modules.resolve(&workspace, Some(&base_dir), &import.specifier);
+      self.modules.source_path(&resolved).map_or(false, |path| canonical(&path) == canonical(target))
+    });
+
+    Some(ImportPlan::new(imports, existing, target, modules::relative_specifier(&base_dir, target)))
+  }
+
+  /// Hover content in a format the client renders
+  pub fn markup(&self, value: String) -> MarkupContent {
+    capabilities::markup(&self.client_capabilities.read().unwrap_or_else(|err| err.into_inner()), value)
+  }
+
+  /// The configured cyber executable for a document's workspace folder, or `cyber` from PATH
+  pub async fn cyber_program(&self, uri: &Url) -> String {
+    self.config_for(uri).await.cyber_path.unwrap_or_else(|| run::DEFAULT_CYBER.to_string())
+  }
+
+  /// Whether processes may be started for a document's workspace folder. Untrusted
+  /// folders only get the tree-sitter based analysis.
+  pub async fn is_trusted(&self, uri: &Url) -> bool {
+    let local = self.workspace_for(uri).scheme() == "file";
+    self.config_for(uri).await.trusted.unwrap_or(local)
+  }
+
+  /// Whether every workspace folder is trusted, for work not tied to a document
+  pub async fn all_trusted(&self) -> bool {
+    let folders: Vec<Url> = self.workspace_map.iter().map(|entry| entry.key().clone()).collect();
+    if folders.is_empty() { return self.config.lock().await.trusted.unwrap_or(true); }
+
+    for folder in folders {
+      if !self.is_trusted(&folder).await { return false; }
+    }
+    true
+  }
+
+  /// Check the globally configured executable, folder overrides are checked on first use
+  #[cfg(feature = "compiler-diagnostics")]
+  pub async fn detect_configured_cyber(&self) {
+    if !self.all_trusted().await {
+      info!("Untrusted workspace, not looking for the cyber executable");
+      return;
+    }
+    let program = self.config.lock().await.cyber_path.clone().unwrap_or_else(|| run::DEFAULT_CYBER.to_string());
+    self.detect_cyber(&program).await;
+  }
+
+  /// Nothing is compiled, so nothing needs the executable up front
+  #[cfg(not(feature = "compiler-diagnostics"))]
+  pub async fn detect_configured_cyber(&self) {}
+
+  /// Whether `program` can be run, detecting it on first use
+  #[cfg(feature = "compiler-diagnostics")]
+  pub async fn cyber_available(&self, program: &str) -> bool {
+    let known = self.cyber_available.get(program).map(|entry| *entry.value());
+    match known {
+      Some(available) => available,
+      None => self.detect_cyber(program).await,
+    }
+  }
+
+  /// Look for the cyber executable. Compile diagnostics are skipped while it is missing,
+  /// leaving only the tree-sitter based checks, and the user is told once how to fix it.
+  #[cfg(feature = "compiler-diagnostics")]
+  pub async fn detect_cyber(&self, program: &str) -> bool {
+    let available = server_info::is_available(program).await;
+    let previous = self.cyber_available.insert(program.to_string(), available);
+
+    if !available && previous != Some(false) {
+      warn!("Cyber executable not found: {}", program);
+      self.notify(Severity::Warning, format!(
+        "The Cyber executable `{}` could not be run. Install Cyber from https://cyberscript.dev and make sure it is on PATH, \
+         or set `cyberls.cyber_path`. Until then only syntax diagnostics are available.", program,
+      )).await;
+    }
+
+    available
+  }
+
+  // --| REPL Requests ----------------
+  // --|-------------------------------
+  #[cfg(feature = "run-commands")]
+  pub async fn on_repl_start(&self, params: ReplParams) -> Result<ReplStartResult> {
+    let workspace = self.workspace_for(&params.uri);
+    let program = self.cyber_program(&params.uri).await;
+    if !self.is_trusted(&params.uri).await { return Err(internal_error(run::UNTRUSTED_MESSAGE.to_string())); }
+
+    self.repl.start(&workspace, &program).await.map_err(|err| {
+      error!("Failed to start REPL with {}: {}", program, err);
+      internal_error(format!("Failed to start REPL with {}: {}", program, err))
+    })
+  }
+
+  #[cfg(feature = "run-commands")]
+  pub async fn on_repl_eval(&self, params: ReplEvalParams) -> Result<ReplEvalResult> {
+    let workspace = self.workspace_for(&params.uri);
+    self.repl.eval(&workspace, &params.code).await.map_err(|err| internal_error(err.to_string()))
+  }
+
+  #[cfg(feature = "run-commands")]
+  pub async fn on_repl_interrupt(&self, params: ReplParams) -> Result<bool> {
+    let workspace = self.workspace_for(&params.uri);
+    Ok(self.repl.interrupt(&workspace).await)
+  }
+
+  // --| Syntax Tree Request ----------
+  // --|-------------------------------
+  pub async fn on_syntax_tree(&self, params: SyntaxTreeParams) -> Result<Option<SyntaxTreeResult>> {
+    let uri = params.text_document.uri;
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let source = match self.docs.lock().await.get(&uri) {
+      Some(doc) => doc.get_content().to_string(),
+      None => return Ok(None),
+    };
+
+    Ok(Some(syntax_tree::syntax_tree(tree.root_node(), &source, params.range, params.format)))
+  }
+
+  // --| Expansion Preview Request ----
+  // --|-------------------------------
+  #[cfg(feature = "run-commands")]
+  pub async fn on_expand(&self, params: ExpandParams) -> Result<Option<ExpandResult>> {
+    let uri = params.text_document.uri;
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let (source, rope) = match self.docs.lock().await.get(&uri) {
+      Some(doc) => (doc.get_content().to_string(), doc.rope.clone()),
+      None => return Ok(None),
+    };
+
+    let (range, snippet) = match expand::expansion_source(tree.root_node(), &source, byte_position(&rope, params.position)) {
+      Some((range, snippet)) => (lsp_range(&rope, range), snippet),
+      None => return Ok(None),
+    };
+
+    if !self.is_trusted(&uri).await { return Err(internal_error(run::UNTRUSTED_MESSAGE.to_string())); }
+    let program = self.cyber_program(&uri).await;
+    let (expansion, success) = expand::compile_snippet(&program, &snippet, self.modules.base_dir(&uri).as_deref()).await
+      .map_err(|err| internal_error(format!("Failed to run {} compile: {}", program, err)))?;
+
+    Ok(Some(ExpandResult { range, source: snippet, expansion, success }))
+  }
+
+  pub async fn on_matching_block(&self, params: MatchingBlockParams) -> Result<Option<MatchingBlockResult>> {
+    let uri = params.text_document.uri;
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let rope = match self.docs.lock().await.get(&uri) {
+      Some(doc) => doc.rope.clone(),
+      None => return Ok(None),
+    };
+
+    Ok(matching::matching_block(tree.root_node(), &rope, params.position))
+  }
+
+  // --| Formatting Requests ----------
+  // --|-------------------------------
+  /// Indentation for edits in a document, from its folder's settings and the client's options
+  pub async fn indent_for(&self, uri: &Url, options: Option<&FormattingOptions>) -> Indent {
+    let config = self.config_for(uri).await;
+    Indent::resolve(config.indent_style, config.indent_width, options)
+  }
+
+  pub async fn on_formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+    let uri = params.text_document.uri;
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let source = match self.docs.lock().await.get(&uri) {
+      Some(doc) => doc.get_content().to_string(),
+      None => return Ok(None),
+    };
+
+    let indent = self.indent_for(&uri, Some(&params.options)).await;
+    Ok(Some(format::format_document(tree.root_node(), &source, indent)))
+  }
+
+  /// Edits the client applies before saving: the formatter's, then the fix all edits not overlapping them
+  pub async fn on_will_save_wait_until(&self, params: WillSaveTextDocumentParams) -> Result<Option<Vec<TextEdit>>> {
+    // Auto saves after a delay would reformat under the cursor while typing
+    if params.reason == TextDocumentSaveReason::AFTER_DELAY { return Ok(None); }
+
+    let uri = params.text_document.uri;
+    let config = self.config_for(&uri).await;
+    if !config.format_on_save && !config.fix_all_on_save { return Ok(None); }
+
+    let (source, rope) = match self.docs.lock().await.get(&uri) {
+      Some(doc) => (doc.get_content().to_string(), doc.rope.clone()),
+      None => return Ok(None),
+    };
+    let tree = self.tree_for(&uri).await;
+
+    let mut edits = vec![];
+    if let (true, Some(tree)) = (config.format_on_save, &tree) {
+      let indent = self.indent_for(&uri, None).await;
+      edits = format::format_document(tree.root_node(), &source, indent);
+    }
+
+    if config.fix_all_on_save {
+      for edit in fix_all::whitespace_edits(&source) {
+        fix_all::merge(&mut edits, vec![edit]);
+      }
+
+      let imports = match &tree {
+        Some(tree) => modules::collect_imports(tree.root_node(), &source),
+        None => vec![],
+      };
+      self.fix_all_imports(&uri, &rope, &imports, &mut edits);
+    }
+
+    if edits.is_empty() { Ok(None) } else { Ok(Some(edits)) }
+  }
+
+  pub async fn on_type_formatting_request(&self, params: DocumentOnTypeFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+    let uri = params.text_document_position.text_document.uri;
+    let position = params.text_document_position.position;
+
+    let source = match self.docs.lock().await.get(&uri) {
+      Some(doc) => doc.get_content().to_string(),
+      None => return Ok(None),
+    };
+
+    let indent = self.indent_for(&uri, Some(&params.options)).await;
+    let edits = format::on_type_edits(&source, position, indent);
+    if edits.is_empty() { Ok(None) } else { Ok(Some(edits)) }
+  }
+
+  // --| Folding Range Request --------
+  // --|-------------------------------
+  pub async fn on_folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+    let uri = params.text_document.uri;
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let source = match self.docs.lock().await.get(&uri) {
+      Some(doc) => doc.get_content().to_string(),
+      None => return Ok(None),
+    };
+
+    let config = self.config_for(&uri).await;
+    Ok(Some(folding::folding_ranges(tree.root_node(), &source, &config.folding)))
+  }
+
+  // --| Document Symbol Request ------
+  // --|-------------------------------
+  pub async fn on_document_symbol(&self, params: DocumentSymbolParams) -> Result<Option<DocumentSymbolResponse>> {
+    let uri = params.text_document.uri;
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let (source, rope) = match self.docs.lock().await.get(&uri) {
+      Some(doc) => (doc.get_content().to_string(), doc.rope.clone()),
+      None => return Ok(None),
+    };
+
+    let config = self.config_for(&uri).await;
+    let symbols = self.queries.symbols(&uri, &source, &tree);
+    let regions = folding::regions(&source, &config.folding);
+    Ok(Some(DocumentSymbolResponse::Nested(folding::outline(&symbols, &regions, &rope))))
+  }
+
+  // --| Moniker Request --------------
+  // --|-------------------------------
+  pub async fn on_moniker(&self, params: MonikerParams) -> Result<Option<Vec<Moniker>>> {
+    let uri = params.text_document_position_params.text_document.uri;
+    let (source, rope) = match self.docs.lock().await.get(&uri) {
+      Some(doc) => (doc.get_content().to_string(), doc.rope.clone()),
+      None => return Ok(None),
+    };
+
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let position = params.text_document_position_params.position.to_doc(&rope).byte_position();
+    let (name, range) = match identifier_at(tree.root_node(), &source, position) {
+      Some(found) => found,
+      None => return Ok(None),
+    };
+
+    // Modules are named by their path below the workspace folder, virtual documents have none
+    let workspace = self.workspace_for(&uri);
+    let root = paths::uri_to_path(&workspace);
+    let package = moniker::package_name(&workspace);
+    let qualify = |path: &Path| {
+      let module = modules::module_name(root.as_deref()?, path)?;
+      Some(format!("{}/{}", package, module))
+    };
+
+    let module = match paths::uri_to_path(&uri).and_then(|path| qualify(&path)) {
+      Some(module) => module,
+      None => return Ok(None),
+    };
+
+    let base_dir = self.modules.base_dir(&uri);
+    let imported = |import: &ImportStatement| match self.modules.resolve(&workspace, base_dir.as_deref(), &import.specifier) {
+      ResolvedModule::Builtin(name) => Some(name),
+      ResolvedModule::File(path) => qualify(&path),
+      _ => None,
+    };
+
+    let symbols = self.queries.symbols(&uri, &source, &tree);
+    let imports = modules::collect_imports(tree.root_node(), &source);
+    let context = MonikerContext { module: &module, symbols: &symbols, imports: &imports };
+
+    let line_prefix = source.lines().nth(range.end.line as usize)
+      .and_then(|line| line.get(..range.end.character as usize))
+      .unwrap_or_default();
+
+    Ok(moniker::moniker_at(&context, &name, range, line_prefix, imported).map(|moniker| vec![moniker]))
+  }
+
+  // --| Workspace Symbol Request -----
+  // --|-------------------------------
+  /// With a partial result token the matches of each shard of files are sent as soon as it
+  /// is searched, name matches first within a shard, instead of once the whole index is
+  pub async fn on_workspace_symbol(&self, params: WorkspaceSymbolParams) -> Result<Option<Vec<SymbolInformation>>> {
+    let token = match params.partial_result_params.partial_result_token {
+      Some(token) => token,
+      None => return Ok(Some(self.client_symbols(self.index.search(&params.query)).await)),
+    };
+
+    let mut pager = Pager::new(Some(token));
+    for shard in self.index.urls().chunks(partial::SEARCH_SHARD_SIZE) {
+      pager.extend(self.client_symbols(self.index.search_in(&params.query, shard)).await);
+      if let Some(page) = pager.flush() {
+        self.client.send_notification::<PartialResult>(page).await;
+      }
+      tokio::task::yield_now().await;
+    }
+
+    Ok(Some(self.finish_pages(pager).await.unwrap_or_default()))
+  }
+
+  /// Workspace symbols in client positions, each file read once
+  async fn client_symbols(&self, mut found: Vec<SymbolInformation>) -> Vec<SymbolInformation> {
+    let mut ropes: HashMap<Url, Option<Rope>> = HashMap::new();
+    for information in found.iter_mut() {
+      let uri = information.location.uri.clone();
+      if !ropes.contains_key(&uri) {
+        let rope = self.rope_of(&uri).await;
+        ropes.insert(uri.clone(), rope);
+      }
+      if let Some(rope) = &ropes[&uri] { information.location.range = lsp_range(rope, information.location.range); }
+    }
+    found
+  }
+
+  // --| Semantic Tokens Request ------
+  // --|-------------------------------
+  pub async fn on_semantic_tokens_full(&self, params: SemanticTokensParams) -> Result<Option<SemanticTokensResult>> {
+    let uri = params.text_document.uri;
+    let tree = match self.tree_for(&uri).await {
+      Some(tree) => tree,
+      None => return Ok(None),
+    };
+
+    let (source, version) = match self.docs.lock().await.get(&uri) {
+      Some(doc) => (doc.get_content().to_string(), (doc.generation, doc.version)),
+      None => return Ok(None),
+    };
+
+    // Tokens are reused until the document changes or is reloaded
+    let cached = self.semantic_tokens.get(&uri).filter(|entry| entry.value().0 == version).map(|entry| entry.value().1.clone());
+    if let Some(tokens) = cached {
+      return Ok(Some(SemanticTokensResult::Tokens(tokens)));
+    }
+
+    let config = self.config_for(&uri).await;
+    if !config.limits.allows_semantic_tokens(source.len()) {
+      debug!("Semantic tokens: document too large {}", uri);
+      return Ok(None);
+    }
+
+    let multiline = capabilities::multiline_tokens(&self.client_capabilities.read().unwrap_or_else(|err| err.into_inner()));
+    let symbols = self.queries.symbols(&uri, &source, &tree);
+    let tokens = semantic_tokens::get_tokens(
+      &tree, &source, &semantic_tokens::HIGHLIGHT_QUERY, &symbols, multiline,
+      config.injection_highlighting, config.rainbow_brackets,
+    );
+    self.semantic_tokens.insert(uri, (version, tokens.clone()));
+    Ok(Some(SemanticTokensResult::Tokens(tokens)))
+  }
+
+  // --| Server Info Request ----------
+  // --|-------------------------------
+  pub async fn on_server_info(&self) -> Result<ServerInfoResult> {
+    let (name, version) = server_info::server_version();
+    let config = self.config.lock().await.clone();
+    let program = config.cyber_path.clone().unwrap_or_else(|| run::DEFAULT_CYBER.to_string());
+    #[cfg(feature = "compiler-diagnostics")]
+    let cyber_version = if self.all_trusted().await { server_info::cyber_version(&program).await } else { None };
+    #[cfg(not(feature = "compiler-diagnostics"))]
+    let cyber_version = None;
+
+    #[cfg(feature = "run-commands")]
+    let repl_sessions = self.repl.session_count().await;
+    #[cfg(not(feature = "run-commands"))]
+    let repl_sessions = 0;
+
+    let subsystems = Subsystems {
+      status: self.status.current(),
+      spellcheck: self.spellchecker.lock().await.is_some(),
+      remote_imports: config.remote_imports,
+      pull_diagnostics: self.pull_diagnostics(),
+      script_running: self.runner.is_running().await,
+      repl_sessions,
+    };
+
+    Ok(ServerInfoResult {
+      name, version,
+      cyber: CyberInfo { path: program, version: cyber_version },
+      doc_packs: DOC_PACKS.len(),
+      indexed_files: self.index.len(),
+      open_documents: self.docs.lock().await.len(),
+      workspace_folders: self.workspace_map.len(),
+      features: server_info::features(),
+      subsystems,
+    })
+  }
+}
+
+fn internal_error(message: String) -> tower_lsp::jsonrpc::Error {
+  let mut error = tower_lsp::jsonrpc::Error::internal_error();
+  error.message = message.into();
+  error
+}
+
+/// Parser errors and grammar errors of the tree
+fn syntax_diagnostics(uri: &Url, context: &str, tree: &Tree) -> Option<ErrorInfo> {
+  let mut err_info = ErrorInfo::new();
+
+  for error in get_parser_errors(context, Some(tree.clone())).iter() {
+    err_info.add_coded(
+      DocPoint::from_byte_position(error.start),
+      DocPoint::from_byte_position(error.end),
+      "Syntax Error".to_string(),
+      Some(DiagnosticSeverity::ERROR),
+      SYNTAX_ERROR_CODE,
+    );
+  }
+
+  let mut tree_results = check_tree_error(&paths::uri_to_path(uri).unwrap_or_default(), context, tree.root_node());
+  if tree_results.is_some() {
+    err_info.combine(tree_results.as_mut().unwrap());
+  }
+
+  Some(err_info)
 }
@@ -0,0 +1,65 @@
+use std::path::Path;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::WalkBuilder;
+use tracing::debug;
+
+/// Workspace file listing additional patterns the server skips
+pub const IGNORE_FILE: &str = ".cyberlsignore";
+
+// --| Ignore Rules ------------------
+// --|--------------------------------
+/// `.gitignore`, `.cyberlsignore` and configured patterns of one workspace folder
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+  matcher: Gitignore,
+}
+
+impl IgnoreRules {
+  pub fn load(root: &Path, patterns: &[String]) -> Self {
+    let mut builder = GitignoreBuilder::new(root);
+
+    for name in [".gitignore", IGNORE_FILE] {
+      let path = root.join(name);
+      if !path.is_file() { continue; }
+      if let Some(err) = builder.add(&path) { debug!("Invalid ignore file {:?}: {}", path, err); }
+    }
+
+    for pattern in patterns {
+      if let Err(err) = builder.add_line(None, pattern) { debug!("Invalid ignore pattern {:?}: {}", pattern, err); }
+    }
+
+    let matcher = builder.build().unwrap_or_else(|err| {
+      debug!("Failed to build ignore rules for {:?}: {}", root, err);
+      Gitignore::empty()
+    });
+
+    Self { matcher }
+  }
+
+  /// Whether the path, or one of its parent directories, is ignored
+  pub fn is_ignored(&self, path: &Path) -> bool {
+    if !path.starts_with(self.matcher.path()) { return false; }
+    self.matcher.matched_path_or_any_parents(path, path.is_dir()).is_ignore()
+  }
+}
+
+/// Walk a folder like git would, honouring nested ignore files and the configured patterns
+pub fn walker(root: &Path, patterns: &[String]) -> ignore::Walk {
+  WalkBuilder::new(root)
+    .require_git(false)
+    .add_custom_ignore_filename(IGNORE_FILE)
+    .overrides(exclusions(root, patterns))
+    .build()
+}
+
+fn exclusions(root: &Path, patterns: &[String]) -> Override {
+  let mut builder = OverrideBuilder::new(root);
+
+  for pattern in patterns {
+    if let Err(err) = builder.add(&format!("!{}", pattern)) { debug!("Invalid ignore pattern {:?}: {}", pattern, err); }
+  }
+
+  builder.build().unwrap_or_else(|_| Override::empty())
+}
@@ -0,0 +1,377 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use dashmap::DashMap;
+use lsp_types::{Location, Range, SymbolInformation, SymbolKind, Url};
+use tracing::{debug, info};
+
+use crate::ignores;
+use crate::utils::paths;
+use crate::symbols::{collect_symbols, DocumentSymbols, Symbol};
+
+#[cfg(feature = "index-cache")]
+mod cache;
+mod store;
+
+use store::{CompactFile, Interner};
+pub use store::Name;
+
+/// Extensions of the files indexed within each workspace folder
+pub const SOURCE_EXTENSIONS: &[&str] = &["cy", "cyber"];
+
+/// Language ids clients use for Cyber documents
+pub const CYBER_LANGUAGE_IDS: &[&str] = &["cyber", "cy"];
+
+/// Whether a path has a Cyber extension, or one of the configured extra extensions
+pub fn is_source_path(path: &Path, extra: &[String]) -> bool {
+  path.extension().and_then(|ext| ext.to_str()).map_or(false, |ext| {
+    SOURCE_EXTENSIONS.contains(&ext) || extra.iter().any(|extra| extra.trim_start_matches('.') == ext)
+  })
+}
+
+/// Whether an opened document is parsed as Cyber. Configured extensions always are, whatever
+/// the client calls them. Otherwise the language id must be Cyber and the file either has a
+/// Cyber extension or none at all, as with untitled buffers.
+pub fn is_cyber_document(language_id: &str, uri: &Url, extra: &[String]) -> bool {
+  let path = paths::document_path(uri);
+  if is_source_path(&path, extra) { return true; }
+  CYBER_LANGUAGE_IDS.contains(&language_id) && path.extension().is_none()
+}
+
+// --| Workspace Index ---------------
+// --|--------------------------------
+/// A file of the index, stable until the index is dropped
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileId(u32);
+
+/// Where a name is declared or used
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+  pub file: FileId,
+  pub range: Range,
+}
+
+/// Symbols of every cyber file in the workspace, open or not. Names are interned and
+/// files numbered, so a symbol costs a few integers and its ranges. `get` and `entries`
+/// hand out regular `DocumentSymbols`, the `Name` based queries avoid building them.
+#[derive(Default, Debug)]
+pub struct WorkspaceIndex {
+  names: Interner,
+  ids: DashMap<Url, FileId>,
+  urls: RwLock<Vec<Url>>,
+  files: DashMap<FileId, CompactFile>,
+  /// Bumped whenever a file's symbols are replaced or dropped, so results derived from them can be checked
+  revisions: DashMap<Url, u64>,
+  next_revision: AtomicU64,
+}
+
+impl WorkspaceIndex {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn update(&self, uri: Url, symbols: DocumentSymbols) {
+    self.revisions.insert(uri.clone(), self.next_revision.fetch_add(1, Ordering::SeqCst) + 1);
+    let file = self.file_id_or_insert(uri);
+    self.files.insert(file, CompactFile::compact(symbols, &self.names));
+  }
+
+  pub fn remove(&self, uri: &Url) {
+    self.revisions.remove(uri);
+    if let Some(file) = self.file_id(uri) { self.files.remove(&file); }
+  }
+
+  pub fn clear(&self) {
+    self.revisions.clear();
+    self.files.clear();
+  }
+
+  /// Revision of a file's symbols, 0 when it is not indexed
+  pub fn revision(&self, uri: &Url) -> u64 {
+    self.revisions.get(uri).map_or(0, |revision| *revision)
+  }
+
+  pub fn len(&self) -> usize {
+    self.files.len()
+  }
+
+  pub fn get(&self, uri: &Url) -> Option<DocumentSymbols> {
+    let file = self.files.get(&self.file_id(uri)?)?;
+    Some(file.expand(&self.names.read()))
+  }
+
+  pub fn urls(&self) -> Vec<Url> {
+    let urls = self.urls.read().unwrap_or_else(|err| err.into_inner());
+    self.files.iter().map(|entry| urls[entry.key().0 as usize].clone()).collect()
+  }
+
+  /// Indexed files below a workspace folder, without their symbols
+  pub fn urls_in(&self, scope: &Url) -> Vec<Url> {
+    self.urls().into_iter().filter(|uri| in_scope(uri, scope)).collect()
+  }
+
+  /// Symbols of a file on disk, indexing it first if it is not known yet
+  pub fn load_file(&self, path: &Path) -> Option<DocumentSymbols> {
+    let uri = paths::path_to_uri(path)?;
+    if let Some(symbols) = self.get(&uri) { return Some(symbols); }
+
+    let symbols = index_file(path)?;
+    self.update(uri, symbols.clone());
+    Some(symbols)
+  }
+
+  /// Every indexed file with its symbols
+  pub fn entries(&self) -> Vec<(Url, DocumentSymbols)> {
+    let (urls, names) = (self.urls.read().unwrap_or_else(|err| err.into_inner()), self.names.read());
+    self.files.iter().map(|entry| (urls[entry.key().0 as usize].clone(), entry.value().expand(&names))).collect()
+  }
+
+  /// Indexed files below a workspace folder
+  pub fn entries_in(&self, scope: &Url) -> Vec<(Url, DocumentSymbols)> {
+    let (urls, names) = (self.urls.read().unwrap_or_else(|err| err.into_inner()), self.names.read());
+    self.files.iter()
+      .filter(|entry| in_scope(&urls[entry.key().0 as usize], scope))
+      .map(|entry| (urls[entry.key().0 as usize].clone(), entry.value().expand(&names)))
+      .collect()
+  }
+
+  /// Drop every file below a workspace folder
+  pub fn remove_scope(&self, scope: &Url) {
+    let urls = self.urls.read().unwrap_or_else(|err| err.into_inner());
+    self.revisions.retain(|uri, _| !in_scope(uri, scope));
+    self.files.retain(|file, _| !in_scope(&urls[file.0 as usize], scope));
+  }
+
+  /// References to a name in every file of the workspace folder except `exclude`
+  pub fn external_references(&self, name: &str, exclude: &Url, scope: &Url) -> usize {
+    let name = match self.names.get(name) { Some(name) => name, None => return 0 };
+    let exclude = self.file_id(exclude);
+
+    self.query_scope(scope, |file, compact| {
+      if Some(file) == exclude { return 0; }
+      compact.references.iter().filter(|reference| reference.name == name).count()
+    }).into_iter().sum()
+  }
+
+  /// Top level functions, objects and variables with the given name within a workspace folder
+  pub fn find_exports(&self, name: &str, scope: &Url) -> Vec<(Url, Symbol)> {
+    self.top_level(scope, |symbol, _| symbol == name)
+      .into_iter()
+      .filter(|(_, symbol)| matches!(symbol.kind, SymbolKind::FUNCTION | SymbolKind::OBJECT | SymbolKind::VARIABLE))
+      .collect()
+  }
+
+  /// Top level functions with the given name within a workspace folder
+  pub fn find_functions(&self, name: &str, scope: &Url) -> Vec<(Url, Symbol)> {
+    self.top_level(scope, |symbol, kind| symbol == name && matches!(kind, SymbolKind::FUNCTION | SymbolKind::METHOD))
+  }
+
+  /// Declarations whose name or doc comment contains the query, name matches first
+  pub fn search(&self, query: &str) -> Vec<SymbolInformation> {
+    let files: Vec<FileId> = self.files.iter().map(|entry| *entry.key()).collect();
+    self.search_files(query, &files)
+  }
+
+  /// `search` within some of the files, to stream results a shard of files at a time.
+  /// Only the shard's files are looked at.
+  pub fn search_in(&self, query: &str, files: &[Url]) -> Vec<SymbolInformation> {
+    let files: Vec<FileId> = files.iter().filter_map(|uri| self.file_id(uri)).collect();
+    self.search_files(query, &files)
+  }
+
+  fn search_files(&self, query: &str, files: &[FileId]) -> Vec<SymbolInformation> {
+    let query = query.to_lowercase();
+    let (urls, names) = (self.urls.read().unwrap_or_else(|err| err.into_inner()), self.names.read());
+
+    let mut found: Vec<(bool, SymbolInformation)> = files.iter()
+      .filter_map(|file| self.files.get(file).map(|compact| (*file, compact)))
+      .flat_map(|(file, compact)| {
+        let uri = &urls[file.0 as usize];
+        compact.symbols.iter()
+          .filter(|symbol| symbol.kind != SymbolKind::MODULE)
+          .filter(|symbol| {
+            names.text(symbol.name).to_lowercase().contains(&query)
+              || symbol.doc.as_ref().map_or(false, |doc| doc.to_lowercase().contains(&query))
+          })
+          .map(|symbol| symbol.expand(&names))
+          .map(|symbol| (!symbol.name.to_lowercase().contains(&query), symbol_information(uri, &symbol)))
+          .collect::<Vec<_>>()
+      })
+      .collect();
+
+    found.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.name.cmp(&b.1.name)));
+    found.into_iter().map(|(_, information)| information).collect()
+  }
+
+  // --| Name Queries ------------------
+  /// The interned name, `None` when no indexed file uses it
+  pub fn name(&self, text: &str) -> Option<Name> {
+    self.names.get(text)
+  }
+
+  pub fn name_text(&self, name: Name) -> Arc<str> {
+    self.names.resolve(name)
+  }
+
+  pub fn file_id(&self, uri: &Url) -> Option<FileId> {
+    self.ids.get(uri).map(|file| *file)
+  }
+
+  pub fn file_url(&self, file: FileId) -> Option<Url> {
+    self.urls.read().unwrap_or_else(|err| err.into_inner()).get(file.0 as usize).cloned()
+  }
+
+  pub fn location(&self, span: Span) -> Option<Location> {
+    Some(Location::new(self.file_url(span.file)?, span.range))
+  }
+
+  /// Name ranges of every declaration of a name within a workspace folder
+  pub fn declarations(&self, name: Name, scope: &Url) -> Vec<Span> {
+    self.query_scope(scope, |file, compact| compact.symbols.iter()
+      .filter(|symbol| symbol.name == name)
+      .map(|symbol| Span { file, range: symbol.selection_range })
+      .collect::<Vec<_>>()
+    ).concat()
+  }
+
+  /// Every use of a name within a workspace folder, member accesses included
+  pub fn references(&self, name: Name, scope: &Url) -> Vec<Span> {
+    self.query_scope(scope, |file, compact| compact.references.iter()
+      .filter(|reference| reference.name == name)
+      .map(|reference| Span { file, range: reference.range })
+      .collect::<Vec<_>>()
+    ).concat()
+  }
+
+  /// Files of a workspace folder declaring, binding, calling or referring to a name
+  pub fn files_mentioning(&self, name: Name, scope: &Url) -> Vec<Url> {
+    let files = self.query_scope(scope, |file, compact| compact.mentions(name).then_some(file));
+    files.into_iter().flatten().filter_map(|file| self.file_url(file)).collect()
+  }
+
+  /// Top level declarations of a workspace folder whose name and kind pass the filter.
+  /// Only matching symbols are built.
+  pub fn top_level(&self, scope: &Url, filter: impl Fn(&str, SymbolKind) -> bool) -> Vec<(Url, Symbol)> {
+    let (urls, names) = (self.urls.read().unwrap_or_else(|err| err.into_inner()), self.names.read());
+    self.files.iter()
+      .filter(|entry| in_scope(&urls[entry.key().0 as usize], scope))
+      .flat_map(|entry| {
+        let uri = &urls[entry.key().0 as usize];
+        entry.value().symbols.iter()
+          .filter(|symbol| symbol.container.is_none() && filter(names.text(symbol.name), symbol.kind))
+          .map(|symbol| (uri.clone(), symbol.expand(&names)))
+          .collect::<Vec<_>>()
+      })
+      .collect()
+  }
+
+  /// Run a query over the compact files of a workspace folder, without building `DocumentSymbols`
+  fn query_scope<T>(&self, scope: &Url, mut query: impl FnMut(FileId, &CompactFile) -> T) -> Vec<T> {
+    let urls = self.urls.read().unwrap_or_else(|err| err.into_inner());
+    self.files.iter()
+      .filter(|entry| in_scope(&urls[entry.key().0 as usize], scope))
+      .map(|entry| query(*entry.key(), entry.value()))
+      .collect()
+  }
+
+  fn file_id_or_insert(&self, uri: Url) -> FileId {
+    if let Some(file) = self.file_id(&uri) { return file; }
+
+    let mut urls = self.urls.write().unwrap_or_else(|err| err.into_inner());
+    *self.ids.entry(uri.clone()).or_insert_with(|| {
+      urls.push(uri);
+      FileId(urls.len() as u32 - 1)
+    })
+  }
+}
+
+/// Whether a file belongs to a workspace folder, so multiple roots don't see each other's symbols
+pub fn in_scope(uri: &Url, scope: &Url) -> bool {
+  let folder = scope.as_str().trim_end_matches('/');
+  uri.as_str().strip_prefix(folder).map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Parse a source string and collect its symbols
+pub fn index_source(source: &str) -> Option<DocumentSymbols> {
+  let mut parser = cyber_tree_sitter::try_init_parser().ok()?;
+  let tree = parser.parse(source, None)?;
+  Some(collect_symbols(tree.root_node(), source))
+}
+
+/// Symbols of a source file on disk
+fn index_file(path: &Path) -> Option<DocumentSymbols> {
+  index_source(&std::fs::read_to_string(path).ok()?)
+}
+
+/// Index every cyber source file below a folder, skipping ignored paths. Blocking.
+pub fn scan_folder(root: &Path, ignore_patterns: &[String], extensions: &[String]) -> Vec<(Url, DocumentSymbols)> {
+  let mut indexed = vec![];
+  #[cfg(feature = "index-cache")]
+  let mut cache = cache::IndexCache::load(root);
+
+  for entry in ignores::walker(root, ignore_patterns) {
+    let entry = match entry {
+      Ok(entry) => entry,
+      Err(err) => { debug!("Skipping unreadable path below {:?}: {}", root, err); continue; }
+    };
+
+    let path = entry.path();
+    if !is_source_path(path, extensions) || !path.is_file() { continue; }
+
+    #[cfg(feature = "index-cache")]
+    let symbols = cache.get_or_index(path, index_file);
+    #[cfg(not(feature = "index-cache"))]
+    let symbols = index_file(path);
+
+    if let (Some(uri), Some(symbols)) = (paths::path_to_uri(path), symbols) {
+      indexed.push((uri, symbols));
+    }
+  }
+
+  #[cfg(feature = "index-cache")]
+  cache.save();
+
+  info!("Indexed {} files in {:?}", indexed.len(), root);
+  indexed
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn index(files: &[(&str, &str)]) -> WorkspaceIndex {
+    let index = WorkspaceIndex::new();
+    for (name, source) in files {
+      let tree = cyber_tree_sitter::init_parser().parse(source, None).unwrap();
+      let uri = Url::parse(&format!("file:///work/{}", name)).unwrap();
+      index.update(uri, collect_symbols(tree.root_node(), source));
+    }
+    index
+  }
+
+  fn names(found: &[SymbolInformation]) -> Vec<&str> {
+    found.iter().map(|information| information.name.as_str()).collect()
+  }
+
+  #[test]
+  fn search_ranks_name_matches_before_doc_matches() {
+    let index = index(&[
+      ("shapes.cy", "-- Area of a circle\nfunc area(radius):\n  return radius\n\n-- Area of a square\nfunc side(length):\n  return length\n"),
+      ("geo.cy", "func surface_area(a):\n  return a\n\nfunc unrelated():\n  pass\n"),
+    ]);
+
+    assert_eq!(names(&index.search("area")), vec!["area", "surface_area", "side"]);
+    assert_eq!(names(&index.search("SQUARE")), vec!["side"]);
+    assert!(index.search("missing").is_empty());
+  }
+
+  #[test]
+  fn search_in_only_looks_at_the_given_files() {
+    let index = index(&[("a.cy", "func area():\n  pass\n"), ("b.cy", "func area_b():\n  pass\n")]);
+    let only_b = [Url::parse("file:///work/b.cy").unwrap()];
+    let found = index.search_in("area", &only_b);
+    assert_eq!(names(&found), vec!["area_b"]);
+    assert_eq!(found[0].location.uri, only_b[0]);
+  }
+}
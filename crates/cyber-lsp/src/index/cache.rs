@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::symbols::DocumentSymbols;
+
+// --| Index Cache -------------------
+// --|--------------------------------
+/// Symbols of the files below a workspace folder as of the last scan, stored in the
+/// user cache directory. Files whose size and modification time are unchanged are
+/// not parsed again. The cache of another server version is discarded.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IndexCache {
+  version: String,
+  files: HashMap<PathBuf, CachedFile>,
+  /// Files looked up during this scan, the rest are dropped on save
+  #[serde(skip)]
+  seen: HashMap<PathBuf, CachedFile>,
+  #[serde(skip)]
+  path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedFile {
+  modified: u128,
+  len: u64,
+  symbols: DocumentSymbols,
+}
+
+impl IndexCache {
+  /// The cache of a folder, empty when there is none or it can't be read
+  pub fn load(root: &Path) -> Self {
+    let path = cache_path(root);
+    let cache = path.as_ref()
+      .and_then(|path| std::fs::read(path).ok())
+      .and_then(|bytes| serde_json::from_slice::<IndexCache>(&bytes).ok())
+      .filter(|cache| cache.version == env!("CARGO_PKG_VERSION"));
+
+    let mut cache = cache.unwrap_or_default();
+    cache.version = env!("CARGO_PKG_VERSION").to_string();
+    cache.path = path;
+    cache
+  }
+
+  /// Cached symbols of an unchanged file, or else those of `index`
+  pub fn get_or_index(&mut self, path: &Path, index: impl FnOnce(&Path) -> Option<DocumentSymbols>) -> Option<DocumentSymbols> {
+    let (modified, len) = match stamp(path) {
+      Some(stamp) => stamp,
+      None => return index(path),
+    };
+
+    let cached = self.files.remove(path).filter(|file| file.modified == modified && file.len == len);
+    let symbols = match cached {
+      Some(file) => file.symbols,
+      None => index(path)?,
+    };
+
+    self.seen.insert(path.to_path_buf(), CachedFile { modified, len, symbols: symbols.clone() });
+    Some(symbols)
+  }
+
+  /// Write the files looked up since loading, failures are only logged. The cache is
+  /// written aside and renamed over the old one, so a crash never leaves half of it.
+  pub fn save(mut self) {
+    let path = match self.path.take() { Some(path) => path, None => return };
+    self.files = std::mem::take(&mut self.seen);
+    let partial = path.with_extension(format!("{}.partial", std::process::id()));
+
+    let written = path.parent().map_or(Ok(()), std::fs::create_dir_all)
+      .and_then(|_| serde_json::to_vec(&self).map_err(std::io::Error::from))
+      .and_then(|bytes| std::fs::write(&partial, bytes))
+      .and_then(|_| std::fs::rename(&partial, &path));
+
+    if let Err(err) = written {
+      debug!("Failed to write the index cache {:?}: {}", path, err);
+      let _ = std::fs::remove_file(&partial);
+    }
+  }
+}
+
+/// Where the cache of a folder is stored, keyed by a digest of its path
+fn cache_path(root: &Path) -> Option<PathBuf> {
+  let digest = format!("{:x}", Sha256::digest(root.to_string_lossy().as_bytes()));
+  Some(dirs::cache_dir()?.join("cyberls").join("index").join(format!("{}.json", digest)))
+}
+
+fn stamp(path: &Path) -> Option<(u128, u64)> {
+  let metadata = std::fs::metadata(path).ok()?;
+  let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+  Some((modified, metadata.len()))
+}
@@ -0,0 +1,184 @@
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+use dashmap::DashMap;
+use lsp_types::{Range, SymbolKind};
+
+use crate::symbols::{CallSite, DocumentSymbols, Parameter, Reference, Symbol};
+
+// --| Interned Names ----------------
+// --|--------------------------------
+/// A symbol name stored once for the whole index
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Name(u32);
+
+/// Names are never released, their count is bounded by the distinct identifiers of the workspace
+#[derive(Default, Debug)]
+pub struct Interner {
+  ids: DashMap<Arc<str>, Name>,
+  names: RwLock<Vec<Arc<str>>>,
+}
+
+impl Interner {
+  pub fn intern(&self, text: &str) -> Name {
+    if let Some(name) = self.ids.get(text) { return *name; }
+
+    let mut names = self.names.write().unwrap_or_else(|err| err.into_inner());
+    // Another thread may have interned it while the lock was taken
+    if let Some(name) = self.ids.get(text) { return *name; }
+
+    let text: Arc<str> = Arc::from(text);
+    let name = Name(names.len() as u32);
+    names.push(text.clone());
+    self.ids.insert(text, name);
+    name
+  }
+
+  /// A name, if any indexed file uses it
+  pub fn get(&self, text: &str) -> Option<Name> {
+    self.ids.get(text).map(|name| *name)
+  }
+
+  pub fn resolve(&self, name: Name) -> Arc<str> {
+    self.names.read().unwrap_or_else(|err| err.into_inner())[name.0 as usize].clone()
+  }
+
+  /// Every name, to resolve many without locking for each
+  pub fn read(&self) -> Names<'_> {
+    Names(self.names.read().unwrap_or_else(|err| err.into_inner()))
+  }
+}
+
+pub struct Names<'a>(RwLockReadGuard<'a, Vec<Arc<str>>>);
+
+impl Names<'_> {
+  pub fn text(&self, name: Name) -> &str {
+    &self.0[name.0 as usize]
+  }
+}
+
+// --| Compact Symbols ---------------
+// --|--------------------------------
+/// `DocumentSymbols` as kept by the index: names interned, lists boxed to their length
+#[derive(Debug)]
+pub struct CompactFile {
+  pub symbols: Box<[CompactSymbol]>,
+  pub calls: Box<[CompactCall]>,
+  pub references: Box<[CompactReference]>,
+  pub bindings: Box<[CompactParameter]>,
+}
+
+#[derive(Debug)]
+pub struct CompactSymbol {
+  pub name: Name,
+  pub kind: SymbolKind,
+  pub range: Range,
+  pub selection_range: Range,
+  pub params: Box<[CompactParameter]>,
+  pub container: Option<Name>,
+  pub doc: Option<Box<str>>,
+}
+
+#[derive(Debug)]
+pub struct CompactParameter {
+  pub name: Name,
+  pub range: Range,
+}
+
+#[derive(Debug)]
+pub struct CompactCall {
+  pub callee: Name,
+  pub receiver: Option<Name>,
+  pub range: Range,
+  pub args_range: Option<Range>,
+  pub arg_ranges: Box<[Range]>,
+  pub named_args: Box<[CompactParameter]>,
+}
+
+#[derive(Debug)]
+pub struct CompactReference {
+  pub name: Name,
+  pub range: Range,
+  pub member: bool,
+}
+
+impl CompactFile {
+  pub fn compact(symbols: DocumentSymbols, interner: &Interner) -> Self {
+    let parameters = |params: Vec<Parameter>| -> Box<[CompactParameter]> {
+      params.into_iter().map(|param| CompactParameter { name: interner.intern(&param.name), range: param.range }).collect()
+    };
+
+    CompactFile {
+      symbols: symbols.symbols.into_iter().map(|symbol| CompactSymbol {
+        name: interner.intern(&symbol.name),
+        kind: symbol.kind,
+        range: symbol.range,
+        selection_range: symbol.selection_range,
+        params: parameters(symbol.params),
+        container: symbol.container.map(|container| interner.intern(&container)),
+        doc: symbol.doc.map(String::into_boxed_str),
+      }).collect(),
+      calls: symbols.calls.into_iter().map(|call| CompactCall {
+        callee: interner.intern(&call.callee),
+        receiver: call.receiver.map(|receiver| interner.intern(&receiver)),
+        range: call.range,
+        args_range: call.args_range,
+        arg_ranges: call.arg_ranges.into_boxed_slice(),
+        named_args: parameters(call.named_args),
+      }).collect(),
+      references: symbols.references.into_iter().map(|reference| CompactReference {
+        name: interner.intern(&reference.name),
+        range: reference.range,
+        member: reference.member,
+      }).collect(),
+      bindings: parameters(symbols.bindings),
+    }
+  }
+
+  pub fn expand(&self, names: &Names) -> DocumentSymbols {
+    DocumentSymbols {
+      symbols: self.symbols.iter().map(|symbol| symbol.expand(names)).collect(),
+      calls: self.calls.iter().map(|call| CallSite {
+        callee: names.text(call.callee).to_string(),
+        receiver: call.receiver.map(|receiver| names.text(receiver).to_string()),
+        range: call.range,
+        args_range: call.args_range,
+        arg_ranges: call.arg_ranges.to_vec(),
+        named_args: call.named_args.iter().map(|param| param.expand(names)).collect(),
+      }).collect(),
+      references: self.references.iter().map(|reference| Reference {
+        name: names.text(reference.name).to_string(),
+        range: reference.range,
+        member: reference.member,
+      }).collect(),
+      bindings: self.bindings.iter().map(|binding| binding.expand(names)).collect(),
+    }
+  }
+
+  /// Whether the file declares, binds, calls or refers to a name
+  pub fn mentions(&self, name: Name) -> bool {
+    self.symbols.iter().any(|symbol| symbol.name == name || symbol.params.iter().any(|param| param.name == name))
+      || self.references.iter().any(|reference| reference.name == name)
+      || self.calls.iter().any(|call| call.callee == name)
+      || self.bindings.iter().any(|binding| binding.name == name)
+  }
+}
+
+impl CompactSymbol {
+  pub fn expand(&self, names: &Names) -> Symbol {
+    Symbol {
+      name: names.text(self.name).to_string(),
+      kind: self.kind,
+      range: self.range,
+      selection_range: self.selection_range,
+      params: self.params.iter().map(|param| param.expand(names)).collect(),
+      container: self.container.map(|container| names.text(container).to_string()),
+      doc: self.doc.as_ref().map(|doc| doc.to_string()),
+    }
+  }
+}
+
+impl CompactParameter {
+  fn expand(&self, names: &Names) -> Parameter {
+    Parameter { name: names.text(self.name).to_string(), range: self.range }
+  }
+}
@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+
+use lsp_types::{CompletionItem, CompletionItemKind, DiagnosticSeverity};
+use tree_sitter::Node;
+
+use crate::diagnostics::ErrorInfo;
+
+// --| Types -------------------------
+// --|--------------------------------
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CyType {
+  Any,
+  None,
+  Bool,
+  Int,
+  Number,
+  String,
+  List(Box<CyType>),
+  Map(Box<CyType>),
+  Function(Box<CyType>),
+  Fiber,
+  Error,
+}
+
+impl std::fmt::Display for CyType {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      CyType::Any => write!(f, "any"),
+      CyType::None => write!(f, "none"),
+      CyType::Bool => write!(f, "bool"),
+      CyType::Int => write!(f, "int"),
+      CyType::Number => write!(f, "number"),
+      CyType::String => write!(f, "string"),
+      CyType::List(inner) => write!(f, "List[{}]", inner),
+      CyType::Map(inner) => write!(f, "Map[{}]", inner),
+      CyType::Function(ret) => write!(f, "func -> {}", ret),
+      CyType::Fiber => write!(f, "fiber"),
+      CyType::Error => write!(f, "error"),
+    }
+  }
+}
+
+impl CyType {
+  pub fn is_numeric(&self) -> bool {
+    matches!(self, CyType::Int | CyType::Number)
+  }
+
+  /// Flow-insensitive merge of two observed types for the same binding
+  pub fn join(self, other: CyType) -> CyType {
+    match (self, other) {
+      (a, b) if a == b => a,
+      (CyType::Int, CyType::Number) | (CyType::Number, CyType::Int) => CyType::Number,
+      (CyType::List(a), CyType::List(b)) => CyType::List(Box::new(a.join(*b))),
+      (CyType::Map(a), CyType::Map(b)) => CyType::Map(Box::new(a.join(*b))),
+      _ => CyType::Any,
+    }
+  }
+
+  /// Methods callable on a value of this type and the names of their return types
+  pub fn methods(&self) -> &'static [(&'static str, &'static str)] {
+    match self {
+      CyType::String => STRING_METHODS,
+      CyType::List(_) => LIST_METHODS,
+      CyType::Map(_) => MAP_METHODS,
+      CyType::Fiber => FIBER_METHODS,
+      _ => &[],
+    }
+  }
+
+  /// Parse a type name as written in the method tables
+  pub fn from_name(name: &str) -> CyType {
+    match name {
+      "none" => CyType::None,
+      "bool" => CyType::Bool,
+      "int" => CyType::Int,
+      "number" => CyType::Number,
+      "string" => CyType::String,
+      "fiber" => CyType::Fiber,
+      "error" => CyType::Error,
+      _ => match name.strip_prefix("List[").and_then(|inner| inner.strip_suffix(']')) {
+        Some(inner) => CyType::List(Box::new(CyType::from_name(inner))),
+        None => CyType::Any,
+      },
+    }
+  }
+}
+
+const STRING_METHODS: &[(&str, &str)] = &[
+  ("append", "string"), ("charAt", "string"), ("codeAt", "int"), ("concat", "string"),
+  ("endsWith", "bool"), ("find", "int"), ("insert", "string"), ("isAscii", "bool"),
+  ("len", "int"), ("lower", "string"), ("repeat", "string"), ("replace", "string"),
+  ("slice", "string"), ("split", "List[string]"), ("startsWith", "bool"), ("trim", "string"),
+  ("upper", "string"),
+];
+
+const LIST_METHODS: &[(&str, &str)] = &[
+  ("append", "none"), ("concat", "none"), ("insert", "none"), ("iterator", "any"),
+  ("joinString", "string"), ("len", "int"), ("remove", "none"), ("resize", "none"),
+  ("slice", "any"), ("sort", "none"),
+];
+
+const MAP_METHODS: &[(&str, &str)] = &[
+  ("iterator", "any"), ("remove", "none"), ("size", "int"),
+];
+
+const FIBER_METHODS: &[(&str, &str)] = &[
+  ("status", "any"),
+];
+
+/// Return types of builtin functions
+fn builtin_return(name: &str) -> Option<CyType> {
+  Some(match name {
+    "print" | "prints" | "panic" => CyType::None,
+    "bool" | "isAlpha" | "isDigit" => CyType::Bool,
+    "int" | "asciiCode" | "typeid" => CyType::Int,
+    "number" => CyType::Number,
+    "string" | "toCyon" | "typeof" | "char" => CyType::String,
+    "arrayFill" => CyType::List(Box::new(CyType::Any)),
+    "error" => CyType::Error,
+    _ => return None,
+  })
+}
+
+// --| Type Environment --------------
+// --|--------------------------------
+#[derive(Default, Debug, Clone)]
+pub struct TypeEnv {
+  pub variables: HashMap<String, CyType>,
+  pub functions: HashMap<String, CyType>,
+}
+
+impl TypeEnv {
+  pub fn lookup(&self, name: &str) -> Option<&CyType> {
+    self.variables.get(name).or_else(|| self.functions.get(name))
+  }
+
+  fn bind(&mut self, name: &str, ty: CyType) {
+    let joined = match self.variables.remove(name) {
+      Some(existing) => existing.join(ty),
+      None => ty,
+    };
+    self.variables.insert(name.to_string(), joined);
+  }
+}
+
+/// Infer the types of every binding in a document
+pub fn infer_document(root: Node, source: &str) -> TypeEnv {
+  let mut env = TypeEnv::default();
+  collect_functions(root, source, &mut env);
+
+  // Run twice so variables assigned from later bindings settle
+  for _ in 0..2 {
+    let mut next = TypeEnv { variables: HashMap::new(), functions: env.functions.clone() };
+    visit(root, &mut |node| {
+      if let Some((name, value)) = assignment_parts(node, source) {
+        let ty = infer_expr(value, source, &env);
+        next.bind(name, ty);
+      }
+    });
+    env = next;
+  }
+
+  env
+}
+
+fn collect_functions(root: Node, source: &str, env: &mut TypeEnv) {
+  let empty = TypeEnv::default();
+
+  visit(root, &mut |node| {
+    if node.kind() != "function_definition" { return; }
+    let name = match name_of(node, source) { Some(name) => name, None => return };
+
+    let mut ret: Option<CyType> = None;
+    visit(node, &mut |inner| {
+      if inner.kind() != "return_statement" { return; }
+      let ty = inner.named_child(0).map_or(CyType::None, |expr| infer_expr(expr, source, &empty));
+      ret = Some(match ret.take() { Some(prev) => prev.join(ty), None => ty });
+    });
+
+    env.functions.insert(name.to_string(), CyType::Function(Box::new(ret.unwrap_or(CyType::None))));
+  });
+}
+
+/// Infer the type of an expression node
+pub fn infer_expr(node: Node, source: &str, env: &TypeEnv) -> CyType {
+  match node.kind() {
+    "integer" | "int" => CyType::Int,
+    "float" | "number" => CyType::Number,
+    "string" | "raw_string" | "string_literal" => CyType::String,
+    "true" | "false" | "boolean" => CyType::Bool,
+    "none" => CyType::None,
+    "list" | "list_literal" | "array" => {
+      let element = node.named_child(0).map_or(CyType::Any, |first| infer_expr(first, source, env));
+      CyType::List(Box::new(element))
+    }
+    "map" | "map_literal" => {
+      let value = node.named_child(0)
+        .and_then(|entry| entry.child_by_field_name("value").or_else(|| entry.named_child(1)))
+        .map_or(CyType::Any, |value| infer_expr(value, source, env));
+      CyType::Map(Box::new(value))
+    }
+    "identifier" => {
+      let name = node.utf8_text(source.as_bytes()).unwrap_or_default();
+      env.lookup(name).cloned().unwrap_or(CyType::Any)
+    }
+    "parenthesized_expression" => node.named_child(0).map_or(CyType::Any, |inner| infer_expr(inner, source, env)),
+    "unary_expression" => match operator_of(node, source) {
+      Some("!") | Some("not") => CyType::Bool,
+      _ => node.named_child(0).map_or(CyType::Any, |inner| infer_expr(inner, source, env)),
+    },
+    "binary_expression" => infer_binary(node, source, env),
+    "call_expression" => infer_call(node, source, env),
+    "coinit_expression" => CyType::Fiber,
+    _ => CyType::Any,
+  }
+}
+
+fn infer_binary(node: Node, source: &str, env: &TypeEnv) -> CyType {
+  let (left, right) = match operands(node) {
+    Some(operands) => operands,
+    None => return CyType::Any,
+  };
+
+  let operator = operator_of(node, source).unwrap_or_default();
+  let (left, right) = (infer_expr(left, source, env), infer_expr(right, source, env));
+
+  match operator {
+    "==" | "!=" | "<" | ">" | "<=" | ">=" | "and" | "or" | "&&" | "||" => CyType::Bool,
+    "/" if left.is_numeric() && right.is_numeric() => CyType::Number,
+    "+" | "-" | "*" | "%" | "^" if left == CyType::Int && right == CyType::Int => CyType::Int,
+    "+" | "-" | "*" | "%" | "^" if left.is_numeric() && right.is_numeric() => CyType::Number,
+    "+" if left == CyType::String && right == CyType::String => CyType::String,
+    _ => CyType::Any,
+  }
+}
+
+fn infer_call(node: Node, source: &str, env: &TypeEnv) -> CyType {
+  let callee = match node.child_by_field_name("function").or_else(|| node.named_child(0)) {
+    Some(callee) => callee,
+    None => return CyType::Any,
+  };
+
+  // Method call on a typed receiver, ie. `name.upper()`
+  if let Some(property) = callee.child_by_field_name("property") {
+    let receiver = callee.child_by_field_name("object").or_else(|| callee.named_child(0));
+    let receiver_ty = receiver.map_or(CyType::Any, |receiver| infer_expr(receiver, source, env));
+    let method = property.utf8_text(source.as_bytes()).unwrap_or_default();
+
+    return receiver_ty.methods().iter()
+      .find(|(name, _)| *name == method)
+      .map_or(CyType::Any, |(_, ret)| CyType::from_name(ret));
+  }
+
+  let name = callee.utf8_text(source.as_bytes()).unwrap_or_default();
+  match env.functions.get(name) {
+    Some(CyType::Function(ret)) => *ret.clone(),
+    _ => builtin_return(name).unwrap_or(CyType::Any),
+  }
+}
+
+// --| Type Diagnostics --------------
+// --|--------------------------------
+/// Warn about operators applied to operands that can never support them
+pub fn check_types(root: Node, source: &str) -> Option<ErrorInfo> {
+  let env = infer_document(root, source);
+  let mut error_info = ErrorInfo::new();
+
+  visit(root, &mut |node| {
+    if node.kind() != "binary_expression" { return; }
+    let (left, right) = match operands(node) { Some(operands) => operands, None => return };
+    let operator = operator_of(node, source).unwrap_or_default();
+
+    let (left_ty, right_ty) = (infer_expr(left, source, &env), infer_expr(right, source, &env));
+    let mixes_string = (left_ty == CyType::String && right_ty.is_numeric())
+      || (left_ty.is_numeric() && right_ty == CyType::String);
+
+    let mismatch = match operator {
+      "+" => mixes_string,
+      "-" | "*" | "/" | "%" | "^" => left_ty == CyType::String || right_ty == CyType::String,
+      _ => false,
+    };
+
+    if mismatch {
+      error_info.add(
+        node.start_position(),
+        node.end_position(),
+        format!("Operator '{}' is not defined for {} and {}", operator, left_ty, right_ty),
+        Some(DiagnosticSeverity::WARNING),
+      );
+    }
+  });
+
+  if error_info.entries.is_empty() { None } else { Some(error_info) }
+}
+
+// --| Member Completion -------------
+// --|--------------------------------
+/// Methods offered after `value.` for a value of the given type
+pub fn member_completions(ty: &CyType) -> Vec<CompletionItem> {
+  ty.methods().iter().map(|(name, ret)| CompletionItem {
+    label: format!("{name}()"),
+    kind: Some(CompletionItemKind::METHOD),
+    detail: Some(format!("{}.{}() -> {}", ty, name, ret)),
+    ..Default::default()
+  }).collect()
+}
+
+// --| Tree Helpers ------------------
+// --|--------------------------------
+fn visit<'t>(node: Node<'t>, f: &mut impl FnMut(Node<'t>)) {
+  f(node);
+  let mut cursor = node.walk();
+  for child in node.named_children(&mut cursor) {
+    visit(child, f);
+  }
+}
+
+fn name_of<'s>(node: Node, source: &'s str) -> Option<&'s str> {
+  let name = node.child_by_field_name("name").or_else(|| {
+    let mut cursor = node.walk();
+    let found = node.named_children(&mut cursor).find(|child| child.kind() == "identifier");
+    found
+  })?;
+  name.utf8_text(source.as_bytes()).ok()
+}
+
+/// The bound name and value of `a = expr` / `var a = expr`
+fn assignment_parts<'t, 's>(node: Node<'t>, source: &'s str) -> Option<(&'s str, Node<'t>)> {
+  if !matches!(node.kind(), "assignment_statement" | "variable_def" | "variable_declaration") { return None; }
+
+  let left = node.child_by_field_name("left").or_else(|| node.child_by_field_name("name")).or_else(|| node.named_child(0))?;
+  let right = node.child_by_field_name("right").or_else(|| node.child_by_field_name("value"))
+    .or_else(|| node.named_child(node.named_child_count().checked_sub(1)?))?;
+
+  if left.kind() != "identifier" || left == right { return None; }
+  Some((left.utf8_text(source.as_bytes()).ok()?, right))
+}
+
+fn operands<'t>(node: Node<'t>) -> Option<(Node<'t>, Node<'t>)> {
+  let left = node.child_by_field_name("left").or_else(|| node.named_child(0))?;
+  let right = node.child_by_field_name("right").or_else(|| node.named_child(1))?;
+  Some((left, right))
+}
+
+fn operator_of<'s>(node: Node, source: &'s str) -> Option<&'s str> {
+  let operator = node.child_by_field_name("operator").or_else(|| {
+    let mut cursor = node.walk();
+    let found = node.children(&mut cursor).find(|child| !child.is_named());
+    found
+  })?;
+  operator.utf8_text(source.as_bytes()).ok()
+}
@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use tracing::debug;
+use tree_sitter::{Node, QueryCursor};
+
+// --| Injected Languages ------------
+// --|--------------------------------
+/// Languages recognised inside string literals
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Injection {
+  /// `{expr}` interpolation placeholders
+  Format,
+  /// A string holding a JSON object or array
+  Json,
+  /// The command passed to `os.exec`
+  Shell,
+}
+
+/// Highlight category of a token inside an injected snippet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InjectedKind {
+  Keyword,
+  String,
+  Number,
+  Property,
+  Variable,
+  Function,
+  Parameter,
+  Operator,
+}
+
+/// A token of an injected snippet, as absolute byte offsets into the document
+#[derive(Clone, Debug)]
+pub struct InjectedToken {
+  pub bytes: Range<usize>,
+  pub kind: InjectedKind,
+}
+
+impl Injection {
+  /// The language an `injection.language` setting of the injections query names
+  fn from_language(language: &str) -> Option<Injection> {
+    match language {
+      "bash" | "sh" => Some(Injection::Shell),
+      "json" => Some(Injection::Json),
+      "format" => Some(Injection::Format),
+      _ => None,
+    }
+  }
+
+  /// Which injection a string matching several patterns gets
+  fn priority(self) -> u8 {
+    match self {
+      Injection::Shell => 2,
+      Injection::Json => 1,
+      Injection::Format => 0,
+    }
+  }
+}
+
+/// The languages embedded in the string literals below `root`, by node id, as captured
+/// by the grammar's `injections.scm` query
+pub fn find_injections(root: Node, source: &str) -> HashMap<usize, Injection> {
+  let mut found: HashMap<usize, Injection> = HashMap::new();
+  let query = match cyber_tree_sitter::injections_query() {
+    Ok(query) => query,
+    Err(err) => {
+      debug!("No injections: {}", err);
+      return found;
+    }
+  };
+  let content = match query.capture_index_for_name("injection.content") {
+    Some(content) => content,
+    None => return found,
+  };
+
+  let mut cursor = QueryCursor::new();
+  for matched in cursor.matches(query, root, source.as_bytes()) {
+    let language = query.property_settings(matched.pattern_index).iter()
+      .find(|property| &*property.key == "injection.language")
+      .and_then(|property| property.value.as_deref())
+      .and_then(Injection::from_language);
+    let injection = match language {
+      Some(injection) => injection,
+      None => continue,
+    };
+
+    for capture in matched.captures.iter().filter(|capture| capture.index == content) {
+      let entry = found.entry(capture.node.id()).or_insert(injection);
+      if injection.priority() > entry.priority() { *entry = injection; }
+    }
+  }
+
+  found
+}
+
+/// Tokens of the snippet inside a string literal node
+pub fn injected_tokens(node: Node, source: &str, injection: Injection) -> Vec<InjectedToken> {
+  let (offset, content) = match string_content(node, source) {
+    Some(found) => found,
+    None => return vec![],
+  };
+
+  let tokens = match injection {
+    Injection::Format => placeholders(content).map(|range| (range, InjectedKind::Variable)).collect(),
+    Injection::Json => json_tokens(content),
+    Injection::Shell => shell_tokens(content),
+  };
+
+  tokens.into_iter()
+    .map(|(range, kind)| InjectedToken { bytes: offset + range.start..offset + range.end, kind })
+    .collect()
+}
+
+/// Text between the quotes, with its absolute start offset
+fn string_content<'a>(node: Node, source: &'a str) -> Option<(usize, &'a str)> {
+  let text = node.utf8_text(source.as_bytes()).ok()?;
+  let quote = text.chars().next().filter(|ch| matches!(ch, '\'' | '"' | '`'))?;
+  let inner = text.strip_prefix(quote)?.strip_suffix(quote).unwrap_or(&text[1..]);
+  Some((node.start_byte() + 1, inner))
+}
+
+// --| Tokenizers --------------------
+// --|--------------------------------
+/// Ranges of `{name}` placeholders, excluding the braces
+fn placeholders(content: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+  content.match_indices('{').filter_map(move |(start, _)| {
+    let end = start + content[start..].find('}')?;
+    let inner = &content[start + 1..end];
+    let valid = !inner.is_empty() && !inner.contains(['{', '"', '\n']) && !inner.trim_start().starts_with(['"', '\'']);
+    valid.then(|| start + 1..end)
+  })
+}
+
+fn json_tokens(content: &str) -> Vec<(Range<usize>, InjectedKind)> {
+  let bytes = content.as_bytes();
+  let mut tokens = vec![];
+  let mut index = 0;
+
+  while index < bytes.len() {
+    let ch = bytes[index];
+    match ch {
+      b'"' => {
+        let start = index;
+        index += 1;
+        while index < bytes.len() && bytes[index] != b'"' {
+          if bytes[index] == b'\\' { index += 1; }
+          index += 1;
+        }
+        index = (index + 1).min(bytes.len());
+
+        // A string followed by a colon is an object key
+        let is_key = content[index..].trim_start().starts_with(':');
+        tokens.push((start..index, if is_key { InjectedKind::Property } else { InjectedKind::String }));
+      }
+      b'-' | b'0'..=b'9' => {
+        let start = index;
+        while index < bytes.len() && matches!(bytes[index], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') { index += 1; }
+        tokens.push((start..index, InjectedKind::Number));
+      }
+      b'a'..=b'z' => {
+        let start = index;
+        while index < bytes.len() && bytes[index].is_ascii_alphabetic() { index += 1; }
+        if matches!(&content[start..index], "true" | "false" | "null") {
+          tokens.push((start..index, InjectedKind::Keyword));
+        }
+      }
+      b'{' | b'}' | b'[' | b']' | b':' | b',' => {
+        tokens.push((index..index + 1, InjectedKind::Operator));
+        index += 1;
+      }
+      _ => index += 1,
+    }
+  }
+
+  tokens
+}
+
+fn shell_tokens(content: &str) -> Vec<(Range<usize>, InjectedKind)> {
+  let mut tokens = vec![];
+  let mut command_expected = true;
+  let mut index = 0;
+
+  while index < content.len() {
+    let rest = &content[index..];
+    let word_len = match rest.chars().next() {
+      Some(ch) if ch.is_whitespace() => { index += ch.len_utf8(); continue; }
+      Some(quote @ ('"' | '\'')) => rest[1..].find(quote).map_or(rest.len(), |end| end + 2),
+      Some(_) => rest.find(|ch: char| ch.is_whitespace()).unwrap_or(rest.len()),
+      None => break,
+    };
+
+    let word = &rest[..word_len];
+    let kind = if matches!(word, "|" | "||" | "&&" | ";" | ">" | ">>" | "<" | "&") {
+      command_expected = word != ">" && word != ">>" && word != "<";
+      Some(InjectedKind::Operator)
+    } else if word.starts_with(['"', '\'']) {
+      Some(InjectedKind::String)
+    } else if command_expected {
+      command_expected = false;
+      Some(InjectedKind::Function)
+    } else if word.starts_with('-') {
+      Some(InjectedKind::Parameter)
+    } else if word.starts_with('$') {
+      Some(InjectedKind::Variable)
+    } else {
+      None
+    };
+
+    if let Some(kind) = kind { tokens.push((index..index + word_len, kind)); }
+    index += word_len;
+  }
+
+  tokens
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn injections(source: &str) -> Vec<(String, Injection)> {
+    let tree = cyber_tree_sitter::init_parser().parse(source, None).unwrap();
+    let found = find_injections(tree.root_node(), source);
+    let mut strings = vec![];
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+      if let Some(injection) = found.get(&node.id()) {
+        strings.push((node.utf8_text(source.as_bytes()).unwrap().to_string(), *injection));
+      }
+      let mut cursor = node.walk();
+      stack.extend(node.children(&mut cursor));
+    }
+    strings.sort_by(|a, b| a.0.cmp(&b.0));
+    strings
+  }
+
+  #[test]
+  fn strings_take_the_language_of_the_query_pattern() {
+    let source = "os.exec('ls -la {dir}')\nvar data = '{ \"a\": 1 }'\nvar msg = 'hello {name}'\nvar plain = 'hello'\n";
+    assert_eq!(injections(source), vec![
+      ("'hello {name}'".to_string(), Injection::Format),
+      ("'ls -la {dir}'".to_string(), Injection::Shell),
+      ("'{ \"a\": 1 }'".to_string(), Injection::Json),
+    ]);
+  }
+}
@@ -0,0 +1,80 @@
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// --| Lane Settings -----------------
+// --|--------------------------------
+/// How much work of each lane runs at once
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LanesConfig {
+  /// Hover, completion and semantic token requests
+  pub interactive: usize,
+  /// Workspace indexing and compiler runs
+  pub background: usize,
+}
+
+impl Default for LanesConfig {
+  fn default() -> Self {
+    Self { interactive: 8, background: 2 }
+  }
+}
+
+impl LanesConfig {
+  /// Messages the server handles at once, enough for both lanes to be busy. `serve` sets it
+  /// from the startup settings only, the transport can't be resized while running.
+  pub fn concurrency(&self) -> usize {
+    self.interactive.max(1) + self.background.max(1)
+  }
+}
+
+// --| Lanes -------------------------
+// --|--------------------------------
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lane {
+  Interactive,
+  Background,
+}
+
+/// Separate limits for interactive requests and background work, so a burst of compiler
+/// runs or a large reindex waits on its own lane instead of delaying hovers and completions
+pub struct Lanes {
+  interactive: RwLock<Arc<Semaphore>>,
+  background: RwLock<Arc<Semaphore>>,
+  config: RwLock<LanesConfig>,
+}
+
+impl Lanes {
+  pub fn new(config: &LanesConfig) -> Self {
+    Self {
+      interactive: RwLock::new(semaphore(config.interactive)),
+      background: RwLock::new(semaphore(config.background)),
+      config: RwLock::new(config.clone()),
+    }
+  }
+
+  /// Resize the lanes. Work already running keeps the permit of the old size.
+  pub fn configure(&self, config: &LanesConfig) {
+    let mut current = self.config.write().unwrap_or_else(|err| err.into_inner());
+    if *current == *config { return; }
+
+    if current.interactive != config.interactive { *self.interactive.write().unwrap_or_else(|err| err.into_inner()) = semaphore(config.interactive); }
+    if current.background != config.background { *self.background.write().unwrap_or_else(|err| err.into_inner()) = semaphore(config.background); }
+    *current = config.clone();
+  }
+
+  /// Wait for room on a lane, the work runs until the permit is dropped
+  pub async fn enter(&self, lane: Lane) -> Option<OwnedSemaphorePermit> {
+    let semaphore = match lane {
+      Lane::Interactive => self.interactive.read().unwrap_or_else(|err| err.into_inner()).clone(),
+      Lane::Background => self.background.read().unwrap_or_else(|err| err.into_inner()).clone(),
+    };
+    semaphore.acquire_owned().await.ok()
+  }
+}
+
+/// A lane of size 0 would block its work forever
+fn semaphore(size: usize) -> Arc<Semaphore> {
+  Arc::new(Semaphore::new(size.max(1)))
+}
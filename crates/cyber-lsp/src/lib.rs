@@ -0,0 +1,342 @@
+//! The Cyber language server as a library. `run_server` serves a client over stdio or
+//! tcp like the `cyberls` binary, `serve` over any pair of streams, and `build_service`
+//! hands out the `LspService` for embedders driving requests themselves.
+//!
+//! Without the default `native` feature nothing starts processes or threads and there
+//! is no stdio or tcp transport, so the crate builds for wasm32-wasi. The host, ie. a web
+//! worker, passes its message streams to `serve`. `compiler-diagnostics` and `run-commands`
+//! bring the features running the cyber executable, commands and requests of features
+//! left out are not advertised to the client.
+
+use std::ops::Deref;
+use std::sync::{Arc, RwLock, Weak};
+use std::sync::atomic::AtomicBool;
+use std::collections::HashMap;
+
+use lsp_types::{ClientCapabilities, Diagnostic, SemanticTokens, Url};
+use dashmap::DashMap;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, Mutex};
+use cyber_tree_sitter::{Tree, Parser};
+use tower_lsp::{ClientSocket, LspService, Server};
+
+use completions::CompletionCache;
+use datatypes::{LogData, Config};
+use index::WorkspaceIndex;
+use ignores::IgnoreRules;
+use dead_code::DeadCodeReport;
+use lanes::Lanes;
+use lints::CustomLint;
+use logs::LogStreamer;
+use memory::IdleTracker;
+use modules::ModuleResolver;
+use panic_guard::CatchPanic;
+use queries::QueryCache;
+#[cfg(feature = "run-commands")]
+use repl::ReplManager;
+use run::Runner;
+use snippet_edits::SnippetEdits;
+use normalize_uris::NormalizeUris;
+use spellcheck::SpellChecker;
+use spans::TraceRequests;
+use stats::{RecordStats, UsageStats};
+use status::StatusTracker;
+#[cfg(feature = "compiler-diagnostics")]
+use diagnostics::compiler::ActiveCompiler;
+use diagnostics::pipeline::DiagnosticPipeline;
+use directives::SuppressionCache;
+use crate::documents::FullTextDocument;
+
+pub mod index;
+mod ignores;
+mod benchmark;
+pub mod commands;
+pub mod core_types;
+mod auto_import;
+mod call_hierarchy;
+mod dead_code;
+pub mod docgen;
+mod declare;
+mod deprecations;
+mod directives;
+#[cfg(feature = "run-commands")]
+mod expand;
+mod fix_all;
+mod folding;
+mod format;
+mod infer;
+mod lanes;
+mod lints;
+pub mod logs;
+pub mod lsif;
+mod injections;
+mod utils;
+mod macros;
+mod matching;
+mod memory;
+mod moniker;
+pub mod modules;
+mod notify;
+mod backend;
+mod capabilities;
+mod handlers;
+pub mod documents;
+pub mod datatypes;
+mod completions;
+pub mod diagnostics;
+mod run;
+mod rename;
+#[cfg(feature = "run-commands")]
+mod repl;
+pub mod symbols;
+mod trace;
+mod undefined;
+mod virtual_docs;
+mod syntax_tree;
+mod signature;
+mod spellcheck;
+pub mod spans;
+mod stats;
+mod status;
+mod structural;
+#[cfg(feature = "native")]
+pub mod supervise;
+mod surround;
+mod snippet_edits;
+mod normalize_uris;
+mod server_info;
+mod panic_guard;
+mod project_config;
+mod partial;
+mod queries;
+mod semantic_tokens;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+
+/// The language server. Build it through `build_service`, or `Backend::new` to drive it directly.
+pub struct Backend {
+  pub(crate) lsp_client: String,
+  pub(crate) parser: Mutex<Parser>,
+  pub(crate) semantic_tokens: DashMap<Url, ((u64, i64), SemanticTokens)>,
+  pub(crate) log_data: Mutex<LogData>,
+  pub(crate) log_file: Option<std::path::PathBuf>,
+  pub(crate) log_stream: LogStreamer,
+  pub(crate) client: tower_lsp::Client,
+  pub(crate) config: Arc<Mutex<Config>>,
+  pub(crate) parse_tree:Mutex<HashMap<Url, Tree>>,
+  pub(crate) idle: IdleTracker,
+  pub(crate) lanes: Lanes,
+  pub(crate) docs: Arc<Mutex<HashMap<lsp_types::Url, FullTextDocument>>>,
+  pub workspace_map: DashMap<Url, String>,
+  pub(crate) folder_configs: DashMap<Url, Config>,
+  pub(crate) project_configs: DashMap<Url, serde_json::Value>,
+  pub(crate) ignores: DashMap<Url, IgnoreRules>,
+  pub(crate) modules: ModuleResolver,
+  pub(crate) index: WorkspaceIndex,
+  pub(crate) queries: QueryCache,
+  pub(crate) completions: CompletionCache,
+  pub(crate) spellchecker: Mutex<Option<SpellChecker>>,
+  pub(crate) runner: Runner,
+  #[cfg(feature = "run-commands")]
+  pub(crate) repl: ReplManager,
+  #[cfg(feature = "compiler-diagnostics")]
+  pub(crate) compiler: ActiveCompiler,
+  pub(crate) dead_code: Mutex<Option<DeadCodeReport>>,
+  pub(crate) client_capabilities: RwLock<ClientCapabilities>,
+  pub(crate) diagnostics: Arc<DashMap<Url, Vec<Diagnostic>>>,
+  pub(crate) status: StatusTracker,
+  pub(crate) stats: Arc<UsageStats>,
+  pub(crate) cyber_available: DashMap<String, bool>,
+  pub(crate) pipeline: DiagnosticPipeline,
+  pub(crate) suppressions: SuppressionCache,
+  pub(crate) lints: DashMap<Url, Vec<CustomLint>>,
+  pub(crate) shutdown: broadcast::Sender<()>,
+  pub(crate) shutting_down: AtomicBool,
+  /// The backend itself when it is shared through `SharedBackend`, empty when driven directly
+  pub(crate) this: Weak<Backend>,
+}
+
+impl Backend {
+  pub fn new(client: tower_lsp::Client, lsp_client: String, log_data: LogData) -> Self {
+    let diagnostics = Arc::new(DashMap::new());
+    let (shutdown, _) = broadcast::channel(1);
+
+    Self {
+      client,
+      lsp_client,
+      log_data: Mutex::new(log_data),
+      log_file: None,
+      log_stream: LogStreamer::new(),
+      workspace_map: DashMap::new(),
+      folder_configs: DashMap::new(),
+      project_configs: DashMap::new(),
+      ignores: DashMap::new(),
+      modules: ModuleResolver::new(),
+      index: WorkspaceIndex::new(),
+      queries: QueryCache::new(),
+      completions: CompletionCache::new(),
+      spellchecker: Mutex::new(None),
+      runner: Runner::new(diagnostics.clone()),
+      #[cfg(feature = "run-commands")]
+      repl: ReplManager::new(),
+      #[cfg(feature = "compiler-diagnostics")]
+      compiler: ActiveCompiler::new(Default::default()),
+      dead_code: Mutex::new(None),
+      client_capabilities: RwLock::new(ClientCapabilities::default()),
+      diagnostics,
+      status: StatusTracker::new(),
+      stats: Arc::new(UsageStats::new(None)),
+      cyber_available: DashMap::new(),
+      pipeline: DiagnosticPipeline::new(),
+      suppressions: SuppressionCache::new(),
+      lints: DashMap::new(),
+      shutdown,
+      shutting_down: AtomicBool::new(false),
+      this: Weak::new(),
+      parse_tree: Mutex::new(HashMap::new()),
+      idle: IdleTracker::new(),
+      lanes: Lanes::new(&Default::default()),
+      docs: Arc::new(Mutex::new(HashMap::new())),
+      config: Arc::new(Mutex::new(Config::default())),
+      parser: Mutex::new(cyber_tree_sitter::init_parser()),
+      semantic_tokens: DashMap::new(),
+    }
+  }
+
+  /// Start from the given settings instead of the defaults, until the client sends its own.
+  /// `CYBERLS_*` environment variables still override them.
+  pub fn with_config(mut self, config: Config) -> Self {
+    let config = config.with_env_overrides();
+    self.stats.set_enabled(config.performance_metrics);
+    self.lanes.configure(&config.lanes);
+    #[cfg(feature = "compiler-diagnostics")]
+    self.compiler.configure(config.compiler_mode);
+    self.config = Arc::new(Mutex::new(config));
+    self
+  }
+
+  /// The folder of the server log, also keeping the usage statistics of `performance_metrics`
+  pub fn with_log_dir(mut self, log_dir: Option<std::path::PathBuf>) -> Self {
+    self.log_file = log_dir.as_ref().map(|dir| dir.join(logs::LOG_FILE.as_str()));
+    self.stats = Arc::new(UsageStats::new(log_dir));
+    self
+  }
+
+  /// A handle to the backend that work can be spawned with, when it is shared
+  pub(crate) fn shared(&self) -> Option<Arc<Backend>> {
+    self.this.upgrade()
+  }
+}
+
+/// The `Backend` as served by `build_service`. Being shared, handlers can hand work off to
+/// tasks outliving them, ie. debounced diagnostics, instead of holding on to their message.
+pub struct SharedBackend(Arc<Backend>);
+
+impl SharedBackend {
+  pub fn new(backend: Backend) -> Self {
+    Self(Arc::new_cyclic(|this| Backend { this: this.clone(), ..backend }))
+  }
+}
+
+impl Deref for SharedBackend {
+  type Target = Backend;
+
+  fn deref(&self) -> &Backend {
+    &self.0
+  }
+}
+
+/// Build the service with the custom `cyberls/*` requests registered
+pub fn build_service(options: ServerOptions) -> (LspService<SharedBackend>, ClientSocket) {
+  let ServerOptions { lsp_client, log_data, config, log_dir, .. } = options;
+  let service = LspService::build(|client| SharedBackend::new(Backend::new(client, lsp_client, log_data).with_log_dir(log_dir).with_config(config)))
+    .custom_method(syntax_tree::SYNTAX_TREE_METHOD, SharedBackend::syntax_tree)
+    .custom_method(matching::MATCHING_BLOCK_METHOD, SharedBackend::matching_block)
+    .custom_method(server_info::SERVER_INFO_METHOD, SharedBackend::server_info)
+    .custom_method(virtual_docs::VIRTUAL_CONTENT_METHOD, SharedBackend::virtual_content);
+
+  // Requests running the cyber executable only exist in builds that can run it
+  #[cfg(feature = "run-commands")]
+  let service = service
+    .custom_method(repl::START_METHOD, SharedBackend::repl_start)
+    .custom_method(repl::EVAL_METHOD, SharedBackend::repl_eval)
+    .custom_method(repl::INTERRUPT_METHOD, SharedBackend::repl_interrupt)
+    .custom_method(expand::EXPAND_METHOD, SharedBackend::expand);
+
+  service.finish()
+}
+
+struct State {
+  client_monitor: bool,
+  _warned_needs_restart: bool,
+}
+
+impl State {
+  pub fn new() -> Self {
+    Self { client_monitor: false, _warned_needs_restart: false}}
+}
+
+#[macro_use]
+extern crate lazy_static;
+
+lazy_static! {
+    pub static ref DOCUMENTS: RwLock<DashMap<Url, FullTextDocument>> = RwLock::new(DashMap::new());
+}
+
+// --| Server Entry Points -----------
+// --|--------------------------------
+/// Port the tcp transport listens on unless told otherwise
+pub const DEFAULT_TCP_PORT: u16 = 9257;
+
+/// How the server talks to its client
+#[cfg(feature = "native")]
+#[derive(Debug, Clone, Copy)]
+pub enum Transport {
+  Stdio,
+  /// Accept a single connection on this address
+  Tcp(std::net::SocketAddr),
+}
+
+/// What a server starts with, before the client sends its configuration
+#[derive(Debug, Default)]
+pub struct ServerOptions {
+  /// `vscode` or `nvim`, hover is formatted for the client
+  pub lsp_client: String,
+  pub log_data: LogData,
+  pub config: Config,
+  /// Folder of the server log. Usage statistics are only kept in memory without one
+  pub log_dir: Option<std::path::PathBuf>,
+  /// Which messages are logged in spans of their own
+  pub log_spans: spans::SpanLevel,
+}
+
+/// Serve a single client over the transport until it disconnects
+#[cfg(feature = "native")]
+pub async fn run_server(transport: Transport, options: ServerOptions) -> std::io::Result<()> {
+  match transport {
+    Transport::Stdio => serve(tokio::io::stdin(), tokio::io::stdout(), options).await,
+    Transport::Tcp(address) => {
+      let listener = tokio::net::TcpListener::bind(address).await?;
+      let (stream, _) = listener.accept().await?;
+      let (read, write) = tokio::io::split(stream);
+      serve(read, write, options).await;
+    }
+  }
+
+  Ok(())
+}
+
+/// Serve a single client over any pair of streams, ie. an in-memory duplex
+pub async fn serve<I: AsyncRead + Unpin, O: AsyncWrite>(input: I, output: O, options: ServerOptions) {
+  // The transport reads this many messages at once for the whole session
+  let concurrency = options.config.clone().with_env_overrides().lanes.concurrency();
+  let log_spans = options.log_spans;
+  let (service, socket) = build_service(options);
+  let client = service.inner().client.clone();
+  let stats = service.inner().stats.clone();
+  Server::new(input, output, socket)
+    .concurrency_level(concurrency)
+    .serve(CatchPanic::new(TraceRequests::new(RecordStats::new(SnippetEdits::new(NormalizeUris::new(service)), stats), log_spans), client))
+    .await;
+}
@@ -0,0 +1,138 @@
+use std::path::Path;
+
+use lsp_types::{DiagnosticSeverity, Url};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use cyber_tree_sitter::pool::QUERY_CURSORS;
+use tree_sitter::{Node, Query};
+
+use crate::diagnostics::ErrorInfo;
+use crate::structural::MATCH_CAPTURE;
+
+/// Folder, relative to a workspace root, holding the custom lint rules
+pub const LINTS_DIR: &str = ".cyberls/lints";
+
+// --| Lint Manifest -----------------
+// --|--------------------------------
+/// `<name>.json` next to `<name>.scm`, describing what a match of the query means
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LintManifest {
+  /// Diagnostic message. `{capture}` expands to the text of a capture of the match
+  pub message: String,
+  #[serde(default)]
+  pub severity: LintSeverity,
+  /// Diagnostic code, defaults to the lint name
+  pub code: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+  Error,
+  #[default]
+  Warning,
+  Information,
+  Hint,
+}
+
+impl From<LintSeverity> for DiagnosticSeverity {
+  fn from(severity: LintSeverity) -> Self {
+    match severity {
+      LintSeverity::Error => DiagnosticSeverity::ERROR,
+      LintSeverity::Warning => DiagnosticSeverity::WARNING,
+      LintSeverity::Information => DiagnosticSeverity::INFORMATION,
+      LintSeverity::Hint => DiagnosticSeverity::HINT,
+    }
+  }
+}
+
+// --| Custom Lints ------------------
+// --|--------------------------------
+pub struct CustomLint {
+  pub name: String,
+  pub query: Query,
+  pub manifest: LintManifest,
+}
+
+/// Load the lints of a workspace. Rules which fail to load are skipped, with the
+/// reason returned so it can be shown to the user.
+pub fn load_lints(root: &Path) -> (Vec<CustomLint>, Vec<String>) {
+  let mut lints = vec![];
+  let mut errors = vec![];
+
+  let entries = match std::fs::read_dir(root.join(LINTS_DIR)) {
+    Ok(entries) => entries,
+    Err(_) => return (lints, errors),
+  };
+
+  let mut queries: Vec<_> = entries.filter_map(|entry| entry.ok())
+    .map(|entry| entry.path())
+    .filter(|path| path.extension().map_or(false, |ext| ext == "scm"))
+    .collect();
+  queries.sort();
+
+  for path in queries {
+    let name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+
+    let source = match std::fs::read_to_string(&path) {
+      Ok(source) => source,
+      Err(err) => { errors.push(format!("{}: {}", path.display(), err)); continue; }
+    };
+
+    let query = match Query::new(cyber_tree_sitter::cyber_language(), &source) {
+      Ok(query) => query,
+      Err(err) => { errors.push(format!("{}: invalid query: {:?}", path.display(), err)); continue; }
+    };
+
+    let manifest_path = path.with_extension("json");
+    let manifest = match std::fs::read_to_string(&manifest_path) {
+      Ok(text) => match serde_json::from_str::<LintManifest>(&text) {
+        Ok(manifest) => manifest,
+        Err(err) => { errors.push(format!("{}: {}", manifest_path.display(), err)); continue; }
+      },
+      Err(_) => LintManifest { message: format!("Matches lint `{}`", name), severity: LintSeverity::default(), code: None },
+    };
+
+    debug!("Loaded lint {} from {:?}", name, path);
+    lints.push(CustomLint { name, query, manifest });
+  }
+
+  for error in &errors { warn!("Failed to load lint {}", error); }
+  (lints, errors)
+}
+
+/// Whether a changed file is a lint rule or manifest
+pub fn is_lint_file(uri: &Url) -> bool {
+  let path = uri.path();
+  path.contains(&format!("/{}/", LINTS_DIR)) && (path.ends_with(".scm") || path.ends_with(".json"))
+}
+
+/// Run every lint over the document. Matches are reported on the `@match` capture, or the first capture.
+pub fn check_lints(lints: &[CustomLint], root: Node, source: &str) -> Option<ErrorInfo> {
+  let mut error_info = ErrorInfo::new();
+  let mut cursor = QUERY_CURSORS.get();
+
+  for lint in lints {
+    let names = lint.query.capture_names();
+    let target = names.iter().position(|name| name == MATCH_CAPTURE);
+    let code = lint.manifest.code.clone().unwrap_or_else(|| lint.name.clone());
+
+    for found in cursor.matches(&lint.query, root, source.as_bytes()) {
+      let node = match target {
+        Some(index) => found.captures.iter().find(|capture| capture.index as usize == index),
+        None => found.captures.first(),
+      };
+      let node = match node { Some(capture) => capture.node, None => continue };
+
+      let mut message = lint.manifest.message.clone();
+      for capture in found.captures {
+        let text = capture.node.utf8_text(source.as_bytes()).unwrap_or_default();
+        message = message.replace(&format!("{{{}}}", names[capture.index as usize]), text);
+      }
+
+      error_info.add_coded(node.start_position(), node.end_position(), message, Some(lint.manifest.severity.into()), &code);
+    }
+  }
+
+  if error_info.entries.is_empty() { None } else { Some(error_info) }
+}
@@ -0,0 +1,226 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use lsp_types::notification::Notification;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tower_lsp::Client;
+
+/// Name of the log a supervised server writes to, the one of its supervisor
+pub const LOG_FILE_ENV: &str = "CYBERLS_LOG_FILE";
+
+/// Name of this process's log in the log folder, `cyberls.<millis>.<pid>.log`, so
+/// servers of several editor windows each keep their own
+pub static LOG_FILE: Lazy<String> = Lazy::new(|| match std::env::var(LOG_FILE_ENV) {
+  Ok(name) if !name.is_empty() => name,
+  _ => {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_millis()).unwrap_or_default();
+    format!("cyberls.{}.{}.log", millis, std::process::id())
+  }
+});
+
+/// Logs of earlier runs kept unless told otherwise
+pub const DEFAULT_KEEP_LOGS: usize = 5;
+
+/// Bytes of the log `cyberls.show_recent_log` returns unless told otherwise
+pub const DEFAULT_TAIL_BYTES: u64 = 64 * 1024;
+
+/// How often a streamed log is checked for new lines
+const STREAM_INTERVAL: Duration = Duration::from_millis(500);
+
+// --| Log Stream Notification -------
+// --|--------------------------------
+pub enum LogStream {}
+
+impl Notification for LogStream {
+  type Params = LogStreamParams;
+  const METHOD: &'static str = "cyberls/logStream";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogStreamParams {
+  /// Whole lines written to the log since the last notification
+  pub text: String,
+}
+
+// --| Log Folder --------------------
+// --|--------------------------------
+/// `cyberls/logs` in the platform data folder, ie. `~/.local/share` on Linux, or next to
+/// the executable where there is none
+pub fn default_log_dir() -> PathBuf {
+  match dirs::data_local_dir() {
+    Some(dir) => dir.join("cyberls").join("logs"),
+    None => std::env::current_exe().map(|exe| exe.with_file_name("")).unwrap_or_else(|_| std::env::temp_dir()),
+  }
+}
+
+/// Logs in the folder other than `current`, newest first
+fn previous_logs(dir: &Path, current: &str) -> Vec<PathBuf> {
+  let mut logs: Vec<(SystemTime, PathBuf)> = std::fs::read_dir(dir).into_iter().flatten().flatten()
+    .filter(|entry| {
+      let name = entry.file_name().to_string_lossy().to_string();
+      name != current && name.starts_with("cyberls.") && name.ends_with(".log")
+    })
+    .map(|entry| (entry.metadata().and_then(|metadata| metadata.modified()).unwrap_or(UNIX_EPOCH), entry.path()))
+    .collect();
+  logs.sort_by(|a, b| b.0.cmp(&a.0));
+  logs.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Make room for the log `current` is starting, keeping the `keep` most recent logs of
+/// earlier runs
+pub fn rotate(dir: &Path, current: &str, keep: usize) -> io::Result<()> {
+  std::fs::create_dir_all(dir)?;
+  for old in previous_logs(dir, current).into_iter().skip(keep) {
+    std::fs::remove_file(old)?;
+  }
+  Ok(())
+}
+
+/// Empty the log `current` and delete those of earlier runs, returning how many were deleted
+pub fn clear(dir: &Path, current: &str) -> io::Result<usize> {
+  let path = dir.join(current);
+  // The appender writes at the end of the file, truncating it in place keeps it writing
+  if path.exists() { std::fs::OpenOptions::new().write(true).open(&path)?.set_len(0)?; }
+
+  let previous = previous_logs(dir, current);
+  for old in &previous {
+    std::fs::remove_file(old)?;
+  }
+  Ok(previous.len())
+}
+
+// --| Reading the Log ---------------
+// --|--------------------------------
+/// The last `bytes` of the log, from the first whole line on
+pub fn tail(path: &Path, bytes: u64) -> io::Result<String> {
+  let mut file = File::open(path)?;
+  let start = file.metadata()?.len().saturating_sub(bytes);
+  file.seek(SeekFrom::Start(start))?;
+
+  let mut buffer = vec![];
+  file.read_to_end(&mut buffer)?;
+  let text = String::from_utf8_lossy(&buffer);
+  if start == 0 { return Ok(text.into_owned()); }
+  Ok(text.split_once('\n').map(|(_, rest)| rest.to_string()).unwrap_or_default())
+}
+
+/// Whole lines appended after `offset`, and the offset to continue from. A log that
+/// shrank was started over and is read from the beginning.
+fn read_from(path: &Path, offset: u64) -> io::Result<(String, u64)> {
+  let mut file = File::open(path)?;
+  let len = file.metadata()?.len();
+  let offset = if len < offset { 0 } else { offset };
+  file.seek(SeekFrom::Start(offset))?;
+
+  let mut buffer = vec![];
+  file.read_to_end(&mut buffer)?;
+  let complete = buffer.iter().rposition(|byte| *byte == b'\n').map_or(0, |end| end + 1);
+  Ok((String::from_utf8_lossy(&buffer[..complete]).into_owned(), offset + complete as u64))
+}
+
+// --| Streaming the Log -------------
+// --|--------------------------------
+/// Sends lines written to the log to the client as `cyberls/logStream` notifications
+/// while switched on
+#[derive(Default)]
+pub struct LogStreamer {
+  task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl LogStreamer {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Follow the log from its current end
+  pub fn start(&self, client: Client, path: PathBuf) {
+    let mut offset = std::fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+    let task = tokio::spawn(async move {
+      loop {
+        tokio::time::sleep(STREAM_INTERVAL).await;
+        let (text, next) = match read_from(&path, offset) { Ok(read) => read, Err(_) => continue };
+        offset = next;
+        if !text.is_empty() { client.send_notification::<LogStream>(LogStreamParams { text }).await; }
+      }
+    });
+
+    if let Some(previous) = self.task.lock().unwrap_or_else(|err| err.into_inner()).replace(task) { previous.abort(); }
+  }
+
+  /// Returns whether the log was being streamed
+  pub fn stop(&self) -> bool {
+    self.task.lock().unwrap_or_else(|err| err.into_inner()).take().map(|task| task.abort()).is_some()
+  }
+
+  pub fn is_streaming(&self) -> bool {
+    self.task.lock().unwrap_or_else(|err| err.into_inner()).is_some()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn log_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cyberls-logs-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  fn write_aged(dir: &Path, name: &str, age: u64) {
+    let file = File::create(dir.join(name)).unwrap();
+    file.set_modified(SystemTime::now() - Duration::from_secs(age)).unwrap();
+  }
+
+  fn names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir).unwrap().flatten().map(|entry| entry.file_name().to_string_lossy().to_string()).collect();
+    names.sort();
+    names
+  }
+
+  #[test]
+  fn log_file_names_are_per_process() {
+    let pid = format!(".{}.log", std::process::id());
+    assert!(LOG_FILE.starts_with("cyberls.") && LOG_FILE.ends_with(&pid), "{}", *LOG_FILE);
+  }
+
+  #[test]
+  fn rotate_keeps_the_most_recent_logs() {
+    let dir = log_dir("rotate");
+    write_aged(&dir, "cyberls.1000.1.log", 300);
+    write_aged(&dir, "cyberls.2000.2.log", 200);
+    write_aged(&dir, "cyberls.3000.3.log", 100);
+    write_aged(&dir, "stats.json", 400);
+
+    rotate(&dir, "cyberls.4000.4.log", 2).unwrap();
+    assert_eq!(names(&dir), vec!["cyberls.2000.2.log", "cyberls.3000.3.log", "stats.json"]);
+
+    // The log being started is never counted
+    write_aged(&dir, "cyberls.4000.4.log", 0);
+    rotate(&dir, "cyberls.4000.4.log", 0).unwrap();
+    assert_eq!(names(&dir), vec!["cyberls.4000.4.log", "stats.json"]);
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn clear_empties_the_current_log_and_deletes_the_others() {
+    let dir = log_dir("clear");
+    std::fs::write(dir.join("cyberls.2000.2.log"), "still running\n").unwrap();
+    write_aged(&dir, "cyberls.1000.1.log", 100);
+
+    assert_eq!(clear(&dir, "cyberls.2000.2.log").unwrap(), 1);
+    assert_eq!(names(&dir), vec!["cyberls.2000.2.log"]);
+    assert_eq!(std::fs::metadata(dir.join("cyberls.2000.2.log")).unwrap().len(), 0);
+
+    // Without a log of its own there's only the others to delete
+    assert_eq!(clear(&dir, "cyberls.3000.3.log").unwrap(), 1);
+    assert!(names(&dir).is_empty());
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
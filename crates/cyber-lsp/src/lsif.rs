@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use lsp_types::{Range, SymbolKind};
+use ropey::Rope;
+use serde_json::{json, Value};
+
+use crate::index;
+use crate::modules;
+use crate::moniker;
+use crate::symbols::DocumentSymbols;
+use crate::utils::paths;
+use crate::core_types::{DocPoint, ToLsp};
+
+/// LSIF version of the emitted dump
+pub const LSIF_VERSION: &str = "0.4.3";
+
+// --| Emitter -----------------------
+// --|--------------------------------
+/// Writes the vertices and edges of an LSIF dump as JSON lines, numbering them as they go
+struct Emitter<W: Write> {
+  out: W,
+  next_id: u64,
+}
+
+impl<W: Write> Emitter<W> {
+  fn emit(&mut self, kind: &str, label: &str, fields: Value) -> std::io::Result<u64> {
+    self.next_id += 1;
+    let mut element = json!({ "id": self.next_id, "type": kind, "label": label });
+    if let (Some(element), Value::Object(fields)) = (element.as_object_mut(), fields) {
+      element.extend(fields);
+    }
+
+    serde_json::to_writer(&mut self.out, &element)?;
+    self.out.write_all(b"\n")?;
+    Ok(self.next_id)
+  }
+
+  fn vertex(&mut self, label: &str, fields: Value) -> std::io::Result<u64> {
+    self.emit("vertex", label, fields)
+  }
+
+  fn edge(&mut self, label: &str, out_v: u64, in_v: u64) -> std::io::Result<u64> {
+    self.emit("edge", label, json!({ "outV": out_v, "inV": in_v }))
+  }
+
+  fn edges(&mut self, label: &str, out_v: u64, in_vs: &[u64], fields: Value) -> std::io::Result<u64> {
+    let mut all = json!({ "outV": out_v, "inVs": in_vs });
+    if let (Some(all), Value::Object(fields)) = (all.as_object_mut(), fields) {
+      all.extend(fields);
+    }
+    self.emit("edge", label, all)
+  }
+
+  /// Range vertex of a symbol range, converted from byte columns to UTF-16
+  fn range(&mut self, rope: &Rope, range: Range) -> std::io::Result<u64> {
+    let start = DocPoint::from_byte_position(range.start).to_lsp(rope);
+    let end = DocPoint::from_byte_position(range.end).to_lsp(rope);
+    self.vertex("range", json!({ "start": start, "end": end }))
+  }
+}
+
+// --| Index Dump --------------------
+// --|--------------------------------
+struct Document {
+  id: u64,
+  rope: Rope,
+  symbols: DocumentSymbols,
+  ranges: Vec<u64>,
+}
+
+/// A declaration and the places referring to it
+struct Declaration {
+  result_set: u64,
+  document: usize,
+  range: u64,
+  /// Reference ranges by document
+  references: HashMap<usize, Vec<u64>>,
+}
+
+/// Index every cyber file below `root` and write an LSIF dump of its definitions, references,
+/// hovers and export monikers to `out`. Returns the number of documents.
+pub fn generate(root: &Path, out: &Path) -> std::io::Result<usize> {
+  let root = std::fs::canonicalize(root)?;
+  let workspace = paths::dir_to_uri(&root)
+    .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} has no file uri", root.display())))?;
+
+  let mut files = index::scan_folder(&root, &[], &[]);
+  files.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+
+  let mut emitter = Emitter { out: BufWriter::new(File::create(out)?), next_id: 0 };
+  let (name, version) = (env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+  emitter.vertex("metaData", json!({
+    "version": LSIF_VERSION,
+    "projectRoot": workspace,
+    "positionEncoding": "utf-16",
+    "toolInfo": { "name": name, "version": version },
+  }))?;
+  let project = emitter.vertex("project", json!({ "kind": "cyber" }))?;
+
+  let mut documents = vec![];
+  for (uri, symbols) in files {
+    let source = match paths::uri_to_path(&uri).and_then(|path| std::fs::read_to_string(path).ok()) {
+      Some(source) => source,
+      None => continue,
+    };
+
+    let id = emitter.vertex("document", json!({ "uri": uri, "languageId": "cyber" }))?;
+    documents.push((uri, Document { id, rope: Rope::from_str(&source), symbols, ranges: vec![] }));
+  }
+
+  // --| Declarations
+  let package = moniker::package_name(&workspace);
+  let mut declarations: Vec<Declaration> = vec![];
+  let mut by_document: HashMap<(usize, String), usize> = HashMap::new();
+  let mut exports: HashMap<String, Vec<usize>> = HashMap::new();
+
+  for (index, (uri, document)) in documents.iter_mut().enumerate() {
+    let module = paths::uri_to_path(uri).and_then(|path| modules::module_name(&root, &path));
+
+    for symbol in document.symbols.symbols.iter().filter(|symbol| symbol.kind != SymbolKind::MODULE) {
+      let range = emitter.range(&document.rope, symbol.selection_range)?;
+      let result_set = emitter.vertex("resultSet", json!({}))?;
+      emitter.edge("next", range, result_set)?;
+
+      let definition = emitter.vertex("definitionResult", json!({}))?;
+      emitter.edge("textDocument/definition", result_set, definition)?;
+      emitter.edges("item", definition, &[range], json!({ "document": document.id }))?;
+
+      let mut hover = format!("```cyber\n{}\n```", symbol.label());
+      if let Some(doc) = &symbol.doc { hover.push_str(&format!("\n---\n{}", doc)); }
+      let hover = emitter.vertex("hoverResult", json!({ "result": { "contents": { "kind": "markdown", "value": hover } } }))?;
+      emitter.edge("textDocument/hover", result_set, hover)?;
+
+      if let (None, Some(module)) = (&symbol.container, &module) {
+        let identifier = format!("{}/{}:{}", package, module, symbol.name);
+        let export = emitter.vertex("moniker", json!({ "scheme": moniker::MONIKER_SCHEME, "identifier": identifier, "unique": "scheme", "kind": "export" }))?;
+        emitter.edge("moniker", result_set, export)?;
+        exports.entry(symbol.name.clone()).or_default().push(declarations.len());
+      }
+
+      by_document.entry((index, symbol.name.clone())).or_insert(declarations.len());
+      document.ranges.push(range);
+      declarations.push(Declaration { result_set, document: index, range, references: HashMap::new() });
+    }
+  }
+
+  // --| References, to a declaration of the same document or else the only export of the name
+  for (index, (_, document)) in documents.iter_mut().enumerate() {
+    for reference in document.symbols.references.iter().filter(|reference| !reference.member) {
+      let target = by_document.get(&(index, reference.name.clone())).copied().or_else(|| {
+        exports.get(&reference.name).filter(|found| found.len() == 1).map(|found| found[0])
+      });
+      let target = match target { Some(target) => target, None => continue };
+
+      let range = emitter.range(&document.rope, reference.range)?;
+      emitter.edge("next", range, declarations[target].result_set)?;
+      declarations[target].references.entry(index).or_default().push(range);
+      document.ranges.push(range);
+    }
+  }
+
+  for declaration in &declarations {
+    let references = emitter.vertex("referenceResult", json!({}))?;
+    emitter.edge("textDocument/references", declaration.result_set, references)?;
+
+    let document = documents[declaration.document].1.id;
+    emitter.edges("item", references, &[declaration.range], json!({ "document": document, "property": "definitions" }))?;
+    for (index, found) in &declaration.references {
+      emitter.edges("item", references, found, json!({ "document": documents[*index].1.id, "property": "references" }))?;
+    }
+  }
+
+  for (_, document) in &documents {
+    if !document.ranges.is_empty() {
+      emitter.edges("contains", document.id, &document.ranges, json!({}))?;
+    }
+  }
+
+  let ids: Vec<u64> = documents.iter().map(|(_, document)| document.id).collect();
+  if !ids.is_empty() { emitter.edges("contains", project, &ids, json!({}))?; }
+
+  emitter.out.flush()?;
+  Ok(documents.len())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn dumps_declarations_and_cross_file_references() {
+    let dir = std::env::temp_dir().join(format!("cyberls-lsif-{}", std::process::id()));
+    let root = dir.join("app");
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("lib.cy"), "func greet():\n  pass\n").unwrap();
+    std::fs::write(root.join("main.cy"), "import lib 'lib.cy'\ngreet()\n").unwrap();
+
+    let out = dir.join("dump.lsif");
+    assert_eq!(generate(&root, &out).unwrap(), 2);
+
+    let elements: Vec<Value> = std::fs::read_to_string(&out).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // Ids count up from 1 and edges only point at vertices emitted before them
+    let mut vertices: HashMap<u64, String> = HashMap::new();
+    for (index, element) in elements.iter().enumerate() {
+      let id = element["id"].as_u64().unwrap();
+      assert_eq!(id, index as u64 + 1);
+      let label = element["label"].as_str().unwrap().to_string();
+      if element["type"] == "vertex" {
+        vertices.insert(id, label);
+        continue;
+      }
+      assert!(vertices.contains_key(&element["outV"].as_u64().unwrap()), "dangling edge {}", element);
+      let in_vs = match element.get("inVs") {
+        Some(in_vs) => in_vs.as_array().unwrap().iter().map(|v| v.as_u64().unwrap()).collect(),
+        None => vec![element["inV"].as_u64().unwrap()],
+      };
+      assert!(in_vs.iter().all(|v| vertices.contains_key(v)), "dangling edge {}", element);
+    }
+
+    assert_eq!(elements[0]["label"], "metaData");
+    assert_eq!(elements[0]["version"], LSIF_VERSION);
+    assert_eq!(elements[1]["label"], "project");
+
+    let labelled = |label: &str| elements.iter().filter(|element| element["label"] == label).collect::<Vec<_>>();
+    let documents = labelled("document");
+    assert_eq!(documents.len(), 2);
+    assert!(documents[0]["uri"].as_str().unwrap().ends_with("/app/lib.cy"));
+    assert!(documents[1]["uri"].as_str().unwrap().ends_with("/app/main.cy"));
+
+    // The project contains both documents
+    let project = elements[1]["id"].as_u64().unwrap();
+    let contains = labelled("contains");
+    let owned = contains.iter().find(|edge| edge["outV"] == project).unwrap();
+    assert_eq!(owned["inVs"], json!([documents[0]["id"], documents[1]["id"]]));
+
+    // `greet` is exported from lib and hovered with its signature
+    let monikers = labelled("moniker");
+    assert!(monikers.iter().any(|moniker| moniker["identifier"] == "app/lib:greet" && moniker["kind"] == "export"));
+    assert!(labelled("hoverResult").iter().any(|hover| hover["result"]["contents"]["value"].as_str().unwrap().contains("greet")));
+
+    // The call in main is a reference to the declaration in lib
+    let references = labelled("item").into_iter().filter(|edge| edge["property"] == "references").collect::<Vec<_>>();
+    assert_eq!(references.len(), 1);
+    assert_eq!(references[0]["document"], documents[1]["id"]);
+    let call = references[0]["inVs"][0].as_u64().unwrap();
+    let range = elements.iter().find(|element| element["id"] == call).unwrap();
+    assert_eq!(range["label"], "range");
+    assert_eq!(range["start"], json!({ "line": 1, "character": 0 }));
+    assert_eq!(range["end"], json!({ "line": 1, "character": 5 }));
+
+    // Every range of main is listed under its document
+    let in_main = contains.iter().find(|edge| edge["outV"] == documents[1]["id"]).unwrap();
+    assert!(in_main["inVs"].as_array().unwrap().iter().any(|v| v.as_u64() == Some(call)));
+  }
+}
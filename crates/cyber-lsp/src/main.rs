@@ -1,61 +1,17 @@
-use std::sync::{Arc, RwLock};
-use std::collections::HashMap;
-use std::net::{Ipv4Addr, SocketAddr};
-
-use lsp_types::Url;
-use dashmap::DashMap;
-use tokio::sync::Mutex;
-use tokio::net::TcpListener;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+
 use clap::{arg, Arg, Command};
-use cyber_tree_sitter::{Tree, Parser};
-use tower_lsp::{LspService, Server};
 
-use tracing::info;
+use tracing::{error, info};
 use tracing_subscriber::filter;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 
-use datatypes::{LogData, Config};
-use crate::documents::FullTextDocument;
-
-mod utils;
-mod macros;
-mod backend;
-mod handlers;
-mod documents;
-mod datatypes;
-mod completions;
-mod diagnostics;
-mod semantic_tokens;
-
-struct Backend {
-  pub(crate) lsp_client: String,
-  pub(crate) parser: Mutex<Parser>,
-  pub(crate) log_data: Mutex<LogData>,
-  pub(crate) client: tower_lsp::Client,
-  pub(crate) config: Arc<Mutex<Config>>,
-  pub(crate) parse_tree:Mutex<HashMap<Url, Tree>>,
-  pub(crate) docs: Arc<Mutex<HashMap<lsp_types::Url, FullTextDocument>>>,
-  pub workspace_map: DashMap<Url, String>,
-}
-
-struct State {
-  client_monitor: bool,
-  _warned_needs_restart: bool,
-  _shutdown: tokio::sync::broadcast::Sender<()>,
-}
-
-impl State {
-  pub fn new() -> Self {
-    let (_shutdown, _) = tokio::sync::broadcast::channel(1);
-    Self { client_monitor: false, _shutdown, _warned_needs_restart: false}}
-}
-
-#[macro_use]
-extern crate lazy_static;
-
-lazy_static! {
-    pub static ref DOCUMENTS: RwLock<DashMap<Url, FullTextDocument>> = RwLock::new(DashMap::new());
-}
+use cyber_lsp_core::datatypes::LogData;
+use cyber_lsp_core::docgen::{self, DocFormat};
+use cyber_lsp_core::logs::{self, LOG_FILE};
+use cyber_lsp_core::spans::SpanLevel;
+use cyber_lsp_core::{lsif, supervise, ServerOptions, Transport, DEFAULT_TCP_PORT};
 
 #[tokio::main]
 async fn main() {
@@ -79,9 +35,23 @@ async fn main() {
       .default_value("info").default_missing_value("info")
       .value_parser(["error", "warn", "info", "debug"]))
 
+    .arg( // --| Log retention ------------------
+      arg!(keep_logs: --"keep-logs" <N> "How many logs of earlier runs are kept")
+      .default_value("5")
+      .value_parser(clap::value_parser!(usize)))
+
+    .arg( // --| Request spans ------------------
+      arg!(log_spans: --"log-spans" <LEVEL> "Which messages are logged in spans with their id, document and duration")
+      .default_value("requests")
+      .value_parser(["off", "requests", "all"]))
+
     .arg( // --| Verbose ------------------------      
       arg!(verbose: -v --verbose "Enable verbose logging")
       .action(clap::ArgAction::SetTrue))
+
+    .arg( // --| Supervise ----------------------
+      arg!(supervise: --supervise "Run the server in a child process, restarting it when it crashes")
+      .action(clap::ArgAction::SetTrue))
     
     .subcommand( // --| Sdtio Communication -----
       Command::new("stdio").long_flag("stdio").about("communicate via stdio"))
@@ -89,18 +59,35 @@ async fn main() {
     .subcommand( // --| TCP Communication -------
       Command::new("tcp").long_flag("tcp").about("run with tcp").arg(
         Arg::new("port").long("port").short('P').help("listen to port")))
+
+    .subcommand( // --| API Documentation -------
+      Command::new("doc").about("generate API documentation from the doc comments of a folder")
+        .arg(Arg::new("path").help("folder to document").default_value("."))
+        .arg(arg!(format: -f --format <FORMAT> "The output format")
+          .default_value("markdown")
+          .value_parser(["markdown", "json"]))
+        .arg(arg!(out: -o --out <DIR> "The directory pages are written to")
+          .default_value("docs")))
+
+    .subcommand( // --| LSIF Index --------------
+      Command::new("lsif").about("write an LSIF index of a workspace for code navigation on code hosts")
+        .arg(Arg::new("workspace").help("workspace folder to index").default_value("."))
+        .arg(arg!(out: -o --out <FILE> "The file the index is written to")
+          .default_value("dump.lsif")))
     .get_matches();
 
-  // Remove log file if exists
-  let log_file_dir = std::env::current_exe().unwrap().with_file_name("");
-  let log_file = log_file_dir.join("cyberls.log");
-  if log_file.exists() {
-    std::fs::remove_file(log_file).expect("error");
+  // Start a new log and keep those of earlier runs, a supervised server shares it with its supervisor
+  let supervised = std::env::var_os(supervise::SUPERVISED_ENV).is_some();
+  let log_file_dir = logs::default_log_dir();
+  if !supervised {
+    let keep = matches.get_one::<usize>("keep_logs").copied().unwrap_or(logs::DEFAULT_KEEP_LOGS);
+    if let Err(err) = logs::rotate(&log_file_dir, &LOG_FILE, keep) {
+      eprintln!("Failed to rotate the logs in {}: {}", log_file_dir.display(), err);
+    }
   }
 
   let log_level = matches.get_one::<String>("level").expect("error");
-  let log_file_dir = std::env::current_exe().unwrap().with_file_name("");
-  let file_appender = RollingFileAppender::new(Rotation::NEVER, log_file_dir, "cyberls.log");
+  let file_appender = RollingFileAppender::new(Rotation::NEVER, &log_file_dir, LOG_FILE.as_str());
   let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
 
   let filter = match log_level.as_str() {
@@ -111,6 +98,8 @@ async fn main() {
     _ => filter::LevelFilter::INFO,
   };
 
+  let log_spans = matches.get_one::<String>("log_spans").and_then(|name| SpanLevel::parse(name)).unwrap_or_default();
+
   tracing_subscriber::fmt()
     .with_ansi(false)
     .with_max_level(filter)
@@ -118,83 +107,87 @@ async fn main() {
     .with_writer(non_blocking)
     .init();
 
-  // --| Sdtio Communication -----
+  // Handler panics are caught and answered by `CatchPanic`, keep the details in the log
+  std::panic::set_hook(Box::new(|info| error!("{}", info)));
+
+  // --| Supervisor --------------
+  if matches.get_flag("supervise") && !supervised {
+    let tcp = matches!(matches.subcommand(), Some(("tcp", _)));
+    info!("Supervising cyberls over {}", if tcp { "tcp" } else { "stdio" });
+
+    let code = supervise::run(tcp).await;
+    drop(_guard);
+    std::process::exit(code);
+  }
+
   match matches.subcommand() {
+    // --| API Documentation -----
+    Some(("doc", arguments)) => {
+      let path = arguments.get_one::<String>("path").expect("error");
+      let out = arguments.get_one::<String>("out").expect("error");
+      let format = match arguments.get_one::<String>("format").map(String::as_str) {
+        Some("json") => DocFormat::Json,
+        _ => DocFormat::Markdown,
+      };
+
+      match docgen::generate(Path::new(path), format, Path::new(out)) {
+        Ok(files) => for file in files { println!("{}", file.display()); },
+        Err(err) => {
+          eprintln!("Failed to generate documentation for {}: {}", path, err);
+          std::process::exit(1);
+        }
+      }
+    }
+
+    // --| LSIF Index ------------
+    Some(("lsif", arguments)) => {
+      let workspace = arguments.get_one::<String>("workspace").expect("error");
+      let out = arguments.get_one::<String>("out").expect("error");
+
+      match lsif::generate(Path::new(workspace), Path::new(out)) {
+        Ok(documents) => println!("Indexed {} documents into {}", documents, out),
+        Err(err) => {
+          eprintln!("Failed to index {}: {}", workspace, err);
+          std::process::exit(1);
+        }
+      }
+    }
+
+    // --| Stdio Communication ---
     Some(("stdio", _)) => {
-      
       let verbose = matches.get_flag("verbose");
       let lsp_client = matches.get_one::<String>("client").expect("error");
       let log_data = LogData::new(filter, verbose);
 
-      info!("Client Connected: {} Log Level: {} Verbose: {}", &lsp_client, &log_data.log_level, &log_data.verbose);
-
-      let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
-      let (service, socket) = LspService::new(|client| Backend {
-        client, 
-        log_data: Mutex::new(log_data),
-        workspace_map: DashMap::new(),  
-        lsp_client: lsp_client.clone(),
-        parse_tree: Mutex::new(HashMap::new()),
-        docs: Arc::new(Mutex::new(HashMap::new())),
-        config: Arc::new(Mutex::new(Config::default())),
-        parser: Mutex::new(cyber_tree_sitter::init_parser()),
-      });
-
+      info!("Client Connected: {} Log Level: {} Verbose: {}", &lsp_client, filter, verbose);
       info!("Starting cyberls server");
-      Server::new(stdin, stdout, socket).serve(service).await;
+
+      let options = ServerOptions { lsp_client: lsp_client.clone(), log_data, log_dir: Some(log_file_dir.clone()), log_spans, ..Default::default() };
+      if let Err(err) = cyber_lsp_core::run_server(Transport::Stdio, options).await {
+        error!("Server stopped: {}", err);
+      }
     }
 
     // --| TCP Communication -----
     Some(("tcp", arguments)) => {
-      #[cfg(feature = "runtime-agnostic")]
-      use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
-
       let verbose = matches.get_flag("verbose");
       let lsp_client = matches.get_one::<String>("client").expect("error");
       let log_data = LogData::new(filter, verbose);
 
-      let stream = {
-        // --| Use port if provided
-        if arguments.contains_id("port") {
-          let port = arguments.get_one::<String>("port").expect("error");
-          let port: u16 = port.parse().unwrap();
-
-          let listener = TcpListener::bind(
-            SocketAddr::new( std::net::IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port)
-          ).await.unwrap();
-
-          let (stream, _) = listener.accept().await.unwrap();
-          stream
-        }
-
-        // --| Use default port
-        else {
-          let listener = TcpListener::bind(
-            SocketAddr::new( std::net::IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9257)
-          ).await.unwrap();
-
-          let (stream, _) = listener.accept().await.unwrap();
-          stream
-        }
+      // --| Use port if provided, or the default port
+      let port: u16 = match arguments.get_one::<String>("port") {
+        Some(port) => port.parse().unwrap(),
+        None => DEFAULT_TCP_PORT,
       };
 
-      let (read, write) = tokio::io::split(stream);
-
-      #[cfg(feature = "runtime-agnostic")]
-      let (read, write) = (read.compat(), write.compat_write());
-
-      let (service, socket) = LspService::new(|client| Backend {
-        client, 
-        log_data: Mutex::new(log_data),
-        workspace_map: DashMap::new(),  
-        lsp_client: lsp_client.clone(), 
-        parse_tree: Mutex::new(HashMap::new()),
-        docs: Arc::new(Mutex::new(HashMap::new())),
-        config: Arc::new(Mutex::new(Config::default())),
-        parser: Mutex::new(cyber_tree_sitter::init_parser()),
-      });
+      info!("Client Connected: {} Log Level: {} Verbose: {}", &lsp_client, filter, verbose);
+      info!("Listening for cyberls clients on port {}", port);
 
-      Server::new(read, write, socket).serve(service).await;
+      let address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port);
+      let options = ServerOptions { lsp_client: lsp_client.clone(), log_data, log_dir: Some(log_file_dir.clone()), log_spans, ..Default::default() };
+      if let Err(err) = cyber_lsp_core::run_server(Transport::Tcp(address), options).await {
+        error!("Server stopped: {}", err);
+      }
     }
     _ => unreachable!(),
   }
@@ -0,0 +1,80 @@
+use lsp_types::{Position, Range, TextDocumentIdentifier};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tree_sitter::{Node, Point};
+
+use crate::core_types::{DocPoint, ToDoc, ToLsp};
+
+pub const MATCHING_BLOCK_METHOD: &str = "cyberls/matchingBlock";
+
+/// Constructs whose body is closed by a dedent rather than a token
+const BLOCK_KINDS: &[&str] = &[
+  "function_definition", "object_definition", "object_declaration", "if_statement", "else_clause",
+  "for_statement", "while_statement", "match_statement", "try_statement",
+];
+
+/// Clauses continuing a construct, they close the block before them
+const CLAUSE_KINDS: &[&str] = &["else_clause", "catch_clause"];
+
+// --| Request Types -----------------
+// --|--------------------------------
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchingBlockParams {
+  pub text_document: TextDocumentIdentifier,
+  pub position: Position,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchingBlockResult {
+  /// The whole construct
+  pub range: Range,
+  /// The line opening the block, ie. `if ready:`
+  pub open: Range,
+  /// What closes the block: the next `else` or `catch` clause, or the construct's last line
+  pub close: Range,
+  /// Where a jump from the cursor lands: `close` from the opening line, `open` from anywhere else
+  pub target: Range,
+}
+
+// --| Matching Block ----------------
+// --|--------------------------------
+/// The innermost block construct spanning several lines around the cursor
+pub fn matching_block(root: Node, rope: &Rope, position: Position) -> Option<MatchingBlockResult> {
+  let point = Point::from(position.to_doc(rope));
+  let mut node = root.named_descendant_for_point_range(point, point)?;
+
+  loop {
+    if BLOCK_KINDS.contains(&node.kind()) && node.end_position().row > node.start_position().row { break; }
+    node = node.parent()?;
+  }
+
+  let start = node.start_position();
+  let open = Range::new(DocPoint::from(start).to_lsp(rope), line_end(rope, start.row));
+
+  let mut cursor = node.walk();
+  let clause = node.named_children(&mut cursor)
+    .find(|child| CLAUSE_KINDS.contains(&child.kind()) && child.start_position().row > point.row);
+
+  let close = match clause {
+    Some(clause) => {
+      let start = clause.start_position();
+      Range::new(DocPoint::from(start).to_lsp(rope), line_end(rope, start.row))
+    }
+    None => {
+      let end = node.end_position();
+      let text = rope.line(end.row).to_string();
+      let indent = text.len() - text.trim_start().len();
+      Range::new(DocPoint::new(end.row, indent).to_lsp(rope), DocPoint::from(end).to_lsp(rope))
+    }
+  };
+
+  let range = Range::new(DocPoint::from(start).to_lsp(rope), DocPoint::from(node.end_position()).to_lsp(rope));
+  let target = if point.row == start.row { close } else { open };
+  Some(MatchingBlockResult { range, open, close, target })
+}
+
+fn line_end(rope: &Rope, row: usize) -> Position {
+  DocPoint::new(row, rope.line(row).len_bytes()).to_lsp(rope)
+}
@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use lsp_types::{SemanticTokens, Url};
+use serde::Serialize;
+use cyber_tree_sitter::Tree;
+
+use crate::documents::FullTextDocument;
+
+/// How often idle documents are looked for, sweeps happen on document events
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Rough size of a syntax node in a tree-sitter tree
+const NODE_BYTES: usize = 48;
+
+// --| Idle Documents ----------------
+// --|--------------------------------
+/// When each document was last used, so cached results of documents that were closed and
+/// left alone for a while can be dropped
+pub struct IdleTracker {
+  used: DashMap<Url, Instant>,
+  last_sweep: std::sync::Mutex<Instant>,
+}
+
+impl IdleTracker {
+  pub fn new() -> Self {
+    Self { used: DashMap::new(), last_sweep: std::sync::Mutex::new(Instant::now()) }
+  }
+
+  pub fn touch(&self, uri: &Url) {
+    self.used.insert(uri.clone(), Instant::now());
+  }
+
+  pub fn forget(&self, uri: &Url) {
+    self.used.remove(uri);
+  }
+
+  pub fn idle_for(&self, uri: &Url) -> Option<Duration> {
+    self.used.get(uri).map(|used| used.elapsed())
+  }
+
+  /// Documents unused for longer than `idle`, looked for at most once a minute. They are
+  /// forgotten until used again, so each is reported once.
+  pub fn sweep(&self, idle: Duration) -> Vec<Url> {
+    {
+      let mut last_sweep = self.last_sweep.lock().unwrap_or_else(|err| err.into_inner());
+      if last_sweep.elapsed() < SWEEP_INTERVAL { return vec![]; }
+      *last_sweep = Instant::now();
+    }
+
+    let stale: Vec<Url> = self.used.iter()
+      .filter(|entry| entry.value().elapsed() >= idle)
+      .map(|entry| entry.key().clone())
+      .collect();
+
+    for uri in &stale { self.used.remove(uri); }
+    stale
+  }
+}
+
+// --| Memory Report -----------------
+// --|--------------------------------
+/// Estimated memory held for an open document, in bytes
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentMemory {
+  pub uri: Url,
+  pub text: usize,
+  pub rope: usize,
+  /// `None` when the document has no tree, ie. it is too large to parse
+  pub tree: Option<usize>,
+  pub semantic_tokens: usize,
+  pub total: usize,
+  /// Seconds since the document was last used
+  pub idle_secs: Option<u64>,
+}
+
+pub fn document_memory(document: &FullTextDocument, tree: Option<&Tree>, tokens: Option<&SemanticTokens>, idle: Option<Duration>) -> DocumentMemory {
+  let text = document.text.capacity();
+  let rope = document.rope.capacity();
+  let tree = tree.map(tree_bytes);
+  let semantic_tokens = tokens.map_or(0, |tokens| tokens.data.len() * std::mem::size_of::<lsp_types::SemanticToken>());
+
+  DocumentMemory {
+    uri: document.uri.clone(),
+    text, rope, tree, semantic_tokens,
+    total: text + rope + tree.unwrap_or(0) + semantic_tokens,
+    idle_secs: idle.map(|idle| idle.as_secs()),
+  }
+}
+
+/// Node count of a tree times the rough size of a node
+fn tree_bytes(tree: &Tree) -> usize {
+  let mut cursor = tree.walk();
+  let mut nodes = 1;
+
+  loop {
+    if cursor.goto_first_child() || cursor.goto_next_sibling() {
+      nodes += 1;
+      continue;
+    }
+
+    loop {
+      if !cursor.goto_parent() { return nodes * NODE_BYTES; }
+      if cursor.goto_next_sibling() { nodes += 1; break; }
+    }
+  }
+}
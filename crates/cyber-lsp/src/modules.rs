@@ -0,0 +1,521 @@
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use dashmap::DashMap;
+use lsp_types::{CompletionItem, CompletionItemKind, Position, Range, TextEdit, Url};
+use ropey::Rope;
+use tracing::debug;
+use tree_sitter::Node;
+
+use crate::diagnostics::ErrorInfo;
+use crate::symbols;
+use crate::utils::paths;
+use crate::core_types::{DocPoint, DocRange, ToLsp};
+use crate::utils::treehelper::range_contains;
+
+mod graph;
+mod remote;
+pub use graph::{check_import_cycles, import_graph, ImportGraph, ImportGraphView, IMPORT_CYCLE_CODE};
+pub use remote::RemoteCache;
+
+/// Modules which are compiled into the cyber runtime and never touch the filesystem
+pub const BUILTIN_MODULES: &[&str] = &["core", "math", "os", "test"];
+
+/// Extension the runtime falls back to when a specifier omits it
+pub const CYBER_EXTENSION: &str = "cy";
+
+/// Diagnostic code of imports resolved through `module_paths`
+pub const MODULE_PATH_CODE: &str = "module_path_import";
+
+// --| Import Statements -------------
+// --|--------------------------------
+#[derive(Clone, Debug)]
+pub struct ImportStatement {
+  /// The local alias the module is bound to.
+  pub alias: String,
+
+  /// The raw specifier without quotes. Falls back to the alias for `import os`.
+  pub specifier: String,
+
+  /// The range of the whole import statement.
+  pub range: Range,
+
+  /// The range of the quoted specifier, if one was written.
+  pub specifier_range: Option<Range>,
+}
+
+/// Collect the top level import statements of a document
+pub fn collect_imports(root: Node, source: &str) -> Vec<ImportStatement> {
+  let mut imports = vec![];
+  let mut cursor = root.walk();
+
+  for child in root.children(&mut cursor) {
+    if child.kind() != "import_statement" { continue; }
+
+    let mut alias: Option<String> = None;
+    let mut specifier: Option<(String, Range)> = None;
+
+    let mut inner = child.walk();
+    for part in child.named_children(&mut inner) {
+      let text = part.utf8_text(source.as_bytes()).unwrap_or_default();
+
+      if part.kind() == "identifier" && alias.is_none() {
+        alias = Some(text.to_string());
+      } else if part.kind().contains("string") && specifier.is_none() {
+        let range = DocRange::of_node(&part).byte_range();
+        specifier = Some((trim_quotes(text).to_string(), range));
+      }
+    }
+
+    if let Some(alias) = alias {
+      let range = DocRange::of_node(&child).byte_range();
+      let (specifier, specifier_range) = match specifier {
+        Some((spec, range)) => (spec, Some(range)),
+        None => (alias.clone(), None),
+      };
+
+      imports.push(ImportStatement { alias, specifier, range, specifier_range });
+    }
+  }
+
+  imports
+}
+
+/// Sort the imports by alias and drop exact duplicates. `None` when they are
+/// already organized, or not written as one block of consecutive lines.
+pub fn organize_imports(imports: &[ImportStatement], rope: &Rope) -> Option<TextEdit> {
+  let (first, last) = (imports.first()?, imports.last()?);
+  if imports.windows(2).any(|pair| pair[1].range.start.line != pair[0].range.end.line + 1) { return None; }
+
+  let byte = |position: Position| DocPoint::from_byte_position(position).offset(rope);
+  let written: Vec<String> = imports.iter()
+    .map(|import| rope.byte_slice(byte(import.range.start)..byte(import.range.end)).to_string())
+    .collect();
+
+  let mut sorted: Vec<(&str, &String)> = imports.iter().map(|import| import.alias.as_str()).zip(written.iter()).collect();
+  sorted.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()).then_with(|| a.1.cmp(b.1)));
+  sorted.dedup_by(|a, b| a.1 == b.1);
+
+  let organized: Vec<&str> = sorted.iter().map(|(_, text)| text.as_str()).collect();
+  if organized.len() == written.len() && organized.iter().zip(written.iter()).all(|(a, b)| *a == b.as_str()) { return None; }
+
+  let newline = if rope.line(first.range.start.line as usize).to_string().ends_with("\r\n") { "\r\n" } else { "\n" };
+  let range = DocRange::from_byte_range(Range::new(first.range.start, last.range.end)).to_lsp(rope);
+  Some(TextEdit::new(range, organized.join(newline)))
+}
+
+/// Find the import statement under the given position, either on the
+/// statement itself or on a later use of its alias.
+pub fn import_at<'a>(imports: &'a [ImportStatement], position: Position, word: Option<&str>) -> Option<&'a ImportStatement> {
+  imports.iter()
+    .find(|import| range_contains(&import.range, position))
+    .or_else(|| word.and_then(|word| imports.iter().find(|import| import.alias == word)))
+}
+
+/// Returns the alias being accessed when the line prefix ends in `alias.` or `alias.partial`
+pub fn member_access_alias(line_prefix: &str) -> Option<&str> {
+  let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+  let before_dot = line_prefix.trim_end_matches(is_ident).strip_suffix('.')?;
+  let start = before_dot.rfind(|c: char| !is_ident(c)).map_or(0, |index| index + 1);
+
+  let alias = &before_dot[start..];
+  if alias.is_empty() { None } else { Some(alias) }
+}
+
+pub fn trim_quotes(text: &str) -> &str {
+  text.trim_matches(|c| c == '\'' || c == '"' || c == '`')
+}
+
+/// Documents with no file on disk, ie. `untitled:` buffers
+pub fn is_virtual(uri: &Url) -> bool {
+  uri.scheme() != "file"
+}
+
+/// Directory of a file document. Use `ModuleResolver::base_dir` to also cover virtual documents
+pub fn document_dir(uri: &Url) -> Option<PathBuf> {
+  paths::uri_to_path(uri).and_then(|path| path.parent().map(Path::to_path_buf))
+}
+
+/// Module path of a file below a folder without its extension, ie. `lib/math`
+pub fn module_name(root: &Path, file: &Path) -> Option<String> {
+  let relative = file.strip_prefix(root).ok()?.with_extension("");
+  Some(relative.components().map(|part| part.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/"))
+}
+
+/// Specifier importing `target` from a document in `from_dir`, relative where possible
+pub fn relative_specifier(from_dir: &Path, target: &Path) -> String {
+  let from: Vec<_> = from_dir.components().collect();
+  let to: Vec<_> = target.components().collect();
+
+  let common = from.iter().zip(to.iter()).take_while(|(a, b)| a == b).count();
+  if common == 0 { return target.to_string_lossy().replace('\\', "/"); }
+
+  let mut parts: Vec<String> = vec!["..".to_string(); from.len() - common];
+  parts.extend(to[common..].iter().map(|part| part.as_os_str().to_string_lossy().to_string()));
+  parts.join("/")
+}
+
+/// The source with the specifiers of imports resolving to files below `base_dir` made
+/// absolute, so a copy compiled from elsewhere still finds the modules next to the original
+pub fn anchor_imports(source: &str, base_dir: &Path) -> String {
+  let lines: Vec<String> = source.split('\n').map(|line| {
+    if !line.trim_start().starts_with("import ") { return line.to_string(); }
+
+    let start = match line.find(|ch| ch == '\'' || ch == '"') {
+      Some(start) => start,
+      None => return line.to_string(),
+    };
+    let quote = &line[start..start + 1];
+    let end = match line[start + 1..].find(quote) {
+      Some(end) => start + 1 + end,
+      None => return line.to_string(),
+    };
+
+    match resolve_specifier(Some(base_dir), &line[start + 1..end]) {
+      ResolvedModule::File(path) if !Path::new(&line[start + 1..end]).is_absolute() => {
+        format!("{}{}{}", &line[..start + 1], path.to_string_lossy().trim_start_matches(r"\\?\").replace('\\', "/"), &line[end..])
+      }
+      _ => line.to_string(),
+    }
+  }).collect();
+
+  lines.join("\n")
+}
+
+// --| Module Resolution -------------
+// --|--------------------------------
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResolvedModule {
+  Builtin(String),
+  File(PathBuf),
+  Remote(Url),
+  Unresolved(String),
+}
+
+/// Resolve an import specifier the same way the cyber runtime does:
+/// builtin names first, then URLs, then paths relative to the importing file.
+pub fn resolve_specifier(base_dir: Option<&Path>, specifier: &str) -> ResolvedModule {
+  if BUILTIN_MODULES.contains(&specifier) {
+    return ResolvedModule::Builtin(specifier.to_string());
+  }
+
+  if specifier.starts_with("http://") || specifier.starts_with("https://") {
+    return match Url::parse(specifier) {
+      Ok(url) => ResolvedModule::Remote(url),
+      Err(_) => ResolvedModule::Unresolved(specifier.to_string()),
+    };
+  }
+
+  let path = Path::new(specifier);
+  let candidate = match (path.is_absolute(), base_dir) {
+    (true, _) => path.to_path_buf(),
+    (false, Some(base_dir)) => base_dir.join(path),
+    (false, None) => return ResolvedModule::Unresolved(specifier.to_string()),
+  };
+
+  let mut candidates = vec![candidate.clone()];
+  if candidate.extension().is_none() { candidates.push(candidate.with_extension(CYBER_EXTENSION)); }
+
+  for candidate in candidates {
+    if candidate.is_file() {
+      return ResolvedModule::File(candidate.canonicalize().unwrap_or(candidate));
+    }
+  }
+
+  ResolvedModule::Unresolved(specifier.to_string())
+}
+
+/// Resolves import specifiers, caching the results per workspace root
+#[derive(Default, Debug)]
+pub struct ModuleResolver {
+  cache: DashMap<Url, DashMap<(Option<PathBuf>, String), (ResolvedModule, bool)>>,
+  remote: RwLock<Option<RemoteCache>>,
+  virtual_root: RwLock<Option<PathBuf>>,
+  search_paths: DashMap<Url, Vec<PathBuf>>,
+  graph: ImportGraph,
+}
+
+impl ModuleResolver {
+  pub fn new() -> Self {
+    Self { cache: DashMap::new(), remote: RwLock::new(None), virtual_root: RwLock::new(None), search_paths: DashMap::new(), graph: ImportGraph::default() }
+  }
+
+  /// Folders imports of a workspace are looked up in when not found next to the document, see `module_paths`
+  pub fn configure_search_paths(&self, workspace: &Url, search_paths: Vec<PathBuf>) {
+    let changed = self.search_paths.get(workspace).map_or(!search_paths.is_empty(), |known| *known != search_paths);
+    if !changed { return; }
+
+    self.search_paths.insert(workspace.clone(), search_paths);
+    self.invalidate(Some(workspace));
+  }
+
+  /// Directory imports of documents without a file path, like `untitled:` buffers, are resolved from
+  pub fn configure_virtual_root(&self, root: Option<PathBuf>) {
+    *self.virtual_root.write().unwrap_or_else(|err| err.into_inner()) = root;
+  }
+
+  /// Directory an import specifier of the given document is relative to: its own
+  /// directory, or the virtual root for documents that only exist in the editor
+  pub fn base_dir(&self, uri: &Url) -> Option<PathBuf> {
+    if is_virtual(uri) { self.virtual_root.read().unwrap_or_else(|err| err.into_inner()).clone() } else { document_dir(uri) }
+  }
+
+  /// Enable analysis of URL imports through the given cache, or disable it with `None`
+  pub fn configure_remote(&self, remote: Option<RemoteCache>) {
+    *self.remote.write().unwrap_or_else(|err| err.into_inner()) = remote;
+  }
+
+  pub fn remote(&self) -> Option<RemoteCache> {
+    self.remote.read().unwrap_or_else(|err| err.into_inner()).clone()
+  }
+
+  /// Local file holding the source of a resolved module, if any
+  pub fn source_path(&self, resolved: &ResolvedModule) -> Option<PathBuf> {
+    match resolved {
+      ResolvedModule::File(path) => Some(path.clone()),
+      ResolvedModule::Remote(url) => self.remote()?.cached(url),
+      _ => None,
+    }
+  }
+
+  pub fn resolve(&self, workspace: &Url, base_dir: Option<&Path>, specifier: &str) -> ResolvedModule {
+    self.resolve_with_origin(workspace, base_dir, specifier).0
+  }
+
+  /// Resolve a specifier, and whether it was only found through the configured
+  /// `module_paths`, which the cyber runtime doesn't search
+  pub fn resolve_with_origin(&self, workspace: &Url, base_dir: Option<&Path>, specifier: &str) -> (ResolvedModule, bool) {
+    let key = (base_dir.map(Path::to_path_buf), specifier.to_string());
+    let cache = self.cache.entry(workspace.clone()).or_default();
+
+    if let Some(resolved) = cache.get(&key) {
+      return resolved.clone();
+    }
+
+    let mut resolved = (resolve_specifier(base_dir, specifier), false);
+
+    // Then from the configured module paths, in order
+    if let (ResolvedModule::Unresolved(_), Some(search_paths)) = (&resolved.0, self.search_paths.get(workspace)) {
+      let found = search_paths.iter()
+        .map(|dir| resolve_specifier(Some(dir), specifier))
+        .find(|found| matches!(found, ResolvedModule::File(_)));
+      if let Some(found) = found { resolved = (found, true); }
+    }
+    debug!("Resolved module {:?} -> {:?}", specifier, resolved);
+
+    cache.insert(key, resolved.clone());
+    resolved
+  }
+
+  /// Drop cached resolutions for a workspace, or for every workspace when `None`.
+  /// The import graph is rebuilt as documents are checked again.
+  pub fn invalidate(&self, workspace: Option<&Url>) {
+    self.graph.clear();
+    match workspace {
+      Some(workspace) => { self.cache.remove(workspace); }
+      None => self.cache.clear(),
+    }
+  }
+}
+
+// --| Import Diagnostics ------------
+// --|--------------------------------
+pub fn check_imports(resolver: &ModuleResolver, workspace: &Url, base_dir: Option<&Path>, root: Node, source: &str) -> Option<ErrorInfo> {
+  let mut error_info = ErrorInfo::new();
+
+  let remote = resolver.remote();
+
+  for import in collect_imports(root, source) {
+    let range = import.specifier_range.unwrap_or(import.range);
+
+    match resolver.resolve_with_origin(workspace, base_dir, &import.specifier) {
+      (ResolvedModule::Unresolved(specifier), _) => {
+        error_info.add(
+          DocPoint::from_byte_position(range.start),
+          DocPoint::from_byte_position(range.end),
+          format!("Unable to resolve module '{}'", specifier),
+          Some(lsp_types::DiagnosticSeverity::ERROR),
+        );
+      }
+
+      // The editor finds these, running the program won't
+      (ResolvedModule::File(path), true) => {
+        error_info.add_coded(
+          DocPoint::from_byte_position(range.start),
+          DocPoint::from_byte_position(range.end),
+          format!("Module '{}' is only found through `module_paths` at {}, the cyber runtime does not search there", import.specifier, path.display()),
+          Some(lsp_types::DiagnosticSeverity::WARNING),
+          MODULE_PATH_CODE,
+        );
+      }
+
+      // Remote modules stay opaque unless remote analysis is enabled
+      (ResolvedModule::Remote(url), _) => {
+        if remote.as_ref().map_or(false, |remote| remote.cached(&url).is_none()) {
+          error_info.add(
+            DocPoint::from_byte_position(range.start),
+            DocPoint::from_byte_position(range.end),
+            format!("Remote module '{}' is not cached", url),
+            Some(lsp_types::DiagnosticSeverity::WARNING),
+          );
+        }
+      }
+      _ => {}
+    }
+  }
+
+  if error_info.entries.is_empty() { None } else { Some(error_info) }
+}
+
+// --| Import Completion -------------
+// --|--------------------------------
+/// Complete builtin module names and files next to the document for a partially typed specifier
+pub fn specifier_completions(base_dir: Option<&Path>, partial: &str) -> Vec<CompletionItem> {
+  let mut items: Vec<CompletionItem> = BUILTIN_MODULES.iter()
+    .filter(|name| name.starts_with(partial))
+    .map(|name| CompletionItem {
+      label: name.to_string(),
+      kind: Some(CompletionItemKind::MODULE),
+      detail: Some("builtin module".to_string()),
+      ..Default::default()
+    }).collect();
+
+  let base_dir = match base_dir { Some(dir) => dir, None => return items };
+
+  // Only list the directory the user is currently typing in
+  let (dir_part, _) = partial.rsplit_once('/').unwrap_or(("", partial));
+  let search_dir = base_dir.join(dir_part);
+
+  if let Ok(entries) = std::fs::read_dir(&search_dir) {
+    for entry in entries.flatten() {
+      let path = entry.path();
+      let name = entry.file_name().to_string_lossy().to_string();
+      if name.starts_with('.') { continue; }
+
+      let is_dir = path.is_dir();
+      let is_module = path.extension().map_or(false, |ext| ext == CYBER_EXTENSION || ext == "cyber");
+      if !is_dir && !is_module { continue; }
+
+      let label = if dir_part.is_empty() { format!("./{}", name) } else { format!("{}/{}", dir_part, name) };
+      items.push(CompletionItem {
+        label,
+        kind: Some(if is_dir { CompletionItemKind::FOLDER } else { CompletionItemKind::FILE }),
+        detail: Some(path.display().to_string()),
+        ..Default::default()
+      });
+    }
+  }
+
+  items
+}
+
+/// Top level functions and variables a module file exposes to importers
+pub fn module_exports(path: &Path) -> Vec<CompletionItem> {
+  let source = match std::fs::read_to_string(path) {
+    Ok(source) => source,
+    Err(_) => return vec![],
+  };
+
+  let mut parser = match cyber_tree_sitter::try_init_parser() {
+    Ok(parser) => parser,
+    Err(_) => return vec![],
+  };
+
+  let tree = match parser.parse(&source, None) {
+    Some(tree) => tree,
+    None => return vec![],
+  };
+
+  let root = tree.root_node();
+  let mut cursor = root.walk();
+  let module_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+
+  root.named_children(&mut cursor).filter_map(|child| {
+    let kind = match child.kind() {
+      "function_definition" => CompletionItemKind::FUNCTION,
+      "variable_def" | "assignment_statement" => CompletionItemKind::VARIABLE,
+      _ => return None,
+    };
+
+    let name_node = child.child_by_field_name("name").or_else(|| {
+      let mut inner = child.walk();
+      let found = child.named_children(&mut inner).find(|node| node.kind() == "identifier");
+      found
+    })?;
+
+    Some(CompletionItem {
+      label: name_node.utf8_text(source.as_bytes()).ok()?.to_string(),
+      kind: Some(kind),
+      detail: Some(format!("from: {}", module_name)),
+      documentation: symbols::doc_comment(child, &source).map(symbols::markdown),
+      ..Default::default()
+    })
+  }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn anchor_imports_makes_relative_files_absolute() {
+    let dir = std::env::temp_dir().join(format!("cyberls-anchor-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib").join("util.cy"), "func help():\n  pass\n").unwrap();
+    let util = dir.join("lib").join("util.cy").canonicalize().unwrap().to_string_lossy().trim_start_matches(r"\\?\").replace('\\', "/");
+
+    let source = "import util 'lib/util.cy'\nimport m 'math'\nimport gone './missing.cy'\nprint 'lib/util.cy'";
+    let anchored = anchor_imports(source, &dir);
+    let lines: Vec<&str> = anchored.split('\n').collect();
+    assert_eq!(lines[0], format!("import util '{}'", util));
+    assert_eq!(&lines[1..], &["import m 'math'", "import gone './missing.cy'", "print 'lib/util.cy'"]);
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn builtin_names_resolve_before_files() {
+    assert_eq!(resolve_specifier(None, "math"), ResolvedModule::Builtin("math".to_string()));
+    assert_eq!(resolve_specifier(Some(&std::env::temp_dir()), "os"), ResolvedModule::Builtin("os".to_string()));
+  }
+
+  #[test]
+  fn urls_resolve_to_remote_modules() {
+    let url = "https://example.com/lib/mod.cy";
+    assert_eq!(resolve_specifier(None, url), ResolvedModule::Remote(Url::parse(url).unwrap()));
+    assert_eq!(resolve_specifier(None, "https://"), ResolvedModule::Unresolved("https://".to_string()));
+  }
+
+  #[test]
+  fn relative_specifiers_resolve_next_to_the_document() {
+    let dir = std::env::temp_dir().join(format!("cyberls-resolve-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib").join("util.cy"), "func help():\n  pass\n").unwrap();
+    let util = ResolvedModule::File(dir.join("lib").join("util.cy").canonicalize().unwrap());
+
+    assert_eq!(resolve_specifier(Some(&dir), "lib/util.cy"), util);
+    assert_eq!(resolve_specifier(Some(&dir), "./lib/util"), util);
+    assert_eq!(resolve_specifier(Some(&dir), "lib/missing.cy"), ResolvedModule::Unresolved("lib/missing.cy".to_string()));
+    assert_eq!(resolve_specifier(None, "lib/util.cy"), ResolvedModule::Unresolved("lib/util.cy".to_string()));
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn only_module_paths_extend_the_runtime_lookup() {
+    let dir = std::env::temp_dir().join(format!("cyberls-module-paths-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::create_dir_all(dir.join("vendor")).unwrap();
+    std::fs::write(dir.join("root.cy"), "var a = 1\n").unwrap();
+    std::fs::write(dir.join("vendor").join("dep.cy"), "var b = 2\n").unwrap();
+    let workspace = paths::dir_to_uri(&dir).unwrap();
+    let base_dir = dir.join("src");
+
+    let resolver = ModuleResolver::new();
+    // The workspace root is not searched, as the runtime doesn't
+    assert_eq!(resolver.resolve(&workspace, Some(&base_dir), "root.cy"), ResolvedModule::Unresolved("root.cy".to_string()));
+
+    resolver.configure_search_paths(&workspace, vec![dir.join("vendor")]);
+    let dep = ResolvedModule::File(dir.join("vendor").join("dep.cy").canonicalize().unwrap());
+    assert_eq!(resolver.resolve_with_origin(&workspace, Some(&base_dir), "dep.cy"), (dep, true));
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
@@ -0,0 +1,291 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+use dashmap::DashMap;
+use lsp_types::{DiagnosticSeverity, Url};
+use serde::Serialize;
+
+use super::{collect_imports, relative_specifier, ImportStatement, ModuleResolver};
+use crate::diagnostics::ErrorInfo;
+use crate::utils::paths;
+use crate::core_types::DocPoint;
+
+/// Diagnostic code of imports leading back to the importing module
+pub const IMPORT_CYCLE_CODE: &str = "import_cycle";
+
+// --| Import Graph ------------------
+// --|--------------------------------
+/// Module files every module file imports, by canonical path. Checked documents record
+/// their imports, other files are parsed from disk the first time a search reaches them.
+#[derive(Default, Debug)]
+pub struct ImportGraph {
+  edges: DashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl ImportGraph {
+  pub fn update(&self, path: PathBuf, targets: Vec<PathBuf>) {
+    self.edges.insert(path, targets);
+  }
+
+  pub fn forget(&self, path: &Path) {
+    self.edges.remove(path);
+  }
+
+  pub fn clear(&self) {
+    self.edges.clear();
+  }
+
+  fn get(&self, path: &Path) -> Option<Vec<PathBuf>> {
+    self.edges.get(path).map(|entry| entry.value().clone())
+  }
+}
+
+impl ModuleResolver {
+  /// Drop the recorded imports of a file that changed on disk
+  pub fn forget_imports(&self, path: &Path) {
+    self.graph.forget(&canonical(path));
+  }
+
+  /// Module file each import resolves to, in the order of `imports`
+  fn import_targets(&self, workspace: &Url, base_dir: Option<&Path>, imports: &[ImportStatement]) -> Vec<Option<PathBuf>> {
+    imports.iter().map(|import| self.source_path(&self.resolve(workspace, base_dir, &import.specifier))).collect()
+  }
+
+  /// Files a module file imports, from the graph or else parsed from disk
+  fn imports_of(&self, workspace: &Url, path: &Path) -> Vec<PathBuf> {
+    if let Some(targets) = self.graph.get(path) { return targets; }
+
+    let targets: Vec<PathBuf> = read_imports(path)
+      .map(|imports| self.import_targets(workspace, path.parent(), &imports).into_iter().flatten().collect())
+      .unwrap_or_default();
+
+    self.graph.update(path.to_path_buf(), targets.clone());
+    targets
+  }
+
+  /// Whether `importer` has an import resolving to the module file `module`
+  pub fn imports_file(&self, workspace: &Url, importer: &Path, module: &Path) -> bool {
+    let module = canonical(module);
+    self.imports_of(workspace, &canonical(importer)).iter().any(|target| canonical(target) == module)
+  }
+
+  /// The given module files and every module file they import, directly or through others
+  pub fn reachable_modules(&self, workspace: &Url, from: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen: HashSet<PathBuf> = from.iter().cloned().collect();
+    let mut queue: VecDeque<PathBuf> = from.into_iter().collect();
+    let mut reachable = vec![];
+
+    while let Some(path) = queue.pop_front() {
+      for target in self.imports_of(workspace, &path) {
+        if seen.insert(target.clone()) { queue.push_back(target); }
+      }
+      reachable.push(path);
+    }
+
+    reachable
+  }
+
+  /// Shortest chain of imports leading from `from` to `to`, both included
+  fn import_chain(&self, workspace: &Url, from: &Path, to: &Path) -> Option<Vec<PathBuf>> {
+    let mut previous: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut queue = VecDeque::from([from.to_path_buf()]);
+    let mut seen = HashSet::from([from.to_path_buf()]);
+
+    while let Some(path) = queue.pop_front() {
+      if path == to {
+        let mut chain = vec![path];
+        while let Some(before) = previous.get(chain.last().unwrap()) { chain.push(before.clone()); }
+        chain.reverse();
+        return Some(chain);
+      }
+
+      for target in self.imports_of(workspace, &path) {
+        if seen.insert(target.clone()) {
+          previous.insert(target.clone(), path.clone());
+          queue.push_back(target);
+        }
+      }
+    }
+
+    None
+  }
+}
+
+fn read_imports(path: &Path) -> Option<Vec<ImportStatement>> {
+  let source = std::fs::read_to_string(path).ok()?;
+  let mut parser = cyber_tree_sitter::try_init_parser().ok()?;
+  let tree = parser.parse(&source, None)?;
+  Some(collect_imports(tree.root_node(), &source))
+}
+
+fn canonical(path: &Path) -> PathBuf {
+  path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+// --| Cycle Diagnostics -------------
+// --|--------------------------------
+/// Record the imports of a document and warn on each one whose module imports it back
+pub fn check_import_cycles(resolver: &ModuleResolver, workspace: &Url, path: &Path, imports: &[ImportStatement]) -> Option<ErrorInfo> {
+  let path = canonical(path);
+  let dir = path.parent().map(Path::to_path_buf);
+  let targets = resolver.import_targets(workspace, dir.as_deref(), imports);
+  resolver.graph.update(path.clone(), targets.iter().flatten().cloned().collect());
+
+  let mut error_info = ErrorInfo::new();
+  for (import, target) in imports.iter().zip(targets) {
+    let chain = match target.and_then(|target| resolver.import_chain(workspace, &target, &path)) {
+      Some(chain) => chain,
+      None => continue,
+    };
+
+    let names: Vec<String> = std::iter::once(&path).chain(chain.iter())
+      .map(|file| dir.as_deref().map_or_else(|| file.display().to_string(), |dir| relative_specifier(dir, file)))
+      .collect();
+
+    let range = import.specifier_range.unwrap_or(import.range);
+    error_info.add_coded(
+      DocPoint::from_byte_position(range.start),
+      DocPoint::from_byte_position(range.end),
+      format!("Circular import: {}", names.join(" -> ")),
+      Some(DiagnosticSeverity::WARNING),
+      IMPORT_CYCLE_CODE,
+    );
+  }
+
+  if error_info.entries.is_empty() { None } else { Some(error_info) }
+}
+
+// --| Graph Export ------------------
+// --|--------------------------------
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphNode {
+  pub uri: Url,
+  /// Path below the workspace folder
+  pub label: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphEdge {
+  pub from: Url,
+  pub to: Url,
+  /// The imported module leads back to the importer
+  pub cyclic: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportGraphView {
+  pub nodes: Vec<GraphNode>,
+  pub edges: Vec<GraphEdge>,
+}
+
+/// Graph of the given module files and every module file they import
+pub fn import_graph(resolver: &ModuleResolver, workspace: &Url, files: Vec<PathBuf>) -> ImportGraphView {
+  let root = paths::uri_to_path(workspace);
+  let mut view = ImportGraphView::default();
+  let mut queue: VecDeque<PathBuf> = files.iter().map(|file| canonical(file)).collect();
+  let mut seen: HashSet<PathBuf> = queue.iter().cloned().collect();
+
+  while let Some(path) = queue.pop_front() {
+    let uri = match paths::path_to_uri(&path) { Some(uri) => uri, None => continue };
+    let label = root.as_deref().and_then(|root| path.strip_prefix(root).ok()).unwrap_or(&path);
+    view.nodes.push(GraphNode { uri: uri.clone(), label: label.to_string_lossy().replace('\\', "/") });
+
+    for target in resolver.imports_of(workspace, &path) {
+      if let Some(to) = paths::path_to_uri(&target) {
+        let cyclic = resolver.import_chain(workspace, &target, &path).is_some();
+        view.edges.push(GraphEdge { from: uri.clone(), to, cyclic });
+      }
+      if seen.insert(target.clone()) { queue.push_back(target); }
+    }
+  }
+
+  view
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn workspace(name: &str, files: &[(&str, &str)]) -> (PathBuf, Url) {
+    let dir = std::env::temp_dir().join(format!("cyberls-graph-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, source) in files { std::fs::write(dir.join(file), source).unwrap(); }
+    let dir = dir.canonicalize().unwrap();
+    let uri = paths::dir_to_uri(&dir).unwrap();
+    (dir, uri)
+  }
+
+  fn cycles(resolver: &ModuleResolver, workspace: &Url, path: &Path) -> Vec<String> {
+    let source = std::fs::read_to_string(path).unwrap();
+    let tree = cyber_tree_sitter::init_parser().parse(&source, None).unwrap();
+    let imports = collect_imports(tree.root_node(), &source);
+    check_import_cycles(resolver, workspace, path, &imports)
+      .map_or(vec![], |info| info.entries.into_iter().map(|entry| entry.message).collect())
+  }
+
+  #[test]
+  fn direct_cycles_are_reported_on_the_import() {
+    let (dir, uri) = workspace("direct", &[
+      ("a.cy", "import b './b.cy'\n"),
+      ("b.cy", "import a './a.cy'\n"),
+      ("c.cy", "import b './b.cy'\n"),
+    ]);
+    let resolver = ModuleResolver::new();
+
+    assert_eq!(cycles(&resolver, &uri, &dir.join("a.cy")), vec!["Circular import: a.cy -> b.cy -> a.cy"]);
+    assert!(cycles(&resolver, &uri, &dir.join("c.cy")).is_empty());
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn transitive_cycles_name_the_whole_chain() {
+    let (dir, uri) = workspace("transitive", &[
+      ("x.cy", "import y './y.cy'\nimport m 'math'\n"),
+      ("y.cy", "import z './z.cy'\n"),
+      ("z.cy", "import x './x.cy'\n"),
+    ]);
+    let resolver = ModuleResolver::new();
+
+    assert_eq!(cycles(&resolver, &uri, &dir.join("x.cy")), vec!["Circular import: x.cy -> y.cy -> z.cy -> x.cy"]);
+
+    let view = import_graph(&resolver, &uri, vec![dir.join("x.cy")]);
+    assert_eq!(view.nodes.iter().map(|node| node.label.as_str()).collect::<Vec<_>>(), vec!["x.cy", "y.cy", "z.cy"]);
+    assert_eq!(view.edges.len(), 3);
+    assert!(view.edges.iter().all(|edge| edge.cyclic));
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn reachable_modules_follow_imports_of_imports() {
+    let (dir, uri) = workspace("reachable", &[
+      ("a.cy", "import b './b.cy'\n"),
+      ("b.cy", "import c './c.cy'\n"),
+      ("c.cy", "import b './b.cy'\n"),
+      ("d.cy", "print 1\n"),
+    ]);
+    let resolver = ModuleResolver::new();
+
+    let reachable = resolver.reachable_modules(&uri, vec![dir.join("b.cy")]);
+    let names: Vec<_> = reachable.iter().map(|path| path.file_name().unwrap().to_string_lossy().to_string()).collect();
+    assert_eq!(names, vec!["b.cy", "c.cy"]);
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn breaking_a_cycle_clears_it() {
+    let (dir, uri) = workspace("broken", &[
+      ("a.cy", "import b './b.cy'\n"),
+      ("b.cy", "import a './a.cy'\n"),
+    ]);
+    let resolver = ModuleResolver::new();
+    assert_eq!(cycles(&resolver, &uri, &dir.join("a.cy")).len(), 1);
+
+    std::fs::write(dir.join("b.cy"), "print 1\n").unwrap();
+    resolver.forget_imports(&dir.join("b.cy"));
+    assert!(cycles(&resolver, &uri, &dir.join("a.cy")).is_empty());
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
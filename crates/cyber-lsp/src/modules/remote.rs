@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use lsp_types::Url;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+
+use super::CYBER_EXTENSION;
+
+// --| Remote Module Cache -----------
+// --|--------------------------------
+/// On-disk cache of modules imported by URL. Every entry is stored next to a
+/// checksum of its contents so truncated or tampered files are refetched. Modules with
+/// a pinned hash are only accepted, downloaded or cached, when their contents match it.
+#[derive(Clone, Debug)]
+pub struct RemoteCache {
+  pub cache_dir: PathBuf,
+  pub offline: bool,
+  /// Expected SHA-256 of modules by URL
+  pub pinned: HashMap<String, String>,
+}
+
+impl RemoteCache {
+  pub fn new(cache_dir: Option<PathBuf>, offline: bool) -> Self {
+    let cache_dir = cache_dir.unwrap_or_else(|| {
+      dirs::cache_dir().unwrap_or_else(std::env::temp_dir).join("cyberls").join("modules")
+    });
+
+    Self { cache_dir, offline, pinned: HashMap::new() }
+  }
+
+  /// Require the modules at the given URLs to have the given SHA-256
+  pub fn with_pinned(mut self, pinned: HashMap<String, String>) -> Self {
+    self.pinned = pinned.into_iter().map(|(url, hash)| (url, hash.trim().to_ascii_lowercase())).collect();
+    self
+  }
+
+  fn pinned_hash(&self, url: &Url) -> Option<&str> {
+    self.pinned.get(url.as_str()).map(String::as_str)
+  }
+
+  /// Path the module for the given url is cached at
+  pub fn entry_path(&self, url: &Url) -> PathBuf {
+    self.cache_dir.join(format!("{}.{}", digest(url.as_str().as_bytes()), CYBER_EXTENSION))
+  }
+
+  fn checksum_path(&self, url: &Url) -> PathBuf {
+    self.cache_dir.join(format!("{}.sha256", digest(url.as_str().as_bytes())))
+  }
+
+  /// Returns the cached module if present and its checksum still matches
+  pub fn cached(&self, url: &Url) -> Option<PathBuf> {
+    let path = self.entry_path(url);
+    let content = std::fs::read(&path).ok()?;
+    let expected = std::fs::read_to_string(self.checksum_path(url)).ok()?;
+
+    let actual = digest(&content);
+    if expected.trim() != actual {
+      warn!("Checksum mismatch for cached module {}: {:?}", url, path);
+      return None;
+    }
+    if self.pinned_hash(url).map_or(false, |pinned| pinned != actual) {
+      warn!("Cached module {} does not match its pinned hash: {:?}", url, path);
+      return None;
+    }
+
+    Some(path)
+  }
+
+  /// Download the module and store it in the cache. Blocking.
+  pub fn fetch(&self, url: &Url) -> anyhow::Result<PathBuf> {
+    if self.offline {
+      return Err(anyhow!("offline mode: remote module {} is not cached", url));
+    }
+
+    info!("Fetching remote module: {}", url);
+    let content = download(url)?;
+    let actual = digest(content.as_bytes());
+    if let Some(pinned) = self.pinned_hash(url) {
+      if pinned != actual {
+        return Err(anyhow!("remote module {} has SHA-256 {}, expected {}", url, actual, pinned));
+      }
+    }
+
+    std::fs::create_dir_all(&self.cache_dir)?;
+
+    // The module first, a checksum left from an older version then fails it until replaced
+    let path = self.entry_path(url);
+    write_atomic(&path, content.as_bytes())?;
+    write_atomic(&self.checksum_path(url), actual.as_bytes())?;
+
+    debug!("Cached remote module {} at {:?}", url, path);
+    Ok(path)
+  }
+
+  /// Returns the cached module, fetching it first if needed. Blocking.
+  pub fn ensure(&self, url: &Url) -> anyhow::Result<PathBuf> {
+    match self.cached(url) {
+      Some(path) => Ok(path),
+      None => self.fetch(url),
+    }
+  }
+}
+
+#[cfg(feature = "native")]
+fn download(url: &Url) -> anyhow::Result<String> {
+  use anyhow::Context;
+
+  ureq::get(url.as_str()).call()
+    .with_context(|| format!("failed to fetch {}", url))?
+    .into_string()
+    .with_context(|| format!("failed to read response from {}", url))
+}
+
+/// Builds without networking only use modules already in the cache
+#[cfg(not(feature = "native"))]
+fn download(url: &Url) -> anyhow::Result<String> {
+  Err(anyhow!("remote module {} is not cached and this build of cyberls can't download it", url))
+}
+
+fn digest(bytes: &[u8]) -> String {
+  format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Write through a temp file in the same folder and rename it into place, so servers
+/// sharing the cache never read a partly written file
+fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+  let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+  let temp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+  std::fs::write(&temp, bytes)?;
+  std::fs::rename(&temp, path).map_err(|err| {
+    let _ = std::fs::remove_file(&temp);
+    err
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn cache(name: &str) -> RemoteCache {
+    let dir = std::env::temp_dir().join(format!("cyberls-remote-{}-{}", name, std::process::id()));
+    RemoteCache::new(Some(dir), true)
+  }
+
+  fn store(cache: &RemoteCache, url: &Url, content: &str) {
+    std::fs::create_dir_all(&cache.cache_dir).unwrap();
+    write_atomic(&cache.entry_path(url), content.as_bytes()).unwrap();
+    write_atomic(&cache.checksum_path(url), digest(content.as_bytes()).as_bytes()).unwrap();
+  }
+
+  #[test]
+  fn cached_modules_must_match_their_pinned_hash() {
+    let url = Url::parse("https://example.com/mod.cy").unwrap();
+    let unpinned = cache("pinned");
+    store(&unpinned, &url, "func f(): pass");
+    assert!(unpinned.cached(&url).is_some());
+
+    let pinned = |hash: &str| unpinned.clone().with_pinned(HashMap::from([(url.to_string(), hash.to_string())]));
+    assert!(pinned(&digest(b"func f(): pass").to_ascii_uppercase()).cached(&url).is_some());
+    assert!(pinned(&digest(b"func g(): pass")).cached(&url).is_none());
+
+    std::fs::remove_dir_all(&unpinned.cache_dir).unwrap();
+  }
+
+  #[test]
+  fn atomic_writes_leave_no_temp_files() {
+    let url = Url::parse("https://example.com/mod.cy").unwrap();
+    let cache = cache("atomic");
+    store(&cache, &url, "var a = 1");
+    store(&cache, &url, "var a = 2");
+
+    assert_eq!(std::fs::read_to_string(cache.entry_path(&url)).unwrap(), "var a = 2");
+    let names: Vec<String> = std::fs::read_dir(&cache.cache_dir).unwrap()
+      .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+      .collect();
+    assert!(names.iter().all(|name| !name.ends_with(".tmp")), "{:?}", names);
+
+    std::fs::remove_dir_all(&cache.cache_dir).unwrap();
+  }
+}
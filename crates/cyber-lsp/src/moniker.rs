@@ -0,0 +1,86 @@
+use lsp_types::{Moniker, MonikerKind, Position, Range, SymbolKind, UniquenessLevel, Url};
+
+use crate::modules::{self, ImportStatement};
+use crate::symbols::{DocumentSymbols, Symbol};
+
+/// Scheme of every moniker the server emits
+pub const MONIKER_SCHEME: &str = "cyber";
+
+// --| Monikers ----------------------
+// --|--------------------------------
+// Exported names are identified as `package/module:path`, ie. `shapes/lib/vec:Vec2.len`,
+// where the package is the workspace folder name and the path qualifies methods by
+// their object. Builtin modules have no package, ie. `math:sqrt`.
+
+/// Package of the modules of a workspace folder: the folder name
+pub fn package_name(workspace: &Url) -> String {
+  workspace.path_segments()
+    .and_then(|segments| segments.filter(|segment| !segment.is_empty()).last())
+    .unwrap_or("workspace")
+    .to_string()
+}
+
+/// The document identifiers are looked up in
+pub struct MonikerContext<'a> {
+  /// `package/module` of the document
+  pub module: &'a str,
+  pub symbols: &'a DocumentSymbols,
+  pub imports: &'a [ImportStatement],
+}
+
+/// Moniker of an identifier. `imported` gives the qualified module an import refers to.
+pub fn moniker_at(context: &MonikerContext, name: &str, range: Range, line_prefix: &str, imported: impl Fn(&ImportStatement) -> Option<String>) -> Option<Moniker> {
+  // `alias.member` names a declaration of the imported module
+  if let Some(alias) = modules::member_access_alias(line_prefix) {
+    let import = context.imports.iter().find(|import| import.alias == alias)?;
+    return Some(moniker(format!("{}:{}", imported(import)?, name), MonikerKind::Import, UniquenessLevel::Scheme));
+  }
+
+  if let Some(import) = context.imports.iter().find(|import| import.alias == name) {
+    return Some(moniker(imported(import)?, MonikerKind::Import, UniquenessLevel::Scheme));
+  }
+
+  let declared = context.symbols.symbols.iter().find(|symbol| symbol.selection_range == range);
+  let symbol = declared.or_else(|| {
+    context.symbols.symbols.iter().find(|symbol| symbol.name == name && symbol.container.is_none())
+  });
+
+  if let Some(symbol) = symbol.filter(|symbol| is_exported(symbol, context.symbols)) {
+    return Some(moniker(format!("{}:{}", context.module, symbol_path(symbol)), MonikerKind::Export, UniquenessLevel::Scheme));
+  }
+
+  // Anything else is only unique within the document, keyed by where it is declared
+  let start = symbol.map(|symbol| symbol.selection_range.start)
+    .or_else(|| local_declaration(context.symbols, name, range.start))?;
+  Some(moniker(format!("{}:{}@{}:{}", context.module, name, start.line, start.character), MonikerKind::Local, UniquenessLevel::Document))
+}
+
+/// Top level declarations and the methods of top level objects are visible to importers
+fn is_exported(symbol: &Symbol, symbols: &DocumentSymbols) -> bool {
+  match &symbol.container {
+    None => symbol.kind != SymbolKind::MODULE,
+    Some(container) => symbol.kind == SymbolKind::METHOD && symbols.symbols.iter()
+      .any(|object| object.kind == SymbolKind::OBJECT && object.container.is_none() && &object.name == container),
+  }
+}
+
+fn symbol_path(symbol: &Symbol) -> String {
+  match &symbol.container {
+    Some(container) => format!("{}.{}", container, symbol.name),
+    None => symbol.name.clone(),
+  }
+}
+
+/// Nearest binding or nested declaration of `name` at or before `position`
+fn local_declaration(symbols: &DocumentSymbols, name: &str, position: Position) -> Option<Position> {
+  let before = |start: &Position| (start.line, start.character) <= (position.line, position.character);
+
+  symbols.symbols.iter().filter(|symbol| symbol.name == name).map(|symbol| symbol.selection_range.start)
+    .chain(symbols.bindings.iter().filter(|binding| binding.name == name).map(|binding| binding.range.start))
+    .filter(before)
+    .max_by_key(|start| (start.line, start.character))
+}
+
+fn moniker(identifier: String, kind: MonikerKind, unique: UniquenessLevel) -> Moniker {
+  Moniker { scheme: MONIKER_SCHEME.to_string(), identifier, unique, kind: Some(kind) }
+}
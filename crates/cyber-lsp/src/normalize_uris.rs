@@ -0,0 +1,92 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use lsp_types::Url;
+use serde_json::Value;
+use tower::Service;
+use tower_lsp::jsonrpc::{Request, Response};
+use tower_lsp::ExitedError;
+
+use crate::utils::paths;
+
+/// Parameter fields holding a document or folder URI
+const URI_FIELDS: [&str; 5] = ["uri", "rootUri", "targetUri", "oldUri", "newUri"];
+
+// --| URI Normalization -------------
+// --|--------------------------------
+/// Rewrite the `file:` URIs in request parameters the way `paths::path_to_uri` spells them,
+/// so documents of the client are the same keys as files the server finds on disk
+fn normalize_params(params: &mut Value) {
+  match params {
+    Value::Object(object) => {
+      for (key, value) in object.iter_mut() {
+        match value {
+          Value::String(text) if URI_FIELDS.contains(&key.as_str()) => {
+            if let Ok(uri) = Url::parse(text) {
+              if uri.scheme() == "file" { *text = paths::normalize_uri(&uri).to_string(); }
+            }
+          }
+          _ => normalize_params(value),
+        }
+      }
+    }
+    Value::Array(values) => values.iter_mut().for_each(normalize_params),
+    _ => {}
+  }
+}
+
+/// Normalizes the URIs of every incoming request and notification
+pub struct NormalizeUris<S> {
+  inner: S,
+}
+
+impl<S> NormalizeUris<S> {
+  pub fn new(inner: S) -> Self {
+    Self { inner }
+  }
+}
+
+impl<S> Service<Request> for NormalizeUris<S>
+where
+  S: Service<Request, Response = Option<Response>, Error = ExitedError>,
+  S::Future: Send + 'static,
+{
+  type Response = Option<Response>;
+  type Error = ExitedError;
+  type Future = Pin<Box<dyn Future<Output = Result<Option<Response>, ExitedError>> + Send>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.inner.poll_ready(cx)
+  }
+
+  fn call(&mut self, request: Request) -> Self::Future {
+    let (method, id, params) = request.into_parts();
+    let mut builder = Request::build(method);
+    if let Some(id) = id { builder = builder.id(id); }
+    if let Some(mut params) = params {
+      normalize_params(&mut params);
+      builder = builder.params(params);
+    }
+    Box::pin(self.inner.call(builder.finish()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn only_file_uris_are_rewritten() {
+    let mut params = json!({
+      "textDocument": { "uri": "file:///work/caf%c3%a9.cy", "text": "file:///work/caf%c3%a9.cy" },
+      "workspaceFolders": [{ "uri": "untitled:Untitled-1", "name": "a" }],
+    });
+    normalize_params(&mut params);
+
+    if !cfg!(windows) { assert_eq!(params["textDocument"]["uri"], "file:///work/caf%C3%A9.cy"); }
+    assert_eq!(params["textDocument"]["text"], "file:///work/caf%c3%a9.cy");
+    assert_eq!(params["workspaceFolders"][0]["uri"], "untitled:Untitled-1");
+  }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+#[cfg(feature = "native")]
+use std::process::Stdio;
+
+use lsp_types::{MessageActionItem, MessageType};
+#[cfg(feature = "native")]
+use tracing::debug;
+
+/// Choices of a confirmation prompt
+pub const APPLY_CHOICE: &str = "Apply";
+pub const CANCEL_CHOICE: &str = "Cancel";
+
+// --| User Notifications ------------
+// --|--------------------------------
+/// Severity of a message shown to the user
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+  Error,
+  Warning,
+  Info,
+}
+
+impl From<Severity> for MessageType {
+  fn from(severity: Severity) -> Self {
+    match severity {
+      Severity::Error => MessageType::ERROR,
+      Severity::Warning => MessageType::WARNING,
+      Severity::Info => MessageType::INFO,
+    }
+  }
+}
+
+#[cfg(feature = "native")]
+impl Severity {
+  /// `notify-send` urgency level
+  fn urgency(&self) -> &'static str {
+    match self {
+      Severity::Error => "critical",
+      Severity::Warning => "normal",
+      Severity::Info => "low",
+    }
+  }
+}
+
+/// Choices offered by a `window/showMessageRequest`
+pub fn actions(titles: &[&str]) -> Vec<MessageActionItem> {
+  titles.iter().map(|title| MessageActionItem { title: title.to_string(), properties: HashMap::new() }).collect()
+}
+
+/// Also show a message as a desktop notification, for editors which hide
+/// `window/showMessage`. Failures are only logged, `notify-send` is often missing.
+#[cfg(feature = "native")]
+pub fn desktop_notification(severity: Severity, message: &str) {
+  let spawned = tokio::process::Command::new("notify-send")
+    .args(["--urgency", severity.urgency(), "cyberls", message])
+    .stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null())
+    .spawn();
+
+  match spawned {
+    // Waited for in the background, so the finished process doesn't linger
+    Ok(mut child) => { tokio::spawn(async move { let _ = child.wait().await; }); }
+    Err(err) => debug!("Desktop notification failed: {}", err),
+  }
+}
+
+/// Builds without processes only have `window/showMessage`
+#[cfg(not(feature = "native"))]
+pub fn desktop_notification(_severity: Severity, _message: &str) {}
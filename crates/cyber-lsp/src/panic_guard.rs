@@ -0,0 +1,93 @@
+use std::any::Any;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use lsp_types::MessageType;
+use tower::Service;
+use tower_lsp::jsonrpc::{Error, Request, Response};
+use tower_lsp::{Client, ExitedError};
+use tracing::error;
+
+// --| Panic Guard -------------------
+// --|--------------------------------
+/// Catches panics of the wrapped service's handlers, so a panicking request is
+/// answered with an internal error instead of taking the server down. The first
+/// panic is also shown to the user, later ones only go to the log.
+pub struct CatchPanic<S> {
+  inner: S,
+  client: Client,
+  reported: Arc<AtomicBool>,
+}
+
+impl<S> CatchPanic<S> {
+  pub fn new(inner: S, client: Client) -> Self {
+    Self { inner, client, reported: Arc::new(AtomicBool::new(false)) }
+  }
+}
+
+impl<S> Service<Request> for CatchPanic<S>
+where
+  S: Service<Request, Response = Option<Response>, Error = ExitedError>,
+  S::Future: Send + 'static,
+{
+  type Response = Option<Response>;
+  type Error = ExitedError;
+  type Future = Pin<Box<dyn Future<Output = Result<Option<Response>, ExitedError>> + Send>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.inner.poll_ready(cx)
+  }
+
+  fn call(&mut self, request: Request) -> Self::Future {
+    let method = request.method().to_string();
+    let id = request.id().cloned();
+    let params = request.params().map(|params| params.to_string()).unwrap_or_default();
+
+    // Handlers keep running on the server's task, spawning them would reorder notifications
+    let handler = CatchUnwind(Box::pin(self.inner.call(request)));
+    let client = self.client.clone();
+    let reported = self.reported.clone();
+
+    Box::pin(async move {
+      let payload = match handler.await {
+        Ok(response) => return response,
+        Err(payload) => payload,
+      };
+
+      error!("Handler for {} panicked: {}\nParams: {}", method, panic_message(payload), params);
+      if !reported.swap(true, Ordering::SeqCst) {
+        let message = format!("cyberls: {} failed unexpectedly and was skipped, see the server log for details", method);
+        client.show_message(MessageType::ERROR, message).await;
+      }
+
+      // Notifications have no id and expect no answer
+      Ok(id.map(|id| Response::from_error(id, Error::internal_error())))
+    })
+  }
+}
+
+/// Polls a future, turning a panic while polling into its payload
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+  type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let future = self.0.as_mut();
+    match catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+      Ok(poll) => poll.map(Ok),
+      Err(payload) => Poll::Ready(Err(payload)),
+    }
+  }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+  match payload.downcast::<String>() {
+    Ok(message) => *message,
+    Err(payload) => payload.downcast_ref::<&str>().map_or_else(|| "unknown panic".to_string(), |message| message.to_string()),
+  }
+}
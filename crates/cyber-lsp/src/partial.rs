@@ -0,0 +1,129 @@
+use lsp_types::notification::Notification;
+use lsp_types::ProgressToken;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// --| Partial Results ---------------
+// --|--------------------------------
+/// Results per `$/progress` notification when a request streams them
+pub const PAGE_SIZE: usize = 100;
+
+/// Indexed files searched between two pages of workspace symbols
+pub const SEARCH_SHARD_SIZE: usize = 64;
+
+/// `$/progress` carrying a page of results for a request's `partialResultToken`.
+/// `lsp_types::ProgressParamsValue` only covers work done reports.
+pub enum PartialResult {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PartialResultParams {
+  pub token: ProgressToken,
+  pub value: Value,
+}
+
+impl Notification for PartialResult {
+  type Params = PartialResultParams;
+  const METHOD: &'static str = "$/progress";
+}
+
+/// Results of a workspace scan. With a partial result token they are handed out a page
+/// at a time while the scan goes on and the response stays empty, without one they are
+/// all kept for the response.
+pub struct Pager<T> {
+  token: Option<ProgressToken>,
+  buffer: Vec<T>,
+  sent: usize,
+}
+
+impl<T: Serialize> Pager<T> {
+  pub fn new(token: Option<ProgressToken>) -> Self {
+    Self { token, buffer: vec![], sent: 0 }
+  }
+
+  pub fn extend(&mut self, items: impl IntoIterator<Item = T>) {
+    self.buffer.extend(items);
+  }
+
+  /// A full page to send, only when streaming
+  pub fn page(&mut self) -> Option<PartialResultParams> {
+    if self.buffer.len() < PAGE_SIZE { return None; }
+    self.take()
+  }
+
+  /// Whatever was found so far, only when streaming, for scans whose first results matter most
+  pub fn flush(&mut self) -> Option<PartialResultParams> {
+    if self.buffer.is_empty() { return None; }
+    self.take()
+  }
+
+  /// The last page to send and the response. A scan that found nothing answers `None`.
+  pub fn finish(mut self) -> (Option<PartialResultParams>, Option<Vec<T>>) {
+    let last = if self.buffer.is_empty() { None } else { self.take() };
+    let found = self.sent > 0 || !self.buffer.is_empty();
+    (last, found.then_some(self.buffer))
+  }
+
+  fn take(&mut self) -> Option<PartialResultParams> {
+    let token = self.token.clone()?;
+    let page = std::mem::take(&mut self.buffer);
+    self.sent += page.len();
+    Some(PartialResultParams { token, value: serde_json::to_value(page).ok()? })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn token() -> Option<ProgressToken> {
+    Some(ProgressToken::String("scan".to_string()))
+  }
+
+  fn page_len(page: &PartialResultParams) -> usize {
+    page.value.as_array().map_or(0, Vec::len)
+  }
+
+  #[test]
+  fn without_a_token_everything_is_kept_for_the_response() {
+    let mut pager = Pager::new(None);
+    pager.extend(0..PAGE_SIZE * 2);
+    assert!(pager.page().is_none());
+    assert!(pager.flush().is_none());
+
+    let (last, response) = pager.finish();
+    assert!(last.is_none());
+    assert_eq!(response.map(|found| found.len()), Some(PAGE_SIZE * 2));
+  }
+
+  #[test]
+  fn with_a_token_full_pages_are_sent_and_the_response_is_empty() {
+    let mut pager = Pager::new(token());
+    pager.extend(0..PAGE_SIZE - 1);
+    assert!(pager.page().is_none());
+
+    pager.extend([PAGE_SIZE]);
+    assert_eq!(pager.page().map(|page| page_len(&page)), Some(PAGE_SIZE));
+
+    pager.extend([1, 2]);
+    let (last, response) = pager.finish();
+    assert_eq!(last.map(|page| page_len(&page)), Some(2));
+    assert_eq!(response, Some(vec![]));
+  }
+
+  #[test]
+  fn flush_sends_whatever_was_found() {
+    let mut pager = Pager::new(token());
+    assert!(pager.flush().is_none());
+
+    pager.extend([1, 2, 3]);
+    assert_eq!(pager.flush().map(|page| page_len(&page)), Some(3));
+    assert_eq!(pager.finish().1, Some(vec![]));
+  }
+
+  #[test]
+  fn a_scan_finding_nothing_answers_none() {
+    let (last, response) = Pager::<u32>::new(token()).finish();
+    assert!(last.is_none());
+    assert!(response.is_none());
+  }
+}
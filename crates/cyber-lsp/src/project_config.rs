@@ -0,0 +1,174 @@
+use std::path::Path;
+
+use lsp_types::Url;
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::datatypes::Config;
+
+/// Settings committed at a workspace root, in the order they are looked for. They use the
+/// keys of the `cyberls` client settings, ie. `indent_width`, `ignore` or `module_paths`.
+pub const PROJECT_FILES: [&str; 2] = ["cyberls.toml", ".cyberls.json"];
+
+/// Whether a changed file is a project configuration file
+pub fn is_project_file(uri: &Url) -> bool {
+  let name = uri.path().rsplit('/').next().unwrap_or_default();
+  PROJECT_FILES.contains(&name)
+}
+
+// --| Loading -----------------------
+// --|--------------------------------
+/// Settings of the first project file found at the root. A file that fails to parse is
+/// reported in the log and skipped.
+pub fn load(root: &Path) -> Option<Value> {
+  for name in PROJECT_FILES {
+    let path = root.join(name);
+    let text = match std::fs::read_to_string(&path) { Ok(text) => text, Err(_) => continue };
+
+    let parsed = if name.ends_with(".toml") {
+      toml::from_str::<Value>(&text).map_err(|err| err.to_string())
+    } else {
+      serde_json::from_str::<Value>(&text).map_err(|err| err.to_string())
+    };
+
+    match parsed {
+      Ok(settings) if settings.is_object() => {
+        debug!("Loaded project configuration {:?}", path);
+        return Some(settings);
+      }
+      Ok(_) => warn!("Ignoring {:?}: expected a table of settings", path),
+      Err(err) => warn!("Ignoring {:?}: {}", path, err),
+    }
+  }
+  None
+}
+
+// --| Applying ----------------------
+// --|--------------------------------
+/// Settings deciding which processes are started, whether the workspace is trusted and what
+/// is downloaded. A cloned repository must not choose them, they only come from the user.
+pub const RESTRICTED_KEYS: [&str; 7] = [
+  "trusted", "cyber_path", "compiler_mode", "cache_dir", "remote_imports", "run_output", "desktop_notifications",
+];
+
+/// The settings with those of the project file laid over them. Tables are merged key by
+/// key, so a project setting `limits.max_parse_size` keeps the other limits. Restricted
+/// keys are left out.
+pub fn apply(config: Config, project: &Value) -> Config {
+  let mut settings = match serde_json::to_value(&config) { Ok(settings) => settings, Err(_) => return config };
+  merge(&mut settings, &without_restricted(project));
+
+  match serde_json::from_value(settings) {
+    Ok(merged) => merged,
+    Err(err) => {
+      warn!("Ignoring project configuration: {}", err);
+      config
+    }
+  }
+}
+
+/// The project settings without the restricted keys, warning about each one dropped
+fn without_restricted(project: &Value) -> Value {
+  let mut project = project.clone();
+  if let Some(settings) = project.as_object_mut() {
+    for key in RESTRICTED_KEYS {
+      if settings.remove(key).is_some() { warn!("Ignoring `{}` of the project configuration, it can only be set by the user", key); }
+    }
+  }
+  project
+}
+
+/// Lay `overlay` over `target`, merging tables key by key
+pub fn merge(target: &mut Value, overlay: &Value) {
+  match (target, overlay) {
+    (Value::Object(target), Value::Object(overlay)) => {
+      for (key, value) in overlay {
+        merge(target.entry(key.clone()).or_insert(Value::Null), value);
+      }
+    }
+    (target, overlay) => *target = overlay.clone(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn scratch_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("cyberls-project-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn merge_keeps_sibling_keys_of_tables() {
+    let mut target = json!({ "limits": { "max_parse_size": 1, "max_diagnostics_size": 2 }, "ignore": ["a"] });
+    merge(&mut target, &json!({ "limits": { "max_parse_size": 10 }, "ignore": ["b"] }));
+    assert_eq!(target, json!({ "limits": { "max_parse_size": 10, "max_diagnostics_size": 2 }, "ignore": ["b"] }));
+  }
+
+  #[test]
+  fn apply_overrides_settings() {
+    let config = apply(Config::default(), &json!({ "indent_width": 4, "module_paths": ["lib"] }));
+    assert_eq!(config.indent_width, Some(4));
+    assert_eq!(config.module_paths, vec!["lib".to_string()]);
+  }
+
+  #[test]
+  fn apply_ignores_restricted_keys() {
+    let project = json!({
+      "trusted": true, "cyber_path": "./payload", "compiler_mode": "server",
+      "cache_dir": "/tmp/x", "remote_imports": true, "run_output": "notification",
+      "desktop_notifications": true, "indent_width": 8,
+    });
+    let config = apply(Config::default(), &project);
+    let default = Config::default();
+
+    assert_eq!(config.trusted, default.trusted);
+    assert_eq!(config.cyber_path, default.cyber_path);
+    assert_eq!(config.compiler_mode, default.compiler_mode);
+    assert_eq!(config.cache_dir, default.cache_dir);
+    assert_eq!(config.remote_imports, default.remote_imports);
+    assert_eq!(config.run_output, default.run_output);
+    assert_eq!(config.desktop_notifications, default.desktop_notifications);
+    assert_eq!(config.indent_width, Some(8));
+  }
+
+  #[test]
+  fn apply_keeps_the_settings_when_a_value_does_not_fit() {
+    let config = apply(Config::default(), &json!({ "indent_width": "wide" }));
+    assert_eq!(config.indent_width, None);
+  }
+
+  #[test]
+  fn load_reads_toml_before_json() {
+    let dir = scratch_dir("toml");
+    std::fs::write(dir.join("cyberls.toml"), "indent_width = 2\n[limits]\nmax_parse_size = 5\n").unwrap();
+    std::fs::write(dir.join(".cyberls.json"), r#"{ "indent_width": 6 }"#).unwrap();
+
+    let settings = load(&dir).unwrap();
+    assert_eq!(settings, json!({ "indent_width": 2, "limits": { "max_parse_size": 5 } }));
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn load_falls_back_to_json_and_skips_invalid_files() {
+    let dir = scratch_dir("json");
+    std::fs::write(dir.join("cyberls.toml"), "indent_width = ").unwrap();
+    std::fs::write(dir.join(".cyberls.json"), r#"{ "indent_width": 6 }"#).unwrap();
+    assert_eq!(load(&dir), Some(json!({ "indent_width": 6 })));
+
+    std::fs::write(dir.join(".cyberls.json"), "[1, 2]").unwrap();
+    assert_eq!(load(&dir), None);
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn project_files_are_recognized_by_name() {
+    assert!(is_project_file(&Url::parse("file:///work/cyberls.toml").unwrap()));
+    assert!(is_project_file(&Url::parse("file:///work/.cyberls.json").unwrap()));
+    assert!(!is_project_file(&Url::parse("file:///work/main.cy").unwrap()));
+  }
+}
@@ -0,0 +1,160 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use cyber_tree_sitter::Tree;
+use dashmap::DashMap;
+use lsp_types::Url;
+
+use crate::diagnostics::ErrorInfo;
+use crate::index::WorkspaceIndex;
+use crate::symbols::{collect_symbols, DocumentSymbols};
+
+/// Identity of a document text, a hash of its content so undoing an edit finds the earlier results
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Revision(u64);
+
+impl Revision {
+  pub fn of(text: &str) -> Self {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    Revision(hasher.finish())
+  }
+}
+
+// --| Query Cache -------------------
+// --|--------------------------------
+/// Results derived from a document, kept until its text changes. Symbols only depend on
+/// the text. Semantic diagnostics also depend on the files the document imports, through
+/// their index revision, and on the environment: settings, lint rules, the spell checker,
+/// dead code hints and which files exist. Rechecking every open document then only
+/// recomputes those whose inputs changed.
+#[derive(Default)]
+pub struct QueryCache {
+  environment: AtomicU64,
+  symbols: DashMap<Url, (Revision, Arc<DocumentSymbols>)>,
+  semantic: DashMap<Url, SemanticMemo>,
+  /// Revision and environment of the last full check, compiler included
+  checked: DashMap<Url, (Revision, u64)>,
+  /// Tree and text of the last revision without syntax errors
+  valid: DashMap<Url, (Tree, Arc<str>)>,
+}
+
+struct SemanticMemo {
+  revision: Revision,
+  environment: u64,
+  /// Imported files with their index revision when the diagnostics were computed
+  dependencies: Vec<(Url, u64)>,
+  errors: ErrorInfo,
+}
+
+impl QueryCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Symbols of a document, collected again only when its text changed
+  pub fn symbols(&self, uri: &Url, text: &str, tree: &Tree) -> Arc<DocumentSymbols> {
+    let revision = Revision::of(text);
+    if let Some(memo) = self.symbols.get(uri).filter(|memo| memo.0 == revision) {
+      return memo.1.clone();
+    }
+
+    let symbols = Arc::new(collect_symbols(tree.root_node(), text));
+    self.symbols.insert(uri.clone(), (revision, symbols.clone()));
+    if !tree.root_node().has_error() { self.valid.insert(uri.clone(), (tree.clone(), text.into())); }
+    symbols
+  }
+
+  /// The last revision of a document that parsed without errors, to look names and types
+  /// up in while the region being edited is broken
+  pub fn last_valid(&self, uri: &Url) -> Option<(Tree, Arc<str>)> {
+    self.valid.get(uri).map(|valid| valid.clone())
+  }
+
+  /// Current environment, to be read before computing results that depend on it
+  pub fn environment(&self) -> u64 {
+    self.environment.load(Ordering::SeqCst)
+  }
+
+  /// Outdate every result depending on the environment
+  pub fn invalidate(&self) {
+    self.environment.fetch_add(1, Ordering::SeqCst);
+  }
+
+  /// Semantic diagnostics of a revision, if neither the environment nor an imported file changed since
+  pub fn semantic(&self, uri: &Url, revision: Revision, index: &WorkspaceIndex) -> Option<ErrorInfo> {
+    let memo = self.semantic.get(uri)?;
+    let valid = memo.revision == revision
+      && memo.environment == self.environment()
+      && memo.dependencies.iter().all(|(dependency, seen)| index.revision(dependency) == *seen);
+    valid.then(|| memo.errors.clone())
+  }
+
+  /// Keep semantic diagnostics computed from a revision within `environment`
+  pub fn store_semantic(&self, uri: &Url, revision: Revision, environment: u64, dependencies: Vec<Url>, errors: &ErrorInfo, index: &WorkspaceIndex) {
+    let dependencies = dependencies.into_iter().map(|dependency| {
+      let seen = index.revision(&dependency);
+      (dependency, seen)
+    }).collect();
+
+    self.semantic.insert(uri.clone(), SemanticMemo { revision, environment, dependencies, errors: errors.clone() });
+  }
+
+  /// Whether the last full check ran on this revision within the current environment
+  pub fn is_checked(&self, uri: &Url, revision: Revision) -> bool {
+    self.checked.get(uri).map_or(false, |checked| *checked == (revision, self.environment()))
+  }
+
+  pub fn set_checked(&self, uri: &Url, revision: Revision, environment: u64) {
+    self.checked.insert(uri.clone(), (revision, environment));
+  }
+
+  /// Drop the results of a closed document
+  pub fn forget(&self, uri: &Url) {
+    self.symbols.remove(uri);
+    self.semantic.remove(uri);
+    self.checked.remove(uri);
+    self.valid.remove(uri);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn url(name: &str) -> Url {
+    Url::parse(&format!("file:///work/{}", name)).unwrap()
+  }
+
+  #[test]
+  fn semantic_memo_outdates_with_any_dependency() {
+    let (cache, index) = (QueryCache::new(), WorkspaceIndex::new());
+    let (a, b, c) = (url("a.cy"), url("b.cy"), url("c.cy"));
+    index.update(b.clone(), DocumentSymbols::default());
+    index.update(c.clone(), DocumentSymbols::default());
+
+    // `a` imports `b`, which imports `c`
+    let revision = Revision::of("import b './b.cy'");
+    cache.store_semantic(&a, revision, cache.environment(), vec![b.clone(), c.clone()], &ErrorInfo::new(), &index);
+    assert!(cache.semantic(&a, revision, &index).is_some());
+    assert!(cache.semantic(&a, Revision::of("import b './b.cy'\n"), &index).is_none());
+
+    // Editing `c`, eg. to import `a` back, outdates the diagnostics of `a`
+    index.update(c, DocumentSymbols::default());
+    assert!(cache.semantic(&a, revision, &index).is_none());
+  }
+
+  #[test]
+  fn semantic_memo_outdates_with_the_environment() {
+    let (cache, index) = (QueryCache::new(), WorkspaceIndex::new());
+    let a = url("a.cy");
+    let revision = Revision::of("print 1");
+
+    cache.store_semantic(&a, revision, cache.environment(), vec![], &ErrorInfo::new(), &index);
+    assert!(cache.semantic(&a, revision, &index).is_some());
+    cache.invalidate();
+    assert!(cache.semantic(&a, revision, &index).is_none());
+  }
+}
@@ -0,0 +1,173 @@
+use lsp_types::{Position, Range, SymbolKind, TextEdit};
+use ropey::Rope;
+
+use crate::core_types::lsp_range;
+use crate::symbols::{DocumentSymbols, Symbol};
+use crate::utils::treehelper::range_contains;
+
+// --| Rename Targets ----------------
+// --|--------------------------------
+#[derive(Clone, Debug)]
+pub enum RenameTarget {
+  /// A parameter of `function`. Named arguments of calls to it are renamed as well
+  Parameter { function: Symbol, name: String },
+  /// A variable declared inside a function
+  Local { scope: Range, name: String },
+  /// A top level declaration, renamed in every file referring to it
+  Global { name: String },
+}
+
+impl RenameTarget {
+  /// Work out what the identifier `name` at `position` refers to
+  pub fn at(symbols: &DocumentSymbols, name: &str, position: Position) -> Self {
+    let enclosing = symbols.symbols.iter()
+      .filter(|symbol| symbol.is_function() && range_contains(&symbol.range, position))
+      .min_by_key(|symbol| (symbol.range.end.line - symbol.range.start.line, symbol.range.end.character));
+
+    if let Some(function) = enclosing {
+      if function.params.iter().any(|param| param.name == name) {
+        return RenameTarget::Parameter { function: function.clone(), name: name.to_string() };
+      }
+
+      let local = symbols.symbols.iter()
+        .any(|symbol| symbol.name == name && symbol.container.as_deref() == Some(function.name.as_str()));
+      if local {
+        return RenameTarget::Local { scope: function.range, name: name.to_string() };
+      }
+    }
+
+    // Named arguments refer to the parameter of the called function
+    for call in &symbols.calls {
+      if !call.named_args.iter().any(|arg| arg.name == name && range_contains(&arg.range, position)) { continue; }
+      if let Some(function) = symbols.function(&call.callee) {
+        return RenameTarget::Parameter { function: function.clone(), name: name.to_string() };
+      }
+    }
+
+    RenameTarget::Global { name: name.to_string() }
+  }
+
+  /// Whether other workspace files can contain occurrences
+  pub fn is_workspace_wide(&self) -> bool {
+    match self {
+      RenameTarget::Parameter { function, .. } => function.container.is_none(),
+      RenameTarget::Local { .. } => false,
+      RenameTarget::Global { .. } => true,
+    }
+  }
+}
+
+// --| Rename Edits ------------------
+// --|--------------------------------
+/// Edits for one document, in client positions of its `rope`. `origin` is the document the
+/// rename was requested in, declarations are only renamed there.
+pub fn rename_edits(symbols: &DocumentSymbols, rope: &Rope, target: &RenameTarget, new_name: &str, origin: bool) -> Vec<TextEdit> {
+  occurrences(symbols, target, origin).into_iter().map(|range| TextEdit::new(lsp_range(rope, range), new_name.to_string())).collect()
+}
+
+/// Byte based ranges of the target in one document, declarations included, in document order
+pub fn occurrences(symbols: &DocumentSymbols, target: &RenameTarget, origin: bool) -> Vec<Range> {
+  let mut ranges: Vec<Range> = vec![];
+
+  match target {
+    RenameTarget::Parameter { function, name } => {
+      if origin {
+        ranges.extend(function.params.iter().filter(|param| &param.name == name).map(|param| param.range));
+        ranges.extend(symbols.references.iter()
+          .filter(|reference| &reference.name == name && range_contains(&function.range, reference.range.start))
+          .map(|reference| reference.range));
+      }
+
+      // Other files call the function through their import alias, method calls are a different function
+      let calls = symbols.calls.iter().filter(|call| call.callee == function.name && match &call.receiver {
+        None => true,
+        Some(receiver) => !origin && symbols.symbols.iter().any(|symbol| &symbol.name == receiver && symbol.kind == SymbolKind::MODULE),
+      });
+      for call in calls {
+        ranges.extend(call.named_args.iter().filter(|arg| &arg.name == name).map(|arg| arg.range));
+      }
+    }
+
+    RenameTarget::Local { scope, name } => {
+      ranges.extend(symbols.symbols.iter()
+        .filter(|symbol| &symbol.name == name && range_contains(scope, symbol.selection_range.start))
+        .map(|symbol| symbol.selection_range));
+      ranges.extend(symbols.references.iter()
+        .filter(|reference| &reference.name == name && range_contains(scope, reference.range.start))
+        .map(|reference| reference.range));
+    }
+
+    RenameTarget::Global { name } => {
+      if origin {
+        ranges.extend(symbols.symbols.iter()
+          .filter(|symbol| &symbol.name == name && symbol.container.is_none())
+          .map(|symbol| symbol.selection_range));
+      }
+
+      ranges.extend(symbols.references.iter()
+        .filter(|reference| &reference.name == name && !shadowed(symbols, name, reference.range.start))
+        .map(|reference| reference.range));
+    }
+  }
+
+  ranges.sort_by_key(|range| (range.start.line, range.start.character));
+  ranges.dedup();
+  ranges
+}
+
+/// Whether a range is the name of a declaration or parameter rather than a use
+pub fn is_declaration(symbols: &DocumentSymbols, range: Range) -> bool {
+  symbols.symbols.iter().any(|symbol| symbol.selection_range == range || symbol.params.iter().any(|param| param.range == range))
+}
+
+/// A function parameter or local declaration hides the top level name at this position
+fn shadowed(symbols: &DocumentSymbols, name: &str, position: Position) -> bool {
+  symbols.symbols.iter()
+    .filter(|function| function.is_function() && range_contains(&function.range, position))
+    .any(|function| {
+      function.params.iter().any(|param| param.name == name)
+        || symbols.symbols.iter().any(|symbol| symbol.name == name && symbol.container.as_deref() == Some(function.name.as_str()))
+    })
+}
+
+/// Identifiers as accepted by the cyber parser
+pub fn is_valid_identifier(name: &str) -> bool {
+  let mut chars = name.chars();
+  match chars.next() {
+    Some(first) if first.is_alphabetic() || first == '_' => chars.all(|ch| ch.is_alphanumeric() || ch == '_'),
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::symbols::collect_symbols;
+
+  #[test]
+  fn rename_edits_count_utf16_columns() {
+    let source = "var x = 1\nprint 'é', x\n";
+    let tree = cyber_tree_sitter::init_parser().parse(source, None).unwrap();
+    let symbols = collect_symbols(tree.root_node(), source);
+    let rope = Rope::from_str(source);
+
+    // `x` is at byte 12 of its line but UTF-16 column 11
+    let target = RenameTarget::at(&symbols, "x", Position::new(1, 12));
+    let edits = rename_edits(&symbols, &rope, &target, "count", true);
+    let ranges: Vec<Range> = edits.iter().map(|edit| edit.range).collect();
+    assert_eq!(ranges, vec![
+      Range::new(Position::new(0, 4), Position::new(0, 5)),
+      Range::new(Position::new(1, 11), Position::new(1, 12)),
+    ]);
+    assert!(edits.iter().all(|edit| edit.new_text == "count"));
+  }
+
+  #[test]
+  fn identifiers_follow_the_parser() {
+    assert!(is_valid_identifier("größe_1"));
+    assert!(is_valid_identifier("_x"));
+    assert!(!is_valid_identifier("1x"));
+    assert!(!is_valid_identifier("a-b"));
+    assert!(!is_valid_identifier(""));
+  }
+}
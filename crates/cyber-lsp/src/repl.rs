@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use lsp_types::Url;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::timeout;
+use tracing::{debug, info};
+
+use crate::utils::paths;
+use crate::utils::platform::{Child, ChildStdin, Command};
+
+pub const START_METHOD: &str = "cyberls/repl/start";
+pub const EVAL_METHOD: &str = "cyberls/repl/eval";
+pub const INTERRUPT_METHOD: &str = "cyberls/repl/interrupt";
+
+/// How long to wait for the first line of output of an evaluation
+const EVAL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Output is considered complete once the REPL stays quiet this long
+const QUIET_PERIOD: Duration = Duration::from_millis(150);
+
+/// Limits of the output collected for one evaluation
+const MAX_OUTPUT_LINES: usize = 10_000;
+const MAX_OUTPUT_TIME: Duration = Duration::from_secs(30);
+
+// --| Request Types -----------------
+// --|--------------------------------
+/// The document the request comes from, used to pick the workspace session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplParams {
+  pub uri: Url,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplEvalParams {
+  pub uri: Url,
+  pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplStartResult {
+  pub pid: u32,
+  /// False when an already running session was reused
+  pub started: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplEvalResult {
+  pub output: Vec<String>,
+  /// True if the REPL produced no output before the timeout
+  pub timed_out: bool,
+}
+
+// --| REPL Sessions -----------------
+// --|--------------------------------
+#[derive(Debug)]
+struct ReplSession {
+  child: Child,
+  stdin: ChildStdin,
+  output: mpsc::UnboundedReceiver<String>,
+}
+
+impl ReplSession {
+  fn spawn(program: &str, workspace: &Url) -> std::io::Result<Self> {
+    let mut command = Command::new(program);
+    command
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .kill_on_drop(true);
+
+    if let Some(dir) = paths::uri_to_path(workspace) { command.current_dir(dir); }
+
+    let mut child = command.spawn()?;
+    let stdin = child.stdin.take().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "REPL stdin unavailable"))?;
+
+    let (sender, output) = mpsc::unbounded_channel();
+    if let Some(stdout) = child.stdout.take() { tokio::spawn(forward_lines(stdout, sender.clone())); }
+    if let Some(stderr) = child.stderr.take() { tokio::spawn(forward_lines(stderr, sender)); }
+
+    Ok(Self { child, stdin, output })
+  }
+
+  fn is_alive(&mut self) -> bool {
+    matches!(self.child.try_wait(), Ok(None))
+  }
+}
+
+/// A running session. The pid is kept outside the session lock, so a running evaluation,
+/// which holds the lock, can still be interrupted.
+#[derive(Debug)]
+struct ReplHandle {
+  pid: Option<u32>,
+  session: Mutex<ReplSession>,
+}
+
+/// One interactive cyber process per workspace folder
+#[derive(Debug, Default)]
+pub struct ReplManager {
+  sessions: Mutex<HashMap<Url, Arc<ReplHandle>>>,
+}
+
+impl ReplManager {
+  pub fn new() -> Self {
+    Self { sessions: Mutex::new(HashMap::new()) }
+  }
+
+  /// The session of the workspace. The map is only locked while looking it up.
+  async fn handle(&self, workspace: &Url) -> Option<Arc<ReplHandle>> {
+    self.sessions.lock().await.get(workspace).cloned()
+  }
+
+  /// Start the workspace session unless one is already running
+  pub async fn start(&self, workspace: &Url, program: &str) -> std::io::Result<ReplStartResult> {
+    let mut sessions = self.sessions.lock().await;
+
+    if let Some(handle) = sessions.get(workspace) {
+      // A session busy evaluating is alive
+      let alive = handle.session.try_lock().map_or(true, |mut session| session.is_alive());
+      if alive { return Ok(ReplStartResult { pid: handle.pid.unwrap_or_default(), started: false }); }
+    }
+
+    let session = ReplSession::spawn(program, workspace)?;
+    let pid = session.child.id();
+    info!("Started REPL for {} (pid {})", workspace, pid.unwrap_or_default());
+
+    sessions.insert(workspace.clone(), Arc::new(ReplHandle { pid, session: Mutex::new(session) }));
+    Ok(ReplStartResult { pid: pid.unwrap_or_default(), started: true })
+  }
+
+  /// Send code to the workspace session and collect what it prints
+  pub async fn eval(&self, workspace: &Url, code: &str) -> std::io::Result<ReplEvalResult> {
+    let not_running = || std::io::Error::new(std::io::ErrorKind::NotConnected, "No REPL session running");
+    let handle = self.handle(workspace).await.ok_or_else(not_running)?;
+    let mut session = handle.session.lock().await;
+    if !session.is_alive() { return Err(not_running()); }
+
+    // Drop output printed since the last evaluation
+    while session.output.try_recv().is_ok() {}
+
+    session.stdin.write_all(code.as_bytes()).await?;
+    if !code.ends_with('\n') { session.stdin.write_all(b"\n").await?; }
+    session.stdin.flush().await?;
+
+    let mut output = vec![];
+    match timeout(EVAL_TIMEOUT, session.output.recv()).await {
+      Ok(Some(line)) => output.push(line),
+      Ok(None) => return Ok(ReplEvalResult { output, timed_out: false }),
+      Err(_) => return Ok(ReplEvalResult { output, timed_out: true }),
+    }
+
+    // Code printing without pause is cut off, what it prints later is dropped by the next evaluation
+    let deadline = Instant::now() + MAX_OUTPUT_TIME;
+    while output.len() < MAX_OUTPUT_LINES && Instant::now() < deadline {
+      match timeout(QUIET_PERIOD, session.output.recv()).await {
+        Ok(Some(line)) => output.push(line),
+        _ => break,
+      }
+    }
+
+    Ok(ReplEvalResult { output, timed_out: false })
+  }
+
+  /// Interrupt the running evaluation. Where signals are unavailable the session is ended instead.
+  pub async fn interrupt(&self, workspace: &Url) -> bool {
+    let handle = match self.handle(workspace).await {
+      Some(handle) => handle,
+      None => return false,
+    };
+    let pid = match handle.pid {
+      Some(pid) => pid,
+      None => return false,
+    };
+
+    debug!("Interrupting REPL for {}", workspace);
+    if cfg!(unix) { return signal("kill", &["-INT", &pid.to_string()]).await; }
+
+    self.sessions.lock().await.remove(workspace);
+    signal("taskkill", &["/PID", &pid.to_string(), "/T", "/F"]).await
+  }
+
+  /// End every session, returns how many were running
+  pub async fn stop_all(&self) -> usize {
+    let handles: Vec<(Url, Arc<ReplHandle>)> = self.sessions.lock().await.drain().collect();
+    let mut stopped = 0;
+
+    for (workspace, handle) in handles {
+      let mut session = handle.session.lock().await;
+      if !session.is_alive() { continue; }
+      debug!("Stopping REPL for {}", workspace);
+      if session.child.kill().await.is_ok() { stopped += 1; }
+    }
+    stopped
+  }
+
+  /// Number of REPL processes still running
+  pub async fn session_count(&self) -> usize {
+    let handles: Vec<Arc<ReplHandle>> = self.sessions.lock().await.values().cloned().collect();
+    let mut running = 0;
+    for handle in handles {
+      if handle.session.try_lock().map_or(true, |mut session| session.is_alive()) { running += 1; }
+    }
+    running
+  }
+}
+
+/// Signal the REPL process through the platform's tool, without blocking the runtime
+async fn signal(program: &str, args: &[&str]) -> bool {
+  Command::new(program).args(args).stdout(Stdio::null()).stderr(Stdio::null()).status().await
+    .map_or(false, |status| status.success())
+}
+
+async fn forward_lines<R: AsyncRead + Unpin>(reader: R, sender: mpsc::UnboundedSender<String>) {
+  let mut lines = BufReader::new(reader).lines();
+  while let Ok(Some(line)) = lines.next_line().await {
+    if sender.send(line).is_err() { break; }
+  }
+}
@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use lsp_types::notification::Notification;
+use dashmap::DashMap;
+use lsp_types::{Diagnostic, Url};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{oneshot, Mutex};
+
+#[cfg(feature = "run-commands")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "run-commands")]
+use std::sync::Weak;
+#[cfg(feature = "run-commands")]
+use std::process::Stdio;
+#[cfg(feature = "run-commands")]
+use lsp_types::MessageType;
+#[cfg(feature = "run-commands")]
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+#[cfg(feature = "run-commands")]
+use tower_lsp::Client;
+#[cfg(feature = "run-commands")]
+use tracing::{debug, info};
+
+#[cfg(feature = "run-commands")]
+use crate::trace;
+#[cfg(feature = "run-commands")]
+use crate::Backend;
+#[cfg(feature = "run-commands")]
+use crate::utils::platform::Command;
+
+/// Executable used when `cyber_path` is not configured
+pub const DEFAULT_CYBER: &str = "cyber";
+
+/// Shown when a workspace is not trusted to start processes
+pub const UNTRUSTED_MESSAGE: &str = "This workspace is not trusted, so cyberls does not run the Cyber executable. \
+  Set `cyberls.trusted` to allow it.";
+
+// --| Run Output Notification -------
+// --|--------------------------------
+pub enum RunOutput {}
+
+impl Notification for RunOutput {
+  type Params = RunOutputParams;
+  const METHOD: &'static str = "cyberls/runOutput";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunOutputParams {
+  pub uri: Url,
+  /// `stdout`, `stderr` or `exit`
+  pub stream: String,
+  pub text: String,
+  pub exit_code: Option<i32>,
+}
+
+/// How script output is sent back to the client
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunOutputMode {
+  /// As `window/logMessage`, shown in the editor's output panel
+  #[default]
+  Log,
+  /// As `cyberls/runOutput` notifications for editor extensions
+  Notification,
+}
+
+// --| Script Runner -----------------
+// --|--------------------------------
+/// Runs one script at a time through the cyber executable
+#[derive(Default, Debug)]
+pub struct Runner {
+  stop: Mutex<Option<oneshot::Sender<()>>>,
+  /// The published diagnostics of the server, panics of a run are added to them
+  #[cfg_attr(not(feature = "run-commands"), allow(dead_code))]
+  diagnostics: Arc<DashMap<Url, Vec<Diagnostic>>>,
+  /// Documents holding diagnostics from the last run's trace
+  traced: Arc<std::sync::Mutex<Vec<Url>>>,
+}
+
+impl Runner {
+  pub fn new(diagnostics: Arc<DashMap<Url, Vec<Diagnostic>>>) -> Self {
+    Self { stop: Mutex::new(None), diagnostics, traced: Default::default() }
+  }
+
+  /// Start running a script, stopping any previous run. Output is streamed
+  /// to the client until the process exits, then a panic trace is published
+  /// as diagnostics. `line_offset` is the first line of a selection run.
+  /// Returns the process id.
+  #[cfg(feature = "run-commands")]
+  pub async fn start(
+    &self, client: Client, backend: Weak<Backend>, program: &str, script: &Path, uri: Url, line_offset: u32, mode: RunOutputMode,
+  ) -> std::io::Result<u32> {
+    self.stop().await;
+
+    let mut command = Command::new(program);
+    command.arg(script)
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .kill_on_drop(true);
+
+    if let Some(dir) = script.parent() { command.current_dir(dir); }
+
+    let mut child = command.spawn()?;
+    let pid = child.id().unwrap_or_default();
+    info!("Running {:?} with {} (pid {})", script, program, pid);
+
+    let (stop_tx, stop_rx) = oneshot::channel();
+    *self.stop.lock().await = Some(stop_tx);
+
+    let stdout = child.stdout.take().map(|stdout| tokio::spawn(stream_lines(client.clone(), uri.clone(), "stdout", stdout, mode)));
+    let stderr = child.stderr.take().map(|stderr| tokio::spawn(stream_lines(client.clone(), uri.clone(), "stderr", stderr, mode)));
+
+    let script = script.to_path_buf();
+    let diagnostics = self.diagnostics.clone();
+    let traced = self.traced.clone();
+
+    tokio::spawn(async move {
+      let status = tokio::select! {
+        status = child.wait() => status.ok(),
+        _ = stop_rx => {
+          debug!("Stopping run (pid {})", pid);
+          let _ = child.kill().await;
+          None
+        }
+      };
+
+      if let Some(task) = stdout { let _ = task.await; }
+      let stderr_lines = match stderr {
+        Some(task) => task.await.unwrap_or_default(),
+        None => vec![],
+      };
+
+      let exit_code = status.and_then(|status| status.code());
+      if exit_code.map_or(false, |code| code != 0) {
+        if let Some(found) = trace::parse_trace(&stderr_lines, &script) {
+          publish_trace(&client, &backend, &diagnostics, &traced, &found, &script, &uri, line_offset).await;
+        }
+      }
+
+      let text = match exit_code {
+        Some(code) => format!("[cyber] process exited with code {}", code),
+        None => "[cyber] process was stopped".to_string(),
+      };
+      emit(&client, &uri, "exit", text, exit_code, mode).await;
+    });
+
+    Ok(pid)
+  }
+
+  /// Whether a started script has not exited yet
+  pub async fn is_running(&self) -> bool {
+    self.stop.lock().await.as_ref().map_or(false, |stop| !stop.is_closed())
+  }
+
+  /// Documents with diagnostics from the last run, forgetting them
+  pub fn take_traced(&self) -> Vec<Url> {
+    std::mem::take(&mut *self.traced.lock().unwrap_or_else(|err| err.into_inner()))
+  }
+
+  /// Kill the running script, returns false if nothing was running
+  pub async fn stop(&self) -> bool {
+    match self.stop.lock().await.take() {
+      Some(stop) => stop.send(()).is_ok(),
+      None => false,
+    }
+  }
+}
+
+/// Add the diagnostics of a trace to those already published for each document. They are
+/// published by the backend, with the severity overrides, when it is shared.
+#[cfg(feature = "run-commands")]
+#[allow(clippy::too_many_arguments)]
+async fn publish_trace(
+  client: &Client, backend: &Weak<Backend>, diagnostics: &DashMap<Url, Vec<Diagnostic>>, traced: &std::sync::Mutex<Vec<Url>>,
+  found: &trace::Trace, script: &Path, uri: &Url, line_offset: u32,
+) {
+  info!("Run of {} panicked: {}", uri, found.message);
+  let mut updated: Vec<Url> = vec![];
+
+  for (target, diagnostic) in trace::trace_diagnostics(found, script, uri, line_offset) {
+    diagnostics.entry(target.clone()).or_default().push(diagnostic);
+    if !updated.contains(&target) { updated.push(target); }
+  }
+
+  {
+    let mut traced = traced.lock().unwrap_or_else(|err| err.into_inner());
+    for target in updated.iter().filter(|target| !traced.contains(target)).cloned().collect::<Vec<Url>>() {
+      traced.push(target);
+    }
+  }
+
+  for target in updated {
+    match backend.upgrade() {
+      Some(backend) => {
+        let current = backend.pipeline.current(&target);
+        backend.publish_diagnostics(target, current).await;
+      }
+      None => {
+        let items = diagnostics.get(&target).map(|entry| entry.value().clone()).unwrap_or_default();
+        client.publish_diagnostics(target, items, None).await;
+      }
+    }
+  }
+}
+
+/// Write a code selection to a temporary script so it can be run on its own.
+/// Relative imports resolve against the temp directory, not the document.
+#[cfg(feature = "run-commands")]
+pub fn write_selection(text: &str) -> std::io::Result<PathBuf> {
+  let path = std::env::temp_dir().join(format!("cyberls-selection-{}.cy", std::process::id()));
+  std::fs::write(&path, text)?;
+  Ok(path)
+}
+
+#[cfg(feature = "run-commands")]
+async fn stream_lines<R: AsyncRead + Unpin>(client: Client, uri: Url, stream: &'static str, reader: R, mode: RunOutputMode) -> Vec<String> {
+  let mut lines = BufReader::new(reader).lines();
+  let mut collected = vec![];
+
+  while let Ok(Some(line)) = lines.next_line().await {
+    emit(&client, &uri, stream, line.clone(), None, mode).await;
+    collected.push(line);
+  }
+
+  collected
+}
+
+#[cfg(feature = "run-commands")]
+async fn emit(client: &Client, uri: &Url, stream: &str, text: String, exit_code: Option<i32>, mode: RunOutputMode) {
+  match mode {
+    RunOutputMode::Log => {
+      let typ = if stream == "stderr" { MessageType::ERROR } else { MessageType::INFO };
+      client.log_message(typ, text).await;
+    }
+    RunOutputMode::Notification => {
+      client.send_notification::<RunOutput>(RunOutputParams {
+        uri: uri.clone(),
+        stream: stream.to_string(),
+        text,
+        exit_code,
+      }).await;
+    }
+  }
+}
@@ -1,8 +1,21 @@
+use std::collections::{HashMap, HashSet};
+
 use cyber_tree_sitter as tree_sitter;
 use tower_lsp::lsp_types::{
-    SemanticToken, SemanticTokenType, SemanticTokens, SemanticTokensLegend,
+    Range, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens, SemanticTokensLegend, SymbolKind,
 };
 use cyber_highlight;
+use once_cell::sync::Lazy;
+
+use crate::core_types::{self, DocPoint};
+use crate::deprecations;
+use crate::injections::{self, InjectedKind, InjectedToken};
+use crate::symbols::{node_range, DocumentSymbols};
+use crate::undefined::BUILTIN_GLOBALS;
+use crate::utils::treehelper::range_contains;
+
+/// The highlights query is compiled once and shared by every request
+pub static HIGHLIGHT_QUERY: Lazy<cyber_highlight::Query> = Lazy::new(cyber_highlight::init_query);
 
 pub fn legend() -> SemanticTokensLegend {
     SemanticTokensLegend {
@@ -16,13 +29,205 @@ pub fn legend() -> SemanticTokensLegend {
             SemanticTokenType::STRING,         // 6
             SemanticTokenType::NUMBER,         // 7
             SemanticTokenType::MACRO,          // 8
+            SemanticTokenType::FUNCTION,       // 9
+            SemanticTokenType::PARAMETER,      // 10
+            SemanticTokenType::PROPERTY,       // 11
+            SemanticTokenType::VARIABLE,       // 12
+            SemanticTokenType::OPERATOR,       // 13
         ],
         token_modifiers: vec![
-            // TODO
+            SemanticTokenModifier::DECLARATION,     // 0
+            SemanticTokenModifier::READONLY,        // 1
+            SemanticTokenModifier::DEFAULT_LIBRARY, // 2
+            SemanticTokenModifier::DEPRECATED,      // 3
+            // 4.. one per nesting depth of brackets, see `BRACKET_DEPTHS`
+            SemanticTokenModifier::new("bracketDepth0"),
+            SemanticTokenModifier::new("bracketDepth1"),
+            SemanticTokenModifier::new("bracketDepth2"),
+            SemanticTokenModifier::new("bracketDepth3"),
+            SemanticTokenModifier::new("bracketDepth4"),
+            SemanticTokenModifier::new("bracketDepth5"),
         ],
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+enum TokenModifier {
+    // Keep these in sync with indices of `token_modifiers` above!
+    Declaration = 0,
+    Readonly = 1,
+    DefaultLibrary = 2,
+    Deprecated = 3,
+}
+
+impl TokenModifier {
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// Rainbow bracket colors cycle after this many nesting levels
+const BRACKET_DEPTHS: usize = 6;
+/// Index of `bracketDepth0` in `token_modifiers`
+const BRACKET_DEPTH_MODIFIER: usize = 4;
+
+fn depth_bit(depth: usize) -> u32 {
+    1 << (BRACKET_DEPTH_MODIFIER + depth % BRACKET_DEPTHS)
+}
+
+/// Nesting depth of brackets and of the delimiters between them, in document order.
+/// Closing brackets take the depth of the bracket they close.
+#[derive(Default)]
+struct BracketDepth {
+    open: usize,
+}
+
+impl BracketDepth {
+    fn bit(&mut self, text: &str, token_type: &cyber_highlight::TokenType) -> u32 {
+        match token_type {
+            cyber_highlight::TokenType::Bracket => match text {
+                "(" | "[" | "{" => {
+                    self.open += 1;
+                    depth_bit(self.open - 1)
+                }
+                ")" | "]" | "}" => {
+                    self.open = self.open.saturating_sub(1);
+                    depth_bit(self.open)
+                }
+                _ => 0,
+            },
+            cyber_highlight::TokenType::Delimiter if self.open > 0 => depth_bit(self.open - 1),
+            _ => 0,
+        }
+    }
+}
+
+/// What the scope analysis knows about the names of a document
+struct Scope<'a> {
+    declarations: HashSet<Range>,
+    readonly: HashSet<&'a str>,
+    local: HashSet<&'a str>,
+    deprecated: HashSet<&'a str>,
+    functions: HashSet<&'a str>,
+    /// Parameter names with the range of the function declaring them
+    parameters: Vec<(Range, &'a str)>,
+}
+
+impl<'a> Scope<'a> {
+    fn new(symbols: &'a DocumentSymbols, root: tree_sitter::Node, source: &str) -> Self {
+        let declarations = symbols.symbols.iter()
+            .map(|symbol| symbol.selection_range)
+            .chain(symbols.symbols.iter().flat_map(|symbol| symbol.params.iter().map(|param| param.range)))
+            .chain(symbols.bindings.iter().map(|binding| binding.range))
+            .collect();
+
+        let local = symbols.symbols.iter().map(|symbol| symbol.name.as_str())
+            .chain(symbols.symbols.iter().flat_map(|symbol| symbol.params.iter().map(|param| param.name.as_str())))
+            .chain(symbols.bindings.iter().map(|binding| binding.name.as_str()))
+            .collect();
+
+        // Functions, objects and imports are never rebound; variables are if assigned more than once
+        let mut assignments: HashMap<String, usize> = HashMap::new();
+        count_assignments(root, source, &mut assignments);
+        let readonly = symbols.symbols.iter()
+            .filter(|symbol| match symbol.kind {
+                SymbolKind::VARIABLE => assignments.get(&symbol.name).map_or(true, |count| *count <= 1),
+                _ => true,
+            })
+            .map(|symbol| symbol.name.as_str())
+            .collect();
+
+        let lines: Vec<&str> = source.lines().collect();
+        let deprecated = symbols.symbols.iter()
+            .filter(|symbol| is_deprecated(&lines, symbol.range.start.line as usize))
+            .map(|symbol| symbol.name.as_str())
+            .collect();
+
+        let functions = symbols.symbols.iter()
+            .filter(|symbol| symbol.is_function())
+            .map(|symbol| symbol.name.as_str())
+            .collect();
+
+        let parameters = symbols.symbols.iter()
+            .flat_map(|symbol| symbol.params.iter().map(move |param| (symbol.range, param.name.as_str())))
+            .collect();
+
+        Self { declarations, readonly, local, deprecated, functions, parameters }
+    }
+
+    /// Token type of an identifier, from its position in the tree and the declarations in scope
+    fn classify(&self, node: tree_sitter::Node, source: &str) -> TokenType {
+        let name = node.utf8_text(source.as_bytes()).unwrap_or_default();
+        let parent = node.parent();
+        let position = node_range(node).start;
+
+        let called = parent.map_or(false, |parent| {
+            parent.kind() == "call_expression"
+                && parent.child_by_field_name("function").or_else(|| parent.named_child(0)) == Some(node)
+        });
+        let member = parent.map_or(false, |parent| {
+            ["property", "field", "key"].iter().any(|field| parent.child_by_field_name(field) == Some(node))
+        });
+        let method = member && parent.and_then(|parent| parent.parent()).map_or(false, |grand| {
+            grand.kind() == "call_expression" && grand.child_by_field_name("function") == parent
+        });
+
+        if method {
+            TokenType::Function
+        } else if member {
+            TokenType::Property
+        } else if self.parameters.iter().any(|(range, param)| *param == name && range_contains(range, position)) {
+            TokenType::Parameter
+        } else if called || self.functions.contains(name) || (BUILTIN_GLOBALS.contains(&name) && !self.local.contains(name)) {
+            TokenType::Function
+        } else {
+            TokenType::Variable
+        }
+    }
+
+    fn bitset(&self, node: tree_sitter::Node, source: &str, builtin: bool) -> u32 {
+        let name = match node.utf8_text(source.as_bytes()) {
+            Ok(name) => name,
+            Err(_) => return 0,
+        };
+
+        let mut bits = 0;
+        if self.declarations.contains(&node_range(node)) { bits |= TokenModifier::Declaration.bit(); }
+        if self.readonly.contains(name) { bits |= TokenModifier::Readonly.bit(); }
+        if self.deprecated.contains(name) || (deprecations::lookup(name).is_some() && !self.local.contains(name)) {
+            bits |= TokenModifier::Deprecated.bit();
+        }
+        if builtin || (BUILTIN_GLOBALS.contains(&name) && !self.local.contains(name)) {
+            bits |= TokenModifier::DefaultLibrary.bit();
+        }
+        bits
+    }
+}
+
+fn count_assignments(node: tree_sitter::Node, source: &str, out: &mut HashMap<String, usize>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if matches!(child.kind(), "assignment_statement" | "variable_def" | "variable_declaration") {
+            let target = child.child_by_field_name("left")
+                .or_else(|| child.child_by_field_name("name"))
+                .or_else(|| child.named_child(0));
+
+            if let Some(name) = target.filter(|target| target.kind() == "identifier").and_then(|target| target.utf8_text(source.as_bytes()).ok()) {
+                *out.entry(name.to_string()).or_default() += 1;
+            }
+        }
+        count_assignments(child, source, out);
+    }
+}
+
+/// A `-- deprecated` comment directly above the declaration
+fn is_deprecated(lines: &[&str], line: usize) -> bool {
+    lines[..line.min(lines.len())].iter().rev()
+        .map(|line| line.trim())
+        .take_while(|line| line.starts_with("--"))
+        .any(|comment| comment.to_lowercase().contains("deprecated"))
+}
+
 #[derive(Debug, Clone, Copy)]
 enum TokenType {
     // Keep these in sync with indices of `token_types` above!
@@ -35,32 +240,51 @@ enum TokenType {
     String = 6,
     Number = 7,
     Special = 8,
+    Function = 9,
+    Parameter = 10,
+    Property = 11,
+    Variable = 12,
+    Operator = 13,
 }
 
-impl std::convert::TryFrom<cyber_highlight::TokenType> for TokenType {
-    type Error = cyber_highlight::TokenType;
-    fn try_from(tt: cyber_highlight::TokenType) -> Result<Self, Self::Error> {
+impl From<cyber_highlight::TokenType> for TokenType {
+    fn from(tt: cyber_highlight::TokenType) -> Self {
         match tt {
-            cyber_highlight::TokenType::Comment => Ok(Self::Comment),
-            cyber_highlight::TokenType::Bracket => Err(tt),
-            cyber_highlight::TokenType::Delimiter => Err(tt),
-            cyber_highlight::TokenType::KeywordImport => Ok(Self::Keyword),
-            cyber_highlight::TokenType::Keyword => Ok(Self::Keyword),
-            cyber_highlight::TokenType::KeywordReturn => Ok(Self::Keyword),
-            cyber_highlight::TokenType::KeywordConditional => Ok(Self::Keyword),
-            cyber_highlight::TokenType::Symbol => Ok(Self::Special),
-            cyber_highlight::TokenType::Namespace => Ok(Self::Namespace),
-            cyber_highlight::TokenType::Type => Ok(Self::Type),
-            cyber_highlight::TokenType::TypeVariable => Ok(Self::TypeVariable),
-            cyber_highlight::TokenType::EnumMember => Ok(Self::Constructor),
-            cyber_highlight::TokenType::TopLevelName => Err(tt),
-            cyber_highlight::TokenType::Variable => Err(tt),
-            cyber_highlight::TokenType::Operator => Err(tt),
-            cyber_highlight::TokenType::String => Ok(Self::String),
-            cyber_highlight::TokenType::Int => Ok(Self::Number),
-            cyber_highlight::TokenType::Float => Ok(Self::Number),
-            cyber_highlight::TokenType::Boolean => Err(tt),
-            cyber_highlight::TokenType::Builtin => Err(tt),
+            cyber_highlight::TokenType::Comment => Self::Comment,
+            cyber_highlight::TokenType::Bracket => Self::Operator,
+            cyber_highlight::TokenType::Delimiter => Self::Operator,
+            cyber_highlight::TokenType::KeywordImport => Self::Keyword,
+            cyber_highlight::TokenType::Keyword => Self::Keyword,
+            cyber_highlight::TokenType::KeywordReturn => Self::Keyword,
+            cyber_highlight::TokenType::KeywordConditional => Self::Keyword,
+            cyber_highlight::TokenType::Symbol => Self::Special,
+            cyber_highlight::TokenType::Namespace => Self::Namespace,
+            cyber_highlight::TokenType::Type => Self::Type,
+            cyber_highlight::TokenType::TypeVariable => Self::TypeVariable,
+            cyber_highlight::TokenType::EnumMember => Self::Constructor,
+            cyber_highlight::TokenType::TopLevelName => Self::Variable,
+            cyber_highlight::TokenType::Variable => Self::Variable,
+            cyber_highlight::TokenType::Operator => Self::Operator,
+            cyber_highlight::TokenType::String => Self::String,
+            cyber_highlight::TokenType::Int => Self::Number,
+            cyber_highlight::TokenType::Float => Self::Number,
+            cyber_highlight::TokenType::Boolean => Self::Keyword,
+            cyber_highlight::TokenType::Builtin => Self::Function,
+        }
+    }
+}
+
+impl From<InjectedKind> for TokenType {
+    fn from(kind: InjectedKind) -> Self {
+        match kind {
+            InjectedKind::Keyword => Self::Keyword,
+            InjectedKind::String => Self::String,
+            InjectedKind::Number => Self::Number,
+            InjectedKind::Property => Self::Property,
+            InjectedKind::Variable => Self::Variable,
+            InjectedKind::Function => Self::Function,
+            InjectedKind::Parameter => Self::Parameter,
+            InjectedKind::Operator => Self::Operator,
         }
     }
 }
@@ -69,12 +293,38 @@ pub fn get_tokens(
     tree: &tree_sitter::Tree,
     source: &str,
     query: &cyber_highlight::Query,
+    symbols: &DocumentSymbols,
+    multiline: bool,
+    injections: bool,
+    rainbow: bool,
 ) -> SemanticTokens {
-    let tokens = cyber_highlight::get_tokens(source, tree, query);
-    let mut tokens_builder = TokensBuilder::new();
+    let tokens = cyber_highlight::get_scoped_tokens(source, tree, query);
+    let scope = Scope::new(symbols, tree.root_node(), source);
+    let injected = if injections { injections::find_injections(tree.root_node(), source) } else { Default::default() };
+    let mut tokens_builder = TokensBuilder::new(source, multiline);
+    let mut brackets = BracketDepth::default();
     for token in tokens {
-        if let Ok(token_type) = token.token_type.try_into() {
-            tokens_builder.push_node(token.node, token_type)
+        let mut bitset = scope.bitset(token.node, source, token.modifiers.builtin);
+        if token.modifiers.definition { bitset |= TokenModifier::Declaration.bit(); }
+        if rainbow {
+            bitset |= brackets.bit(token.node.utf8_text(source.as_bytes()).unwrap_or_default(), &token.token_type);
+        }
+        let token_type = match TokenType::from(token.token_type) {
+            TokenType::Variable => scope.classify(token.node, source),
+            token_type => token_type,
+        };
+
+        let injection = match token_type {
+            TokenType::String => injected.get(&token.node.id()).copied(),
+            _ => None,
+        };
+
+        match injection {
+            Some(injection) => {
+                let injected = injections::injected_tokens(token.node, source, injection);
+                tokens_builder.push_injected(token.node, &injected, bitset)
+            }
+            None => tokens_builder.push_node(token.node, token_type, bitset),
         }
     }
     SemanticTokens {
@@ -83,38 +333,116 @@ pub fn get_tokens(
     }
 }
 
-struct TokensBuilder(Vec<Node>);
+/// Positions and lengths are in UTF-16 code units, as the protocol expects
+struct TokensBuilder<'a> {
+    nodes: Vec<Node>,
+    source: &'a str,
+    lines: Vec<&'a str>,
+    /// Byte offset at which each line starts
+    line_starts: Vec<usize>,
+    /// The client accepts tokens spanning several lines
+    multiline: bool,
+}
 
 #[derive(Debug)]
 struct Node {
     start_line: usize,
     start_col: usize,
     token_type: TokenType,
+    modifiers: u32,
     length: usize,
 }
 
-impl TokensBuilder {
-    fn new() -> Self {
-        Self(Vec::new())
+impl<'a> TokensBuilder<'a> {
+    fn new(source: &'a str, multiline: bool) -> Self {
+        let lines: Vec<&str> = source.split('\n').collect();
+        let line_starts = lines.iter()
+            .scan(0, |offset, line| {
+                let start = *offset;
+                *offset += line.len() + 1;
+                Some(start)
+            })
+            .collect();
+
+        Self { nodes: Vec::new(), source, lines, line_starts, multiline }
     }
 
-    fn push_node(&mut self, node: tree_sitter::Node, token_type: TokenType) {
-        let tree_sitter::Point { row, column } = node.start_position();
-        let length = node.byte_range().len();
-        self.0.push(Node {
-            start_line: row,
-            start_col: column,
+    fn push_node(&mut self, node: tree_sitter::Node, token_type: TokenType, modifiers: u32) {
+        self.push_bytes(node.start_byte(), node.end_byte(), token_type, modifiers)
+    }
+
+    /// Push a string literal holding an injected snippet. The snippet's tokens
+    /// replace the parts of the string they cover, the rest stays a string.
+    fn push_injected(&mut self, node: tree_sitter::Node, injected: &[InjectedToken], modifiers: u32) {
+        let mut offset = node.start_byte();
+        for token in injected.iter().filter(|token| token.bytes.start >= node.start_byte() && token.bytes.end <= node.end_byte()) {
+            if token.bytes.start < offset { continue; }
+            self.push_bytes(offset, token.bytes.start, TokenType::String, modifiers);
+            self.push_bytes(token.bytes.start, token.bytes.end, TokenType::from(token.kind), 0);
+            offset = token.bytes.end;
+        }
+        self.push_bytes(offset, node.end_byte(), TokenType::String, modifiers);
+    }
+
+    /// Push a byte range, split into one token per line unless the client supports multiline tokens
+    fn push_bytes(&mut self, start_byte: usize, end_byte: usize, token_type: TokenType, modifiers: u32) {
+        if end_byte <= start_byte { return; }
+        let start = self.point(start_byte);
+        let end = self.point(end_byte);
+
+        if start.line == end.line || self.multiline {
+            let start_col = self.utf16_column(start.line, start.column);
+            let length = match self.source.get(start_byte..end_byte) {
+                Some(text) => text.encode_utf16().count(),
+                None => end_byte - start_byte,
+            };
+            self.push(start.line, start_col, length, token_type, modifiers);
+            return;
+        }
+
+        for row in start.line..=end.line {
+            let line = self.line(row);
+            let from = if row == start.line { start.column } else { 0 };
+            let to = if row == end.line { end.column } else { line.len() };
+
+            let start_col = self.utf16_column(row, from);
+            let end_col = self.utf16_column(row, to);
+            if end_col > start_col {
+                self.push(row, start_col, end_col - start_col, token_type, modifiers);
+            }
+        }
+    }
+
+    fn push(&mut self, line: usize, col: usize, length: usize, token_type: TokenType, modifiers: u32) {
+        self.nodes.push(Node {
+            start_line: line,
+            start_col: col,
             length,
             token_type,
+            modifiers,
         })
     }
 
+    fn point(&self, byte: usize) -> DocPoint {
+        let line = self.line_starts.partition_point(|start| *start <= byte).saturating_sub(1);
+        DocPoint::new(line, byte - self.line_starts.get(line).copied().unwrap_or_default())
+    }
+
+    /// A line without its line break
+    fn line(&self, row: usize) -> &'a str {
+        self.lines.get(row).map_or("", |line| line.strip_suffix('\r').unwrap_or(line))
+    }
+
+    fn utf16_column(&self, line: usize, byte_column: usize) -> usize {
+        core_types::utf16_column(self.line(line), byte_column)
+    }
+
     fn into_tokens(mut self) -> Option<Vec<SemanticToken>> {
         let mut tokens = Vec::new();
-        self.0.sort_by_key(|node| (node.start_line, node.start_col));
+        self.nodes.sort_by_key(|node| (node.start_line, node.start_col));
         let mut current_line = 0;
         let mut current_col = 0;
-        for node in self.0 {
+        for node in self.nodes {
             let delta_line: u32 = (node.start_line - current_line).try_into().ok()?;
             let delta_start: u32 = if delta_line > 0 {
                 node.start_col.try_into().ok()?
@@ -125,7 +453,7 @@ impl TokensBuilder {
                 delta_line,
                 delta_start,
                 token_type: node.token_type as u32,
-                token_modifiers_bitset: 0,
+                token_modifiers_bitset: node.modifiers,
                 length: node.length as u32,
             });
             current_line = node.start_line;
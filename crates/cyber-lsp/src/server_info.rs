@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+use crate::status::StatusParams;
+
+#[cfg(feature = "compiler-diagnostics")]
+use std::process::Stdio;
+#[cfg(feature = "compiler-diagnostics")]
+use tracing::debug;
+#[cfg(feature = "compiler-diagnostics")]
+use crate::utils::platform::Command;
+
+pub const SERVER_INFO_METHOD: &str = "cyberls/serverInfo";
+
+// --| Response Types ----------------
+// --|--------------------------------
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerInfoResult {
+  pub name: String,
+  pub version: String,
+  pub cyber: CyberInfo,
+  /// Language definition files embedded from `lang_docs`
+  pub doc_packs: usize,
+  pub indexed_files: usize,
+  pub open_documents: usize,
+  pub workspace_folders: usize,
+  /// Cargo features of this build, ie. `run-commands`
+  pub features: Vec<String>,
+  pub subsystems: Subsystems,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CyberInfo {
+  /// Executable that was queried
+  pub path: String,
+  /// First line of `cyber version`, `None` when the executable could not be run
+  pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Subsystems {
+  pub status: StatusParams,
+  pub spellcheck: bool,
+  pub remote_imports: bool,
+  pub pull_diagnostics: bool,
+  pub script_running: bool,
+  pub repl_sessions: usize,
+}
+
+/// Optional cargo features this server was built with
+pub fn features() -> Vec<String> {
+  let features = [
+    ("native", cfg!(feature = "native")),
+    ("compiler-diagnostics", cfg!(feature = "compiler-diagnostics")),
+    ("run-commands", cfg!(feature = "run-commands")),
+    ("index-cache", cfg!(feature = "index-cache")),
+  ];
+  features.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| name.to_string()).collect()
+}
+
+/// Name and version of this server build
+pub fn server_version() -> (String, String) {
+  (env!("CARGO_PKG_NAME").to_string(), env!("CARGO_PKG_VERSION").to_string())
+}
+
+/// Whether the cyber executable can be started at all
+#[cfg(feature = "compiler-diagnostics")]
+pub async fn is_available(program: &str) -> bool {
+  Command::new(program).arg("version")
+    .stdin(Stdio::null())
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .status().await
+    .is_ok()
+}
+
+/// Ask the cyber executable for its version
+#[cfg(feature = "compiler-diagnostics")]
+pub async fn cyber_version(program: &str) -> Option<String> {
+  let output = match Command::new(program).arg("version").output().await {
+    Ok(output) => output,
+    Err(err) => {
+      debug!("Failed to query {} version: {}", program, err);
+      return None;
+    }
+  };
+
+  let text = if output.stdout.is_empty() { output.stderr } else { output.stdout };
+  String::from_utf8_lossy(&text).lines().map(str::trim).find(|line| !line.is_empty()).map(str::to_string)
+}
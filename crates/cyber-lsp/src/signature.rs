@@ -0,0 +1,108 @@
+use lsp_types::{
+  CompletionItem, CompletionItemKind, CompletionTextEdit, DiagnosticSeverity, InsertTextFormat, ParameterInformation,
+  ParameterLabel, Position, SignatureHelp, SignatureInformation,
+};
+
+use crate::diagnostics::ErrorInfo;
+use crate::symbols::{CallSite, Symbol};
+use crate::core_types::DocPoint;
+
+// --| Signature Help ----------------
+// --|--------------------------------
+/// Index of the argument the cursor is in
+pub fn active_parameter(call: &CallSite, position: Position) -> u32 {
+  call.arg_ranges.iter()
+    .filter(|range| (range.end.line, range.end.character) < (position.line, position.character))
+    .count() as u32
+}
+
+pub fn signature_help(function: &Symbol, call: &CallSite, position: Position) -> SignatureHelp {
+  let active = active_parameter(call, position);
+
+  let parameters = function.params.iter().map(|param| ParameterInformation {
+    label: ParameterLabel::Simple(param.name.clone()),
+    documentation: None,
+  }).collect();
+
+  SignatureHelp {
+    signatures: vec![SignatureInformation {
+      label: function.signature(),
+      documentation: function.documentation(),
+      parameters: Some(parameters),
+      active_parameter: Some(active),
+    }],
+    active_signature: Some(0),
+    active_parameter: Some(active),
+  }
+}
+
+// --| Argument Count Diagnostics ----
+// --|--------------------------------
+/// Warn about calls passing a different number of arguments than the resolved function declares
+pub fn check_call_arity(calls: &[CallSite], resolve: impl Fn(&CallSite) -> Option<Symbol>) -> Option<ErrorInfo> {
+  let mut error_info = ErrorInfo::new();
+
+  for call in calls {
+    if call.args_range.is_none() { continue; }
+    let function = match resolve(call) { Some(function) => function, None => continue };
+
+    let expected = function.params.len();
+    let found = call.arg_ranges.len();
+    if expected == found { continue; }
+
+    error_info.add(
+      DocPoint::from_byte_position(call.range.start),
+      DocPoint::from_byte_position(call.range.end),
+      format!("`{}` expects {} argument{}, found {}", function.name, expected, if expected == 1 { "" } else { "s" }, found),
+      Some(DiagnosticSeverity::WARNING),
+    );
+  }
+
+  if error_info.entries.is_empty() { None } else { Some(error_info) }
+}
+
+// --| Call Snippets -----------------
+// --|--------------------------------
+/// Snippet calling `callee` with a placeholder per parameter, ie. `add(${1:a}, ${2:b})`
+pub fn call_snippet(callee: &str, function: &Symbol) -> String {
+  let placeholders: Vec<String> = function.params.iter().enumerate()
+    .map(|(index, param)| format!("${{{}:{}}}", index + 1, param.name))
+    .collect();
+
+  format!("{}({})$0", callee, placeholders.join(", "))
+}
+
+/// Add a snippet alternative after every function completion with a known parameter list
+pub fn with_call_snippets(items: Vec<CompletionItem>, resolve: impl Fn(&str) -> Option<Symbol>) -> Vec<CompletionItem> {
+  let mut result = Vec::with_capacity(items.len());
+
+  for item in items {
+    let snippet = (item.kind == Some(CompletionItemKind::FUNCTION))
+      .then(|| resolve(item.label.trim_end_matches("()")))
+      .flatten()
+      .map(|function| snippet_item(&item, &function));
+
+    result.push(item);
+    result.extend(snippet);
+  }
+
+  result
+}
+
+fn snippet_item(item: &CompletionItem, function: &Symbol) -> CompletionItem {
+  let params: Vec<&str> = function.params.iter().map(|param| param.name.as_str()).collect();
+  let mut snippet = item.clone();
+
+  snippet.label = format!("{}({})", function.name, params.join(", "));
+  snippet.insert_text_format = Some(InsertTextFormat::SNIPPET);
+  snippet.filter_text = Some(function.name.clone());
+  snippet.sort_text = Some(format!("{}~", function.name));
+
+  match &mut snippet.text_edit {
+    Some(CompletionTextEdit::Edit(edit)) => edit.new_text = call_snippet(&edit.new_text, function),
+    Some(CompletionTextEdit::InsertAndReplace(edit)) => edit.new_text = call_snippet(&edit.new_text, function),
+    None => snippet.insert_text = Some(call_snippet(&function.name, function)),
+  }
+
+  snippet
+}
@@ -0,0 +1,161 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use lsp_types::CodeAction;
+use serde_json::{json, Value};
+use tower::Service;
+use tower_lsp::jsonrpc::{Request, Response};
+use tower_lsp::ExitedError;
+
+/// Key in a code action's `data` marking its edit as a snippet
+const SNIPPET_DATA: &str = "cyberlsSnippetEdit";
+
+/// `InsertTextFormat.Snippet`
+const SNIPPET_FORMAT: u32 = 2;
+
+// --| Snippet Text Edits ------------
+// --|--------------------------------
+/// Mark the edit of a code action as snippet text, for clients announcing
+/// `experimental.snippetTextEdit`. `lsp_types` has no snippet text edit, so the marked
+/// actions are rewritten by `SnippetEdits` on their way out.
+pub fn mark(mut action: CodeAction) -> CodeAction {
+  action.data = Some(json!({ SNIPPET_DATA: true }));
+  action
+}
+
+/// Escape text inserted into a snippet so it isn't read as tab stops or placeholders
+pub fn escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for ch in text.chars() {
+    if matches!(ch, '$' | '}' | '\\') { escaped.push('\\'); }
+    escaped.push(ch);
+  }
+  escaped
+}
+
+/// Turn the `changes` of marked actions into `documentChanges` whose edits are
+/// `SnippetTextEdit`s, ie. carry `insertTextFormat: 2`
+fn rewrite_actions(actions: &mut Value) {
+  let actions = match actions.as_array_mut() {
+    Some(actions) => actions,
+    None => return,
+  };
+
+  for action in actions.iter_mut() {
+    let marked = action.get("data").and_then(|data| data.get(SNIPPET_DATA)).map_or(false, |marked| marked == &Value::Bool(true));
+    if !marked { continue; }
+
+    let object = match action.as_object_mut() {
+      Some(object) => object,
+      None => continue,
+    };
+    object.remove("data");
+
+    let changes = match object.get_mut("edit").and_then(|edit| edit.as_object_mut()).and_then(|edit| edit.remove("changes")) {
+      Some(Value::Object(changes)) => changes,
+      _ => continue,
+    };
+
+    let document_changes: Vec<Value> = changes.into_iter().map(|(uri, edits)| {
+      let mut edits = match edits {
+        Value::Array(edits) => edits,
+        _ => vec![],
+      };
+      for edit in edits.iter_mut().filter_map(Value::as_object_mut) {
+        edit.insert("insertTextFormat".to_string(), json!(SNIPPET_FORMAT));
+      }
+      json!({ "textDocument": { "uri": uri, "version": null }, "edits": edits })
+    }).collect();
+
+    if let Some(edit) = object.get_mut("edit").and_then(|edit| edit.as_object_mut()) {
+      edit.insert("documentChanges".to_string(), Value::Array(document_changes));
+    }
+  }
+}
+
+/// Rewrites the snippet edits marked by `mark` in `textDocument/codeAction` answers
+pub struct SnippetEdits<S> {
+  inner: S,
+}
+
+impl<S> SnippetEdits<S> {
+  pub fn new(inner: S) -> Self {
+    Self { inner }
+  }
+}
+
+impl<S> Service<Request> for SnippetEdits<S>
+where
+  S: Service<Request, Response = Option<Response>, Error = ExitedError>,
+  S::Future: Send + 'static,
+{
+  type Response = Option<Response>;
+  type Error = ExitedError;
+  type Future = Pin<Box<dyn Future<Output = Result<Option<Response>, ExitedError>> + Send>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.inner.poll_ready(cx)
+  }
+
+  fn call(&mut self, request: Request) -> Self::Future {
+    if request.method() != "textDocument/codeAction" { return Box::pin(self.inner.call(request)); }
+
+    let handler = self.inner.call(request);
+    Box::pin(async move {
+      let response = match handler.await? {
+        Some(response) => response,
+        None => return Ok(None),
+      };
+
+      let (id, result) = response.into_parts();
+      let result = result.map(|mut actions| {
+        rewrite_actions(&mut actions);
+        actions
+      });
+      Ok(Some(Response::from_parts(id, result)))
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashMap;
+  use lsp_types::{Position, Range, TextEdit, Url, WorkspaceEdit};
+
+  fn action(text: &str) -> CodeAction {
+    let uri = Url::parse("file:///work/main.cy").unwrap();
+    let edit = TextEdit::new(Range::new(Position::new(0, 0), Position::new(0, 4)), text.to_string());
+    CodeAction {
+      title: "Surround".to_string(),
+      edit: Some(WorkspaceEdit { changes: Some(HashMap::from([(uri, vec![edit])])), ..Default::default() }),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn escapes_snippet_syntax() {
+    assert_eq!(escape(r"print '${a}' \ b"), r"print '\${a\}' \\ b");
+    assert_eq!(escape("plain"), "plain");
+  }
+
+  #[test]
+  fn marked_actions_become_snippet_text_edits() {
+    let mut actions = serde_json::to_value(vec![mark(action("if ${1:condition}:")), action("$0")]).unwrap();
+    rewrite_actions(&mut actions);
+
+    let marked = &actions[0];
+    assert!(marked.get("data").is_none());
+    assert!(marked["edit"].get("changes").is_none());
+    let change = &marked["edit"]["documentChanges"][0];
+    assert_eq!(change["textDocument"]["uri"], "file:///work/main.cy");
+    assert_eq!(change["edits"][0]["newText"], "if ${1:condition}:");
+    assert_eq!(change["edits"][0]["insertTextFormat"], 2);
+
+    // Unmarked actions stay plain text edits
+    let plain = &actions[1];
+    assert_eq!(plain["edit"]["changes"]["file:///work/main.cy"][0]["newText"], "$0");
+    assert!(plain["edit"].get("documentChanges").is_none());
+  }
+}
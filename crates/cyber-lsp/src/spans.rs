@@ -0,0 +1,99 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use serde_json::Value;
+use tower::Service;
+use tower_lsp::jsonrpc::{Request, Response};
+use tower_lsp::ExitedError;
+use tracing::{debug, field, info, info_span, warn, Instrument};
+
+// --| Span Level --------------------
+// --|--------------------------------
+/// How much of the traffic gets a span in the log, `--log-spans`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SpanLevel {
+  Off,
+  /// A span per request, with its outcome and duration
+  #[default]
+  Requests,
+  /// Notifications too, and the params of every message at debug level
+  All,
+}
+
+impl SpanLevel {
+  pub fn parse(name: &str) -> Option<Self> {
+    match name {
+      "off" => Some(Self::Off),
+      "requests" => Some(Self::Requests),
+      "all" => Some(Self::All),
+      _ => None,
+    }
+  }
+}
+
+// --| Request Spans -----------------
+// --|--------------------------------
+/// Runs each handler in a `request` span carrying the method, JSON-RPC id and the document's
+/// uri and version, and logs how it finished and how long it took
+pub struct TraceRequests<S> {
+  inner: S,
+  level: SpanLevel,
+}
+
+impl<S> TraceRequests<S> {
+  pub fn new(inner: S, level: SpanLevel) -> Self {
+    Self { inner, level }
+  }
+}
+
+impl<S> Service<Request> for TraceRequests<S>
+where
+  S: Service<Request, Response = Option<Response>, Error = ExitedError>,
+  S::Future: Send + 'static,
+{
+  type Response = Option<Response>;
+  type Error = ExitedError;
+  type Future = Pin<Box<dyn Future<Output = Result<Option<Response>, ExitedError>> + Send>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.inner.poll_ready(cx)
+  }
+
+  fn call(&mut self, request: Request) -> Self::Future {
+    let notification = request.id().is_none();
+    if self.level == SpanLevel::Off || (notification && self.level != SpanLevel::All) {
+      return Box::pin(self.inner.call(request));
+    }
+
+    let span = info_span!("request", method = request.method(), id = field::Empty, uri = field::Empty, version = field::Empty);
+    if let Some(id) = request.id() { span.record("id", field::display(id)); }
+
+    let document = request.params().and_then(|params| params.get("textDocument"));
+    if let Some(uri) = document.and_then(|document| document.get("uri")).and_then(Value::as_str) {
+      span.record("uri", uri);
+    }
+    if let Some(version) = document.and_then(|document| document.get("version")).and_then(Value::as_i64) {
+      span.record("version", version);
+    }
+    if self.level == SpanLevel::All {
+      if let Some(params) = request.params() { debug!(parent: &span, %params, "params"); }
+    }
+
+    let handler = self.inner.call(request).instrument(span.clone());
+    let start = Instant::now();
+
+    Box::pin(async move {
+      let response = handler.await;
+      let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+      match &response {
+        Ok(Some(response)) if response.is_error() => warn!(parent: &span, elapsed_ms, outcome = "error", "finished"),
+        Ok(_) if notification => info!(parent: &span, elapsed_ms, outcome = "handled", "finished"),
+        Ok(_) => info!(parent: &span, elapsed_ms, outcome = "ok", "finished"),
+        Err(_) => warn!(parent: &span, elapsed_ms, outcome = "exited", "finished"),
+      }
+      response
+    })
+  }
+}
@@ -0,0 +1,250 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use lsp_types::{
+  CodeAction, CodeActionKind, CodeActionOrCommand, Command, Diagnostic, DiagnosticSeverity, TextEdit, Url, WorkspaceEdit,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info};
+use tree_sitter::{Node, Point};
+
+use crate::diagnostics::ErrorInfo;
+use crate::commands;
+
+/// Diagnostic code attached to misspelled words
+pub const SPELLING_CODE: &str = "spelling";
+
+/// Dictionary used when none is configured
+pub const SYSTEM_DICTIONARY: &str = "/usr/share/dict/words";
+
+/// Workspace relative file holding words accepted through the quick fix
+pub const WORKSPACE_WORDS: &str = ".cyberls/words.txt";
+
+// --| Configuration -----------------
+// --|--------------------------------
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct SpellCheckConfig {
+  pub enable: bool,
+  /// Word list with one word per line. Defaults to the system dictionary
+  pub dictionary: Option<String>,
+  /// Additional words that are never reported
+  pub ignore_words: Vec<String>,
+}
+
+// --| Spell Checker -----------------
+// --|--------------------------------
+#[derive(Debug, Default)]
+pub struct SpellChecker {
+  words: HashSet<String>,
+}
+
+impl SpellChecker {
+  /// Load the configured dictionary, the ignore list and each workspace's accepted words
+  pub fn load(config: &SpellCheckConfig, workspace_roots: &[PathBuf]) -> Self {
+    let mut checker = SpellChecker::default();
+
+    let dictionary = config.dictionary.clone().unwrap_or_else(|| SYSTEM_DICTIONARY.to_string());
+    checker.extend_from_file(Path::new(&dictionary));
+
+    for root in workspace_roots {
+      checker.extend_from_file(&root.join(WORKSPACE_WORDS));
+    }
+
+    checker.words.extend(config.ignore_words.iter().map(|word| word.to_lowercase()));
+    checker.words.extend(KEYWORDS.iter().map(|word| word.to_string()));
+
+    info!("Spell checker loaded {} words", checker.words.len());
+    checker
+  }
+
+  fn extend_from_file(&mut self, path: &Path) {
+    match std::fs::read_to_string(path) {
+      Ok(content) => self.words.extend(content.lines().map(|line| line.trim().to_lowercase()).filter(|word| !word.is_empty())),
+      Err(err) => debug!("Unable to read word list {:?}: {}", path, err),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.words.is_empty()
+  }
+
+  pub fn accept(&mut self, word: &str) {
+    self.words.insert(word.to_lowercase());
+  }
+
+  pub fn is_known(&self, word: &str) -> bool {
+    self.words.contains(&word.to_lowercase())
+  }
+
+  /// Closest dictionary words, keeping the capitalization of the original
+  pub fn suggest(&self, word: &str, limit: usize) -> Vec<String> {
+    let lower = word.to_lowercase();
+    let max_distance = if lower.chars().count() > 5 { 2 } else { 1 };
+
+    let mut candidates: Vec<(usize, &String)> = self.words.iter()
+      .filter(|candidate| candidate.len().abs_diff(lower.len()) <= max_distance)
+      .filter_map(|candidate| {
+        let distance = edit_distance(&lower, candidate);
+        (distance <= max_distance).then_some((distance, candidate))
+      })
+      .collect();
+
+    candidates.sort();
+    candidates.into_iter().take(limit).map(|(_, candidate)| match_case(word, candidate)).collect()
+  }
+
+  /// Report unknown words inside comments and string literals
+  pub fn check(&self, root: Node, source: &str) -> Option<ErrorInfo> {
+    let mut error_info = ErrorInfo::new();
+    if self.is_empty() { return None; }
+
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+      if node.kind() == "comment" || node.kind().contains("string") {
+        self.check_text(node, source, &mut error_info);
+        continue;
+      }
+
+      let mut cursor = node.walk();
+      stack.extend(node.named_children(&mut cursor));
+    }
+
+    if error_info.entries.is_empty() { None } else { Some(error_info) }
+  }
+
+  fn check_text(&self, node: Node, source: &str, error_info: &mut ErrorInfo) {
+    let text = node.utf8_text(source.as_bytes()).unwrap_or_default();
+    let start = node.start_position();
+
+    for (row_offset, line) in text.split('\n').enumerate() {
+      for (column, word) in words(line) {
+        if self.is_known(word) { continue; }
+
+        let row = start.row + row_offset;
+        let column = if row_offset == 0 { start.column + column } else { column };
+
+        error_info.add_coded(
+          Point { row, column },
+          Point { row, column: column + word.len() },
+          format!("Unknown word '{}'", word),
+          Some(DiagnosticSeverity::HINT),
+          SPELLING_CODE,
+        );
+      }
+    }
+  }
+}
+
+/// Replacement suggestions plus an action accepting the word into the workspace dictionary
+pub fn quick_fixes(checker: &SpellChecker, uri: &Url, word: &str, diagnostic: &Diagnostic) -> Vec<CodeActionOrCommand> {
+  let mut actions: Vec<CodeActionOrCommand> = checker.suggest(word, 3).into_iter().enumerate().map(|(index, suggestion)| {
+    CodeActionOrCommand::CodeAction(CodeAction {
+      title: format!("Change to '{}'", suggestion),
+      kind: Some(CodeActionKind::QUICKFIX),
+      diagnostics: Some(vec![diagnostic.clone()]),
+      edit: Some(WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), vec![TextEdit::new(diagnostic.range, suggestion)])])),
+        ..Default::default()
+      }),
+      is_preferred: Some(index == 0),
+      ..Default::default()
+    })
+  }).collect();
+
+  let title = format!("Add '{}' to workspace dictionary", word);
+  actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+    title: title.clone(),
+    kind: Some(CodeActionKind::QUICKFIX),
+    diagnostics: Some(vec![diagnostic.clone()]),
+    command: Some(Command {
+      title,
+      command: commands::SPELL_IGNORE.to_string(),
+      arguments: Some(vec![json!(word), json!(uri)]),
+    }),
+    ..Default::default()
+  }));
+
+  actions
+}
+
+/// Append a word to the workspace word list so it survives restarts
+pub fn persist_word(workspace_root: &Path, word: &str) -> std::io::Result<()> {
+  let path = workspace_root.join(WORKSPACE_WORDS);
+  if let Some(parent) = path.parent() { std::fs::create_dir_all(parent)?; }
+
+  let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+  writeln!(file, "{}", word.to_lowercase())
+}
+
+// --| Word Splitting ----------------
+// --|--------------------------------
+const KEYWORDS: &[&str] = &[
+  "func", "var", "coinit", "coyield", "coresume", "cyber", "cyberls", "str", "bool", "int",
+  "elif", "params", "args", "repl", "stdin", "stdout", "stderr", "todo", "fixme",
+];
+
+/// Checkable words of a line with their byte column. Splits camelCase and
+/// digits apart and skips short words and acronyms.
+fn words(line: &str) -> Vec<(usize, &str)> {
+  let mut result = vec![];
+  let mut token_start: Option<usize> = None;
+
+  let chars: Vec<(usize, char)> = line.char_indices().collect();
+  for (index, &(byte, ch)) in chars.iter().enumerate() {
+    let starts_word = ch.is_alphabetic() && token_start.is_none();
+    let camel_break = ch.is_uppercase() && index > 0 && chars[index - 1].1.is_lowercase();
+
+    if camel_break {
+      if let Some(start) = token_start.take() { result.push((start, &line[start..byte])); }
+      token_start = Some(byte);
+    } else if starts_word {
+      token_start = Some(byte);
+    } else if !ch.is_alphabetic() && ch != '\'' {
+      if let Some(start) = token_start.take() { result.push((start, &line[start..byte])); }
+    }
+  }
+
+  if let Some(start) = token_start { result.push((start, &line[start..])); }
+
+  result.into_iter()
+    .map(|(start, word)| (start, word.trim_end_matches('\'')))
+    .filter(|(_, word)| word.chars().count() >= 3)
+    .filter(|(_, word)| !word.chars().all(|ch| ch.is_uppercase()))
+    .collect()
+}
+
+fn match_case(original: &str, suggestion: &str) -> String {
+  match original.chars().next() {
+    Some(first) if first.is_uppercase() => {
+      let mut chars = suggestion.chars();
+      chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+    }
+    _ => suggestion.to_string(),
+  }
+}
+
+/// Levenshtein distance with adjacent transpositions
+fn edit_distance(a: &str, b: &str) -> usize {
+  let a: Vec<char> = a.chars().collect();
+  let b: Vec<char> = b.chars().collect();
+  let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+
+  for (i, row) in rows.iter_mut().enumerate() { row[0] = i; }
+  for (j, cell) in rows[0].iter_mut().enumerate() { *cell = j; }
+
+  for i in 1..=a.len() {
+    for j in 1..=b.len() {
+      let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+      rows[i][j] = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+
+      if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+        rows[i][j] = rows[i][j].min(rows[i - 2][j - 2] + 1);
+      }
+    }
+  }
+
+  rows[a.len()][b.len()]
+}
@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use lsp_types::{Command as LspCommand, CompletionItem};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower::Service;
+use tower_lsp::jsonrpc::{Request, Response};
+use tower_lsp::ExitedError;
+use tracing::{debug, info};
+
+use crate::commands;
+
+/// File the statistics are kept in, next to the server log
+pub const STATS_FILE: &str = "cyberls-stats.json";
+
+const COMPLETION_METHOD: &str = "textDocument/completion";
+/// Accepted completion items, counted through the command they carry
+const ACCEPTED_COMPLETIONS: &str = "completion/accepted";
+
+// --| Usage Statistics --------------
+// --|--------------------------------
+/// Local counts and latencies of the requests the server handles, collected while the
+/// `performance_metrics` setting is on. Nothing leaves the machine: the totals of every
+/// session are merged into `cyberls-stats.json` in the log directory.
+pub struct UsageStats {
+  enabled: AtomicBool,
+  file: Option<PathBuf>,
+  totals: Mutex<Option<StatsTotals>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatsTotals {
+  /// Unix time the first statistics were collected
+  pub since: u64,
+  pub sessions: u64,
+  pub methods: BTreeMap<String, MethodStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MethodStats {
+  pub count: u64,
+  /// Requests answered with an error
+  pub errors: u64,
+  pub total_micros: u64,
+  pub max_micros: u64,
+}
+
+impl UsageStats {
+  /// Statistics persisted in `log_dir`, or only kept in memory without one
+  pub fn new(log_dir: Option<PathBuf>) -> Self {
+    Self { enabled: AtomicBool::new(false), file: log_dir.map(|dir| dir.join(STATS_FILE)), totals: Mutex::new(None) }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.enabled.load(Ordering::Relaxed)
+  }
+
+  /// Start or stop collecting. Enabling loads the totals of earlier sessions, disabling saves them
+  pub fn set_enabled(&self, enabled: bool) {
+    if self.enabled.swap(enabled, Ordering::SeqCst) == enabled { return; }
+
+    if enabled {
+      let mut totals = self.load();
+      totals.sessions += 1;
+      *self.totals.lock().unwrap_or_else(|err| err.into_inner()) = Some(totals);
+      info!("Collecting usage statistics");
+    } else {
+      self.save();
+      *self.totals.lock().unwrap_or_else(|err| err.into_inner()) = None;
+    }
+  }
+
+  /// Count a handled message, notifications being recorded with their handling time too
+  pub fn record(&self, method: &str, elapsed: Duration, failed: bool) {
+    if !self.is_enabled() { return; }
+
+    let mut totals = self.totals.lock().unwrap_or_else(|err| err.into_inner());
+    let totals = match totals.as_mut() { Some(totals) => totals, None => return };
+    let stats = totals.methods.entry(method.to_string()).or_default();
+
+    let micros = elapsed.as_micros().min(u64::MAX as u128) as u64;
+    stats.count += 1;
+    stats.errors += failed as u64;
+    stats.total_micros = stats.total_micros.saturating_add(micros);
+    stats.max_micros = stats.max_micros.max(micros);
+  }
+
+  /// Count a completion item the client accepted, ie. ran the command of
+  pub fn record_accepted(&self) {
+    self.record(ACCEPTED_COMPLETIONS, Duration::ZERO, false);
+  }
+
+  /// Current totals with average latencies and the completion acceptance rate, the share
+  /// of completion requests followed by an accepted item
+  pub fn report(&self) -> Value {
+    let totals = self.totals.lock().unwrap_or_else(|err| err.into_inner()).clone().unwrap_or_else(|| self.load());
+    let count = |method: &str| totals.methods.get(method).map_or(0, |stats| stats.count);
+
+    let methods: BTreeMap<&String, Value> = totals.methods.iter().map(|(method, stats)| {
+      let average_ms = stats.total_micros as f64 / stats.count.max(1) as f64 / 1000.0;
+      (method, serde_json::json!({
+        "count": stats.count,
+        "errors": stats.errors,
+        "average_ms": (average_ms * 100.0).round() / 100.0,
+        "max_ms": stats.max_micros as f64 / 1000.0,
+      }))
+    }).collect();
+
+    let acceptance = match count(COMPLETION_METHOD) {
+      0 => None,
+      completions => Some(count(ACCEPTED_COMPLETIONS) as f64 / completions as f64),
+    };
+
+    serde_json::json!({
+      "enabled": self.is_enabled(),
+      "file": self.file,
+      "since": totals.since,
+      "sessions": totals.sessions,
+      "completion_acceptance": acceptance,
+      "methods": methods,
+    })
+  }
+
+  /// Write the totals collected so far, failures are only logged
+  pub fn save(&self) {
+    let (file, totals) = match (&self.file, self.totals.lock().unwrap_or_else(|err| err.into_inner()).clone()) {
+      (Some(file), Some(totals)) => (file, totals),
+      _ => return,
+    };
+
+    let written = serde_json::to_vec_pretty(&totals).map_err(std::io::Error::from)
+      .and_then(|bytes| std::fs::write(file, bytes));
+    if let Err(err) = written {
+      debug!("Failed to write usage statistics {:?}: {}", file, err);
+    }
+  }
+
+  /// Forget every statistic, including the saved ones
+  pub fn reset(&self) {
+    let mut totals = self.totals.lock().unwrap_or_else(|err| err.into_inner());
+    if totals.is_some() { *totals = Some(StatsTotals { since: now(), sessions: 1, ..Default::default() }); }
+    if let Some(file) = &self.file { let _ = std::fs::remove_file(file); }
+  }
+
+  fn load(&self) -> StatsTotals {
+    self.file.as_ref()
+      .and_then(|file| std::fs::read(file).ok())
+      .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+      .unwrap_or_else(|| StatsTotals { since: now(), ..Default::default() })
+  }
+}
+
+/// Give completion items without a command one counting them as accepted. Clients run
+/// the command of the item inserted, unlike resolves which they send for any focused item.
+pub fn with_accept_command(items: Vec<CompletionItem>) -> Vec<CompletionItem> {
+  items.into_iter().map(|mut item| {
+    if item.command.is_none() {
+      item.command = Some(LspCommand::new(String::new(), commands::COMPLETION_ACCEPTED.to_string(), None));
+    }
+    item
+  }).collect()
+}
+
+fn now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+// --| Stats Recorder ----------------
+// --|--------------------------------
+/// Times every message the wrapped service handles into `UsageStats`
+pub struct RecordStats<S> {
+  inner: S,
+  stats: Arc<UsageStats>,
+}
+
+impl<S> RecordStats<S> {
+  pub fn new(inner: S, stats: Arc<UsageStats>) -> Self {
+    Self { inner, stats }
+  }
+}
+
+impl<S> Service<Request> for RecordStats<S>
+where
+  S: Service<Request, Response = Option<Response>, Error = ExitedError>,
+  S::Future: Send + 'static,
+{
+  type Response = Option<Response>;
+  type Error = ExitedError;
+  type Future = Pin<Box<dyn Future<Output = Result<Option<Response>, ExitedError>> + Send>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.inner.poll_ready(cx)
+  }
+
+  fn call(&mut self, request: Request) -> Self::Future {
+    if !self.stats.is_enabled() { return Box::pin(self.inner.call(request)); }
+
+    let method = request.method().to_string();
+    let handler = self.inner.call(request);
+    let stats = self.stats.clone();
+    let start = Instant::now();
+
+    Box::pin(async move {
+      let response = handler.await;
+      let failed = matches!(&response, Ok(Some(response)) if response.is_error());
+      stats.record(&method, start.elapsed(), failed);
+      response
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn acceptance_counts_accepted_items_per_completion_request() {
+    let stats = UsageStats::new(None);
+    stats.set_enabled(true);
+    for _ in 0..4 { stats.record(COMPLETION_METHOD, Duration::from_millis(1), false); }
+    stats.record_accepted();
+
+    assert_eq!(stats.report()["completion_acceptance"], 0.25);
+  }
+
+  #[test]
+  fn accept_commands_leave_existing_commands() {
+    let mut commanded = CompletionItem::new_simple("print".to_string(), String::new());
+    commanded.command = Some(LspCommand::new(String::new(), "editor.action.triggerParameterHints".to_string(), None));
+    let items = with_accept_command(vec![CompletionItem::new_simple("var".to_string(), String::new()), commanded]);
+
+    assert_eq!(items[0].command.as_ref().map(|command| command.command.as_str()), Some(commands::COMPLETION_ACCEPTED));
+    assert_eq!(items[1].command.as_ref().map(|command| command.command.as_str()), Some("editor.action.triggerParameterHints"));
+  }
+}
@@ -0,0 +1,101 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use lsp_types::notification::Notification;
+use serde::{Deserialize, Serialize};
+
+// --| Status Notification -----------
+// --|--------------------------------
+/// Server activity for editor extensions to render in their status bar
+pub enum Status {}
+
+impl Notification for Status {
+  type Params = StatusParams;
+  const METHOD: &'static str = "cyberls/status";
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusState {
+  Idle,
+  Indexing,
+  Checking,
+  Error,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusParams {
+  pub state: StatusState,
+  pub message: Option<String>,
+}
+
+/// Work that keeps the server busy while it is in progress
+#[derive(Clone, Copy, Debug)]
+pub enum Activity {
+  Indexing,
+  Checking,
+}
+
+// --| Status Tracker ----------------
+// --|--------------------------------
+/// Combines the activity of the subsystems into one status. Indexing takes
+/// precedence over checking, and an error is shown until it is cleared.
+#[derive(Debug, Default)]
+pub struct StatusTracker {
+  indexing: AtomicUsize,
+  checking: AtomicUsize,
+  error: Mutex<Option<String>>,
+  last: Mutex<Option<StatusParams>>,
+}
+
+impl StatusTracker {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn begin(&self, activity: Activity) {
+    self.counter(activity).fetch_add(1, Ordering::SeqCst);
+  }
+
+  pub fn end(&self, activity: Activity) {
+    let _ = self.counter(activity).fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| count.checked_sub(1));
+  }
+
+  pub fn set_error(&self, message: Option<String>) {
+    *self.error.lock().unwrap_or_else(|err| err.into_inner()) = message;
+  }
+
+  pub fn current(&self) -> StatusParams {
+    if let Some(message) = self.error.lock().unwrap_or_else(|err| err.into_inner()).clone() {
+      return StatusParams { state: StatusState::Error, message: Some(message) };
+    }
+
+    let state = if self.indexing.load(Ordering::SeqCst) > 0 {
+      StatusState::Indexing
+    } else if self.checking.load(Ordering::SeqCst) > 0 {
+      StatusState::Checking
+    } else {
+      StatusState::Idle
+    };
+
+    StatusParams { state, message: None }
+  }
+
+  /// The current status, if it differs from the last one returned
+  pub fn changed(&self) -> Option<StatusParams> {
+    let current = self.current();
+    let mut last = self.last.lock().unwrap_or_else(|err| err.into_inner());
+    if last.as_ref() == Some(&current) { return None; }
+
+    *last = Some(current.clone());
+    Some(current)
+  }
+
+  fn counter(&self, activity: Activity) -> &AtomicUsize {
+    match activity {
+      Activity::Indexing => &self.indexing,
+      Activity::Checking => &self.checking,
+    }
+  }
+}
@@ -0,0 +1,88 @@
+use cyber_tree_sitter::Tree;
+use lsp_types::TextEdit;
+use tree_sitter::{Node, Query, QueryCursor};
+
+use crate::core_types::DocRange;
+
+pub const STRUCTURAL_REPLACE_COMMAND: &str = "cyberls.structural_replace";
+
+/// Capture naming the node to replace. Without it the first capture of the pattern is replaced.
+pub const MATCH_CAPTURE: &str = "match";
+
+// --| Structural Replace ------------
+// --|--------------------------------
+pub fn compile(query: &str) -> Result<Query, String> {
+  Query::new(cyber_tree_sitter::cyber_language(), query).map_err(|err| format!("Invalid query: {:?}", err))
+}
+
+/// Parse a file which is not open in the editor
+pub fn parse_source(source: &str) -> Option<Tree> {
+  cyber_tree_sitter::try_init_parser().ok()?.parse(source, None)
+}
+
+/// Replace every match of `query` with `template`, where `$name` or `${name}` expand
+/// to the text of a capture and `$$` to a dollar sign. Matches overlapping an earlier one are skipped.
+pub fn replacements(query: &Query, root: Node, source: &str, template: &str) -> Vec<TextEdit> {
+  let names = query.capture_names();
+  let target = names.iter().position(|name| name == MATCH_CAPTURE);
+  let mut cursor = QueryCursor::new();
+  let mut replaced: Vec<(usize, usize, TextEdit)> = vec![];
+
+  for found in cursor.matches(query, root, source.as_bytes()) {
+    let node = match target {
+      Some(index) => found.captures.iter().find(|capture| capture.index as usize == index),
+      None => found.captures.first(),
+    };
+    let node = match node { Some(capture) => capture.node, None => continue };
+
+    let (start, end) = (node.start_byte(), node.end_byte());
+    if replaced.iter().any(|(other_start, other_end, _)| start < *other_end && *other_start < end) { continue; }
+
+    let text = expand(template, |name| {
+      let index = names.iter().position(|capture| capture == name)?;
+      let capture = found.captures.iter().find(|capture| capture.index as usize == index)?;
+      capture.node.utf8_text(source.as_bytes()).ok()
+    });
+
+    let range = DocRange::of_node(&node).byte_range();
+    replaced.push((start, end, TextEdit::new(range, text)));
+  }
+
+  replaced.sort_by_key(|(start, _, _)| *start);
+  replaced.into_iter().map(|(_, _, edit)| edit).collect()
+}
+
+/// Substitute capture references in a replacement template. Unknown captures expand to nothing.
+fn expand<'a>(template: &str, capture: impl Fn(&str) -> Option<&'a str>) -> String {
+  let mut output = String::new();
+  let mut rest = template;
+
+  while let Some(index) = rest.find('$') {
+    output.push_str(&rest[..index]);
+    rest = &rest[index + 1..];
+
+    if let Some(after) = rest.strip_prefix('$') {
+      output.push('$');
+      rest = after;
+      continue;
+    }
+
+    let (name, after) = match rest.strip_prefix('{').and_then(|inner| inner.find('}').map(|end| (&inner[..end], &inner[end + 1..]))) {
+      Some(braced) => braced,
+      None => {
+        let end = rest.find(|ch: char| !(ch.is_alphanumeric() || ch == '_')).unwrap_or(rest.len());
+        (&rest[..end], &rest[end..])
+      }
+    };
+
+    if name.is_empty() {
+      output.push('$');
+    } else {
+      output.push_str(capture(name).unwrap_or_default());
+    }
+    rest = after;
+  }
+
+  output.push_str(rest);
+  output
+}
@@ -0,0 +1,333 @@
+use std::collections::{HashMap, VecDeque};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lsp_types::{DidChangeTextDocumentParams, DidOpenTextDocumentParams};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::documents::FullTextDocument;
+use crate::logs::{LOG_FILE, LOG_FILE_ENV};
+
+/// Set for the server process started by the supervisor, so it runs the server itself
+pub const SUPERVISED_ENV: &str = "CYBERLS_SUPERVISED";
+
+/// Crashes allowed within `RESTART_WINDOW` before the supervisor gives up
+const MAX_RESTARTS: usize = 5;
+const RESTART_WINDOW: Duration = Duration::from_secs(60);
+
+/// JSON-RPC `InternalError`, answered to requests the crashed server never responded to
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Unanswered client requests remembered for a restart, the oldest are forgotten first
+const MAX_PENDING: usize = 1024;
+
+// --| Supervisor --------------------
+// --|--------------------------------
+/// Run the server in a child process started with the same arguments, and start it
+/// again when it crashes. Over stdio the restart is invisible to the client: the
+/// supervisor replays `initialize` and the open documents to the new process. Over
+/// tcp the client reconnects to the same port. The new process starts from the index
+/// cache on disk, which the crashed one only ever replaced whole. Returns the exit code
+/// to exit with.
+pub async fn run(tcp: bool) -> i32 {
+  let mut crashes: Vec<Instant> = vec![];
+
+  if tcp {
+    loop {
+      let mut child = match spawn_server(false) {
+        Ok(child) => child,
+        Err(err) => { error!("Failed to start the server: {}", err); return 1; }
+      };
+
+      match child.wait().await {
+        Ok(status) if status.success() => return 0,
+        Ok(status) => warn!("Server exited with {}, restarting so the client can reconnect", status),
+        Err(err) => warn!("Lost the server process: {}, restarting", err),
+      }
+      if !allow_restart(&mut crashes) { return 1; }
+    }
+  }
+
+  supervise_stdio(&mut crashes).await
+}
+
+fn spawn_server(piped: bool) -> std::io::Result<Child> {
+  let program = std::env::current_exe()?;
+  let mut command = Command::new(program);
+  command.args(std::env::args_os().skip(1)).env(SUPERVISED_ENV, "1").env(LOG_FILE_ENV, LOG_FILE.as_str()).kill_on_drop(true);
+
+  if piped {
+    command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::inherit());
+  }
+  command.spawn()
+}
+
+/// Record the crash, false once the server crashed too often to keep restarting it
+fn allow_restart(crashes: &mut Vec<Instant>) -> bool {
+  let now = Instant::now();
+  crashes.retain(|crash| now.duration_since(*crash) < RESTART_WINDOW);
+  crashes.push(now);
+
+  if crashes.len() > MAX_RESTARTS {
+    error!("Server crashed {} times within {:?}, giving up", crashes.len(), RESTART_WINDOW);
+    return false;
+  }
+  true
+}
+
+// --| Session Replay ----------------
+// --|--------------------------------
+/// What a new server process needs to be brought to the state the client expects
+#[derive(Default)]
+struct Session {
+  initialize: Option<Vec<u8>>,
+  initialized: Option<Vec<u8>>,
+  /// Open documents with their changes applied, replayed as a single `didOpen` each
+  documents: HashMap<String, FullTextDocument>,
+  /// Ids of client requests the server has not answered yet, oldest first
+  pending: VecDeque<String>,
+  /// Id of the replayed `initialize`, whose response the client must not see
+  replayed: Option<String>,
+  exiting: bool,
+}
+
+impl Session {
+  /// Remember a message from the client which has to be replayed after a restart
+  fn record(&mut self, body: &[u8]) {
+    let message: Value = match serde_json::from_slice(body) { Ok(message) => message, Err(_) => return };
+    let method = message.get("method").and_then(Value::as_str).unwrap_or_default();
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+    if let Some(id) = message.get("id").filter(|_| !method.is_empty()) {
+      if self.pending.len() >= MAX_PENDING { self.pending.pop_front(); }
+      self.pending.push_back(id.to_string());
+    }
+
+    match method {
+      "initialize" => self.initialize = Some(body.to_vec()),
+      "initialized" => self.initialized = Some(body.to_vec()),
+      "exit" => self.exiting = true,
+      "textDocument/didOpen" => {
+        if let Ok(params) = serde_json::from_value::<DidOpenTextDocumentParams>(params) {
+          let document = params.text_document;
+          let opened = FullTextDocument::new(document.uri.clone(), document.language_id, document.version.into(), document.text);
+          self.documents.insert(document.uri.to_string(), opened);
+        }
+      }
+      "textDocument/didChange" => {
+        if let Ok(params) = serde_json::from_value::<DidChangeTextDocumentParams>(params) {
+          if let Some(document) = self.documents.get_mut(params.text_document.uri.as_str()) {
+            document.update(params.content_changes, params.text_document.version.into());
+          }
+        }
+      }
+      "textDocument/didClose" => {
+        if let Some(uri) = message.pointer("/params/textDocument/uri").and_then(Value::as_str) { self.documents.remove(uri); }
+      }
+      _ => {}
+    }
+  }
+
+  /// Whether a message from the server is passed on to the client
+  fn forward(&mut self, body: &[u8]) -> bool {
+    let message: Value = match serde_json::from_slice(body) { Ok(message) => message, Err(_) => return true };
+    if message.get("method").is_some() { return true; }
+
+    let id = match message.get("id") { Some(id) => id.to_string(), None => return true };
+    if self.replayed.as_ref() == Some(&id) {
+      self.replayed = None;
+      return false;
+    }
+    self.pending.retain(|pending| pending != &id);
+    true
+  }
+
+  /// Messages bringing a new server process up to date, in the order they were sent
+  fn replay(&mut self) -> Vec<Vec<u8>> {
+    let initialize = match &self.initialize { Some(initialize) => initialize.clone(), None => return vec![] };
+    self.replayed = serde_json::from_slice::<Value>(&initialize).ok().and_then(|message| message.get("id").map(Value::to_string));
+
+    let mut messages = vec![initialize];
+    messages.extend(self.initialized.clone());
+    messages.extend(self.documents.values().map(|document| {
+      let open = json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": { "textDocument": {
+        "uri": document.uri, "languageId": document.language_id, "version": document.version, "text": document.rope.to_string(),
+      } } });
+      open.to_string().into_bytes()
+    }));
+    messages
+  }
+}
+
+// --| Stdio Proxy -------------------
+// --|--------------------------------
+async fn supervise_stdio(crashes: &mut Vec<Instant>) -> i32 {
+  let session = Arc::new(Mutex::new(Session::default()));
+  let (to_server, mut from_client) = mpsc::unbounded_channel::<Vec<u8>>();
+  let (to_client, mut outgoing) = mpsc::unbounded_channel::<Vec<u8>>();
+
+  // Client messages are recorded as they arrive, so a restart replays everything sent so far
+  let recorder = session.clone();
+  tokio::spawn(async move {
+    let mut stdin = BufReader::new(tokio::io::stdin());
+    while let Ok(Some(body)) = read_message(&mut stdin).await {
+      recorder.lock().unwrap_or_else(|err| err.into_inner()).record(&body);
+      if to_server.send(body).is_err() { break; }
+    }
+  });
+
+  tokio::spawn(async move {
+    let mut stdout = tokio::io::stdout();
+    while let Some(body) = outgoing.recv().await {
+      if write_message(&mut stdout, &body).await.is_err() { break; }
+    }
+  });
+
+  let mut restarted = false;
+  loop {
+    let mut child = match spawn_server(true) {
+      Ok(child) => child,
+      Err(err) => { error!("Failed to start the server: {}", err); return 1; }
+    };
+    let (mut stdin, stdout) = match (child.stdin.take(), child.stdout.take()) {
+      (Some(stdin), Some(stdout)) => (stdin, stdout),
+      _ => { error!("Server started without stdio pipes"); return 1; }
+    };
+
+    let forwarder = session.clone();
+    let client = to_client.clone();
+    tokio::spawn(async move {
+      let mut stdout = BufReader::new(stdout);
+      while let Ok(Some(body)) = read_message(&mut stdout).await {
+        let forward = forwarder.lock().unwrap_or_else(|err| err.into_inner()).forward(&body);
+        if forward && client.send(body).is_err() { break; }
+      }
+    });
+
+    if restarted {
+      let replay = session.lock().unwrap_or_else(|err| err.into_inner()).replay();
+      info!("Replaying {} messages to the restarted server", replay.len());
+      for body in replay {
+        if write_message(&mut stdin, &body).await.is_err() { break; }
+      }
+    }
+
+    let status = loop {
+      tokio::select! {
+        body = from_client.recv() => match body {
+          Some(body) => { let _ = write_message(&mut stdin, &body).await; }
+          // The client is gone, closing stdin lets the server exit on its own
+          None => { drop(stdin); break child.wait().await; }
+        },
+        status = child.wait() => break status,
+      }
+    };
+
+    let exiting = session.lock().unwrap_or_else(|err| err.into_inner()).exiting;
+    match status {
+      Ok(status) if status.success() || exiting => return status.code().unwrap_or(0),
+      Ok(status) => warn!("Server exited with {}, restarting", status),
+      Err(err) => warn!("Lost the server process: {}, restarting", err),
+    }
+    if !allow_restart(crashes) { return 1; }
+
+    // Messages not delivered yet are part of the replay or answered below
+    while from_client.try_recv().is_ok() {}
+
+    let pending: Vec<String> = session.lock().unwrap_or_else(|err| err.into_inner()).pending.drain(..).collect();
+    for id in pending {
+      let id: Value = serde_json::from_str(&id).unwrap_or(Value::Null);
+      let response = json!({ "jsonrpc": "2.0", "id": id, "error": { "code": INTERNAL_ERROR, "message": "cyberls restarted while handling the request" } });
+      let _ = to_client.send(response.to_string().into_bytes());
+    }
+
+    let notice = json!({ "jsonrpc": "2.0", "method": "window/showMessage", "params": { "type": 2, "message": "cyberls crashed and was restarted" } });
+    let _ = to_client.send(notice.to_string().into_bytes());
+    restarted = true;
+  }
+}
+
+async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Option<Vec<u8>>> {
+  let mut length = None;
+  loop {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 { return Ok(None); }
+
+    let line = line.trim_end();
+    if line.is_empty() { break; }
+    if let Some(value) = line.strip_prefix("Content-Length:") { length = value.trim().parse::<usize>().ok(); }
+  }
+
+  let length = length.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Missing Content-Length header"))?;
+  let mut body = vec![0; length];
+  reader.read_exact(&mut body).await?;
+  Ok(Some(body))
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, body: &[u8]) -> std::io::Result<()> {
+  writer.write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes()).await?;
+  writer.write_all(body).await?;
+  writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn record(session: &mut Session, message: Value) {
+    session.record(message.to_string().as_bytes());
+  }
+
+  fn open(session: &mut Session, uri: &str, text: &str) {
+    record(session, json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+      "textDocument": { "uri": uri, "languageId": "cyber", "version": 1, "text": text } } }));
+  }
+
+  #[test]
+  fn changes_are_folded_into_the_replayed_open() {
+    let mut session = Session::default();
+    record(&mut session, json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }));
+    open(&mut session, "file:///work/main.cy", "var a = 1\n");
+    for version in 2..50 {
+      record(&mut session, json!({ "jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+        "textDocument": { "uri": "file:///work/main.cy", "version": version },
+        "contentChanges": [{ "range": { "start": { "line": 0, "character": 8 }, "end": { "line": 0, "character": 9 } }, "text": version.to_string() }] } }));
+    }
+
+    let replay = session.replay();
+    assert_eq!(replay.len(), 2);
+    let reopened: Value = serde_json::from_slice(&replay[1]).unwrap();
+    assert_eq!(reopened["params"]["textDocument"]["text"], "var a = 49\n");
+    assert_eq!(reopened["params"]["textDocument"]["version"], 49);
+  }
+
+  #[test]
+  fn closed_documents_are_not_replayed() {
+    let mut session = Session::default();
+    record(&mut session, json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }));
+    open(&mut session, "file:///work/main.cy", "");
+    record(&mut session, json!({ "jsonrpc": "2.0", "method": "textDocument/didClose", "params": { "textDocument": { "uri": "file:///work/main.cy" } } }));
+
+    assert!(session.documents.is_empty());
+    assert_eq!(session.replay().len(), 1);
+  }
+
+  #[test]
+  fn pending_requests_are_cleared_on_response_and_capped() {
+    let mut session = Session::default();
+    for id in 0..MAX_PENDING + 10 {
+      record(&mut session, json!({ "jsonrpc": "2.0", "id": id, "method": "textDocument/hover", "params": {} }));
+    }
+    assert_eq!(session.pending.len(), MAX_PENDING);
+    assert_eq!(session.pending.front().map(String::as_str), Some("10"));
+
+    assert!(session.forward(json!({ "jsonrpc": "2.0", "id": 20, "result": null }).to_string().as_bytes()));
+    assert_eq!(session.pending.len(), MAX_PENDING - 1);
+    assert!(!session.pending.contains(&"20".to_string()));
+  }
+}
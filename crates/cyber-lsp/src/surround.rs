@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use lsp_types::{CodeAction, CodeActionKind, Position, Range, TextEdit, Url, WorkspaceEdit};
+
+use crate::format::Indent;
+use crate::snippet_edits;
+
+/// Kind of the surround actions, below `refactor.rewrite`
+pub const SURROUND_KIND: &str = "refactor.rewrite.surround";
+
+pub fn surround_kind() -> CodeActionKind {
+  CodeActionKind::new(SURROUND_KIND)
+}
+
+/// Whether the client asked for surround actions, or did not restrict the kinds
+pub fn requested(only: Option<&Vec<CodeActionKind>>) -> bool {
+  only.map_or(true, |only| only.iter().any(|kind| SURROUND_KIND.starts_with(kind.as_str())))
+}
+
+/// A construct selected statements can be wrapped in. `{1}`, `{2}` in the header
+/// and footer are replaced by the placeholders
+struct Construct {
+  name: &'static str,
+  header: &'static str,
+  footer: Option<&'static str>,
+  placeholders: &'static [&'static str],
+}
+
+const CONSTRUCTS: &[Construct] = &[
+  Construct { name: "if", header: "if {1}:", footer: None, placeholders: &["condition"] },
+  Construct { name: "for", header: "for {1} each {2}:", footer: None, placeholders: &["items", "item"] },
+  Construct { name: "while", header: "while {1}:", footer: None, placeholders: &["condition"] },
+  Construct { name: "try", header: "try:", footer: Some("catch {1}:"), placeholders: &["err"] },
+];
+
+// --| Surround With -----------------
+// --|--------------------------------
+/// Actions wrapping the lines of the selection in a block, one level deeper than
+/// the least indented of them. With `snippets` the placeholders become tab stops and
+/// the edit is sent as a snippet text edit, otherwise it is plain text.
+pub fn surround_actions(source: &str, uri: &Url, range: Range, indent: Indent, snippets: bool) -> Vec<CodeAction> {
+  if range.start == range.end { return vec![]; }
+
+  let lines: Vec<&str> = source.lines().collect();
+  let first = range.start.line as usize;
+  let mut last = range.end.line as usize;
+
+  // A selection ending at the start of a line does not include that line
+  if range.end.character == 0 && last > first { last -= 1; }
+  if first >= lines.len() { return vec![]; }
+  let last = last.min(lines.len() - 1);
+
+  let selected: Vec<&str> = lines[first..=last].iter().map(|line| line.strip_suffix('\r').unwrap_or(line)).collect();
+  let base = match selected.iter().filter(|line| !line.trim().is_empty()).min_by_key(|line| indent.measure(line)) {
+    Some(line) => leading(line).to_string(),
+    None => return vec![],
+  };
+
+  let unit = indent.unit();
+  let body: Vec<String> = selected.iter()
+    .map(|line| if line.trim().is_empty() { String::new() } else { format!("{}{}", unit, line) })
+    .map(|line| if snippets { snippet_edits::escape(&line) } else { line })
+    .collect();
+
+  let end = Position::new(last as u32, selected.last().map_or(0, |line| line.encode_utf16().count() as u32));
+  let replaced = Range::new(Position::new(first as u32, 0), end);
+
+  CONSTRUCTS.iter().map(|construct| {
+    let mut text = format!("{}{}\n{}", base, fill(construct.header, construct.placeholders, snippets), body.join("\n"));
+    if let Some(footer) = construct.footer {
+      let stop = if snippets { "$0" } else { "pass" };
+      text.push_str(&format!("\n{}{}\n{}{}{}", base, fill(footer, construct.placeholders, snippets), base, unit, stop));
+    }
+
+    let action = CodeAction {
+      title: format!("Surround with `{}`", construct.name),
+      kind: Some(surround_kind()),
+      edit: Some(WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), vec![TextEdit::new(replaced, text)])])),
+        ..Default::default()
+      }),
+      ..Default::default()
+    };
+    if snippets { snippet_edits::mark(action) } else { action }
+  }).collect()
+}
+
+fn leading(line: &str) -> &str {
+  &line[..line.len() - line.trim_start().len()]
+}
+
+/// Replace `{n}` with the n-th placeholder, as a `${n:text}` tab stop when snippets are supported
+fn fill(template: &str, placeholders: &[&str], snippets: bool) -> String {
+  let mut text = template.to_string();
+  for (index, placeholder) in placeholders.iter().enumerate() {
+    let value = if snippets { format!("${{{}:{}}}", index + 1, placeholder) } else { placeholder.to_string() };
+    text = text.replace(&format!("{{{}}}", index + 1), &value);
+  }
+  text
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::format::IndentStyle;
+
+  fn surround(source: &str, snippets: bool) -> Vec<CodeAction> {
+    let uri = Url::parse("file:///work/main.cy").unwrap();
+    let range = Range::new(Position::new(0, 0), Position::new(0, 9));
+    surround_actions(source, &uri, range, Indent::new(IndentStyle::Spaces, 2), snippets)
+  }
+
+  fn new_text(action: &CodeAction) -> &str {
+    let changes = action.edit.as_ref().and_then(|edit| edit.changes.as_ref()).unwrap();
+    &changes.values().next().unwrap()[0].new_text
+  }
+
+  #[test]
+  fn plain_edits_have_no_snippet_syntax() {
+    let actions = surround("print '$x'", false);
+    let try_action = actions.iter().find(|action| action.title == "Surround with `try`").unwrap();
+    assert_eq!(new_text(try_action), "try:\n  print '$x'\ncatch err:\n  pass");
+    assert!(actions.iter().all(|action| action.data.is_none()));
+  }
+
+  #[test]
+  fn snippet_edits_escape_the_selection() {
+    let actions = surround("print '$x'", true);
+    let if_action = actions.iter().find(|action| action.title == "Surround with `if`").unwrap();
+    assert_eq!(new_text(if_action), "if ${1:condition}:\n  print '\\$x'");
+    assert!(actions.iter().all(|action| action.data.is_some()));
+  }
+}
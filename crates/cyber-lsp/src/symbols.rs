@@ -0,0 +1,435 @@
+use lsp_types::{Documentation, MarkupContent, MarkupKind, Position, Range, SymbolKind};
+use serde::{Deserialize, Serialize};
+use tree_sitter::Node;
+
+use crate::core_types::DocRange;
+
+// --| Document Symbols --------------
+// --|--------------------------------
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Parameter {
+  pub name: String,
+  pub range: Range,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Symbol {
+  pub name: String,
+  pub kind: SymbolKind,
+
+  /// The range of the whole declaration.
+  pub range: Range,
+
+  /// The range of the declared name.
+  pub selection_range: Range,
+
+  /// Parameters, for functions and methods.
+  pub params: Vec<Parameter>,
+
+  /// The enclosing function or object, if not declared at the top level.
+  pub container: Option<String>,
+
+  /// Comment lines directly above the declaration, as Markdown.
+  pub doc: Option<String>,
+}
+
+impl Symbol {
+  pub fn is_function(&self) -> bool {
+    self.kind == SymbolKind::FUNCTION || self.kind == SymbolKind::METHOD
+  }
+
+  /// Signature label as shown in signature help, ie. `func add(a, b)`
+  pub fn signature(&self) -> String {
+    let params: Vec<&str> = self.params.iter().map(|param| param.name.as_str()).collect();
+    format!("func {}({})", self.name, params.join(", "))
+  }
+
+  /// Declaration as shown above its documentation, ie. `func add(a, b)` or `object Vec2`
+  pub fn label(&self) -> String {
+    match self.kind {
+      SymbolKind::FUNCTION | SymbolKind::METHOD => self.signature(),
+      SymbolKind::OBJECT => format!("object {}", self.name),
+      _ => self.name.clone(),
+    }
+  }
+
+  pub fn documentation(&self) -> Option<Documentation> {
+    self.doc.clone().map(markdown)
+  }
+
+  /// Whether a workspace symbol query matches the name or the documentation, ignoring case
+  pub fn matches(&self, query: &str) -> bool {
+    let query = query.to_lowercase();
+    self.name.to_lowercase().contains(&query) || self.doc.as_ref().map_or(false, |doc| doc.to_lowercase().contains(&query))
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CallSite {
+  /// The called function or method name.
+  pub callee: String,
+
+  /// The receiver for `receiver.callee()` calls.
+  pub receiver: Option<String>,
+
+  /// The range of the whole call expression.
+  pub range: Range,
+
+  /// The range of the argument list including its parentheses, if written.
+  pub args_range: Option<Range>,
+
+  /// The range of every argument.
+  pub arg_ranges: Vec<Range>,
+
+  /// The names of arguments passed as `name: value`.
+  pub named_args: Vec<Parameter>,
+}
+
+/// An identifier that uses, rather than declares, a name.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Reference {
+  pub name: String,
+  pub range: Range,
+
+  /// True for `object.name` properties and map keys, which are not looked up in scope.
+  pub member: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DocumentSymbols {
+  pub symbols: Vec<Symbol>,
+  pub calls: Vec<CallSite>,
+  pub references: Vec<Reference>,
+
+  /// Loop and catch variables, lambda parameters and object fields.
+  pub bindings: Vec<Parameter>,
+}
+
+impl DocumentSymbols {
+  /// Finds a top level function by name
+  pub fn function(&self, name: &str) -> Option<&Symbol> {
+    self.symbols.iter().find(|symbol| symbol.is_function() && symbol.container.is_none() && symbol.name == name)
+  }
+
+  /// Innermost call whose argument list contains the position
+  /// Number of references to a name within the document
+  pub fn reference_count(&self, name: &str) -> usize {
+    self.references.iter().filter(|reference| reference.name == name).count()
+  }
+
+  pub fn call_at(&self, position: Position) -> Option<&CallSite> {
+    self.calls.iter()
+      .filter(|call| call.args_range.map_or(false, |range| {
+        let after_start = (position.line, position.character) > (range.start.line, range.start.character);
+        let before_end = (position.line, position.character) < (range.end.line, range.end.character);
+        after_start && before_end
+      }))
+      .max_by_key(|call| {
+        let start = call.args_range.unwrap().start;
+        (start.line, start.character)
+      })
+  }
+}
+
+/// Collect declarations and call sites of a document
+pub fn collect_symbols(root: Node, source: &str) -> DocumentSymbols {
+  let mut symbols = DocumentSymbols::default();
+  collect(root, source, None, &mut symbols);
+
+  // Names of declarations, parameters and named arguments are visited as identifiers too
+  let declared: Vec<Range> = symbols.symbols.iter()
+    .flat_map(|symbol| std::iter::once(symbol.selection_range).chain(symbol.params.iter().map(|param| param.range)))
+    .chain(symbols.calls.iter().flat_map(|call| call.named_args.iter().map(|arg| arg.range)))
+    .collect();
+  symbols.references.retain(|reference| !declared.contains(&reference.range));
+
+  symbols
+}
+
+fn collect(node: Node, source: &str, container: Option<&str>, out: &mut DocumentSymbols) {
+  let mut cursor = node.walk();
+
+  for child in node.named_children(&mut cursor) {
+    match child.kind() {
+      "function_definition" => {
+        let name = match name_node(child) {
+          Some(name) => name,
+          None => { collect(child, source, container, out); continue; }
+        };
+
+        let name_text = text(name, source).to_string();
+        out.symbols.push(Symbol {
+          name: name_text.clone(),
+          kind: if container.is_some() { SymbolKind::METHOD } else { SymbolKind::FUNCTION },
+          range: node_range(child),
+          selection_range: node_range(name),
+          params: parameters(child, source),
+          container: container.map(str::to_string),
+          doc: doc_comment(child, source),
+        });
+
+        collect(child, source, Some(name_text.as_str()), out);
+      }
+
+      "object_definition" | "object_declaration" => {
+        let name = match name_node(child) {
+          Some(name) => name,
+          None => { collect(child, source, container, out); continue; }
+        };
+
+        let name_text = text(name, source).to_string();
+        out.symbols.push(Symbol {
+          name: name_text.clone(),
+          kind: SymbolKind::OBJECT,
+          range: node_range(child),
+          selection_range: node_range(name),
+          params: vec![],
+          container: container.map(str::to_string),
+          doc: doc_comment(child, source),
+        });
+
+        collect(child, source, Some(name_text.as_str()), out);
+      }
+
+      "assignment_statement" | "variable_def" | "variable_declaration" => {
+        let target = child.child_by_field_name("left")
+          .or_else(|| child.child_by_field_name("name"))
+          .or_else(|| child.named_child(0));
+
+        if let Some(target) = target.filter(|target| target.kind() == "identifier") {
+          let name = text(target, source);
+          let declared = out.symbols.iter().any(|symbol| {
+            symbol.name == name && symbol.container.as_deref() == container
+          });
+
+          if !declared {
+            out.symbols.push(Symbol {
+              name: name.to_string(),
+              kind: SymbolKind::VARIABLE,
+              range: node_range(child),
+              selection_range: node_range(target),
+              params: vec![],
+              container: container.map(str::to_string),
+              doc: doc_comment(child, source),
+            });
+          }
+        }
+
+        collect(child, source, container, out);
+      }
+
+      "import_statement" => {
+        let mut inner = child.walk();
+        let alias = child.named_children(&mut inner).find(|part| part.kind() == "identifier");
+
+        if let Some(alias) = alias {
+          out.symbols.push(Symbol {
+            name: text(alias, source).to_string(),
+            kind: SymbolKind::MODULE,
+            range: node_range(child),
+            selection_range: node_range(alias),
+            params: vec![],
+            container: container.map(str::to_string),
+            doc: None,
+          });
+        }
+      }
+
+      "call_expression" => {
+        if let Some(call) = call_site(child, source) { out.calls.push(call); }
+        collect(child, source, container, out);
+      }
+
+      "identifier" => {
+        let name = text(child, source).to_string();
+        if is_binding(node, child) {
+          out.bindings.push(Parameter { name, range: node_range(child) });
+        } else {
+          out.references.push(Reference { name, range: node_range(child), member: is_member(node, child) });
+        }
+      }
+
+      _ => collect(child, source, container, out),
+    }
+  }
+}
+
+fn call_site(node: Node, source: &str) -> Option<CallSite> {
+  let callee = node.child_by_field_name("function").or_else(|| node.named_child(0))?;
+
+  let (receiver, name) = match callee.kind() {
+    "identifier" => (None, text(callee, source).to_string()),
+    _ => {
+      let property = callee.child_by_field_name("property").or_else(|| callee.named_child(1))?;
+      let object = callee.child_by_field_name("object").or_else(|| callee.named_child(0))?;
+      (Some(text(object, source).to_string()), text(property, source).to_string())
+    }
+  };
+
+  let args = node.child_by_field_name("arguments").or_else(|| {
+    let mut cursor = node.walk();
+    let found = node.named_children(&mut cursor).find(|child| matches!(child.kind(), "argument_list" | "arguments"));
+    found
+  });
+
+  let arg_nodes = args.map(|args| {
+    let mut cursor = args.walk();
+    let nodes: Vec<Node> = args.named_children(&mut cursor).filter(|arg| arg.kind() != "comment").collect();
+    nodes
+  }).unwrap_or_default();
+
+  let named_args = arg_nodes.iter().filter_map(|arg| {
+    let name = match arg.kind() {
+      "named_argument" | "keyword_argument" => arg.child_by_field_name("name").or_else(|| arg.named_child(0))?,
+      _ => arg.child_by_field_name("name")?,
+    };
+    (name.kind() == "identifier").then(|| Parameter { name: text(name, source).to_string(), range: node_range(name) })
+  }).collect();
+
+  Some(CallSite {
+    callee: name,
+    receiver,
+    range: node_range(node),
+    args_range: args.map(node_range),
+    arg_ranges: arg_nodes.into_iter().map(node_range).collect(),
+    named_args,
+  })
+}
+
+fn parameters(node: Node, source: &str) -> Vec<Parameter> {
+  let list = node.child_by_field_name("parameters").or_else(|| {
+    let mut cursor = node.walk();
+    let found = node.named_children(&mut cursor).find(|child| matches!(child.kind(), "parameter_list" | "parameters"));
+    found
+  });
+
+  let list = match list { Some(list) => list, None => return vec![] };
+  let mut cursor = list.walk();
+
+  let params = list.named_children(&mut cursor).filter_map(|param| {
+    let name = match param.kind() {
+      "identifier" => param,
+      "comment" => return None,
+      _ => name_node(param)?,
+    };
+    Some(Parameter { name: text(name, source).to_string(), range: node_range(name) })
+  }).collect();
+
+  params
+}
+
+/// Identifiers introduced by `each`/`catch` clauses, parameter lists and object bodies
+fn is_binding(parent: Node, node: Node) -> bool {
+  let kind = parent.kind();
+  if matches!(kind, "parameter_list" | "parameters" | "parameter") || kind.contains("field") {
+    return true;
+  }
+
+  if kind.starts_with("object") && parent.child_by_field_name("name") != Some(node) {
+    return true;
+  }
+
+  let mut previous = node.prev_sibling();
+  while let Some(sibling) = previous {
+    if matches!(sibling.kind(), "each" | "catch" | "as") { return true; }
+    if sibling.is_named() && sibling.kind() != "identifier" { break; }
+    previous = sibling.prev_sibling();
+  }
+
+  false
+}
+
+fn is_member(parent: Node, node: Node) -> bool {
+  ["property", "field", "key"].iter().any(|field| parent.child_by_field_name(field) == Some(node))
+}
+
+// --| Node Helpers ------------------
+// --|--------------------------------
+/// The name of a declaration, by field or as its first identifier
+pub fn name_node(node: Node) -> Option<Node> {
+  node.child_by_field_name("name").or_else(|| {
+    let mut cursor = node.walk();
+    let found = node.named_children(&mut cursor).find(|child| child.kind() == "identifier");
+    found
+  })
+}
+
+/// Documentation rendered as Markdown, doc comments are passed through as written
+pub fn markdown(value: String) -> Documentation {
+  Documentation::MarkupContent(MarkupContent { kind: MarkupKind::Markdown, value })
+}
+
+/// Contiguous `--` comment lines directly above a declaration, without their markers.
+/// Trailing comments of the line above and comments separated by a blank line don't count.
+pub fn doc_comment(node: Node, source: &str) -> Option<String> {
+  // Comments are siblings of the outermost node starting with the declaration
+  let mut node = node;
+  while let Some(parent) = node.parent().filter(|parent| parent.parent().is_some() && parent.start_byte() == node.start_byte()) {
+    node = parent;
+  }
+
+  let mut lines = vec![];
+  let mut row = node.start_position().row;
+  let mut previous = node.prev_sibling();
+
+  while let Some(comment) = previous.filter(|sibling| sibling.kind() == "comment") {
+    let own_line = source[..comment.start_byte()].rsplit('\n').next().map_or(true, |before| before.trim().is_empty());
+    if !own_line || comment.start_position().row + 1 != row { break; }
+
+    let line = text(comment, source).trim_end();
+    let line = line.strip_prefix("--").unwrap_or(line);
+    lines.push(line.strip_prefix(' ').unwrap_or(line).to_string());
+
+    row = comment.start_position().row;
+    previous = comment.prev_sibling();
+  }
+
+  if lines.iter().all(|line| line.trim().is_empty()) { return None; }
+  lines.reverse();
+  Some(lines.join("\n"))
+}
+
+/// Range of a node with byte columns, see `DocPoint::from_byte_position`
+pub fn node_range(node: Node) -> Range {
+  DocRange::of_node(&node).byte_range()
+}
+
+fn text<'s>(node: Node, source: &'s str) -> &'s str {
+  node.utf8_text(source.as_bytes()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn docs(source: &str) -> Vec<(String, Option<String>)> {
+    let tree = cyber_tree_sitter::init_parser().parse(source, None).unwrap();
+    collect_symbols(tree.root_node(), source).symbols.into_iter().map(|symbol| (symbol.name, symbol.doc)).collect()
+  }
+
+  fn doc_of(source: &str, name: &str) -> Option<String> {
+    docs(source).into_iter().find(|(symbol, _)| symbol == name).and_then(|(_, doc)| doc)
+  }
+
+  #[test]
+  fn doc_comments_join_the_lines_above_a_declaration() {
+    let source = "-- Area of a circle\n--   with the given radius\nfunc area(radius):\n  return radius\n";
+    assert_eq!(doc_of(source, "area").as_deref(), Some("Area of a circle\n  with the given radius"));
+  }
+
+  #[test]
+  fn doc_comments_stop_at_blank_lines_and_code() {
+    let source = "-- License header\n\nfunc a():\n  pass\nvar x = 1 -- trailing\nfunc b():\n  pass\n--\nfunc c():\n  pass\n";
+    assert_eq!(doc_of(source, "a"), None);
+    assert_eq!(doc_of(source, "b"), None);
+    assert_eq!(doc_of(source, "c"), None);
+  }
+
+  #[test]
+  fn doc_comments_of_variables_objects_and_methods() {
+    let source = "-- The limit\nvar max = 10\n\n-- A square\nobject Square:\n  side\n\n  -- Sum of the sides\n  func perimeter(self):\n    return self.side * 4\n";
+    assert_eq!(doc_of(source, "max").as_deref(), Some("The limit"));
+    assert_eq!(doc_of(source, "Square").as_deref(), Some("A square"));
+    assert_eq!(doc_of(source, "perimeter").as_deref(), Some("Sum of the sides"));
+  }
+}
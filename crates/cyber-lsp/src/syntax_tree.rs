@@ -0,0 +1,133 @@
+use lsp_types::{Position, Range, TextDocumentIdentifier};
+use serde::{Deserialize, Serialize};
+use tree_sitter::{Node, Point};
+
+use crate::symbols::node_range;
+use crate::core_types::DocPoint;
+use crate::utils::treehelper::NodeWrapper;
+
+pub const SYNTAX_TREE_METHOD: &str = "cyberls/syntaxTree";
+
+// --| Request Types -----------------
+// --|--------------------------------
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyntaxTreeFormat {
+  /// Indented text as written to the log by `TreeWrapper`
+  #[default]
+  Text,
+  Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyntaxTreeParams {
+  pub text_document: TextDocumentIdentifier,
+  /// Only the smallest node covering this range is returned. Whole document when omitted
+  pub range: Option<Range>,
+  #[serde(default)]
+  pub format: SyntaxTreeFormat,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyntaxTreeResult {
+  /// Set for the `text` format
+  pub text: Option<String>,
+  /// Set for the `json` format
+  pub tree: Option<SyntaxNode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyntaxNode {
+  pub kind: String,
+  /// The field name this node has within its parent
+  pub field: Option<String>,
+  pub range: Range,
+  pub is_error: bool,
+  /// Source text, for leaf nodes
+  pub text: Option<String>,
+  pub children: Vec<SyntaxNode>,
+}
+
+// --| Tree Export -------------------
+// --|--------------------------------
+/// Render the tree, or the node covering `range`, in the requested format
+pub fn syntax_tree(root: Node, source: &str, range: Option<Range>, format: SyntaxTreeFormat) -> SyntaxTreeResult {
+  let node = range
+    .and_then(|range| root.named_descendant_for_point_range(DocPoint::from_byte_position(range.start).into(), DocPoint::from_byte_position(range.end).into()))
+    .unwrap_or(root);
+
+  match format {
+    SyntaxTreeFormat::Text => SyntaxTreeResult { text: Some(NodeWrapper(node).to_string()), tree: None },
+    SyntaxTreeFormat::Json => SyntaxTreeResult { text: None, tree: Some(to_syntax_node(node, None, source)) },
+  }
+}
+
+// --| Developer Hover ---------------
+// --|--------------------------------
+/// Node kind, byte range and scope chain at `position`, appended to hovers in developer mode
+pub fn developer_details(root: Node, source: &str, position: Position) -> Option<String> {
+  let point: Point = DocPoint::from_byte_position(position).into();
+  let node = root.descendant_for_point_range(point, point)?;
+
+  let mut chain = vec![];
+  let mut current = Some(node);
+  while let Some(scope) = current {
+    let name = scope.child_by_field_name("name")
+      .filter(|name| name.id() != node.id())
+      .and_then(|name| name.utf8_text(source.as_bytes()).ok());
+    chain.push(match name {
+      Some(name) => format!("`{}` {}", scope.kind(), name),
+      None => format!("`{}`", scope.kind()),
+    });
+    current = scope.parent();
+  }
+  chain.reverse();
+
+  let field = node.parent().and_then(|parent| {
+    let mut cursor = parent.walk();
+    if !cursor.goto_first_child() { return None; }
+    while cursor.node().id() != node.id() {
+      if !cursor.goto_next_sibling() { return None; }
+    }
+    cursor.field_name().map(str::to_string)
+  });
+
+  let mut details = format!("**Node** `{}`", node.kind());
+  if let Some(field) = field { details.push_str(&format!(" (field `{}`)", field)); }
+  if node.is_error() || node.is_missing() { details.push_str(" (error)"); }
+
+  details.push_str(&format!("\n\n**Bytes** {}..{}", node.start_byte(), node.end_byte()));
+  details.push_str(&format!("\n\n**Scope** {}", chain.join(" › ")));
+  Some(details)
+}
+
+fn to_syntax_node(node: Node, field: Option<&str>, source: &str) -> SyntaxNode {
+  let mut children = vec![];
+  let mut cursor = node.walk();
+
+  if cursor.goto_first_child() {
+    loop {
+      let child = cursor.node();
+      if child.is_named() { children.push(to_syntax_node(child, cursor.field_name(), source)); }
+      if !cursor.goto_next_sibling() { break; }
+    }
+  }
+
+  let text = if node.child_count() == 0 {
+    node.utf8_text(source.as_bytes()).ok().map(str::to_string)
+  } else {
+    None
+  };
+
+  SyntaxNode {
+    kind: node.kind().to_string(),
+    field: field.map(str::to_string),
+    range: node_range(node),
+    is_error: node.is_error() || node.is_missing(),
+    text,
+    children,
+  }
+}
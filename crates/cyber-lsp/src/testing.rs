@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use lsp_types::notification::{self, Notification};
+use lsp_types::request::{self, Request};
+use lsp_types::*;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream, ReadHalf, WriteHalf};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tower_lsp::jsonrpc;
+
+use crate::diagnostics::pipeline::SEMANTIC_DEBOUNCE;
+use crate::utils::paths;
+use crate::ServerOptions;
+
+/// How long a request may take before the test fails
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Size of the in-memory pipes between the client and the server
+const PIPE_CAPACITY: usize = 1 << 16;
+
+// --| Fixtures ----------------------
+// --|--------------------------------
+/// Folder of the `.cy` fixture corpus shipped with the crate
+pub fn fixtures_dir() -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures")
+}
+
+/// Source of a fixture, ie. `fixture("syntax_error.cy")`
+pub fn fixture(name: &str) -> String {
+  let path = fixtures_dir().join(name);
+  std::fs::read_to_string(&path).unwrap_or_else(|err| panic!("Missing fixture {}: {}", path.display(), err))
+}
+
+/// Document URI of a fixture
+pub fn fixture_uri(name: &str) -> Url {
+  paths::path_to_uri(&fixtures_dir().join(name)).expect("fixture path has a file uri")
+}
+
+// --| Scripted Client ---------------
+// --|--------------------------------
+/// How the in-memory client answers the requests the server sends it
+#[derive(Debug, Clone, Default)]
+pub struct ClientScript {
+  pub capabilities: ClientCapabilities,
+  pub workspace_folders: Vec<WorkspaceFolder>,
+  pub initialization_options: Option<Value>,
+  /// Settings answered to every `workspace/configuration` item
+  pub configuration: Value,
+  /// Whether `workspace/applyEdit` requests are applied
+  pub apply_edits: bool,
+  /// Action picked in `window/showMessageRequest`, the request is dismissed when unset
+  pub message_action: Option<String>,
+}
+
+impl ClientScript {
+  /// A client with a single workspace folder
+  pub fn with_workspace(mut self, folder: &std::path::Path) -> Self {
+    let uri = paths::dir_to_uri(folder).expect("workspace folder has a file uri");
+    let name = folder.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    self.workspace_folders = vec![WorkspaceFolder { uri, name }];
+    self
+  }
+}
+
+// --| Test Server -------------------
+// --|--------------------------------
+/// A server running in-process, talking JSON-RPC to a scripted client over in-memory
+/// pipes. Requests answer through `request`, diagnostics the server publishes are kept
+/// per document and server requests are answered from the `ClientScript`.
+pub struct TestServer {
+  writer: WriteHalf<DuplexStream>,
+  messages: mpsc::UnboundedReceiver<Value>,
+  server: JoinHandle<()>,
+  script: ClientScript,
+  next_id: i64,
+  versions: HashMap<Url, i32>,
+  /// Last diagnostics published for each document
+  pub diagnostics: HashMap<Url, Vec<Diagnostic>>,
+  /// Every notification received, in order
+  pub notifications: Vec<Value>,
+  /// Edits of `workspace/applyEdit` requests, applied or not
+  pub applied_edits: Vec<WorkspaceEdit>,
+}
+
+impl TestServer {
+  /// Start a server and run the `initialize` handshake
+  pub async fn start(options: ServerOptions, script: ClientScript) -> (Self, InitializeResult) {
+    let (client_end, server_end) = tokio::io::duplex(PIPE_CAPACITY);
+    let (server_read, server_write) = tokio::io::split(server_end);
+    let server = tokio::spawn(crate::serve(server_read, server_write, options));
+
+    let (reader, writer) = tokio::io::split(client_end);
+    let (sender, messages) = mpsc::unbounded_channel();
+    tokio::spawn(read_messages(reader, sender));
+
+    let mut test = TestServer {
+      writer, messages, server, script, next_id: 0, versions: HashMap::new(),
+      diagnostics: HashMap::new(), notifications: vec![], applied_edits: vec![],
+    };
+
+    #[allow(deprecated)]
+    let params = InitializeParams {
+      capabilities: test.script.capabilities.clone(),
+      workspace_folders: Some(test.script.workspace_folders.clone()).filter(|folders| !folders.is_empty()),
+      initialization_options: test.script.initialization_options.clone(),
+      ..Default::default()
+    };
+
+    let result = test.request::<request::Initialize>(params).await.expect("initialize failed");
+    test.notify::<notification::Initialized>(InitializedParams {}).await;
+    (test, result)
+  }
+
+  /// Send a request and wait for its response, answering the server's requests meanwhile
+  pub async fn request<R: Request>(&mut self, params: R::Params) -> Result<R::Result, jsonrpc::Error> {
+    self.next_id += 1;
+    let id = self.next_id;
+    self.send(json!({ "jsonrpc": "2.0", "id": id, "method": R::METHOD, "params": params })).await;
+
+    loop {
+      let message = self.receive(REQUEST_TIMEOUT).await
+        .unwrap_or_else(|| panic!("No response to {} within {:?}", R::METHOD, REQUEST_TIMEOUT));
+      if message.get("method").is_some() || message.get("id") != Some(&json!(id)) {
+        self.handle(message).await;
+        continue;
+      }
+
+      if let Some(error) = message.get("error") {
+        return Err(serde_json::from_value(error.clone()).expect("malformed error response"));
+      }
+      let result = message.get("result").cloned().unwrap_or(Value::Null);
+      return Ok(serde_json::from_value(result).expect("malformed response"));
+    }
+  }
+
+  pub async fn notify<N: Notification>(&mut self, params: N::Params) {
+    self.send(json!({ "jsonrpc": "2.0", "method": N::METHOD, "params": params })).await;
+  }
+
+  /// Open a document with the given text
+  pub async fn open(&mut self, uri: &Url, text: &str) {
+    self.versions.insert(uri.clone(), 1);
+    let text_document = TextDocumentItem::new(uri.clone(), "cyber".to_string(), 1, text.to_string());
+    self.notify::<notification::DidOpenTextDocument>(DidOpenTextDocumentParams { text_document }).await;
+  }
+
+  /// Replace the whole text of an open document
+  pub async fn change(&mut self, uri: &Url, text: &str) {
+    let change = TextDocumentContentChangeEvent { range: None, range_length: None, text: text.to_string() };
+    self.edit(uri, vec![change]).await;
+  }
+
+  /// Apply incremental changes to an open document
+  pub async fn edit(&mut self, uri: &Url, content_changes: Vec<TextDocumentContentChangeEvent>) {
+    let version = self.versions.entry(uri.clone()).or_insert(0);
+    *version += 1;
+    let text_document = VersionedTextDocumentIdentifier::new(uri.clone(), *version);
+    self.notify::<notification::DidChangeTextDocument>(DidChangeTextDocumentParams { text_document, content_changes }).await;
+  }
+
+  pub async fn save(&mut self, uri: &Url) {
+    let text_document = TextDocumentIdentifier::new(uri.clone());
+    self.notify::<notification::DidSaveTextDocument>(DidSaveTextDocumentParams { text_document, text: None }).await;
+  }
+
+  pub async fn close(&mut self, uri: &Url) {
+    self.versions.remove(uri);
+    let text_document = TextDocumentIdentifier::new(uri.clone());
+    self.notify::<notification::DidCloseTextDocument>(DidCloseTextDocumentParams { text_document }).await;
+  }
+
+  /// Handle server messages until it stays quiet past the semantic debounce, so the
+  /// diagnostics of every tier have been published
+  pub async fn settle(&mut self) {
+    let quiet = SEMANTIC_DEBOUNCE * 2;
+    while let Some(message) = self.receive(quiet).await {
+      self.handle(message).await;
+    }
+  }
+
+  /// Settled diagnostics of a document
+  pub async fn diagnostics_of(&mut self, uri: &Url) -> Vec<Diagnostic> {
+    self.settle().await;
+    self.diagnostics.get(uri).cloned().unwrap_or_default()
+  }
+
+  /// Run the `shutdown` and `exit` sequence and wait for the server to stop
+  pub async fn shutdown(mut self) {
+    self.request::<request::Shutdown>(()).await.expect("shutdown failed");
+    self.notify::<notification::Exit>(()).await;
+    let _ = tokio::time::timeout(REQUEST_TIMEOUT, self.server).await;
+  }
+
+  async fn send(&mut self, message: Value) {
+    let body = message.to_string();
+    let frame = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+    self.writer.write_all(frame.as_bytes()).await.expect("server pipe closed");
+  }
+
+  async fn receive(&mut self, timeout: Duration) -> Option<Value> {
+    tokio::time::timeout(timeout, self.messages.recv()).await.ok().flatten()
+  }
+
+  /// Record a notification or answer a request of the server
+  async fn handle(&mut self, message: Value) {
+    let method = message.get("method").and_then(Value::as_str).unwrap_or_default().to_string();
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+    let id = match message.get("id") {
+      Some(id) => id.clone(),
+      None => {
+        if method == notification::PublishDiagnostics::METHOD {
+          if let Ok(published) = serde_json::from_value::<PublishDiagnosticsParams>(params) {
+            self.diagnostics.insert(published.uri, published.diagnostics);
+          }
+        }
+        self.notifications.push(message);
+        return;
+      }
+    };
+
+    let result = match method.as_str() {
+      request::WorkspaceConfiguration::METHOD => {
+        let items = params.get("items").and_then(Value::as_array).map_or(0, Vec::len);
+        Value::Array(vec![self.script.configuration.clone(); items])
+      }
+      request::ApplyWorkspaceEdit::METHOD => {
+        if let Some(edit) = params.get("edit").and_then(|edit| serde_json::from_value(edit.clone()).ok()) {
+          self.applied_edits.push(edit);
+        }
+        json!({ "applied": self.script.apply_edits })
+      }
+      request::ShowMessageRequest::METHOD => {
+        let offered = params.get("actions").and_then(Value::as_array).cloned().unwrap_or_default();
+        self.script.message_action.as_ref()
+          .and_then(|title| offered.into_iter().find(|action| action.get("title") == Some(&json!(title))))
+          .unwrap_or(Value::Null)
+      }
+      // Registrations, progress tokens and refreshes only need an answer
+      _ => Value::Null,
+    };
+
+    self.send(json!({ "jsonrpc": "2.0", "id": id, "result": result })).await;
+  }
+}
+
+/// Parse `Content-Length` framed messages from the server until the pipe closes
+async fn read_messages(reader: ReadHalf<DuplexStream>, sender: mpsc::UnboundedSender<Value>) {
+  let mut reader = BufReader::new(reader);
+
+  loop {
+    let mut length = None;
+    loop {
+      let mut line = String::new();
+      match reader.read_line(&mut line).await {
+        Ok(0) | Err(_) => return,
+        Ok(_) => {}
+      }
+
+      let line = line.trim_end();
+      if line.is_empty() { break; }
+      if let Some(value) = line.strip_prefix("Content-Length:") {
+        length = value.trim().parse::<usize>().ok();
+      }
+    }
+
+    let mut body = vec![0; match length { Some(length) => length, None => continue }];
+    if reader.read_exact(&mut body).await.is_err() { return; }
+    if let Ok(message) = serde_json::from_slice(&body) {
+      if sender.send(message).is_err() { return; }
+    }
+  }
+}
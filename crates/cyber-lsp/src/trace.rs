@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+
+use lsp_types::{Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString, Position, Range, Url};
+
+use crate::utils::paths;
+
+/// Diagnostic source of errors raised while running a script
+pub const RUNTIME_SOURCE: &str = "cyber-runtime";
+
+/// Diagnostic code of a panic raised while running a script
+pub const PANIC_CODE: &str = "runtime_panic";
+
+// --| Stack Traces ------------------
+// --|--------------------------------
+/// A `path:line:col function:` frame of a trace, with 1-based line and column
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceFrame {
+  pub path: PathBuf,
+  pub line: u32,
+  pub column: u32,
+  pub function: Option<String>,
+}
+
+/// A panic message with its frames, innermost first
+#[derive(Clone, Debug)]
+pub struct Trace {
+  pub message: String,
+  pub frames: Vec<TraceFrame>,
+}
+
+/// Parse the stderr of a run. Relative frame paths, and the `main` pseudo path
+/// older releases print, are resolved against the script.
+pub fn parse_trace(lines: &[String], script: &Path) -> Option<Trace> {
+  let message = lines.iter()
+    .map(|line| line.trim())
+    .find(|line| line.starts_with("panic:") || line.starts_with("error:"))?
+    .to_string();
+
+  let frames: Vec<TraceFrame> = lines.iter().filter_map(|line| parse_frame(line, script)).collect();
+  if frames.is_empty() { return None; }
+
+  Some(Trace { message, frames })
+}
+
+fn parse_frame(line: &str, script: &Path) -> Option<TraceFrame> {
+  let line = line.trim_end();
+  let (location, function) = match line.split_once(' ') {
+    Some((location, function)) => (location, Some(function.trim_end_matches(':').trim().to_string())),
+    None => (line.trim_end_matches(':'), None),
+  };
+
+  let mut parts = location.rsplitn(3, ':');
+  let column = parts.next()?.parse::<u32>().ok()?;
+  let row = parts.next()?.parse::<u32>().ok()?;
+  let path = parts.next().filter(|path| !path.is_empty())?;
+
+  let path = if path == "main" {
+    script.to_path_buf()
+  } else if Path::new(path).is_relative() {
+    script.parent().map_or_else(|| PathBuf::from(path), |dir| dir.join(path))
+  } else {
+    PathBuf::from(path)
+  };
+
+  Some(TraceFrame { path, line: row, column, function: function.filter(|function| !function.is_empty()) })
+}
+
+// --| Trace Diagnostics -------------
+// --|--------------------------------
+/// An error at the failing frame, with the remaining frames attached as related
+/// information and marked in their own files. Frames in `script` map to `uri`,
+/// shifted by `line_offset` for runs of a selection.
+pub fn trace_diagnostics(trace: &Trace, script: &Path, uri: &Url, line_offset: u32) -> Vec<(Url, Diagnostic)> {
+  let locate = |frame: &TraceFrame| -> Option<Location> {
+    let (target, offset) = if frame.path == script { (uri.clone(), line_offset) } else { (paths::path_to_uri(&frame.path)?, 0) };
+    let start = Position::new(frame.line.saturating_sub(1) + offset, frame.column.saturating_sub(1));
+    Some(Location::new(target, Range::new(start, Position::new(start.line + 1, 0))))
+  };
+
+  let located: Vec<(&TraceFrame, Location)> = trace.frames.iter()
+    .filter_map(|frame| locate(frame).map(|location| (frame, location)))
+    .collect();
+
+  let ((_, failing), callers) = match located.split_first() {
+    Some(split) => split,
+    None => return vec![],
+  };
+
+  let related: Vec<DiagnosticRelatedInformation> = callers.iter()
+    .map(|(frame, location)| DiagnosticRelatedInformation {
+      location: location.clone(),
+      message: format!("called from {}", frame.function.as_deref().unwrap_or("here")),
+    })
+    .collect();
+
+  let mut diagnostics = vec![(failing.uri.clone(), runtime_diagnostic(failing.range, DiagnosticSeverity::ERROR, trace.message.clone(), Some(related)))];
+
+  for (frame, location) in callers.iter().filter(|(_, location)| location.uri != failing.uri) {
+    let message = format!("{} raised {}", frame.function.as_deref().unwrap_or("call"), trace.message);
+    diagnostics.push((location.uri.clone(), runtime_diagnostic(location.range, DiagnosticSeverity::INFORMATION, message, None)));
+  }
+
+  diagnostics
+}
+
+fn runtime_diagnostic(range: Range, severity: DiagnosticSeverity, message: String, related: Option<Vec<DiagnosticRelatedInformation>>) -> Diagnostic {
+  Diagnostic {
+    range,
+    severity: Some(severity),
+    code: Some(NumberOrString::String(PANIC_CODE.to_string())),
+    code_description: None,
+    source: Some(RUNTIME_SOURCE.to_string()),
+    message,
+    related_information: related.filter(|related| !related.is_empty()),
+    tags: None,
+    data: None,
+  }
+}
+
+/// Whether a diagnostic came from a run rather than a check
+pub fn is_runtime(diagnostic: &Diagnostic) -> bool {
+  diagnostic.source.as_deref() == Some(RUNTIME_SOURCE)
+}
@@ -0,0 +1,46 @@
+use std::collections::HashSet;
+
+use lsp_types::DiagnosticSeverity;
+
+use crate::diagnostics::ErrorInfo;
+use crate::symbols::DocumentSymbols;
+use crate::core_types::DocPoint;
+
+/// Diagnostic code of identifiers that are never declared
+pub const UNDEFINED_CODE: &str = "undefined_identifier";
+
+/// Names available in every script without a declaration or import
+pub const BUILTIN_GLOBALS: &[&str] = &[
+  "arrayFill", "asciiCode", "bool", "char", "copy", "error", "evalJS", "exit", "fetchUrl", "getInput",
+  "int", "isAlpha", "isDigit", "listFill", "must", "number", "opaque", "panic", "parseCyber", "parseCyon",
+  "performGC", "print", "prints", "rawstring", "readAll", "readFile", "readLine", "runestr", "string",
+  "toCyon", "typeid", "typeof", "valtag", "writeFile", "self",
+];
+
+// --| Undefined Identifiers ---------
+// --|--------------------------------
+/// Warn about identifiers with no declaration anywhere in the document. The check
+/// is flow-insensitive: a declaration anywhere in the file counts.
+pub fn check_undefined(symbols: &DocumentSymbols) -> Option<ErrorInfo> {
+  let mut error_info = ErrorInfo::new();
+
+  let declared: HashSet<&str> = symbols.symbols.iter()
+    .flat_map(|symbol| std::iter::once(symbol.name.as_str()).chain(symbol.params.iter().map(|param| param.name.as_str())))
+    .chain(symbols.bindings.iter().map(|binding| binding.name.as_str()))
+    .chain(BUILTIN_GLOBALS.iter().copied())
+    .collect();
+
+  for reference in symbols.references.iter().filter(|reference| !reference.member) {
+    if declared.contains(reference.name.as_str()) { continue; }
+
+    error_info.add_coded(
+      DocPoint::from_byte_position(reference.range.start),
+      DocPoint::from_byte_position(reference.range.end),
+      format!("Undefined identifier `{}`", reference.name),
+      Some(DiagnosticSeverity::WARNING),
+      UNDEFINED_CODE,
+    );
+  }
+
+  if error_info.entries.is_empty() { None } else { Some(error_info) }
+}
@@ -1,9 +1,11 @@
-use std::process::Command as cmd;
 use serde::{Deserialize, Serialize};
 
 mod doc_loader;
 pub use doc_loader::*;
+pub mod doc_schema;
 pub mod treehelper;
+pub mod paths;
+pub mod platform;
 
 #[derive(Deserialize, Debug, Serialize, Clone)]
 pub enum FileType {
@@ -19,21 +21,3 @@ impl std::fmt::Display for FileType {
         }
     }
 }
-
-// --| Notify Send ----------------
-pub enum Type { Error, Warning, Info, }
-
-impl ToString for Type {
-    fn to_string(&self) -> String {
-        match self {
-            Type::Warning => "Warning".to_string(),
-            Type::Error => "Error".to_string(),
-            Type::Info => "Info".to_string(),
-        }
-    }
-}
-
-pub(crate) fn notify_send(input: &str, typeinput: Type) {
-    cmd::new("notify-send").arg(typeinput.to_string()).arg(input).spawn().expect("Error");
-}
-
@@ -0,0 +1,253 @@
+// --| Language Doc Schema -----------
+// --|--------------------------------
+// Validation of the language definition files in `lang_docs`, following the schema
+// published as `lang_docs/schema.json`. Shared with `build.rs`, which refuses to embed
+// invalid files, so it only depends on serde and the standard library.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Extensions of language definition files, YAML being an alternative authoring format
+pub const EXTENSIONS: &[&str] = &["json", "yaml", "yml"];
+
+const DEFINITION_FIELDS: &[&str] = &["lsp_action", "type_categories"];
+const CATEGORY_FIELDS: &[&str] = &["category", "keywords", "keyword_details"];
+const DETAIL_FIELDS: &[&str] = &["keyword", "syntax", "type", "node_type", "description", "example"];
+const OPTIONAL_DETAIL_FIELDS: &[&str] = &["deprecated", "replacement"];
+
+/// A problem in a definition file, located by a JSON pointer into the document
+#[derive(Debug, Clone, Serialize)]
+pub struct DocError {
+  pub file: String,
+  /// Empty when the file as a whole is at fault, ie. it fails to parse
+  pub pointer: String,
+  pub message: String,
+}
+
+impl fmt::Display for DocError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.pointer.is_empty() {
+      write!(f, "{}: {}", self.file, self.message)
+    } else {
+      write!(f, "{}#{}: {}", self.file, self.pointer, self.message)
+    }
+  }
+}
+
+/// `<dir>/*/*.{json,yaml,yml}`, sorted
+pub fn doc_files(dir: &Path) -> Vec<PathBuf> {
+  let mut files: Vec<PathBuf> = std::fs::read_dir(dir).into_iter().flatten().flatten()
+    .map(|folder| folder.path())
+    .filter(|folder| folder.is_dir())
+    .flat_map(|folder| std::fs::read_dir(folder).into_iter().flatten().flatten())
+    .map(|file| file.path())
+    .filter(|file| is_doc_file(file))
+    .collect();
+
+  files.sort();
+  files
+}
+
+pub fn is_doc_file(path: &Path) -> bool {
+  path.extension().map_or(false, |extension| EXTENSIONS.iter().any(|known| extension == *known))
+}
+
+/// Read and validate definition files. Returns the valid definitions as JSON, without
+/// their `$schema` key, and every error found, including an `lsp_action` defined twice.
+pub fn load_all(files: &[PathBuf]) -> (Vec<(PathBuf, Value)>, Vec<DocError>) {
+  let mut definitions = vec![];
+  let mut errors = vec![];
+  let mut actions: HashMap<String, String> = HashMap::new();
+
+  for path in files {
+    let mut definition = match load(path) {
+      Ok(definition) => definition,
+      Err(found) => { errors.extend(found); continue; }
+    };
+
+    let file = display(path);
+    if let Some(action) = definition.get("lsp_action").and_then(Value::as_str) {
+      if let Some(other) = actions.insert(action.to_string(), file.clone()) {
+        errors.push(DocError::new(&file, "/lsp_action", format!("`{}` is already defined by {}", action, other)));
+        continue;
+      }
+    }
+
+    if let Some(definition) = definition.as_object_mut() { definition.remove("$schema"); }
+    definitions.push((path.clone(), definition));
+  }
+
+  (definitions, errors)
+}
+
+/// Read, parse and validate a single definition file
+pub fn load(path: &Path) -> Result<Value, Vec<DocError>> {
+  let file = display(path);
+  let text = std::fs::read_to_string(path).map_err(|err| vec![DocError::new(&file, "", err.to_string())])?;
+
+  let yaml = path.extension().map_or(false, |extension| extension == "yaml" || extension == "yml");
+  let parsed = if yaml {
+    serde_yaml::from_str::<Value>(&text).map_err(|err| err.to_string())
+  } else {
+    serde_json::from_str::<Value>(&text).map_err(|err| err.to_string())
+  };
+  let definition = parsed.map_err(|message| vec![DocError::new(&file, "", message)])?;
+
+  let errors = validate(&file, &definition);
+  if errors.is_empty() { Ok(definition) } else { Err(errors) }
+}
+
+/// Check a parsed definition against the schema, plus the rules it can't express:
+/// documented keywords must be listed in their category and documented once
+pub fn validate(file: &str, definition: &Value) -> Vec<DocError> {
+  let mut check = Checker { file, errors: vec![] };
+
+  let fields = match check.object("", definition, DEFINITION_FIELDS, &["$schema"]) {
+    Some(fields) => fields,
+    None => return check.errors,
+  };
+
+  check.string("/lsp_action", fields.get("lsp_action"), true);
+  let mut documented = HashSet::new();
+  for (i, category) in check.array("/type_categories", fields.get("type_categories")).iter().enumerate() {
+    check.category(&format!("/type_categories/{}", i), category, &mut documented);
+  }
+
+  check.errors
+}
+
+impl DocError {
+  fn new(file: &str, pointer: &str, message: impl Into<String>) -> Self {
+    DocError { file: file.to_string(), pointer: pointer.to_string(), message: message.into() }
+  }
+}
+
+fn display(path: &Path) -> String {
+  path.display().to_string().replace('\\', "/")
+}
+
+// --| Checker -----------------------
+// --|--------------------------------
+struct Checker<'a> {
+  file: &'a str,
+  errors: Vec<DocError>,
+}
+
+impl<'a> Checker<'a> {
+  fn category(&mut self, pointer: &str, value: &Value, documented: &mut HashSet<String>) {
+    let fields = match self.object(pointer, value, CATEGORY_FIELDS, &[]) {
+      Some(fields) => fields,
+      None => return,
+    };
+
+    self.string(&child(pointer, "category"), fields.get("category"), true);
+    let keywords: Vec<&str> = self.array(&child(pointer, "keywords"), fields.get("keywords")).iter().enumerate()
+      .filter_map(|(i, keyword)| self.string(&format!("{}/keywords/{}", pointer, i), Some(keyword), true))
+      .collect();
+
+    for (i, detail) in self.array(&child(pointer, "keyword_details"), fields.get("keyword_details")).iter().enumerate() {
+      self.detail(&format!("{}/keyword_details/{}", pointer, i), detail, &keywords, documented);
+    }
+  }
+
+  fn detail(&mut self, pointer: &str, value: &Value, keywords: &[&str], documented: &mut HashSet<String>) {
+    let fields = match self.object(pointer, value, DETAIL_FIELDS, OPTIONAL_DETAIL_FIELDS) {
+      Some(fields) => fields,
+      None => return,
+    };
+
+    for field in ["syntax", "type", "description"] {
+      self.string(&child(pointer, field), fields.get(field), true);
+    }
+    self.string(&child(pointer, "example"), fields.get("example"), false);
+    for (i, node_type) in self.array(&child(pointer, "node_type"), fields.get("node_type")).iter().enumerate() {
+      self.string(&format!("{}/node_type/{}", pointer, i), Some(node_type), true);
+    }
+
+    let keyword_at = child(pointer, "keyword");
+    if let Some(keyword) = self.string(&keyword_at, fields.get("keyword"), true) {
+      if !keywords.contains(&keyword) {
+        self.error(&keyword_at, format!("`{}` is documented but missing from the keywords of its category", keyword));
+      }
+      if !documented.insert(keyword.to_string()) {
+        self.error(&keyword_at, format!("`{}` is documented twice", keyword));
+      }
+    }
+
+    let deprecated = self.optional_string(&child(pointer, "deprecated"), fields.get("deprecated"));
+    let replacement = self.optional_string(&child(pointer, "replacement"), fields.get("replacement"));
+    if replacement.is_some() && deprecated.is_none() {
+      self.error(&child(pointer, "replacement"), "a replacement requires `deprecated` to be set");
+    }
+  }
+
+  /// The fields of an object, reporting missing required and unknown ones
+  fn object<'v>(&mut self, pointer: &str, value: &'v Value, required: &[&str], optional: &[&str]) -> Option<&'v Map<String, Value>> {
+    let fields = match value.as_object() {
+      Some(fields) => fields,
+      None => { self.expected(pointer, "object", value); return None; }
+    };
+
+    for field in required.iter().filter(|field| !fields.contains_key(**field)) {
+      self.error(pointer, format!("missing required field `{}`", field));
+    }
+    for field in fields.keys().filter(|field| !required.contains(&field.as_str()) && !optional.contains(&field.as_str())) {
+      self.error(&child(pointer, field), format!("unknown field `{}`, expected one of {}", field, required.join(", ")));
+    }
+
+    Some(fields)
+  }
+
+  /// Items of an array, none when the field is missing or not an array
+  fn array<'v>(&mut self, pointer: &str, value: Option<&'v Value>) -> &'v [Value] {
+    match value {
+      Some(Value::Array(items)) => items.as_slice(),
+      Some(value) => { self.expected(pointer, "array", value); &[] }
+      None => &[],
+    }
+  }
+
+  /// A string field, missing fields are reported by `object`
+  fn string<'v>(&mut self, pointer: &str, value: Option<&'v Value>, non_empty: bool) -> Option<&'v str> {
+    match value? {
+      Value::String(text) if non_empty && text.trim().is_empty() => { self.error(pointer, "expected a non-empty string"); None }
+      Value::String(text) => Some(text.as_str()),
+      value => { self.expected(pointer, "string", value); None }
+    }
+  }
+
+  fn optional_string<'v>(&mut self, pointer: &str, value: Option<&'v Value>) -> Option<&'v str> {
+    match value {
+      Some(Value::Null) | None => None,
+      value => self.string(pointer, value, false),
+    }
+  }
+
+  fn expected(&mut self, pointer: &str, expected: &str, found: &Value) {
+    self.error(pointer, format!("expected {}, found {}", expected, kind(found)));
+  }
+
+  fn error(&mut self, pointer: &str, message: impl Into<String>) {
+    self.errors.push(DocError::new(self.file, pointer, message));
+  }
+}
+
+/// Pointer to a field of the object at `pointer`, escaped as in RFC 6901
+fn child(pointer: &str, field: &str) -> String {
+  format!("{}/{}", pointer, field.replace('~', "~0").replace('/', "~1"))
+}
+
+fn kind(value: &Value) -> &'static str {
+  match value {
+    Value::Null => "null",
+    Value::Bool(_) => "boolean",
+    Value::Number(_) => "number",
+    Value::String(_) => "string",
+    Value::Array(_) => "array",
+    Value::Object(_) => "object",
+  }
+}
@@ -0,0 +1,220 @@
+use std::path::{Path, PathBuf};
+
+use lsp_types::Url;
+
+// --| URI Conversions ---------------
+// --|--------------------------------
+// `uri.path()` is not a filesystem path: on Windows it reads `/C:/...` and clients
+// send the drive colon percent-encoded, ie. `file:///c%3A/...`, and shares arrive
+// with the server in the host, `file://server/share/...`. Every conversion between
+// document URIs and paths goes through here so they come out the same on each platform.
+
+/// Bytes kept as-is in a path segment, everything else is percent-encoded
+fn is_unreserved(byte: u8) -> bool {
+  byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&byte)
+}
+
+fn percent_encode(segment: &str) -> String {
+  let mut encoded = String::with_capacity(segment.len());
+  for byte in segment.bytes() {
+    if is_unreserved(byte) { encoded.push(byte as char); } else { encoded.push_str(&format!("%{:02X}", byte)); }
+  }
+  encoded
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+  let bytes = text.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+
+  while index < bytes.len() {
+    if bytes[index] == b'%' {
+      let hex = text.get(index + 1..index + 3)?;
+      decoded.push(u8::from_str_radix(hex, 16).ok()?);
+      index += 3;
+    } else {
+      decoded.push(bytes[index]);
+      index += 1;
+    }
+  }
+  String::from_utf8(decoded).ok()
+}
+
+/// Host of a `file:` URI naming a share, `localhost` means the local machine
+fn share_host(uri: &Url) -> Option<&str> {
+  uri.host_str().filter(|host| !host.is_empty() && !host.eq_ignore_ascii_case("localhost"))
+}
+
+/// Drive letter a decoded URI path starts with, ie. `C` for `/c:/dev`
+fn drive_letter(path: &str) -> Option<char> {
+  let mut chars = path.strip_prefix('/')?.chars();
+  let (letter, colon) = (chars.next()?, chars.next()?);
+  let rest = chars.next();
+  (letter.is_ascii_alphabetic() && colon == ':' && matches!(rest, None | Some('/'))).then_some(letter)
+}
+
+/// Windows form of a `file:` URI, ie. `C:\dev\main.cy` or `\\server\share\main.cy`
+fn windows_path(uri: &Url) -> Option<String> {
+  if uri.scheme() != "file" { return None; }
+  let path = percent_decode(uri.path())?;
+
+  if let Some(host) = share_host(uri) {
+    return Some(format!("\\\\{}{}", host, path.replace('/', "\\")));
+  }
+
+  let letter = drive_letter(&path)?;
+  let rest = &path[3..];
+  let rest = if rest.is_empty() { "\\".to_string() } else { rest.replace('/', "\\") };
+  Some(format!("{}:{}", letter.to_ascii_uppercase(), rest))
+}
+
+/// Unix form of a `file:` URI, only local files have one
+fn unix_path(uri: &Url) -> Option<String> {
+  if uri.scheme() != "file" || share_host(uri).is_some() { return None; }
+  percent_decode(uri.path())
+}
+
+/// `file:` URI of a Windows path. Verbatim `\\?\` prefixes are dropped and drive
+/// letters are written uppercase with a plain colon.
+fn windows_uri(path: &str) -> Option<Url> {
+  let path = path.replace('/', "\\");
+  let path = match path.strip_prefix(r"\\?\UNC\") {
+    Some(share) => format!(r"\\{}", share),
+    None => path.strip_prefix(r"\\?\").map(str::to_string).unwrap_or(path),
+  };
+
+  let (host, rest) = match path.strip_prefix(r"\\") {
+    Some(share) => {
+      let (host, rest) = share.split_once('\\').unwrap_or((share, ""));
+      if host.is_empty() { return None; }
+      (host.to_string(), format!("/{}", rest))
+    }
+    None => {
+      let letter = drive_letter(&format!("/{}", path.replace('\\', "/")))?;
+      (String::new(), format!("/{}:{}", letter.to_ascii_uppercase(), path[2..].replace('\\', "/")))
+    }
+  };
+
+  let encoded: Vec<String> = rest.split(['\\', '/']).map(percent_encode).collect();
+  Url::parse(&format!("file://{}{}", host, encoded.join("/"))).ok()
+}
+
+/// `file:` URI of an absolute Unix path
+fn unix_uri(path: &str) -> Option<Url> {
+  if !path.starts_with('/') { return None; }
+  let encoded: Vec<String> = path.split('/').map(percent_encode).collect();
+  Url::parse(&format!("file://{}", encoded.join("/"))).ok()
+}
+
+/// Filesystem path of a document, `None` for virtual documents and URIs this platform can't open
+pub fn uri_to_path(uri: &Url) -> Option<PathBuf> {
+  let path = if cfg!(windows) { windows_path(uri) } else { unix_path(uri) };
+  path.map(PathBuf::from)
+}
+
+/// Document URI of an absolute path
+pub fn path_to_uri(path: &Path) -> Option<Url> {
+  let path = path.to_str()?;
+  if cfg!(windows) { windows_uri(path) } else { unix_uri(path) }
+}
+
+/// Spelling of a `file:` URI as `path_to_uri` produces it, ie. `file:///c%3A/...` becomes
+/// `file:///C:/...`, so documents sent by the client and files found on disk compare equal.
+/// Virtual documents and URIs without a path here are kept as they are.
+pub fn normalize_uri(uri: &Url) -> Url {
+  let normalized = if cfg!(windows) { windows_path(uri).and_then(|path| windows_uri(&path)) } else { unix_path(uri).and_then(|path| unix_uri(&path)) };
+  match normalized {
+    // The directory slash is part of the spelling
+    Some(mut normalized) => {
+      if uri.path().ends_with('/') && !normalized.path().ends_with('/') { normalized.set_path(&format!("{}/", normalized.path())); }
+      normalized
+    }
+    None => uri.clone(),
+  }
+}
+
+/// Path of a document as shown to the compiler, virtual documents use their decoded name
+pub fn document_path(uri: &Url) -> PathBuf {
+  uri_to_path(uri).unwrap_or_else(|| PathBuf::from(percent_decode(uri.path()).unwrap_or_else(|| uri.path().to_string())))
+}
+
+/// URI of a directory, with the trailing slash relative references resolve against
+pub fn dir_to_uri(path: &Path) -> Option<Url> {
+  let mut uri = path_to_uri(path)?;
+  if !uri.path().ends_with('/') { uri.set_path(&format!("{}/", uri.path())); }
+  Some(uri)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn uri(text: &str) -> Url {
+    Url::parse(text).unwrap()
+  }
+
+  #[test]
+  fn windows_drive_letters_lose_the_leading_slash() {
+    assert_eq!(windows_path(&uri("file:///C:/dev/main.cy")).as_deref(), Some(r"C:\dev\main.cy"));
+    assert_eq!(windows_path(&uri("file:///c%3A/dev/main.cy")).as_deref(), Some(r"C:\dev\main.cy"));
+    assert_eq!(windows_path(&uri("file:///d:")).as_deref(), Some(r"D:\"));
+  }
+
+  #[test]
+  fn windows_unc_paths_keep_the_server() {
+    assert_eq!(windows_path(&uri("file://server/share/lib/mod.cy")).as_deref(), Some(r"\\server\share\lib\mod.cy"));
+    assert_eq!(windows_path(&uri("file://localhost/C:/dev/main.cy")).as_deref(), Some(r"C:\dev\main.cy"));
+    assert_eq!(windows_uri(r"\\server\share\lib\mod.cy"), Some(uri("file://server/share/lib/mod.cy")));
+    assert_eq!(windows_uri(r"\\?\UNC\server\share\mod.cy"), Some(uri("file://server/share/mod.cy")));
+  }
+
+  #[test]
+  fn windows_paths_round_trip() {
+    for path in [r"C:\dev\main.cy", r"C:\My Projects\über #1\main.cy", r"\\server\share\a b.cy"] {
+      let converted = windows_uri(path).unwrap();
+      assert_eq!(windows_path(&converted).as_deref(), Some(path));
+    }
+    assert_eq!(windows_uri(r"\\?\c:\dev\main.cy"), Some(uri("file:///C:/dev/main.cy")));
+    assert_eq!(windows_uri(r"dev\main.cy"), None);
+  }
+
+  #[test]
+  fn percent_encoding_round_trips() {
+    let converted = unix_uri("/home/me/my project/#1?.cy").unwrap();
+    assert_eq!(converted.as_str(), "file:///home/me/my%20project/%231%3F.cy");
+    assert_eq!(unix_path(&converted).as_deref(), Some("/home/me/my project/#1?.cy"));
+    assert_eq!(unix_path(&uri("file:///home/me/caf%C3%A9.cy")).as_deref(), Some("/home/me/café.cy"));
+  }
+
+  #[test]
+  fn virtual_documents_have_no_path() {
+    assert_eq!(uri_to_path(&uri("untitled:Untitled-1")), None);
+    assert_eq!(unix_path(&uri("file://server/share/mod.cy")), None);
+    assert_eq!(windows_path(&uri("untitled:Untitled-1")), None);
+  }
+
+  #[test]
+  fn directories_end_with_a_slash() {
+    let dir = if cfg!(windows) { r"C:\dev" } else { "/dev" };
+    assert!(dir_to_uri(Path::new(dir)).unwrap().as_str().ends_with("dev/"));
+  }
+
+  #[test]
+  fn client_spellings_normalize_to_the_scanned_uri() {
+    // What `normalize_uri` does on Windows
+    let windows = |text: &str| windows_path(&uri(text)).and_then(|path| windows_uri(&path));
+    assert_eq!(windows("file:///c%3A/dev/main.cy"), Some(uri("file:///C:/dev/main.cy")));
+    assert_eq!(windows("file:///C:/dev/main.cy"), Some(uri("file:///C:/dev/main.cy")));
+
+    assert_eq!(normalize_uri(&uri("untitled:Untitled-1")), uri("untitled:Untitled-1"));
+    if !cfg!(windows) {
+      assert_eq!(normalize_uri(&uri("file:///home/me/caf%c3%a9.cy")), uri("file:///home/me/caf%C3%A9.cy"));
+      assert_eq!(normalize_uri(&uri("file:///home/me/project/")), uri("file:///home/me/project/"));
+    }
+  }
+
+  #[test]
+  fn virtual_documents_are_shown_by_name() {
+    assert_eq!(document_path(&uri("untitled:Untitled%201")), PathBuf::from("Untitled 1"));
+  }
+}
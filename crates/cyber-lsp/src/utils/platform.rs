@@ -0,0 +1,27 @@
+// --| Platform ----------------------
+// --|--------------------------------
+// What the server needs from the operating system beyond files: child processes and
+// threads for blocking work. Processes only exist in builds with a feature running the
+// cyber executable. Builds without the `native` feature, ie. wasm32-wasi for browser
+// editors, have no threads either and run blocking work on the calling task.
+
+use tracing::error;
+
+#[cfg(any(feature = "compiler-diagnostics", feature = "run-commands"))]
+pub use tokio::process::{Child, ChildStdin, Command};
+
+/// Run blocking work off the async runtime where threads are available.
+/// Returns `None` if the work panicked.
+pub async fn blocking<T, F>(work: F) -> Option<T>
+where
+  F: FnOnce() -> T + Send + 'static,
+  T: Send + 'static,
+{
+  #[cfg(feature = "native")]
+  let result = tokio::task::spawn_blocking(work).await.map_err(|err| err.to_string());
+
+  #[cfg(not(feature = "native"))]
+  let result: Result<T, String> = Ok(work());
+
+  result.map_err(|err| error!("Blocking task failed: {}", err)).ok()
+}
@@ -1,4 +1,6 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::ops::Range as ByteRange;
 
 use cyber_tree_sitter::InputEdit;
 use cyber_tree_sitter::Tree;
@@ -6,14 +8,17 @@ use lsp_types::Range;
 use lsp_types::Position;
 use lsp_types::TextDocumentContentChangeEvent;
 use once_cell::sync::Lazy;
+use ropey::Rope;
 use tracing::debug;
 use tracing::error;
 use tracing::info;
 use tree_sitter::{ Node, Point, Query, QueryCursor };
 
 use crate::datatypes::*;
-use super::loader::LANGUAGE_DOCS;
+use super::loader::LANGUAGE_DEFINITIONS;
+use crate::core_types::{DocPoint, DocRange, ToDoc};
 use crate::documents::FullTextDocument;
+use crate::symbols::name_node;
 
 macro_rules! query_for_nodes {
   ($query:expr,$node:expr,$source:expr) => {
@@ -28,15 +33,7 @@ macro_rules! query_for_nodes {
 
 macro_rules! query_for_ranges {
   ($query:expr,$node:expr,$source:expr) => {
-    query_for_nodes!($query, $node, $source).map(|node| {
-      let start = node.start_position();
-      let end = node.end_position();
-
-      Range {
-        start: Position { line: start.row as u32, character: start.column as u32, },
-        end: Position { line: end.row as u32, character: end.column as u32, },
-      }
-    })
+    query_for_nodes!($query, $node, $source).map(|node| DocRange::of_node(&node).byte_range())
   };
 }
 
@@ -47,17 +44,12 @@ pub fn get_range(start_row: u32, start_column: u32, end_row: u32, end_column: u3
     )
 }
 
-/// Converts [tree_sitter] Point to [lsp_types] Position
-/// treesitter to lsp_types
-#[inline]
-pub fn point_to_position(input: Point) -> Position {
-  Position { line: input.row as u32, character: input.column as u32 }
-}
-
-/// Converts [lsp_types] Position to [tree_sitter] Point
+/// Returns true if the position lies within the range, inclusive of both ends
 #[inline]
-pub fn position_to_point(input: Position) -> Point {
-  Point { row: input.line as usize, column: input.character as usize }
+pub fn range_contains(range: &Range, position: Position) -> bool {
+  let after_start = (position.line, position.character) >= (range.start.line, range.start.character);
+  let before_end = (position.line, position.character) <= (range.end.line, range.end.character);
+  after_start && before_end
 }
 
 /// Search the documentation store for the relevant keyword details for the given position
@@ -65,11 +57,7 @@ pub fn get_from_position(location: Position, root: Node, source: &str, lsp_actio
   match (get_string_at_pos(location, root, source), get_pos_type(location, root, source, PositionType::NotFind)) {
     (Some(message), _) => {
       info!("Message: {}", message);
-
-      let mut value = MESSAGE_STORAGE.get(&lsp_action);
-      if value.is_none() { value = MESSAGE_STORAGE.get(&lsp_action.to_lowercase()); }
-     
-     return value?.lookup(&message).map(|x| x.clone());
+      lookup_keyword(&lsp_action, &message)
     }
 
     (None, _) => {
@@ -78,11 +66,110 @@ pub fn get_from_position(location: Position, root: Node, source: &str, lsp_actio
   }
 }
 
+/// Details of a keyword or builtin for an lsp action, ie. `hover`
+pub fn lookup_keyword(lsp_action: &str, word: &str) -> Option<KeywordDetail> {
+  let mut value = MESSAGE_STORAGE.get(lsp_action);
+  if value.is_none() { value = MESSAGE_STORAGE.get(&lsp_action.to_lowercase()); }
+  value?.lookup(word).cloned()
+}
+
+// --| Node Text ---------------------
+// --|--------------------------------
+/// Text a node can be read from, either the document text or its rope
+pub trait NodeSource {
+  fn byte_text(&self, bytes: ByteRange<usize>) -> Cow<'_, str>;
+}
+
+impl NodeSource for str {
+  fn byte_text(&self, bytes: ByteRange<usize>) -> Cow<'_, str> {
+    Cow::Borrowed(self.get(bytes).unwrap_or_default())
+  }
+}
+
+impl NodeSource for Rope {
+  fn byte_text(&self, bytes: ByteRange<usize>) -> Cow<'_, str> {
+    self.get_byte_slice(bytes).map(Cow::from).unwrap_or_default()
+  }
+}
+
+/// Text of a node, read by its byte range so multibyte characters and nodes
+/// spanning lines are kept whole. A range outside the source, eg. from a stale tree, reads as empty.
+pub fn node_text<'a, S: NodeSource + ?Sized>(node: Node, source: &'a S) -> Cow<'a, str> {
+  source.byte_text(node.start_byte()..node.end_byte())
+}
+
 /// Get string from current document the given position
+/// The identifier node at, or directly before, a position
+pub fn identifier_at(root: Node, source: &str, position: Position) -> Option<(String, Range)> {
+  let point: Point = DocPoint::from_byte_position(position).into();
+  let before = Point { row: point.row, column: point.column.saturating_sub(1) };
+
+  let found = [point, before].iter()
+    .filter_map(|point| root.descendant_for_point_range(*point, *point))
+    .find(|node| node.kind() == "identifier")
+    .map(|node| {
+      let text = node_text(node, source).into_owned();
+      (text, DocRange::of_node(&node).byte_range())
+    });
+
+  // Broken code may hold the name as some other token
+  found.or_else(|| if in_error(root, position) { word_at(source, position) } else { None })
+}
+
+// --| Error Recovery ----------------
+// --|--------------------------------
+/// Whether the position lies within an ERROR or MISSING node, where node kinds can't be trusted
+pub fn in_error(root: Node, position: Position) -> bool {
+  let point: Point = DocPoint::from_byte_position(position).into();
+  let mut node = root.descendant_for_point_range(point, point);
+  while let Some(current) = node {
+    if current.is_error() || current.is_missing() { return true; }
+    node = current.parent();
+  }
+  false
+}
+
+/// Innermost named node at a position that is neither an ERROR nor a MISSING node, the
+/// nearest thing to go by when the tokens around the position don't parse
+pub fn named_ancestor_at(root: Node, position: Position) -> Option<Node> {
+  let point: Point = DocPoint::from_byte_position(position).into();
+  let mut node = root.descendant_for_point_range(point, point);
+  while let Some(current) = node {
+    if current.is_named() && !current.is_error() && !current.is_missing() { return Some(current); }
+    node = current.parent();
+  }
+  None
+}
+
+/// Name of the declaration enclosing a position, ie. the function whose parameter list is
+/// being typed, for when there is no word under the cursor
+pub fn enclosing_name(root: Node, source: &str, position: Position) -> Option<String> {
+  let mut node = named_ancestor_at(root, position);
+  while let Some(current) = node {
+    let declaration = matches!(current.kind(), "function_definition" | "object_definition" | "object_declaration");
+    if let Some(name) = name_node(current).filter(|_| declaration) { return Some(node_text(name, source).into_owned()); }
+    node = current.parent();
+  }
+  None
+}
+
+/// Identifier characters around a byte position, read from the text where the tree can't tell
+pub fn word_at(source: &str, position: Position) -> Option<(String, Range)> {
+  let line = source.lines().nth(position.line as usize)?;
+  let column = (position.character as usize).min(line.len());
+  if !line.is_char_boundary(column) { return None; }
+
+  let is_ident = |ch: char| ch.is_alphanumeric() || ch == '_';
+  let start = line[..column].char_indices().rev().find(|(_, ch)| !is_ident(*ch)).map_or(0, |(index, ch)| index + ch.len_utf8());
+  let end = line[column..].find(|ch: char| !is_ident(ch)).map_or(line.len(), |offset| column + offset);
+
+  let word = &line[start..end];
+  if word.is_empty() || word.starts_with(|ch: char| ch.is_ascii_digit()) { return None; }
+  Some((word.to_string(), Range::new(Position::new(position.line, start as u32), Position::new(position.line, end as u32))))
+}
+
 pub fn get_string_at_pos(location: Position, root: Node, source: &str) -> Option<String> {
-  let position = position_to_point(location);
-  let source_array: Vec<&str> = source.lines().collect();
-  
+  let position: Point = DocPoint::from_byte_position(location).into();
   let mut cursor = root.walk();
 
   for child in root.children(&mut cursor) {
@@ -92,16 +179,8 @@ pub fn get_string_at_pos(location: Position, root: Node, source: &str) -> Option
         if recurse_pos.is_some() { return recurse_pos; };
       }
 
-      else if child.start_position().row == child.end_position().row 
-        && position.column <= child.end_position().column 
-          && position.column >= child.start_position().column {
-                          
-        let h = child.start_position().row;
-        let x = child.start_position().column;
-        let y = child.end_position().column;
-
-        let message = &source_array[h][x..y];
-        return Some(message.to_string());
+      else if child.start_position() <= position && position <= child.end_position() {
+        return Some(node_text(child, source).into_owned());
       }
     }
   }
@@ -112,7 +191,7 @@ pub fn get_string_at_pos(location: Position, root: Node, source: &str) -> Option
 
 /// Get Tree Node range based on the current position
 pub fn get_position_range(location: Position, root: Node) -> Option<Range> {
-  let position = position_to_point(location);
+  let position: Point = DocPoint::from_byte_position(location).into();
   let mut cursor = root.walk();
 
   for child in root.children(&mut cursor){
@@ -129,10 +208,7 @@ pub fn get_position_range(location: Position, root: Node) -> Option<Range> {
           position.column <= child.end_position().column && 
             position.column >= child.start_position().column
         {
-          return Some(Range {
-            start: point_to_position(child.start_position()),
-            end: point_to_position(child.end_position()),
-          });
+          return Some(DocRange::of_node(&child).byte_range());
         }
       }
   }
@@ -140,31 +216,52 @@ pub fn get_position_range(location: Position, root: Node) -> Option<Range> {
 }
 
 pub fn get_tree_edits(change: &TextDocumentContentChangeEvent, document: &mut FullTextDocument, version: i64,) -> Option<InputEdit> {
-    if change.range.is_none() || change.range_length.is_none() { return None; }
-
-    let range = change.range.unwrap();
-    let start = range.start;
-    let end = range.end;
-    let start_char = document.rope.line_to_char(start.line as usize) + start.character as usize;
-    let old_end_char = document.rope.line_to_char(end.line as usize) + end.character as usize;
+    // `rangeLength` is deprecated and optional, the range alone describes the change
+    let range = match change.range {
+        Some(range) => range,
+        None => return full_text_edit(change, document, version),
+    };
 
-    let start_byte = document.rope.char_to_byte(start_char);
-    let old_end_byte = document.rope.char_to_byte(old_end_char);
+    let range = range.to_doc(&document.rope);
+    let start_char = range.start.char_index(&document.rope);
+    let start_byte = range.start.offset(&document.rope);
+    let old_end_byte = range.end.offset(&document.rope);
+    let start_position = range.start.into();
+    let old_end_position = range.end.into();
 
     document.update(vec![change.clone()], version);
     let new_end_char = start_char + change.text.chars().count();
-    let new_end_byte = document.rope.char_to_byte(new_end_char);
-
-    let new_end_line = document.rope.char_to_line(new_end_char);
-    let new_end_line_first_character = document.rope.line_to_char(new_end_line);
-    let new_end_character = new_end_byte - new_end_line_first_character;
-
-    Some(InputEdit {
-        start_byte, old_end_byte, new_end_byte,
-        start_position: Point::new(start.line as usize, start.character as usize),
-        old_end_position: Point::new(end.line as usize, end.character as usize),
-        new_end_position: Point::new(new_end_line, new_end_character),
-    })
+    let new_end_byte = document.rope.char_to_byte(new_end_char.min(document.rope.len_chars()));
+    let new_end_position = DocPoint::at_offset(&document.rope, new_end_byte).into();
+
+    Some(InputEdit { start_byte, old_end_byte, new_end_byte, start_position, old_end_position, new_end_position })
+}
+
+/// A client sending the whole document is diffed against the previous text, so the
+/// tree is still edited in place and reparsed incrementally. The edit covers
+/// everything between the unchanged prefix and suffix.
+fn full_text_edit(change: &TextDocumentContentChangeEvent, document: &mut FullTextDocument, version: i64) -> Option<InputEdit> {
+    let old = document.rope.to_string();
+    let new = change.text.as_str();
+
+    let mut prefix = old.bytes().zip(new.bytes()).take_while(|(a, b)| a == b).count();
+    while !old.is_char_boundary(prefix) || !new.is_char_boundary(prefix) { prefix -= 1; }
+
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let mut suffix = old.bytes().rev().zip(new.bytes().rev()).take(max_suffix).take_while(|(a, b)| a == b).count();
+    while !old.is_char_boundary(old.len() - suffix) || !new.is_char_boundary(new.len() - suffix) { suffix -= 1; }
+
+    let start_byte = prefix;
+    let old_end_byte = old.len() - suffix;
+    let new_end_byte = new.len() - suffix;
+    let start_position = DocPoint::at_offset(&document.rope, start_byte).into();
+    let old_end_position = DocPoint::at_offset(&document.rope, old_end_byte).into();
+
+    document.update(vec![change.clone()], version);
+    if start_byte == old_end_byte && start_byte == new_end_byte { return None; }
+    let new_end_position = DocPoint::at_offset(&document.rope, new_end_byte).into();
+
+    Some(InputEdit { start_byte, old_end_byte, new_end_byte, start_position, old_end_position, new_end_position })
 }
 
 // --| Diagnostic Helpers -------------
@@ -189,27 +286,11 @@ pub fn get_parser_errors(source: &str, tree: Option<Tree>) -> Vec<Range> {
 // --| Language Definitions Storage ---
 // --|---------------------------------
 pub static MESSAGE_STORAGE: Lazy<HashMap<String, LanguageDefinition>> = Lazy::new(|| {
-  let mut storage: HashMap<String, LanguageDefinition> = HashMap::new();
-  info!("Loading language definitions from disk");
-
-  // Load json files from disk and deserialize them into `datatypes::LanguageDefinitions`
-  let language_docs: Vec<LanguageDoc>  = LANGUAGE_DOCS.clone().into_iter().map(|x| x.into()).collect();
-  info!("Loaded {} language definitions", language_docs.len()); 
-
-  for lang_doc in language_docs {
-    let def_name = lang_doc.docname.to_string();
-    info!("Loading language definition: {}", def_name);
+  // Validated at build time, see `build.rs`
+  let definitions: Vec<LanguageDefinition> = serde_json::from_str(LANGUAGE_DEFINITIONS).expect("embedded language definitions");
+  info!("Loaded {} language definitions", definitions.len());
 
-    let lang_doc_json = std::fs::read_to_string(&lang_doc.path).expect("Failed to read file");
-
-    let lang_defs: LanguageDefinition = match serde_json::from_str::<LanguageDefinition>(&lang_doc_json) {
-        Ok(def) => { info!("Loaded language definition: {}", def_name); def },
-        Err(err) => { error!("Failed to parse language definition: {}", err); continue; }
-    };
-
-    storage.insert(lang_defs.lsp_action.to_string(), lang_defs);
-  } 
-  storage
+  definitions.into_iter().map(|definition| (definition.lsp_action.to_string(), definition)).collect()
 });
 
 #[derive(Clone, Copy, Debug)]
@@ -231,8 +312,7 @@ pub enum LanguageConstruct {
 }
 
 pub fn get_pos_type( location: Position, root: Node, source: &str, inputtype: PositionType,) -> PositionType {
-  let position = position_to_point(location);
-  let source_array: Vec<&str> = source.lines().collect();
+  let position: Point = DocPoint::from_byte_position(location).into();
   let mut cursor = root.walk();
 
   for child in root.children(&mut cursor) {
@@ -241,11 +321,8 @@ pub fn get_pos_type( location: Position, root: Node, source: &str, inputtype: Po
       if child.child_count() != 0 {
         let _match_type = match child.kind() {
           "import_statement" | "assignment_statement" | "if_statement" => {
-            let h = child.start_position().row;
             let ids = child.child(0).unwrap();
-            let x = ids.start_position().column;
-            let y = ids.end_position().column;
-            let name = source_array[h][x..y].to_lowercase();
+            let name = node_text(ids, source).to_lowercase();
             
             debug!("name: {}", name);
             match name.as_str() { _ => PositionType::Variable, }
@@ -260,11 +337,8 @@ pub fn get_pos_type( location: Position, root: Node, source: &str, inputtype: Po
           },
 
           _ => {
-            let h = child.start_position().row;
             let ids = child.child(0).unwrap();
-            let x = ids.start_position().column;
-            let y = ids.end_position().column;
-            let name = source_array[h][x..y].to_lowercase();
+            let name = node_text(ids, source).to_lowercase();
 
             debug!("name: {} kind: {}", name, child.kind());
             PositionType::Variable
@@ -291,6 +365,14 @@ impl std::fmt::Display for TreeWrapper {
     }
 }
 
+/// Displays the subtree below a single node
+pub struct NodeWrapper<'a>(pub Node<'a>);
+impl std::fmt::Display for NodeWrapper<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        pretty_display(f, self.0)
+    }
+}
+
 pub fn pretty_display(f: &mut std::fmt::Formatter<'_>, root: Node) -> std::fmt::Result {
     let mut stack = Vec::new();
     if !root.is_named() { return Ok(()); }
@@ -312,3 +394,68 @@ pub fn pretty_display(f: &mut std::fmt::Formatter<'_>, root: Node) -> std::fmt::
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(source: &str) -> Tree {
+    cyber_tree_sitter::init_parser().parse(source, None).unwrap()
+  }
+
+  fn word(source: &str, line: u32, character: u32) -> Option<String> {
+    word_at(source, Position::new(line, character)).map(|(word, _)| word)
+  }
+
+  #[test]
+  fn word_at_reads_identifier_characters_around_the_position() {
+    let source = "var total = add(a, b)\nprint größe_1\n";
+    assert_eq!(word(source, 0, 4).as_deref(), Some("total"));
+    assert_eq!(word(source, 0, 6).as_deref(), Some("total"));
+    // Directly after a word, ie. where the cursor is while typing it
+    assert_eq!(word(source, 0, 15).as_deref(), Some("add"));
+    assert_eq!(word_at(source, Position::new(0, 15)).unwrap().1, Range::new(Position::new(0, 12), Position::new(0, 15)));
+    // Byte columns, so multibyte identifiers are read whole
+    assert_eq!(word(source, 1, 8).as_deref(), Some("größe_1"));
+  }
+
+  #[test]
+  fn word_at_skips_numbers_and_blanks() {
+    let source = "var x = 42\n\n  \n";
+    assert_eq!(word(source, 0, 9), None);
+    assert_eq!(word(source, 0, 7), None);
+    assert_eq!(word(source, 1, 0), None);
+    assert_eq!(word(source, 2, 1), None);
+    assert_eq!(word(source, 9, 0), None);
+    // Positions past the end of a line read the word ending it
+    assert_eq!(word(source, 0, 40), None);
+    assert_eq!(word("print total", 0, 40).as_deref(), Some("total"));
+  }
+
+  #[test]
+  fn in_error_only_within_broken_regions() {
+    let valid = "func add(a, b):\n  return a + b\n";
+    let tree = parse(valid);
+    for (line, text) in valid.lines().enumerate() {
+      for character in 0..=text.len() as u32 {
+        assert!(!in_error(tree.root_node(), Position::new(line as u32, character)), "{}:{}", line, character);
+      }
+    }
+
+    let broken = "var x = 1\nvar y = (x +\nprint x\n";
+    let tree = parse(broken);
+    assert!(tree.root_node().has_error());
+    assert!(!in_error(tree.root_node(), Position::new(0, 4)));
+    assert!((0..12).any(|character| in_error(tree.root_node(), Position::new(1, character))));
+  }
+
+  #[test]
+  fn enclosing_name_climbs_to_the_nearest_named_declaration() {
+    let source = "func add(a, b):\n  var sum = a + b\n  return sum\n\nprint 1\n";
+    let tree = parse(source);
+    // Indentation of the body, with no word under the cursor
+    assert_eq!(enclosing_name(tree.root_node(), source, Position::new(2, 1)).as_deref(), Some("add"));
+    assert_eq!(named_ancestor_at(tree.root_node(), Position::new(2, 1)).map(|node| node.is_named()), Some(true));
+    assert_eq!(enclosing_name(tree.root_node(), source, Position::new(4, 0)), None);
+  }
+}
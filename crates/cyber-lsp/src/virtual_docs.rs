@@ -0,0 +1,59 @@
+use lsp_types::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::datatypes::KeywordDetail;
+use crate::utils::treehelper::MESSAGE_STORAGE;
+
+pub const VIRTUAL_CONTENT_METHOD: &str = "cyberls/virtualContent";
+
+/// Scheme of the documentation of builtins, which have no source file to go to
+pub const DOC_SCHEME: &str = "cyber-doc";
+
+// --| Request Types -----------------
+// --|--------------------------------
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualContentParams {
+  pub uri: Url,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualContentResult {
+  /// Markdown
+  pub content: String,
+}
+
+// --| Builtin Documentation ---------
+// --|--------------------------------
+/// Documentation of a builtin keyword or function
+pub fn lookup(name: &str) -> Option<&'static KeywordDetail> {
+  MESSAGE_STORAGE.values().find_map(|definition| definition.lookup(name))
+}
+
+/// Virtual document of a builtin, ie. `cyber-doc:///print.md`
+pub fn builtin_uri(name: &str) -> Option<Url> {
+  Url::parse(&format!("{}:///{}.md", DOC_SCHEME, name)).ok()
+}
+
+/// The builtin a `cyber-doc` URI documents
+pub fn builtin_name(uri: &Url) -> Option<&str> {
+  if uri.scheme() != DOC_SCHEME { return None; }
+  uri.path().trim_start_matches('/').strip_suffix(".md")
+}
+
+pub fn render(detail: &KeywordDetail) -> String {
+  let mut content = format!("# {}\n\n*{}*\n\n", detail.keyword, detail.keyword_detail_type);
+  if let Some(deprecated) = &detail.deprecated {
+    content.push_str(&format!("> **Deprecated:** {}\n\n", deprecated));
+  }
+  if !detail.syntax.is_empty() {
+    content.push_str(&format!("```cyber\n{}\n```\n\n", detail.syntax));
+  }
+
+  content.push_str(&format!("{}\n", detail.description));
+  if !detail.example.is_empty() {
+    content.push_str(&format!("\n## Example\n\n```cyber\n{}\n```\n", detail.example));
+  }
+  content
+}
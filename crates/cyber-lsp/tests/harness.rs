@@ -0,0 +1,153 @@
+use cyber_lsp_core::testing::{fixture, fixture_uri, fixtures_dir, ClientScript, TestServer};
+use cyber_lsp_core::ServerOptions;
+use lsp_types::request::{HoverRequest, PrepareRenameRequest, References, Rename};
+use lsp_types::*;
+
+async fn start() -> TestServer {
+  let script = ClientScript::default().with_workspace(&fixtures_dir());
+  let (server, _) = TestServer::start(ServerOptions::default(), script).await;
+  server
+}
+
+/// Open a fixture and edit it to its own text, which runs the diagnostic tiers
+async fn check(server: &mut TestServer, name: &str) -> (Url, Vec<Diagnostic>) {
+  let (uri, text) = (fixture_uri(name), fixture(name));
+  server.open(&uri, &text).await;
+  server.change(&uri, &text).await;
+  let diagnostics = server.diagnostics_of(&uri).await;
+  (uri, diagnostics)
+}
+
+fn has_code(diagnostics: &[Diagnostic], code: &str) -> bool {
+  diagnostics.iter().any(|diagnostic| diagnostic.code == Some(NumberOrString::String(code.to_string())))
+}
+
+#[tokio::test]
+async fn initialize_advertises_capabilities() {
+  let (server, result) = TestServer::start(ServerOptions::default(), ClientScript::default()).await;
+  assert!(result.capabilities.hover_provider.is_some());
+  assert!(result.capabilities.completion_provider.is_some());
+  server.shutdown().await;
+}
+
+#[tokio::test]
+async fn clean_document_has_no_diagnostics() {
+  let mut server = start().await;
+  let (_, diagnostics) = check(&mut server, "clean.cy").await;
+  assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+  server.shutdown().await;
+}
+
+#[tokio::test]
+async fn syntax_errors_are_reported() {
+  let mut server = start().await;
+  let (_, diagnostics) = check(&mut server, "syntax_error.cy").await;
+  assert!(diagnostics.iter().any(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::ERROR)), "{:?}", diagnostics);
+  server.shutdown().await;
+}
+
+#[tokio::test]
+async fn undefined_identifiers_clear_once_declared() {
+  let mut server = start().await;
+  let (uri, diagnostics) = check(&mut server, "undefined.cy").await;
+  assert!(has_code(&diagnostics, "undefined_identifier"), "{:?}", diagnostics);
+
+  server.change(&uri, &format!("var missing = 2\n{}", fixture("undefined.cy"))).await;
+  let diagnostics = server.diagnostics_of(&uri).await;
+  assert!(!has_code(&diagnostics, "undefined_identifier"), "{:?}", diagnostics);
+  server.shutdown().await;
+}
+
+#[tokio::test]
+async fn hover_shows_doc_comments() {
+  let mut server = start().await;
+  let uri = fixture_uri("lib/shapes.cy");
+  server.open(&uri, &fixture("lib/shapes.cy")).await;
+
+  let params = HoverParams {
+    text_document_position_params: TextDocumentPositionParams::new(TextDocumentIdentifier::new(uri), Position::new(1, 6)),
+    work_done_progress_params: Default::default(),
+  };
+  let hover = server.request::<HoverRequest>(params).await.unwrap().expect("no hover");
+  let contents = serde_json::to_string(&hover.contents).unwrap();
+  assert!(contents.contains("Area of a circle"), "{}", contents);
+  server.shutdown().await;
+}
+
+#[tokio::test]
+async fn hover_shows_doc_comments_of_imported_declarations() {
+  let mut server = start().await;
+  let uri = fixture_uri("imports.cy");
+  server.open(&uri, &fixture("imports.cy")).await;
+  server.settle().await;
+
+  // `area` of `shapes.area(2)`, documented in `lib/shapes.cy`
+  let params = HoverParams {
+    text_document_position_params: TextDocumentPositionParams::new(TextDocumentIdentifier::new(uri), Position::new(2, 14)),
+    work_done_progress_params: Default::default(),
+  };
+  let hover = server.request::<HoverRequest>(params).await.unwrap().expect("no hover");
+  let contents = serde_json::to_string(&hover.contents).unwrap();
+  assert!(contents.contains("func area(radius)") && contents.contains("Area of a circle"), "{}", contents);
+  server.shutdown().await;
+}
+
+#[tokio::test]
+async fn rename_after_non_ascii_text_edits_utf16_columns() {
+  let mut server = start().await;
+  let uri = fixture_uri("unicode.cy");
+  server.open(&uri, &fixture("unicode.cy")).await;
+
+  // `x` in `print 'é', x` is at UTF-16 column 11, byte column 12
+  let position = TextDocumentPositionParams::new(TextDocumentIdentifier::new(uri.clone()), Position::new(1, 11));
+  let prepared = server.request::<PrepareRenameRequest>(position.clone()).await.unwrap();
+  assert_eq!(prepared, Some(PrepareRenameResponse::Range(Range::new(Position::new(1, 11), Position::new(1, 12)))));
+
+  let params = RenameParams { text_document_position: position, new_name: "count".to_string(), work_done_progress_params: Default::default() };
+  let edit = server.request::<Rename>(params).await.unwrap().expect("no rename");
+  let mut ranges: Vec<Range> = edit.changes.unwrap()[&uri].iter().map(|edit| edit.range).collect();
+  ranges.sort_by_key(|range| (range.start.line, range.start.character));
+  assert_eq!(ranges, vec![
+    Range::new(Position::new(0, 4), Position::new(0, 5)),
+    Range::new(Position::new(1, 11), Position::new(1, 12)),
+  ]);
+  server.shutdown().await;
+}
+
+#[tokio::test]
+async fn references_after_non_ascii_text_are_utf16_ranges() {
+  let mut server = start().await;
+  let uri = fixture_uri("unicode.cy");
+  server.open(&uri, &fixture("unicode.cy")).await;
+
+  let params = ReferenceParams {
+    text_document_position: TextDocumentPositionParams::new(TextDocumentIdentifier::new(uri.clone()), Position::new(1, 11)),
+    context: ReferenceContext { include_declaration: true },
+    work_done_progress_params: Default::default(),
+    partial_result_params: Default::default(),
+  };
+  let locations = server.request::<References>(params).await.unwrap().expect("no references");
+  let ranges: Vec<Range> = locations.iter().filter(|location| location.uri == uri).map(|location| location.range).collect();
+  assert_eq!(ranges, vec![
+    Range::new(Position::new(0, 4), Position::new(0, 5)),
+    Range::new(Position::new(1, 11), Position::new(1, 12)),
+  ]);
+  server.shutdown().await;
+}
+
+#[tokio::test]
+async fn hover_after_non_ascii_text_keeps_the_client_position() {
+  let mut server = start().await;
+  let uri = fixture_uri("unicode.cy");
+  server.open(&uri, &fixture("unicode.cy")).await;
+
+  let params = HoverParams {
+    text_document_position_params: TextDocumentPositionParams::new(TextDocumentIdentifier::new(uri), Position::new(1, 11)),
+    work_done_progress_params: Default::default(),
+  };
+  let hover = server.request::<HoverRequest>(params).await.unwrap().expect("no hover");
+  let contents = serde_json::to_string(&hover.contents).unwrap();
+  assert!(contents.contains("x: "), "{}", contents);
+  assert_eq!(hover.range, Some(Range::new(Position::new(1, 11), Position::new(1, 11))));
+  server.shutdown().await;
+}
@@ -0,0 +1,66 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use cyber_tree_sitter::pool::{QueryCursorPool, TreeCursorPool};
+use cyber_tree_sitter::{Node, Query, QueryCursor};
+
+/// Copies of each snapshot case in the fixture, a few thousand lines in total
+const REPEAT: usize = 200;
+
+fn fixture() -> String {
+    let mut cases: Vec<_> = std::fs::read_dir("tests/snapshots")
+        .unwrap()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "cy"))
+        .collect();
+    cases.sort();
+
+    let sources: Vec<String> = cases.iter().map(|path| std::fs::read_to_string(path).unwrap()).collect();
+    (0..REPEAT).map(|_| sources.join("\n")).collect::<Vec<_>>().join("\n")
+}
+
+fn count_fresh(node: Node) -> usize {
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.named_children(&mut cursor).collect();
+    1 + children.into_iter().map(count_fresh).sum::<usize>()
+}
+
+fn count_pooled<'tree>(node: Node<'tree>, pool: &TreeCursorPool<'tree>) -> usize {
+    1 + pool.named_children(node).into_iter().map(|child| count_pooled(child, pool)).sum::<usize>()
+}
+
+fn cursor_pool(c: &mut Criterion) {
+    let source = fixture();
+    let tree = cyber_tree_sitter::init_parser().parse(&source, None).unwrap();
+    let query = Query::new(cyber_tree_sitter::cyber_language(), cyber_tree_sitter::CYBER_HIGHLIGHTS_QUERY).unwrap();
+
+    // Highlighting the fixture in small ranges, the way semantic token range requests do
+    let chunk = 2048;
+    let ranges: Vec<_> = (0..source.len()).step_by(chunk).map(|start| start..(start + chunk).min(source.len())).collect();
+
+    let mut group = c.benchmark_group("query_cursor");
+    group.bench_function("fresh", |b| b.iter(|| {
+        ranges.iter().map(|range| {
+            let mut cursor = QueryCursor::new();
+            cursor.set_byte_range(range.clone());
+            let matches = cursor.matches(&query, tree.root_node(), source.as_bytes()).count();
+            matches
+        }).sum::<usize>()
+    }));
+    let pool = QueryCursorPool::new();
+    group.bench_function("pooled", |b| b.iter(|| {
+        ranges.iter().map(|range| {
+            let mut cursor = pool.get();
+            cursor.set_byte_range(range.clone());
+            let matches = cursor.matches(&query, tree.root_node(), source.as_bytes()).count();
+            matches
+        }).sum::<usize>()
+    }));
+    group.finish();
+
+    let mut group = c.benchmark_group("tree_cursor");
+    group.bench_function("fresh", |b| b.iter(|| count_fresh(tree.root_node())));
+    group.bench_function("pooled", |b| b.iter(|| count_pooled(tree.root_node(), &TreeCursorPool::new())));
+    group.finish();
+}
+
+criterion_group!(benches, cursor_pool);
+criterion_main!(benches);
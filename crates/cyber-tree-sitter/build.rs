@@ -0,0 +1,111 @@
+//! Generates the typed AST wrappers of `src/ast.rs` from the grammar's `node-types.json`
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+const NODE_TYPES: &str = "../../../tree-sitter-cyber/src/node-types.json";
+
+/// Rust keywords a field may be named after, accessors for them are raw identifiers
+const KEYWORDS: &[&str] = &[
+  "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn", "else",
+  "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "macro", "match",
+  "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "static", "struct", "trait", "true",
+  "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Keywords which can't be raw identifiers, accessors for them get a trailing `_`
+const RESERVED: &[&str] = &["crate", "self", "Self", "super"];
+
+#[derive(Deserialize)]
+struct NodeType {
+  #[serde(rename = "type")]
+  kind: String,
+  named: bool,
+  #[serde(default)]
+  fields: BTreeMap<String, Field>,
+  #[serde(default)]
+  subtypes: Option<Vec<TypeRef>>,
+}
+
+#[derive(Deserialize)]
+struct Field {
+  multiple: bool,
+  types: Vec<TypeRef>,
+}
+
+#[derive(Deserialize)]
+struct TypeRef {
+  #[serde(rename = "type")]
+  kind: String,
+  named: bool,
+}
+
+fn main() {
+  println!("cargo:rerun-if-changed={}", NODE_TYPES);
+  let out = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("ast.rs");
+
+  // The grammar is a path dependency, without its sources the crate can't build anyway
+  let types: Vec<NodeType> = match std::fs::read_to_string(NODE_TYPES) {
+    Ok(json) => serde_json::from_str(&json).expect("node-types.json is malformed"),
+    Err(err) => panic!("the typed AST is generated from {}, which is unreadable: {}", NODE_TYPES, err),
+  };
+
+  std::fs::write(out, generate(&types)).unwrap();
+}
+
+fn generate(types: &[NodeType]) -> String {
+  // Concrete named nodes get a wrapper, supertypes and anonymous tokens stay `Node`s
+  let wrapped: Vec<&NodeType> = types.iter()
+    .filter(|node| node.named && node.subtypes.is_none() && !node.kind.starts_with('_'))
+    .collect();
+  let has_wrapper = |kind: &str| wrapped.iter().any(|node| node.kind == kind);
+
+  let mut code = String::new();
+  for node in &wrapped {
+    let name = pascal_case(&node.kind);
+    writeln!(code, "/// Typed `{}` node", node.kind).unwrap();
+    writeln!(code, "#[derive(Clone, Copy, Debug, PartialEq, Eq)]").unwrap();
+    writeln!(code, "pub struct {}<'tree>(Node<'tree>);\n", name).unwrap();
+
+    writeln!(code, "impl<'tree> AstNode<'tree> for {}<'tree> {{", name).unwrap();
+    writeln!(code, "  const KIND: &'static str = {:?};", node.kind).unwrap();
+    writeln!(code, "  fn from_node_unchecked(node: Node<'tree>) -> Self {{ Self(node) }}").unwrap();
+    writeln!(code, "  fn node(&self) -> Node<'tree> {{ self.0 }}").unwrap();
+    writeln!(code, "}}\n").unwrap();
+
+    if node.fields.is_empty() { continue; }
+    writeln!(code, "impl<'tree> {}<'tree> {{", name).unwrap();
+    for (field, info) in &node.fields {
+      let method = method_name(field);
+      let named: Vec<&TypeRef> = info.types.iter().filter(|ty| ty.named).collect();
+      let typed = match named.as_slice() {
+        [only] if info.types.len() == 1 && has_wrapper(&only.kind) => Some(pascal_case(&only.kind)),
+        _ => None,
+      };
+
+      match (info.multiple, typed) {
+        (false, Some(ty)) => writeln!(code, "  pub fn {}(&self) -> Option<{}<'tree>> {{ field(self.0, {:?}).and_then({}::cast) }}", method, ty, field, ty),
+        (false, None) => writeln!(code, "  pub fn {}(&self) -> Option<Node<'tree>> {{ field(self.0, {:?}) }}", method, field),
+        (true, Some(ty)) => writeln!(code, "  pub fn {}(&self) -> Vec<{}<'tree>> {{ fields(self.0, {:?}).into_iter().filter_map({}::cast).collect() }}", method, ty, field, ty),
+        (true, None) => writeln!(code, "  pub fn {}(&self) -> Vec<Node<'tree>> {{ fields(self.0, {:?}) }}", method, field),
+      }.unwrap();
+    }
+    writeln!(code, "}}\n").unwrap();
+  }
+  code
+}
+
+fn pascal_case(kind: &str) -> String {
+  kind.split('_').filter(|part| !part.is_empty()).map(|part| {
+    let mut chars = part.chars();
+    chars.next().map(|first| first.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+  }).collect()
+}
+
+fn method_name(field: &str) -> String {
+  if RESERVED.contains(&field) { return format!("{}_", field); }
+  if KEYWORDS.contains(&field) { format!("r#{}", field) } else { field.to_string() }
+}
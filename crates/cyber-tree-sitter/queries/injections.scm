@@ -0,0 +1,16 @@
+; The command passed to `os.exec`
+((call_expression
+  function: (_) @_callee
+  (_ (string_literal) @injection.content))
+  (#match? @_callee "^os\\.exec$")
+  (#set! injection.language "bash"))
+
+; Strings holding a JSON object or array
+((string_literal) @injection.content
+  (#match? @injection.content "^['\"]\\s*[\\[{]")
+  (#set! injection.language "json"))
+
+; Strings with `{expr}` interpolation placeholders
+((string_literal) @injection.content
+  (#match? @injection.content "\\{[^{}'\"\\n]+\\}")
+  (#set! injection.language "format"))
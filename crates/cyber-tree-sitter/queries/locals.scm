@@ -0,0 +1,23 @@
+; Scopes
+(function_definition) @local.scope
+(object_definition) @local.scope
+(for_statement) @local.scope
+(while_statement) @local.scope
+(if_statement) @local.scope
+(try_statement) @local.scope
+
+; Definitions
+(function_definition
+  name: (identifier) @local.definition.function)
+
+(object_definition
+  name: (identifier) @local.definition.type)
+
+(variable_declaration
+  (identifier) @local.definition.var)
+
+(import_statement
+  (identifier) @local.definition.import)
+
+; References
+(identifier) @local.reference
@@ -0,0 +1,11 @@
+(function_definition
+  name: (identifier) @name) @definition.function
+
+(object_definition
+  name: (identifier) @name) @definition.class
+
+(call_expression
+  function: (identifier) @name) @reference.call
+
+(import_statement
+  (identifier) @name) @reference.module
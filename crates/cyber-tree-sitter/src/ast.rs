@@ -0,0 +1,51 @@
+//! Typed wrappers over `Node`, one per named node kind of the grammar, with an
+//! accessor per field. Generated by `build.rs` from `node-types.json`, so a field
+//! renamed in the grammar breaks the build instead of silently returning `None`.
+
+use tree_sitter::Node;
+
+pub trait AstNode<'tree>: Sized + Copy {
+  const KIND: &'static str;
+
+  #[doc(hidden)]
+  fn from_node_unchecked(node: Node<'tree>) -> Self;
+
+  fn node(&self) -> Node<'tree>;
+
+  /// Wrap `node` when it has this kind
+  fn cast(node: Node<'tree>) -> Option<Self> {
+    (node.kind() == Self::KIND).then(|| Self::from_node_unchecked(node))
+  }
+
+  /// The nearest ancestor of `node` with this kind
+  fn ancestor_of(node: Node<'tree>) -> Option<Self> {
+    let mut current = node.parent();
+    while let Some(parent) = current {
+      if let Some(found) = Self::cast(parent) { return Some(found); }
+      current = parent.parent();
+    }
+    None
+  }
+
+  /// Named children of this kind, ie. `FunctionDefinition::children_of(root)`
+  fn children_of(node: Node<'tree>) -> Vec<Self> {
+    let mut cursor = node.walk();
+    let children: Vec<Node<'tree>> = node.named_children(&mut cursor).collect();
+    children.into_iter().filter_map(Self::cast).collect()
+  }
+}
+
+// Only called by generated accessors, a grammar may have no fields of either kind
+#[allow(dead_code)]
+fn field<'tree>(node: Node<'tree>, name: &str) -> Option<Node<'tree>> {
+  node.child_by_field_name(name)
+}
+
+#[allow(dead_code)]
+fn fields<'tree>(node: Node<'tree>, name: &str) -> Vec<Node<'tree>> {
+  let mut cursor = node.walk();
+  let children: Vec<Node<'tree>> = node.children_by_field_name(name, &mut cursor).collect();
+  children
+}
+
+include!(concat!(env!("OUT_DIR"), "/ast.rs"));
@@ -3,6 +3,14 @@ pub use tree_sitter_cyber::{
   language as cyber_language, HIGHLIGHTS_QUERY as CYBER_HIGHLIGHTS_QUERY,
 };
 
+pub mod ast;
+pub mod pool;
+pub mod queries;
+pub use queries::{
+  highlights_query, injections_query, locals_query, tags_query, QueryLoadError,
+  INJECTIONS_QUERY as CYBER_INJECTIONS_QUERY, LOCALS_QUERY as CYBER_LOCALS_QUERY, TAGS_QUERY as CYBER_TAGS_QUERY,
+};
+
 pub fn init_parser() -> tree_sitter::Parser {
   try_init_parser().unwrap_or_else(|lang_err| {
     panic!("Error initialising tree-sitter parser with cyber language: {}", lang_err)
@@ -18,3 +26,86 @@ pub fn try_init_parser() -> Result<tree_sitter::Parser, tree_sitter::LanguageErr
 pub fn get_language() -> Language {
   cyber_language()
 }
+
+/// Parse `source` into an indented s-expression of its named nodes, with field
+/// names, as stored in the `.ast` snapshot files
+pub fn parse_to_sexp(source: &str) -> String {
+  let tree = init_parser().parse(source, None).expect("parser has a language and no timeout");
+  tree_to_sexp(&tree)
+}
+
+pub fn tree_to_sexp(tree: &Tree) -> String {
+  let mut out = String::new();
+  write_sexp(&mut tree.walk(), None, 0, &mut out);
+  out.push('\n');
+  out
+}
+
+fn write_sexp(cursor: &mut TreeCursor, field: Option<&str>, depth: usize, out: &mut String) {
+  let node = cursor.node();
+  if depth > 0 { out.push('\n'); }
+  out.push_str(&"  ".repeat(depth));
+  if let Some(field) = field { out.push_str(field); out.push_str(": "); }
+
+  if node.is_missing() {
+    out.push_str(&format!("(MISSING {})", node.kind()));
+    return;
+  }
+  out.push('(');
+  out.push_str(node.kind());
+
+  if cursor.goto_first_child() {
+    loop {
+      let child = cursor.node();
+      if child.is_named() || child.is_missing() {
+        let field = cursor.field_name();
+        write_sexp(cursor, field, depth + 1, out);
+      }
+      if !cursor.goto_next_sibling() { break; }
+    }
+    cursor.goto_parent();
+  }
+  out.push(')');
+}
+
+/// A syntax error left in the tree, either an `ERROR` node or a node the parser inserted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorNode {
+  /// `ERROR`, or `MISSING <kind>`
+  pub kind: String,
+  pub start: Point,
+  pub end: Point,
+  /// The source text the error node covers, empty for missing nodes
+  pub text: String,
+}
+
+impl std::fmt::Display for ErrorNode {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{} at {}:{}-{}:{}", self.kind, self.start.row, self.start.column, self.end.row, self.end.column)?;
+    if !self.text.is_empty() { write!(f, " {:?}", self.text)?; }
+    Ok(())
+  }
+}
+
+/// Every error and missing node of the tree, in document order. Nested errors are
+/// reported once, by their outermost node
+pub fn error_nodes(tree: &Tree, source: &str) -> Vec<ErrorNode> {
+  let mut errors = vec![];
+  collect_errors(tree.root_node(), source, &mut errors);
+  errors
+}
+
+fn collect_errors(node: Node, source: &str, errors: &mut Vec<ErrorNode>) {
+  if node.is_error() || node.is_missing() {
+    let kind = if node.is_missing() { format!("MISSING {}", node.kind()) } else { "ERROR".to_string() };
+    let text = if node.is_missing() { String::new() } else { node.utf8_text(source.as_bytes()).unwrap_or_default().to_string() };
+    errors.push(ErrorNode { kind, start: node.start_position(), end: node.end_position(), text });
+    return;
+  }
+  if !node.has_error() { return; }
+
+  let mut cursor = node.walk();
+  for child in node.children(&mut cursor) {
+    collect_errors(child, source, errors);
+  }
+}
@@ -0,0 +1,135 @@
+//! Reusable cursors for hot paths that run on every edit, like semantic tokens and
+//! diagnostics, so each pass doesn't allocate fresh cursors.
+
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use tree_sitter::{Node, Point, QueryCursor, TreeCursor};
+
+/// Cursors kept by a pool once returned, extra ones are dropped
+const MAX_POOLED: usize = 16;
+
+/// Shared pool for callers without one of their own
+pub static QUERY_CURSORS: Lazy<QueryCursorPool> = Lazy::new(QueryCursorPool::new);
+
+// --| Query Cursors -----------------
+// --|--------------------------------
+#[derive(Default)]
+pub struct QueryCursorPool {
+  cursors: Mutex<Vec<QueryCursor>>,
+}
+
+impl QueryCursorPool {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// A cursor with no byte or point range set, returned to the pool when dropped
+  pub fn get(&self) -> PooledQueryCursor<'_> {
+    let cursor = self.cursors.lock().unwrap_or_else(|err| err.into_inner()).pop().unwrap_or_else(QueryCursor::new);
+    PooledQueryCursor { pool: self, cursor: Some(cursor) }
+  }
+
+  pub fn len(&self) -> usize {
+    self.cursors.lock().unwrap_or_else(|err| err.into_inner()).len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  fn release(&self, mut cursor: QueryCursor) {
+    // Limits set by the last user would silently restrict the next one
+    cursor.set_byte_range(0..u32::MAX as usize);
+    cursor.set_point_range(Point::new(0, 0)..Point::new(u32::MAX as usize, u32::MAX as usize));
+
+    let mut cursors = self.cursors.lock().unwrap_or_else(|err| err.into_inner());
+    if cursors.len() < MAX_POOLED { cursors.push(cursor); }
+  }
+}
+
+pub struct PooledQueryCursor<'pool> {
+  pool: &'pool QueryCursorPool,
+  cursor: Option<QueryCursor>,
+}
+
+impl Deref for PooledQueryCursor<'_> {
+  type Target = QueryCursor;
+
+  fn deref(&self) -> &QueryCursor {
+    self.cursor.as_ref().unwrap()
+  }
+}
+
+impl DerefMut for PooledQueryCursor<'_> {
+  fn deref_mut(&mut self) -> &mut QueryCursor {
+    self.cursor.as_mut().unwrap()
+  }
+}
+
+impl Drop for PooledQueryCursor<'_> {
+  fn drop(&mut self) {
+    if let Some(cursor) = self.cursor.take() { self.pool.release(cursor); }
+  }
+}
+
+// --| Tree Cursors ------------------
+// --|--------------------------------
+/// Tree cursors borrow the tree they walk, so this pool lives for one pass over a
+/// tree, ie. a recursive walk that would otherwise call `node.walk()` per node.
+#[derive(Default)]
+pub struct TreeCursorPool<'tree> {
+  cursors: RefCell<Vec<TreeCursor<'tree>>>,
+}
+
+impl<'tree> TreeCursorPool<'tree> {
+  pub fn new() -> Self {
+    Self { cursors: RefCell::new(vec![]) }
+  }
+
+  /// A cursor positioned on `node`, returned to the pool when dropped
+  pub fn get(&self, node: Node<'tree>) -> PooledTreeCursor<'_, 'tree> {
+    let cursor = match self.cursors.borrow_mut().pop() {
+      Some(mut cursor) => { cursor.reset(node); cursor }
+      None => node.walk(),
+    };
+    PooledTreeCursor { pool: self, cursor: Some(cursor) }
+  }
+
+  /// Named children of `node`, collected with a pooled cursor
+  pub fn named_children(&self, node: Node<'tree>) -> Vec<Node<'tree>> {
+    let mut cursor = self.get(node);
+    let children: Vec<Node<'tree>> = node.named_children(&mut cursor).collect();
+    children
+  }
+}
+
+pub struct PooledTreeCursor<'pool, 'tree> {
+  pool: &'pool TreeCursorPool<'tree>,
+  cursor: Option<TreeCursor<'tree>>,
+}
+
+impl<'tree> Deref for PooledTreeCursor<'_, 'tree> {
+  type Target = TreeCursor<'tree>;
+
+  fn deref(&self) -> &TreeCursor<'tree> {
+    self.cursor.as_ref().unwrap()
+  }
+}
+
+impl<'tree> DerefMut for PooledTreeCursor<'_, 'tree> {
+  fn deref_mut(&mut self) -> &mut TreeCursor<'tree> {
+    self.cursor.as_mut().unwrap()
+  }
+}
+
+impl Drop for PooledTreeCursor<'_, '_> {
+  fn drop(&mut self) {
+    if let Some(cursor) = self.cursor.take() {
+      let mut cursors = self.pool.cursors.borrow_mut();
+      if cursors.len() < MAX_POOLED { cursors.push(cursor); }
+    }
+  }
+}
@@ -0,0 +1,87 @@
+use once_cell::sync::Lazy;
+use tree_sitter::{Query, QueryError};
+
+use crate::cyber_language;
+
+pub use tree_sitter_cyber::HIGHLIGHTS_QUERY;
+
+/// Scopes, definitions and references, in the `@local.*` captures of nvim-treesitter
+pub const LOCALS_QUERY: &str = include_str!("../queries/locals.scm");
+/// Embedded languages, in the `@injection.*` captures of tree-sitter
+pub const INJECTIONS_QUERY: &str = include_str!("../queries/injections.scm");
+/// Definitions and references for symbol indexing, in the `@definition.*`/`@reference.*` captures of tree-sitter tags
+pub const TAGS_QUERY: &str = include_str!("../queries/tags.scm");
+
+// --| Query Errors ------------------
+// --|--------------------------------
+#[derive(Debug)]
+pub enum QueryLoadError {
+  /// The grammar ships no such query
+  Missing(&'static str),
+  /// The query does not compile against the pinned grammar
+  Invalid(&'static str, QueryError),
+}
+
+impl std::fmt::Display for QueryLoadError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      QueryLoadError::Missing(name) => write!(f, "cyber grammar has no {} query", name),
+      QueryLoadError::Invalid(name, err) => write!(f, "cyber {} query does not compile: {}", name, err),
+    }
+  }
+}
+
+impl std::error::Error for QueryLoadError {}
+
+// --| Compiled Queries --------------
+// --|--------------------------------
+static HIGHLIGHTS: Lazy<Result<Query, QueryLoadError>> = Lazy::new(|| compile("highlights", HIGHLIGHTS_QUERY));
+static LOCALS: Lazy<Result<Query, QueryLoadError>> = Lazy::new(|| compile("locals", LOCALS_QUERY));
+static INJECTIONS: Lazy<Result<Query, QueryLoadError>> = Lazy::new(|| compile("injections", INJECTIONS_QUERY));
+static TAGS: Lazy<Result<Query, QueryLoadError>> = Lazy::new(|| compile("tags", TAGS_QUERY));
+
+fn compile(name: &'static str, source: &str) -> Result<Query, QueryLoadError> {
+  if source.lines().all(|line| line.trim().is_empty() || line.trim_start().starts_with(';')) {
+    return Err(QueryLoadError::Missing(name));
+  }
+  Query::new(cyber_language(), source).map_err(|err| QueryLoadError::Invalid(name, err))
+}
+
+/// Compiled once on first use, later calls return the same query or error
+pub fn highlights_query() -> Result<&'static Query, &'static QueryLoadError> {
+  HIGHLIGHTS.as_ref()
+}
+
+pub fn locals_query() -> Result<&'static Query, &'static QueryLoadError> {
+  LOCALS.as_ref()
+}
+
+pub fn injections_query() -> Result<&'static Query, &'static QueryLoadError> {
+  INJECTIONS.as_ref()
+}
+
+pub fn tags_query() -> Result<&'static Query, &'static QueryLoadError> {
+  TAGS.as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Compiling checks every node kind and field the query names against the grammar
+  #[test]
+  fn shipped_queries_compile_against_the_grammar() {
+    let queries = [("highlights", highlights_query()), ("locals", locals_query()), ("injections", injections_query()), ("tags", tags_query())];
+    for (name, query) in queries {
+      if let Err(err) = query { panic!("{} query: {}", name, err); }
+    }
+  }
+
+  #[test]
+  fn queries_have_the_captures_consumers_match_on() {
+    let captures = |query: &Query| query.capture_names().to_vec();
+    assert!(captures(locals_query().unwrap()).iter().any(|name| name == "local.scope"));
+    assert!(captures(injections_query().unwrap()).iter().any(|name| name == "injection.content"));
+    assert!(captures(tags_query().unwrap()).iter().any(|name| name.starts_with("definition.")));
+  }
+}
@@ -0,0 +1,51 @@
+use std::path::Path;
+
+/// Set to rewrite the `.ast` files from the current grammar instead of comparing against them
+const UPDATE_ENV: &str = "CYBER_UPDATE_SNAPSHOTS";
+
+datatest_stable::harness!(
+    ast_snapshot,
+    "tests/snapshots",
+    r"\.cy$",
+);
+
+fn ast_snapshot(path: &Path) -> datatest_stable::Result<()> {
+    let source = std::fs::read_to_string(path)?;
+    let tree = cyber_tree_sitter::init_parser()
+        .parse(&source, None)
+        .unwrap();
+
+    let mut actual = cyber_tree_sitter::tree_to_sexp(&tree);
+    let errors = cyber_tree_sitter::error_nodes(&tree, &source);
+    if !errors.is_empty() {
+        actual.push_str("\n-- errors\n");
+        for error in errors {
+            actual.push_str(&format!("{}\n", error));
+        }
+    }
+
+    let golden = path.with_extension("ast");
+    if std::env::var_os(UPDATE_ENV).is_some() {
+        std::fs::write(&golden, &actual)?;
+        return Ok(());
+    }
+
+    // A missing golden fails without writing one, so only committed trees are ever compared
+    let expected = match std::fs::read_to_string(&golden) {
+        Ok(expected) => expected,
+        Err(_) => {
+            return Err(format!(
+                "{} has no snapshot {}, generate it with {}=1 and commit it\n--- actual\n{}",
+                path.display(), golden.display(), UPDATE_ENV, actual,
+            ).into());
+        }
+    };
+
+    if expected.replace("\r\n", "\n") != actual {
+        return Err(format!(
+            "{} no longer matches {}, rerun with {}=1 if the change is intended\n--- expected\n{}\n--- actual\n{}",
+            path.display(), golden.display(), UPDATE_ENV, expected, actual,
+        ).into());
+    }
+    Ok(())
+}